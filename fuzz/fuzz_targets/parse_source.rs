@@ -0,0 +1,15 @@
+#![no_main]
+
+//! Feeds arbitrary text through the lexer and parser. Any outcome is fine
+//! except a panic or a hang.
+
+use libfuzzer_sys::fuzz_target;
+use put_lang::lexer::Lexer;
+use put_lang::parser::Parser;
+
+fuzz_target!(|source: &str| {
+    let _ = Parser::new(Lexer::new(source)).parse();
+    let _ = Parser::new(Lexer::new(source))
+        .newline_terminated(true)
+        .parse();
+});
@@ -0,0 +1,126 @@
+#![no_main]
+
+//! Feeds the parser token streams the lexer would never produce: arbitrary
+//! token types and lexemes, lines out of order, and possibly no `EOF`.
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use put_lang::parser::Parser;
+use put_lang::token::{Token, TokenType};
+
+const TOKEN_TYPES: &[TokenType] = &[
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Comma,
+    TokenType::Dot,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::Semicolon,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Ampersand,
+    TokenType::Pipe,
+    TokenType::Caret,
+    TokenType::At,
+    TokenType::Colon,
+    TokenType::Question,
+    TokenType::Bang,
+    TokenType::BangEqual,
+    TokenType::Equal,
+    TokenType::EqualEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Assign,
+    TokenType::ShiftLeft,
+    TokenType::ShiftRight,
+    TokenType::Arrow,
+    TokenType::ColonColon,
+    TokenType::FatArrow,
+    TokenType::DotDot,
+    TokenType::DotDotEqual,
+    TokenType::QuestionDot,
+    TokenType::PlusEqual,
+    TokenType::MinusEqual,
+    TokenType::StarEqual,
+    TokenType::SlashEqual,
+    TokenType::Identifier,
+    TokenType::String,
+    TokenType::Number,
+    TokenType::Char,
+    TokenType::And,
+    TokenType::As,
+    TokenType::Breakpoint,
+    TokenType::Catch,
+    TokenType::Class,
+    TokenType::Const,
+    TokenType::Do,
+    TokenType::Else,
+    TokenType::Enum,
+    TokenType::False,
+    TokenType::Fun,
+    TokenType::For,
+    TokenType::If,
+    TokenType::Import,
+    TokenType::In,
+    TokenType::Match,
+    TokenType::Nil,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Spawn,
+    TokenType::Struct,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::Throw,
+    TokenType::True,
+    TokenType::Try,
+    TokenType::Use,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::Comment,
+    TokenType::Error,
+    TokenType::EOF,
+];
+
+#[derive(Arbitrary, Debug)]
+struct FuzzToken {
+    kind: u8,
+    lexeme: String,
+    line: u8,
+    column: u8,
+}
+
+impl FuzzToken {
+    fn into_token(self) -> Token {
+        let token_type = TOKEN_TYPES[self.kind as usize % TOKEN_TYPES.len()];
+        Token::new(
+            token_type,
+            self.lexeme,
+            self.line as usize,
+            self.column as usize,
+        )
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    tokens: Vec<FuzzToken>,
+    newline_terminated: bool,
+}
+
+fuzz_target!(|input: Input| {
+    let tokens: Vec<Token> = input
+        .tokens
+        .into_iter()
+        .map(FuzzToken::into_token)
+        .collect();
+    let _ = Parser::new(tokens)
+        .newline_terminated(input.newline_terminated)
+        .parse();
+});
@@ -1,181 +1,1473 @@
-#![allow(dead_code)] // Suppress all dead_code warnings in this file
-
-use std::any::Any;
-use std::fmt::Debug;
-
-pub trait StatementNode: Debug {
-    fn as_any(&self) -> &dyn Any;
-}
-
-#[derive(Debug)]
-pub enum DataType {
-    Integer,
-    Float,
-    String,
-    Boolean,
-    Void, // For functions with no return type or for unit type
-}
-
-#[derive(Debug)]
-pub struct ProgramNode {
-    pub statements: Vec<Box<dyn StatementNode>>,
-}
-
-impl ProgramNode {
-    pub fn new() -> Self {
-        ProgramNode {
-            statements: Vec::new(),
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct VariableNode {
-    pub name: String,
-    pub data_type: DataType,
-}
-
-impl VariableNode {
-    pub fn new(name: String, data_type: DataType) -> Self {
-        VariableNode { name, data_type }
-    }
-}
-
-impl StatementNode for VariableNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub struct NumberNode {
-    pub value: String,
-    pub data_type: DataType,
-}
-
-impl NumberNode {
-    pub fn new(value: String, data_type: DataType) -> Self {
-        NumberNode { value, data_type }
-    }
-}
-
-impl StatementNode for NumberNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub struct AssignmentNode {
-    pub left: Box<dyn StatementNode>,
-    pub right: Box<dyn StatementNode>,
-}
-
-impl AssignmentNode {
-    pub fn new(left: Box<dyn StatementNode>, right: Box<dyn StatementNode>) -> Self {
-        AssignmentNode { left, right }
-    }
-}
-
-impl StatementNode for AssignmentNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub enum BinaryOperator {
-    Add,
-    Subtract,
-    Multiply,
-    Divide,
-}
-
-#[derive(Debug)]
-pub struct BinaryOperationNode {
-    pub left: Box<dyn StatementNode>,
-    pub operator: BinaryOperator,
-    pub right: Box<dyn StatementNode>,
-}
-
-impl BinaryOperationNode {
-    pub fn new(
-        left: Box<dyn StatementNode>,
-        operator: BinaryOperator,
-        right: Box<dyn StatementNode>,
-    ) -> Self {
-        BinaryOperationNode {
-            left,
-            operator,
-            right,
-        }
-    }
-}
-
-impl StatementNode for BinaryOperationNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub struct ParenthesisNode {
-    pub expression: Box<dyn StatementNode>,
-}
-
-impl ParenthesisNode {
-    pub fn new(expression: Box<dyn StatementNode>) -> Self {
-        ParenthesisNode { expression }
-    }
-}
-
-impl StatementNode for ParenthesisNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub struct IfNode {
-    pub condition: Box<dyn StatementNode>,
-    pub then_branch: Box<dyn StatementNode>,
-    pub else_branch: Option<Box<dyn StatementNode>>,
-}
-
-impl IfNode {
-    pub fn new(
-        condition: Box<dyn StatementNode>,
-        then_branch: Box<dyn StatementNode>,
-        else_branch: Option<Box<dyn StatementNode>>,
-    ) -> Self {
-        IfNode {
-            condition,
-            then_branch,
-            else_branch,
-        }
-    }
-}
-
-impl StatementNode for IfNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
-
-#[derive(Debug)]
-pub struct WhileNode {
-    pub condition: Box<dyn StatementNode>,
-    pub body: Box<dyn StatementNode>,
-}
-
-impl WhileNode {
-    pub fn new(condition: Box<dyn StatementNode>, body: Box<dyn StatementNode>) -> Self {
-        WhileNode { condition, body }
-    }
-}
-
-impl StatementNode for WhileNode {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
-}
+#![allow(dead_code)] // Suppress all dead_code warnings in this file
+
+//! The PUT syntax tree. Expressions (`Expr`) and statements (`Stmt`) are
+//! separate enums so every pass can match on them exhaustively; each
+//! variant carries a `*Node` struct holding that construct's data.
+
+use crate::tensor::Tensor;
+use crate::token::Span;
+use crate::visitor::{walk_expr, walk_stmt, Visitor};
+
+/// Where a node starts in the source, for nodes built by the parser.
+/// Positions are bookkeeping rather than structure, so any two spans
+/// compare equal: the same tree laid out differently is still equal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeSpan(pub Option<Span>);
+
+impl PartialEq for NodeSpan {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+/// What the type checker worked out about an expression: its type, and
+/// for a tensor its shape, where those are known. Like `NodeSpan`, any two
+/// compare equal, so a checked tree still equals the tree it was parsed
+/// as.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedType(
+    // Boxed so that unchecked trees, and the parser's stack, don't pay for
+    // it
+    Option<Box<Resolution>>,
+);
+
+type Resolution = (Option<DataType>, Option<Vec<usize>>);
+
+impl ResolvedType {
+    pub fn new(data_type: Option<DataType>, shape: Option<Vec<usize>>) -> Self {
+        ResolvedType(Some(Box::new((data_type, shape))))
+    }
+
+    pub fn data_type(&self) -> Option<&DataType> {
+        self.0.as_ref()?.0.as_ref()
+    }
+
+    pub fn shape(&self) -> Option<&[usize]> {
+        self.0.as_ref()?.1.as_deref()
+    }
+}
+
+impl PartialEq for ResolvedType {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataType {
+    Integer,
+    Float,
+    String,
+    Boolean,
+    Char,
+    Tensor,
+    Struct(String),          // A user-defined struct, by name
+    Enum(String),            // A user-defined enum, by name
+    Void,                    // For functions with no return type or for unit type
+    Optional(Box<DataType>), // `int?`: a value of the inner type, or null
+    Array(Box<DataType>),    // `[int]`: an array of values of the element type
+    Parameter(String),       // `T` in `fn first<T>(...)`, standing for any type
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProgramNode {
+    pub statements: Vec<Stmt>,
+}
+
+impl ProgramNode {
+    pub fn new() -> Self {
+        ProgramNode {
+            statements: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(NumberNode),
+    String(StringNode),
+    Char(CharNode),
+    Boolean(BooleanNode),
+    Null(NullNode),
+    Variable(VariableNode),
+    Assignment(AssignmentNode),
+    CompoundAssignment(CompoundAssignmentNode),
+    Binary(BinaryOperationNode),
+    Unary(UnaryOperationNode),
+    Cast(CastNode),
+    Parenthesis(ParenthesisNode),
+    Conditional(ConditionalExpressionNode),
+    Range(RangeNode),
+    Call(CallNode),
+    MethodCall(MethodCallNode),
+    MemberAccess(MemberAccessNode),
+    Index(IndexNode),
+    EnumVariant(EnumVariantNode),
+    TensorLiteral(TensorLiteralNode),
+    Lambda(LambdaNode),
+    Spawn(SpawnNode),
+    Match(MatchExpressionNode),
+    Cfg(CfgNode),
+}
+
+impl Expr {
+    /// Where the expression appears in the source: its first token, or
+    /// for an operation, its operator. `None` for built trees.
+    pub fn span(&self) -> Option<Span> {
+        let span = match self {
+            Expr::Number(node) => node.span,
+            Expr::String(node) => node.span,
+            Expr::Char(node) => node.span,
+            Expr::Boolean(node) => node.span,
+            Expr::Null(node) => node.span,
+            Expr::Variable(node) => node.span,
+            Expr::Assignment(node) => node.span,
+            Expr::CompoundAssignment(node) => node.span,
+            Expr::Binary(node) => node.span,
+            Expr::Unary(node) => node.span,
+            Expr::Cast(node) => node.span,
+            Expr::Parenthesis(node) => node.span,
+            Expr::Conditional(node) => node.span,
+            Expr::Range(node) => node.span,
+            Expr::Call(node) => node.span,
+            Expr::MethodCall(node) => node.span,
+            Expr::MemberAccess(node) => node.span,
+            Expr::Index(node) => node.span,
+            Expr::EnumVariant(node) => node.span,
+            Expr::TensorLiteral(node) => node.span,
+            Expr::Lambda(node) => node.span,
+            Expr::Spawn(node) => node.span,
+            Expr::Match(node) => node.span,
+            Expr::Cfg(node) => node.span,
+        };
+        span.0
+    }
+
+    /// What the type checker worked out about the expression; empty
+    /// until `TypeChecker::infer_types` has run over it.
+    pub fn resolved(&self) -> &ResolvedType {
+        match self {
+            Expr::Number(node) => &node.resolved,
+            Expr::String(node) => &node.resolved,
+            Expr::Char(node) => &node.resolved,
+            Expr::Boolean(node) => &node.resolved,
+            Expr::Null(node) => &node.resolved,
+            Expr::Variable(node) => &node.resolved,
+            Expr::Assignment(node) => &node.resolved,
+            Expr::CompoundAssignment(node) => &node.resolved,
+            Expr::Binary(node) => &node.resolved,
+            Expr::Unary(node) => &node.resolved,
+            Expr::Cast(node) => &node.resolved,
+            Expr::Parenthesis(node) => &node.resolved,
+            Expr::Conditional(node) => &node.resolved,
+            Expr::Range(node) => &node.resolved,
+            Expr::Call(node) => &node.resolved,
+            Expr::MethodCall(node) => &node.resolved,
+            Expr::MemberAccess(node) => &node.resolved,
+            Expr::Index(node) => &node.resolved,
+            Expr::EnumVariant(node) => &node.resolved,
+            Expr::TensorLiteral(node) => &node.resolved,
+            Expr::Lambda(node) => &node.resolved,
+            Expr::Spawn(node) => &node.resolved,
+            Expr::Match(node) => &node.resolved,
+            Expr::Cfg(node) => &node.resolved,
+        }
+    }
+
+    pub fn set_resolved(&mut self, resolved: ResolvedType) {
+        *self.node_resolved() = resolved;
+    }
+
+    fn node_resolved(&mut self) -> &mut ResolvedType {
+        match self {
+            Expr::Number(node) => &mut node.resolved,
+            Expr::String(node) => &mut node.resolved,
+            Expr::Char(node) => &mut node.resolved,
+            Expr::Boolean(node) => &mut node.resolved,
+            Expr::Null(node) => &mut node.resolved,
+            Expr::Variable(node) => &mut node.resolved,
+            Expr::Assignment(node) => &mut node.resolved,
+            Expr::CompoundAssignment(node) => &mut node.resolved,
+            Expr::Binary(node) => &mut node.resolved,
+            Expr::Unary(node) => &mut node.resolved,
+            Expr::Cast(node) => &mut node.resolved,
+            Expr::Parenthesis(node) => &mut node.resolved,
+            Expr::Conditional(node) => &mut node.resolved,
+            Expr::Range(node) => &mut node.resolved,
+            Expr::Call(node) => &mut node.resolved,
+            Expr::MethodCall(node) => &mut node.resolved,
+            Expr::MemberAccess(node) => &mut node.resolved,
+            Expr::Index(node) => &mut node.resolved,
+            Expr::EnumVariant(node) => &mut node.resolved,
+            Expr::TensorLiteral(node) => &mut node.resolved,
+            Expr::Lambda(node) => &mut node.resolved,
+            Expr::Spawn(node) => &mut node.resolved,
+            Expr::Match(node) => &mut node.resolved,
+            Expr::Cfg(node) => &mut node.resolved,
+        }
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        *self.node_span() = NodeSpan(Some(span));
+    }
+
+    fn node_span(&mut self) -> &mut NodeSpan {
+        match self {
+            Expr::Number(node) => &mut node.span,
+            Expr::String(node) => &mut node.span,
+            Expr::Char(node) => &mut node.span,
+            Expr::Boolean(node) => &mut node.span,
+            Expr::Null(node) => &mut node.span,
+            Expr::Variable(node) => &mut node.span,
+            Expr::Assignment(node) => &mut node.span,
+            Expr::CompoundAssignment(node) => &mut node.span,
+            Expr::Binary(node) => &mut node.span,
+            Expr::Unary(node) => &mut node.span,
+            Expr::Cast(node) => &mut node.span,
+            Expr::Parenthesis(node) => &mut node.span,
+            Expr::Conditional(node) => &mut node.span,
+            Expr::Range(node) => &mut node.span,
+            Expr::Call(node) => &mut node.span,
+            Expr::MethodCall(node) => &mut node.span,
+            Expr::MemberAccess(node) => &mut node.span,
+            Expr::Index(node) => &mut node.span,
+            Expr::EnumVariant(node) => &mut node.span,
+            Expr::TensorLiteral(node) => &mut node.span,
+            Expr::Lambda(node) => &mut node.span,
+            Expr::Spawn(node) => &mut node.span,
+            Expr::Match(node) => &mut node.span,
+            Expr::Cfg(node) => &mut node.span,
+        }
+    }
+
+    /// The comments written around the expression, if any.
+    pub fn comments(&self) -> Option<&Comments> {
+        let comments = match self {
+            Expr::Number(node) => &node.comments,
+            Expr::String(node) => &node.comments,
+            Expr::Char(node) => &node.comments,
+            Expr::Boolean(node) => &node.comments,
+            Expr::Null(node) => &node.comments,
+            Expr::Variable(node) => &node.comments,
+            Expr::Assignment(node) => &node.comments,
+            Expr::CompoundAssignment(node) => &node.comments,
+            Expr::Binary(node) => &node.comments,
+            Expr::Unary(node) => &node.comments,
+            Expr::Cast(node) => &node.comments,
+            Expr::Parenthesis(node) => &node.comments,
+            Expr::Conditional(node) => &node.comments,
+            Expr::Range(node) => &node.comments,
+            Expr::Call(node) => &node.comments,
+            Expr::MethodCall(node) => &node.comments,
+            Expr::MemberAccess(node) => &node.comments,
+            Expr::Index(node) => &node.comments,
+            Expr::EnumVariant(node) => &node.comments,
+            Expr::TensorLiteral(node) => &node.comments,
+            Expr::Lambda(node) => &node.comments,
+            Expr::Spawn(node) => &node.comments,
+            Expr::Match(node) => &node.comments,
+            Expr::Cfg(node) => &node.comments,
+        };
+        comments.as_deref()
+    }
+
+    pub fn comments_mut(&mut self) -> &mut Comments {
+        self.node_comments().get_or_insert_with(Default::default)
+    }
+
+    /// Removes the expression's comments, to move them to another node.
+    pub fn take_comments(&mut self) -> Option<Box<Comments>> {
+        self.node_comments().take()
+    }
+
+    fn node_comments(&mut self) -> &mut Option<Box<Comments>> {
+        match self {
+            Expr::Number(node) => &mut node.comments,
+            Expr::String(node) => &mut node.comments,
+            Expr::Char(node) => &mut node.comments,
+            Expr::Boolean(node) => &mut node.comments,
+            Expr::Null(node) => &mut node.comments,
+            Expr::Variable(node) => &mut node.comments,
+            Expr::Assignment(node) => &mut node.comments,
+            Expr::CompoundAssignment(node) => &mut node.comments,
+            Expr::Binary(node) => &mut node.comments,
+            Expr::Unary(node) => &mut node.comments,
+            Expr::Cast(node) => &mut node.comments,
+            Expr::Parenthesis(node) => &mut node.comments,
+            Expr::Conditional(node) => &mut node.comments,
+            Expr::Range(node) => &mut node.comments,
+            Expr::Call(node) => &mut node.comments,
+            Expr::MethodCall(node) => &mut node.comments,
+            Expr::MemberAccess(node) => &mut node.comments,
+            Expr::Index(node) => &mut node.comments,
+            Expr::EnumVariant(node) => &mut node.comments,
+            Expr::TensorLiteral(node) => &mut node.comments,
+            Expr::Lambda(node) => &mut node.comments,
+            Expr::Spawn(node) => &mut node.comments,
+            Expr::Match(node) => &mut node.comments,
+            Expr::Cfg(node) => &mut node.comments,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expression(Expr),
+    VarDeclaration(VarDeclarationNode),
+    ConstDeclaration(ConstDeclarationNode),
+    FunctionDeclaration(FunctionDeclarationNode),
+    StructDeclaration(StructDeclarationNode),
+    EnumDeclaration(EnumDeclarationNode),
+    Return(ReturnNode),
+    Breakpoint(BreakpointNode),
+    If(IfNode),
+    While(WhileNode),
+    DoWhile(DoWhileNode),
+    For(ForNode),
+    ForIn(ForInNode),
+    Block(BlockNode),
+    Match(MatchNode),
+    Try(TryNode),
+    Throw(ThrowNode),
+    Import(ImportNode),
+    // A comment with no statement to attach to, alone in a block or
+    // program
+    Comment(CommentNode),
+}
+
+impl Stmt {
+    /// The comments written around the statement, if any. An expression
+    /// statement's are its expression's.
+    pub fn comments(&self) -> Option<&Comments> {
+        let comments = match self {
+            Stmt::Expression(expr) => return expr.comments(),
+            Stmt::VarDeclaration(node) => &node.comments,
+            Stmt::ConstDeclaration(node) => &node.comments,
+            Stmt::FunctionDeclaration(node) => &node.comments,
+            Stmt::StructDeclaration(node) => &node.comments,
+            Stmt::EnumDeclaration(node) => &node.comments,
+            Stmt::Return(node) => &node.comments,
+            Stmt::Breakpoint(node) => &node.comments,
+            Stmt::If(node) => &node.comments,
+            Stmt::While(node) => &node.comments,
+            Stmt::DoWhile(node) => &node.comments,
+            Stmt::For(node) => &node.comments,
+            Stmt::ForIn(node) => &node.comments,
+            Stmt::Block(node) => &node.comments,
+            Stmt::Match(node) => &node.comments,
+            Stmt::Try(node) => &node.comments,
+            Stmt::Throw(node) => &node.comments,
+            Stmt::Import(node) => &node.comments,
+            Stmt::Comment(_) => return None,
+        };
+        comments.as_deref()
+    }
+
+    /// `None` for a `Stmt::Comment`, which is a comment itself.
+    pub fn comments_mut(&mut self) -> Option<&mut Comments> {
+        let comments = match self {
+            Stmt::Expression(expr) => return Some(expr.comments_mut()),
+            Stmt::VarDeclaration(node) => &mut node.comments,
+            Stmt::ConstDeclaration(node) => &mut node.comments,
+            Stmt::FunctionDeclaration(node) => &mut node.comments,
+            Stmt::StructDeclaration(node) => &mut node.comments,
+            Stmt::EnumDeclaration(node) => &mut node.comments,
+            Stmt::Return(node) => &mut node.comments,
+            Stmt::Breakpoint(node) => &mut node.comments,
+            Stmt::If(node) => &mut node.comments,
+            Stmt::While(node) => &mut node.comments,
+            Stmt::DoWhile(node) => &mut node.comments,
+            Stmt::For(node) => &mut node.comments,
+            Stmt::ForIn(node) => &mut node.comments,
+            Stmt::Block(node) => &mut node.comments,
+            Stmt::Match(node) => &mut node.comments,
+            Stmt::Try(node) => &mut node.comments,
+            Stmt::Throw(node) => &mut node.comments,
+            Stmt::Import(node) => &mut node.comments,
+            Stmt::Comment(_) => return None,
+        };
+        Some(comments.get_or_insert_with(Default::default))
+    }
+
+    /// The line the statement starts on, going by the spans of its own
+    /// expressions (not those of statements nested in it). Statements
+    /// without any, like `return;` or a block, have no line.
+    pub fn line(&self) -> Option<usize> {
+        struct FirstLine(Option<usize>);
+
+        impl Visitor for FirstLine {
+            // Nested statements have lines of their own
+            fn visit_stmt(&mut self, _: &Stmt) {}
+
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let Some(span) = expr.span() {
+                    self.0 = Some(self.0.map_or(span.line, |line| line.min(span.line)));
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        if let Stmt::Breakpoint(node) = self {
+            return node.span.0.map(|span| span.line);
+        }
+        let mut first = FirstLine(None);
+        walk_stmt(&mut first, self);
+        first.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableNode {
+    pub name: String,
+    // The variable's type, once `TypeChecker::infer_types` has worked it out
+    pub data_type: Option<DataType>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl VariableNode {
+    pub fn new(name: String) -> Self {
+        VariableNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            name,
+            data_type: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberNode {
+    pub value: String,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl NumberNode {
+    pub fn new(value: String, data_type: DataType) -> Self {
+        NumberNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            value,
+            data_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringNode {
+    pub value: String,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl StringNode {
+    pub fn new(value: String) -> Self {
+        StringNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            value,
+            data_type: DataType::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BooleanNode {
+    pub value: bool,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl BooleanNode {
+    pub fn new(value: bool) -> Self {
+        BooleanNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            value,
+            data_type: DataType::Boolean,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NullNode {
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharNode {
+    pub value: char,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl CharNode {
+    pub fn new(value: char) -> Self {
+        CharNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            value,
+            data_type: DataType::Char,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDeclarationNode {
+    pub name: String,
+    // `None` when the declaration has no `: type` annotation
+    pub data_type: Option<DataType>,
+    pub initializer: Option<Expr>,
+
+    // At the declared name
+    pub span: NodeSpan,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl VarDeclarationNode {
+    pub fn new(name: String, data_type: Option<DataType>, initializer: Option<Expr>) -> Self {
+        VarDeclarationNode {
+            name,
+            data_type,
+            initializer,
+            span: NodeSpan::default(),
+            comments: None,
+        }
+    }
+}
+
+/// `const PI = 3.14159;`: a binding that can never be reassigned, so its
+/// value can be folded into every use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstDeclarationNode {
+    pub name: String,
+    pub data_type: Option<DataType>,
+    pub value: Expr,
+
+    // At the declared name
+    pub span: NodeSpan,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ConstDeclarationNode {
+    pub fn new(name: String, data_type: Option<DataType>, value: Expr) -> Self {
+        ConstDeclarationNode {
+            name,
+            data_type,
+            value,
+            span: NodeSpan::default(),
+            comments: None,
+        }
+    }
+}
+
+/// `target = value` where the target is a variable or a member access.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignmentNode {
+    pub target: Box<Expr>,
+    pub value: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl AssignmentNode {
+    pub fn new(target: Expr, value: Expr) -> Self {
+        AssignmentNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            target: Box::new(target),
+            value: Box::new(value),
+        }
+    }
+}
+
+/// `target += value` (likewise `-=`, `*=`, `/=`). The desugaring pass
+/// rewrites it to `target = target + value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundAssignmentNode {
+    pub target: Box<Expr>,
+    pub operator: BinaryOperator,
+    pub value: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl CompoundAssignmentNode {
+    pub fn new(target: Expr, operator: BinaryOperator, value: Expr) -> Self {
+        CompoundAssignmentNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            target: Box::new(target),
+            operator,
+            value: Box::new(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    MatMul, // `a @ b`, matrix multiplication of tensors
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryOperationNode {
+    pub left: Box<Expr>,
+    pub operator: BinaryOperator,
+    pub right: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl BinaryOperationNode {
+    pub fn new(left: Expr, operator: BinaryOperator, right: Expr) -> Self {
+        BinaryOperationNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnaryOperationNode {
+    pub operator: UnaryOperator,
+    pub operand: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl UnaryOperationNode {
+    pub fn new(operator: UnaryOperator, operand: Expr) -> Self {
+        UnaryOperationNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            operator,
+            operand: Box::new(operand),
+        }
+    }
+}
+
+/// `expression as int`: the value converted to another type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastNode {
+    pub expression: Box<Expr>,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl CastNode {
+    pub fn new(expression: Expr, data_type: DataType) -> Self {
+        CastNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            expression: Box::new(expression),
+            data_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParenthesisNode {
+    pub expression: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ParenthesisNode {
+    pub fn new(expression: Expr) -> Self {
+        ParenthesisNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            expression: Box::new(expression),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalExpressionNode {
+    pub condition: Box<Expr>,
+    pub then_expression: Box<Expr>,
+    pub else_expression: Box<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ConditionalExpressionNode {
+    pub fn new(condition: Expr, then_expression: Expr, else_expression: Expr) -> Self {
+        ConditionalExpressionNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            condition: Box::new(condition),
+            then_expression: Box::new(then_expression),
+            else_expression: Box::new(else_expression),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeNode {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub inclusive: bool,
+    pub step: Option<Box<Expr>>, // `0..10 step 2`; a missing step counts by 1
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl RangeNode {
+    pub fn new(start: Expr, end: Expr, inclusive: bool, step: Option<Expr>) -> Self {
+        RangeNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            start: Box::new(start),
+            end: Box::new(end),
+            inclusive,
+            step: step.map(Box::new),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallNode {
+    pub callee: Box<Expr>,
+    pub arguments: Vec<Expr>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl CallNode {
+    pub fn new(callee: Expr, arguments: Vec<Expr>) -> Self {
+        CallNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            callee: Box::new(callee),
+            arguments,
+        }
+    }
+}
+
+/// `object.method(arguments)`, e.g. `t.matmul(u)` or `s.len()`; with `?.`
+/// the call is skipped and yields null when the object is null.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodCallNode {
+    pub object: Box<Expr>,
+    pub method: String,
+    pub arguments: Vec<Expr>,
+    pub optional: bool,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl MethodCallNode {
+    pub fn new(object: Expr, method: String, arguments: Vec<Expr>, optional: bool) -> Self {
+        MethodCallNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            object: Box::new(object),
+            method,
+            arguments,
+            optional,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockNode {
+    pub statements: Vec<Stmt>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl BlockNode {
+    pub fn new(statements: Vec<Stmt>) -> Self {
+        BlockNode {
+            statements,
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnNode {
+    pub value: Option<Expr>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ReturnNode {
+    pub fn new(value: Option<Expr>) -> Self {
+        ReturnNode {
+            value,
+            comments: None,
+        }
+    }
+}
+
+/// `breakpoint;` pauses the interpreter's debugger, if one is attached,
+/// and does nothing otherwise.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BreakpointNode {
+    pub span: NodeSpan,
+    pub comments: Option<Box<Comments>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberAccessNode {
+    pub object: Box<Expr>,
+    pub member: String,
+    pub optional: bool, // `object?.member` yields null when the object is null
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl MemberAccessNode {
+    pub fn new(object: Expr, member: String, optional: bool) -> Self {
+        MemberAccessNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            object: Box::new(object),
+            member,
+            optional,
+        }
+    }
+}
+
+/// `object[index]`, or `object?.[index]` which yields null when the object
+/// is null.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexNode {
+    pub object: Box<Expr>,
+    pub index: Box<Expr>,
+    pub optional: bool,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl IndexNode {
+    pub fn new(object: Expr, index: Expr, optional: bool) -> Self {
+        IndexNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            object: Box::new(object),
+            index: Box::new(index),
+            optional,
+        }
+    }
+}
+
+/// `@cfg("key")`: a setting from project.zom's `## Custom Settings`,
+/// replaced by its value before the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CfgNode {
+    pub key: String,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl CfgNode {
+    pub fn new(key: String) -> Self {
+        CfgNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumVariantNode {
+    pub enum_name: String,
+    pub variant: String,
+    pub data_type: DataType,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl EnumVariantNode {
+    pub fn new(enum_name: String, variant: String) -> Self {
+        let data_type = DataType::Enum(enum_name.clone());
+        EnumVariantNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            enum_name,
+            variant,
+            data_type,
+        }
+    }
+}
+
+/// `[[1, 2], [3, 4]]`: the elements flattened in row-major order, plus the
+/// shape inferred from the nesting (here `[2, 2]`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorLiteralNode {
+    pub elements: Vec<Expr>,
+    pub shape: Vec<usize>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl TensorLiteralNode {
+    pub fn new(elements: Vec<Expr>, shape: Vec<usize>) -> Self {
+        TensorLiteralNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            elements,
+            shape,
+        }
+    }
+
+    /// Builds the tensor directly when every element is a numeric literal
+    /// (optionally negated); `None` if any element needs evaluating first.
+    pub fn to_tensor(&self) -> Option<Tensor> {
+        let data = self
+            .elements
+            .iter()
+            .map(|element| match element {
+                Expr::Number(number) => number.value.parse::<f64>().ok(),
+                Expr::Unary(unary) if unary.operator == UnaryOperator::Negate => {
+                    match &*unary.operand {
+                        Expr::Number(number) => number.value.parse::<f64>().ok().map(|v| -v),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        Some(Tensor::new(data, self.shape.clone()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfNode {
+    pub condition: Expr,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl IfNode {
+    pub fn new(condition: Expr, then_branch: Stmt, else_branch: Option<Stmt>) -> Self {
+        IfNode {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileNode {
+    pub condition: Expr,
+    pub body: Box<Stmt>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl WhileNode {
+    pub fn new(condition: Expr, body: Stmt) -> Self {
+        WhileNode {
+            condition,
+            body: Box::new(body),
+            comments: None,
+        }
+    }
+}
+
+/// `do { body } while (condition);` runs the body once before the first
+/// test of the condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoWhileNode {
+    pub body: Box<Stmt>,
+    pub condition: Expr,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl DoWhileNode {
+    pub fn new(body: Stmt, condition: Expr) -> Self {
+        DoWhileNode {
+            body: Box::new(body),
+            condition,
+            comments: None,
+        }
+    }
+}
+
+/// `try { ... } catch (e) { ... }`: a value thrown in the body, or a
+/// runtime error raised there, runs the handler, with the thrown value or
+/// the error's message bound to `error_name` when one is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TryNode {
+    pub body: BlockNode,
+    pub error_name: Option<String>,
+    pub handler: BlockNode,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl TryNode {
+    pub fn new(body: BlockNode, error_name: Option<String>, handler: BlockNode) -> Self {
+        TryNode {
+            body,
+            error_name,
+            handler,
+            comments: None,
+        }
+    }
+}
+
+/// `throw value;` stops execution until a `try` catches the value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrowNode {
+    pub value: Expr,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ThrowNode {
+    pub fn new(value: Expr) -> Self {
+        ThrowNode {
+            value,
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterNode {
+    pub name: String,
+    pub data_type: Option<DataType>,
+
+    pub span: NodeSpan,
+}
+
+impl ParameterNode {
+    pub fn new(name: String, data_type: Option<DataType>) -> Self {
+        ParameterNode {
+            name,
+            data_type,
+            span: NodeSpan::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDeclarationNode {
+    pub name: String,
+    // `T` in `fn first<T>(items: [T]) -> T`; empty unless generic
+    pub type_parameters: Vec<String>,
+    pub parameters: Vec<ParameterNode>,
+    pub return_type: Option<DataType>,
+    pub body: Vec<Stmt>,
+
+    // At the function's name
+    pub span: NodeSpan,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl FunctionDeclarationNode {
+    pub fn new(
+        name: String,
+        parameters: Vec<ParameterNode>,
+        return_type: Option<DataType>,
+        body: Vec<Stmt>,
+    ) -> Self {
+        FunctionDeclarationNode {
+            name,
+            type_parameters: Vec::new(),
+            parameters,
+            return_type,
+            body,
+            span: NodeSpan::default(),
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LambdaBody {
+    Expression(Box<Expr>), // `|x| x * 2`
+    Block(Vec<Stmt>),      // `fn(x) { return x * 2; }` or `|x| { ... }`
+}
+
+/// An anonymous function: `|a, b| a + b` or `fn(a, b) { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LambdaNode {
+    pub parameters: Vec<ParameterNode>,
+    pub return_type: Option<DataType>,
+    pub body: LambdaBody,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl LambdaNode {
+    pub fn new(
+        parameters: Vec<ParameterNode>,
+        return_type: Option<DataType>,
+        body: LambdaBody,
+    ) -> Self {
+        LambdaNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            parameters,
+            return_type,
+            body,
+        }
+    }
+}
+
+/// `spawn { ... }`: runs the block on a new thread and evaluates to a
+/// handle for `join`, which gives back the value of the block's trailing
+/// expression or `return`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnNode {
+    pub body: Vec<Stmt>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl SpawnNode {
+    pub fn new(body: Vec<Stmt>) -> Self {
+        SpawnNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            body,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForNode {
+    pub initializer: Option<Box<Stmt>>,
+    pub condition: Option<Expr>,
+    pub increment: Option<Expr>,
+    pub body: Box<Stmt>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ForNode {
+    pub fn new(
+        initializer: Option<Stmt>,
+        condition: Option<Expr>,
+        increment: Option<Expr>,
+        body: Stmt,
+    ) -> Self {
+        ForNode {
+            initializer: initializer.map(Box::new),
+            condition,
+            increment,
+            body: Box::new(body),
+            comments: None,
+        }
+    }
+
+    /// Lowers the loop for backends that only understand `while`:
+    /// `{ initializer; while (condition) { body; increment; } }`.
+    /// A missing condition loops forever.
+    pub fn into_while(self) -> BlockNode {
+        let condition = self
+            .condition
+            .unwrap_or(Expr::Boolean(BooleanNode::new(true)));
+
+        let mut loop_body = vec![*self.body];
+        loop_body.extend(self.increment.map(Stmt::Expression));
+        let while_node = WhileNode::new(condition, Stmt::Block(BlockNode::new(loop_body)));
+
+        let mut statements: Vec<Stmt> = self.initializer.map(|init| *init).into_iter().collect();
+        statements.push(Stmt::While(while_node));
+        BlockNode::new(statements)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForInNode {
+    pub variable: String,
+    pub iterable: Expr,
+    pub body: Box<Stmt>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ForInNode {
+    pub fn new(variable: String, iterable: Expr, body: Stmt) -> Self {
+        ForInNode {
+            variable,
+            iterable,
+            body: Box::new(body),
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldNode {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+impl FieldNode {
+    pub fn new(name: String, data_type: DataType) -> Self {
+        FieldNode { name, data_type }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDeclarationNode {
+    pub name: String,
+    pub fields: Vec<FieldNode>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl StructDeclarationNode {
+    pub fn new(name: String, fields: Vec<FieldNode>) -> Self {
+        StructDeclarationNode {
+            name,
+            fields,
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDeclarationNode {
+    pub name: String,
+    pub variants: Vec<String>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl EnumDeclarationNode {
+    pub fn new(name: String, variants: Vec<String>) -> Self {
+        EnumDeclarationNode {
+            name,
+            variants,
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Literal(Expr),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArmNode {
+    pub pattern: Pattern,
+    pub body: Box<Stmt>,
+}
+
+impl MatchArmNode {
+    pub fn new(pattern: Pattern, body: Stmt) -> Self {
+        MatchArmNode {
+            pattern,
+            body: Box::new(body),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchNode {
+    pub subject: Expr,
+    pub arms: Vec<MatchArmNode>,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl MatchNode {
+    pub fn new(subject: Expr, arms: Vec<MatchArmNode>) -> Self {
+        MatchNode {
+            subject,
+            arms,
+            comments: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExpressionArmNode {
+    pub pattern: Pattern,
+    pub value: Expr,
+}
+
+impl MatchExpressionArmNode {
+    pub fn new(pattern: Pattern, value: Expr) -> Self {
+        MatchExpressionArmNode { pattern, value }
+    }
+}
+
+/// `match x { 1 => "one", _ => "other" }` used as a value: the first arm
+/// whose pattern matches supplies the result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExpressionNode {
+    pub subject: Box<Expr>,
+    pub arms: Vec<MatchExpressionArmNode>,
+
+    pub span: NodeSpan,
+    pub resolved: ResolvedType,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl MatchExpressionNode {
+    pub fn new(subject: Expr, arms: Vec<MatchExpressionArmNode>) -> Self {
+        MatchExpressionNode {
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+            subject: Box::new(subject),
+            arms,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportSource {
+    Basket(Vec<String>), // `use math;` or `use math::linalg;`
+    File(String),        // `import "utils.put";`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportNode {
+    pub source: ImportSource,
+    pub comments: Option<Box<Comments>>,
+}
+
+impl ImportNode {
+    pub fn new(source: ImportSource) -> Self {
+        ImportNode {
+            source,
+            comments: None,
+        }
+    }
+}
+
+/// A source comment, kept so tools such as the formatter can write it
+/// back out. `text` is the raw comment, delimiters included, and
+/// `same_line` says whether it sat on the line of the token before it.
+/// Like a span, that is layout, so comments compare by their text alone.
+#[derive(Debug, Clone)]
+pub struct CommentNode {
+    pub text: String,
+    pub same_line: bool,
+}
+
+impl PartialEq for CommentNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl CommentNode {
+    pub fn new(text: String, same_line: bool) -> Self {
+        CommentNode { text, same_line }
+    }
+
+    /// Whether the comment runs to the end of its line, so nothing can
+    /// follow it there.
+    pub fn is_line_comment(&self) -> bool {
+        self.text.starts_with("//")
+    }
+}
+
+/// The comments the parser found around a node. An expression leads with
+/// the comments written just before it and trails with those between it
+/// and the next token; a statement trails with the comments on the line it
+/// ends on, and the last statement of a block or program with every
+/// comment up to its end.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Comments {
+    pub leading: Vec<CommentNode>,
+    pub trailing: Vec<CommentNode>,
+}
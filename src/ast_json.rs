@@ -0,0 +1,1062 @@
+//! JSON encoding of the syntax tree, so ASTs can be handed to other tools
+//! or loaded back without going through source text. Every node becomes an
+//! object tagged with a `"type"` key naming its `Expr`/`Stmt` variant.
+
+use crate::ast::*;
+use crate::json::JsonValue;
+
+impl ProgramNode {
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    pub fn from_json(text: &str) -> Result<ProgramNode, String> {
+        ProgramNode::from_json_value(&JsonValue::parse(text)?)
+    }
+
+    // The tree before it is written out as text, shared with the `.putc`
+    // encoding
+    pub(crate) fn to_json_value(&self) -> JsonValue {
+        node(
+            "Program",
+            vec![("statements", stmts_to_json(&self.statements))],
+        )
+    }
+
+    pub(crate) fn from_json_value(value: &JsonValue) -> Result<ProgramNode, String> {
+        if kind(value)? != "Program" {
+            return Err(format!("Expected a Program node, found '{}'", kind(value)?));
+        }
+        Ok(ProgramNode {
+            statements: stmts_from_json(field(value, "statements")?)?,
+        })
+    }
+}
+
+fn node(kind: &str, fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    let mut entries = vec![("type".to_string(), string(kind))];
+    entries.extend(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    JsonValue::Object(entries)
+}
+
+fn string(value: &str) -> JsonValue {
+    JsonValue::String(value.to_string())
+}
+
+fn optional<T>(value: Option<&T>, encode: impl Fn(&T) -> JsonValue) -> JsonValue {
+    value.map_or(JsonValue::Null, encode)
+}
+
+fn stmts_to_json(statements: &[Stmt]) -> JsonValue {
+    JsonValue::Array(statements.iter().map(stmt_to_json).collect())
+}
+
+fn exprs_to_json(expressions: &[Expr]) -> JsonValue {
+    JsonValue::Array(expressions.iter().map(expr_to_json).collect())
+}
+
+fn data_type_to_json(data_type: &DataType) -> JsonValue {
+    match data_type {
+        DataType::Struct(name) => JsonValue::Object(vec![("Struct".to_string(), string(name))]),
+        DataType::Enum(name) => JsonValue::Object(vec![("Enum".to_string(), string(name))]),
+        DataType::Optional(inner) => {
+            JsonValue::Object(vec![("Optional".to_string(), data_type_to_json(inner))])
+        }
+        DataType::Array(element) => {
+            JsonValue::Object(vec![("Array".to_string(), data_type_to_json(element))])
+        }
+        DataType::Parameter(name) => {
+            JsonValue::Object(vec![("Parameter".to_string(), string(name))])
+        }
+        simple => string(&format!("{:?}", simple)),
+    }
+}
+
+fn parameters_to_json(parameters: &[ParameterNode]) -> JsonValue {
+    JsonValue::Array(
+        parameters
+            .iter()
+            .map(|parameter| {
+                JsonValue::Object(vec![
+                    ("name".to_string(), string(&parameter.name)),
+                    (
+                        "data_type".to_string(),
+                        optional(parameter.data_type.as_ref(), data_type_to_json),
+                    ),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn pattern_to_json(pattern: &Pattern) -> JsonValue {
+    match pattern {
+        Pattern::Literal(literal) => expr_to_json(literal),
+        Pattern::Wildcard => node("Wildcard", vec![]),
+    }
+}
+
+fn expr_to_json(expr: &Expr) -> JsonValue {
+    with_comments(expr_node_to_json(expr), expr.comments())
+}
+
+fn expr_node_to_json(expr: &Expr) -> JsonValue {
+    match expr {
+        Expr::Number(number) => node(
+            "Number",
+            vec![
+                ("value", string(&number.value)),
+                ("data_type", data_type_to_json(&number.data_type)),
+            ],
+        ),
+        Expr::String(string_node) => node("String", vec![("value", string(&string_node.value))]),
+        Expr::Char(char_node) => node(
+            "Char",
+            vec![("value", string(&char_node.value.to_string()))],
+        ),
+        Expr::Boolean(boolean) => node("Boolean", vec![("value", JsonValue::Bool(boolean.value))]),
+        Expr::Null(_) => node("Null", vec![]),
+        Expr::Variable(variable) => node(
+            "Variable",
+            vec![
+                ("name", string(&variable.name)),
+                (
+                    "data_type",
+                    optional(variable.data_type.as_ref(), data_type_to_json),
+                ),
+            ],
+        ),
+        Expr::Assignment(assignment) => node(
+            "Assignment",
+            vec![
+                ("target", expr_to_json(&assignment.target)),
+                ("value", expr_to_json(&assignment.value)),
+            ],
+        ),
+        Expr::CompoundAssignment(assignment) => node(
+            "CompoundAssignment",
+            vec![
+                ("target", expr_to_json(&assignment.target)),
+                ("operator", string(&format!("{:?}", assignment.operator))),
+                ("value", expr_to_json(&assignment.value)),
+            ],
+        ),
+        Expr::Binary(binary) => node(
+            "Binary",
+            vec![
+                ("operator", string(&format!("{:?}", binary.operator))),
+                ("left", expr_to_json(&binary.left)),
+                ("right", expr_to_json(&binary.right)),
+            ],
+        ),
+        Expr::Unary(unary) => node(
+            "Unary",
+            vec![
+                ("operator", string(&format!("{:?}", unary.operator))),
+                ("operand", expr_to_json(&unary.operand)),
+            ],
+        ),
+        Expr::Cast(cast) => node(
+            "Cast",
+            vec![
+                ("expression", expr_to_json(&cast.expression)),
+                ("data_type", data_type_to_json(&cast.data_type)),
+            ],
+        ),
+        Expr::Parenthesis(paren) => node(
+            "Parenthesis",
+            vec![("expression", expr_to_json(&paren.expression))],
+        ),
+        Expr::Conditional(conditional) => node(
+            "Conditional",
+            vec![
+                ("condition", expr_to_json(&conditional.condition)),
+                (
+                    "then_expression",
+                    expr_to_json(&conditional.then_expression),
+                ),
+                (
+                    "else_expression",
+                    expr_to_json(&conditional.else_expression),
+                ),
+            ],
+        ),
+        Expr::Range(range) => node(
+            "Range",
+            vec![
+                ("start", expr_to_json(&range.start)),
+                ("end", expr_to_json(&range.end)),
+                ("inclusive", JsonValue::Bool(range.inclusive)),
+                ("step", optional(range.step.as_deref(), expr_to_json)),
+            ],
+        ),
+        Expr::Call(call) => node(
+            "Call",
+            vec![
+                ("callee", expr_to_json(&call.callee)),
+                ("arguments", exprs_to_json(&call.arguments)),
+            ],
+        ),
+        Expr::MethodCall(method_call) => node(
+            "MethodCall",
+            vec![
+                ("object", expr_to_json(&method_call.object)),
+                ("method", string(&method_call.method)),
+                ("arguments", exprs_to_json(&method_call.arguments)),
+                ("optional", JsonValue::Bool(method_call.optional)),
+            ],
+        ),
+        Expr::MemberAccess(member) => node(
+            "MemberAccess",
+            vec![
+                ("object", expr_to_json(&member.object)),
+                ("member", string(&member.member)),
+                ("optional", JsonValue::Bool(member.optional)),
+            ],
+        ),
+        Expr::Index(index) => node(
+            "Index",
+            vec![
+                ("object", expr_to_json(&index.object)),
+                ("index", expr_to_json(&index.index)),
+                ("optional", JsonValue::Bool(index.optional)),
+            ],
+        ),
+        Expr::EnumVariant(variant) => node(
+            "EnumVariant",
+            vec![
+                ("enum_name", string(&variant.enum_name)),
+                ("variant", string(&variant.variant)),
+            ],
+        ),
+        Expr::Cfg(cfg) => node("Cfg", vec![("key", string(&cfg.key))]),
+        Expr::TensorLiteral(tensor) => node(
+            "TensorLiteral",
+            vec![
+                ("elements", exprs_to_json(&tensor.elements)),
+                (
+                    "shape",
+                    JsonValue::Array(
+                        tensor
+                            .shape
+                            .iter()
+                            .map(|&dim| JsonValue::Number(dim as f64))
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Expr::Match(match_node) => node(
+            "Match",
+            vec![
+                ("subject", expr_to_json(&match_node.subject)),
+                (
+                    "arms",
+                    JsonValue::Array(
+                        match_node
+                            .arms
+                            .iter()
+                            .map(|arm| {
+                                JsonValue::Object(vec![
+                                    ("pattern".to_string(), pattern_to_json(&arm.pattern)),
+                                    ("value".to_string(), expr_to_json(&arm.value)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Expr::Spawn(spawn) => node("Spawn", vec![("body", stmts_to_json(&spawn.body))]),
+        Expr::Lambda(lambda) => {
+            let body = match &lambda.body {
+                LambdaBody::Expression(body) => {
+                    JsonValue::Object(vec![("expression".to_string(), expr_to_json(body))])
+                }
+                LambdaBody::Block(body) => {
+                    JsonValue::Object(vec![("block".to_string(), stmts_to_json(body))])
+                }
+            };
+            node(
+                "Lambda",
+                vec![
+                    ("parameters", parameters_to_json(&lambda.parameters)),
+                    (
+                        "return_type",
+                        optional(lambda.return_type.as_ref(), data_type_to_json),
+                    ),
+                    ("body", body),
+                ],
+            )
+        }
+    }
+}
+
+fn stmt_to_json(stmt: &Stmt) -> JsonValue {
+    match stmt {
+        // An expression statement's comments are its expression's
+        Stmt::Expression(_) => stmt_node_to_json(stmt),
+        _ => with_comments(stmt_node_to_json(stmt), stmt.comments()),
+    }
+}
+
+fn stmt_node_to_json(stmt: &Stmt) -> JsonValue {
+    match stmt {
+        Stmt::Expression(expr) => node("Expression", vec![("expression", expr_to_json(expr))]),
+        Stmt::VarDeclaration(declaration) => node(
+            "VarDeclaration",
+            vec![
+                ("name", string(&declaration.name)),
+                (
+                    "data_type",
+                    optional(declaration.data_type.as_ref(), data_type_to_json),
+                ),
+                (
+                    "initializer",
+                    optional(declaration.initializer.as_ref(), expr_to_json),
+                ),
+            ],
+        ),
+        Stmt::ConstDeclaration(declaration) => node(
+            "ConstDeclaration",
+            vec![
+                ("name", string(&declaration.name)),
+                (
+                    "data_type",
+                    optional(declaration.data_type.as_ref(), data_type_to_json),
+                ),
+                ("value", expr_to_json(&declaration.value)),
+            ],
+        ),
+        Stmt::FunctionDeclaration(function) => node(
+            "FunctionDeclaration",
+            vec![
+                ("name", string(&function.name)),
+                (
+                    "type_parameters",
+                    JsonValue::Array(
+                        function
+                            .type_parameters
+                            .iter()
+                            .map(|name| string(name))
+                            .collect(),
+                    ),
+                ),
+                ("parameters", parameters_to_json(&function.parameters)),
+                (
+                    "return_type",
+                    optional(function.return_type.as_ref(), data_type_to_json),
+                ),
+                ("body", stmts_to_json(&function.body)),
+            ],
+        ),
+        Stmt::StructDeclaration(struct_node) => node(
+            "StructDeclaration",
+            vec![
+                ("name", string(&struct_node.name)),
+                (
+                    "fields",
+                    JsonValue::Array(
+                        struct_node
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                JsonValue::Object(vec![
+                                    ("name".to_string(), string(&field.name)),
+                                    ("data_type".to_string(), data_type_to_json(&field.data_type)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Stmt::EnumDeclaration(enum_node) => node(
+            "EnumDeclaration",
+            vec![
+                ("name", string(&enum_node.name)),
+                (
+                    "variants",
+                    JsonValue::Array(enum_node.variants.iter().map(|v| string(v)).collect()),
+                ),
+            ],
+        ),
+        Stmt::Return(return_node) => node(
+            "Return",
+            vec![("value", optional(return_node.value.as_ref(), expr_to_json))],
+        ),
+        Stmt::Throw(throw) => node("Throw", vec![("value", expr_to_json(&throw.value))]),
+        Stmt::Breakpoint(_) => node("Breakpoint", vec![]),
+        Stmt::If(if_node) => node(
+            "If",
+            vec![
+                ("condition", expr_to_json(&if_node.condition)),
+                ("then_branch", stmt_to_json(&if_node.then_branch)),
+                (
+                    "else_branch",
+                    optional(if_node.else_branch.as_deref(), stmt_to_json),
+                ),
+            ],
+        ),
+        Stmt::While(while_node) => node(
+            "While",
+            vec![
+                ("condition", expr_to_json(&while_node.condition)),
+                ("body", stmt_to_json(&while_node.body)),
+            ],
+        ),
+        Stmt::DoWhile(do_while) => node(
+            "DoWhile",
+            vec![
+                ("body", stmt_to_json(&do_while.body)),
+                ("condition", expr_to_json(&do_while.condition)),
+            ],
+        ),
+        Stmt::For(for_node) => node(
+            "For",
+            vec![
+                (
+                    "initializer",
+                    optional(for_node.initializer.as_deref(), stmt_to_json),
+                ),
+                (
+                    "condition",
+                    optional(for_node.condition.as_ref(), expr_to_json),
+                ),
+                (
+                    "increment",
+                    optional(for_node.increment.as_ref(), expr_to_json),
+                ),
+                ("body", stmt_to_json(&for_node.body)),
+            ],
+        ),
+        Stmt::ForIn(for_in) => node(
+            "ForIn",
+            vec![
+                ("variable", string(&for_in.variable)),
+                ("iterable", expr_to_json(&for_in.iterable)),
+                ("body", stmt_to_json(&for_in.body)),
+            ],
+        ),
+        Stmt::Block(block) => node(
+            "Block",
+            vec![("statements", stmts_to_json(&block.statements))],
+        ),
+        Stmt::Match(match_node) => node(
+            "Match",
+            vec![
+                ("subject", expr_to_json(&match_node.subject)),
+                (
+                    "arms",
+                    JsonValue::Array(
+                        match_node
+                            .arms
+                            .iter()
+                            .map(|arm| {
+                                JsonValue::Object(vec![
+                                    ("pattern".to_string(), pattern_to_json(&arm.pattern)),
+                                    ("body".to_string(), stmt_to_json(&arm.body)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+            ],
+        ),
+        Stmt::Try(try_node) => node(
+            "Try",
+            vec![
+                ("body", stmts_to_json(&try_node.body.statements)),
+                (
+                    "error_name",
+                    optional(try_node.error_name.as_ref(), |name| string(name)),
+                ),
+                ("handler", stmts_to_json(&try_node.handler.statements)),
+            ],
+        ),
+        Stmt::Import(import) => {
+            let source = match &import.source {
+                ImportSource::Basket(path) => (
+                    "basket",
+                    JsonValue::Array(path.iter().map(|segment| string(segment)).collect()),
+                ),
+                ImportSource::File(path) => ("file", string(path)),
+            };
+            node("Import", vec![source])
+        }
+        Stmt::Comment(comment) => comment_to_json(comment),
+    }
+}
+
+fn comment_to_json(comment: &CommentNode) -> JsonValue {
+    node(
+        "Comment",
+        vec![
+            ("text", string(&comment.text)),
+            ("same_line", JsonValue::Bool(comment.same_line)),
+        ],
+    )
+}
+
+// Adds a `"comments"` key to a node that has any
+fn with_comments(mut json: JsonValue, comments: Option<&Comments>) -> JsonValue {
+    if let (JsonValue::Object(entries), Some(comments)) = (&mut json, comments) {
+        let list = |comments: &[CommentNode]| {
+            JsonValue::Array(comments.iter().map(comment_to_json).collect())
+        };
+        let comments = JsonValue::Object(vec![
+            ("leading".to_string(), list(&comments.leading)),
+            ("trailing".to_string(), list(&comments.trailing)),
+        ]);
+        entries.push(("comments".to_string(), comments));
+    }
+    json
+}
+
+fn kind(value: &JsonValue) -> Result<&str, String> {
+    value
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| "Expected an object with a string 'type' field".to_string())
+}
+
+fn field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a JsonValue, String> {
+    value.get(key).ok_or_else(|| {
+        let owner = kind(value).unwrap_or("object");
+        format!("Missing field '{}' in {}", key, owner)
+    })
+}
+
+fn wrong_type(value: &JsonValue, key: &str, expected: &str) -> String {
+    let owner = kind(value).unwrap_or("object");
+    format!("Field '{}' in {} must be {}", key, owner, expected)
+}
+
+fn string_field(value: &JsonValue, key: &str) -> Result<String, String> {
+    field(value, key)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| wrong_type(value, key, "a string"))
+}
+
+fn bool_field(value: &JsonValue, key: &str) -> Result<bool, String> {
+    field(value, key)?
+        .as_bool()
+        .ok_or_else(|| wrong_type(value, key, "a boolean"))
+}
+
+fn array_field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a [JsonValue], String> {
+    field(value, key)?
+        .as_array()
+        .ok_or_else(|| wrong_type(value, key, "an array"))
+}
+
+// A missing key and an explicit `null` both mean "absent"
+fn optional_field<'a>(value: &'a JsonValue, key: &str) -> Option<&'a JsonValue> {
+    value.get(key).filter(|v| **v != JsonValue::Null)
+}
+
+fn stmts_from_json(value: &JsonValue) -> Result<Vec<Stmt>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| "Expected an array of statements".to_string())?
+        .iter()
+        .map(stmt_from_json)
+        .collect()
+}
+
+fn exprs_from_json(value: &JsonValue, key: &str) -> Result<Vec<Expr>, String> {
+    array_field(value, key)?
+        .iter()
+        .map(expr_from_json)
+        .collect()
+}
+
+fn boxed_expr(value: &JsonValue, key: &str) -> Result<Box<Expr>, String> {
+    Ok(Box::new(expr_from_json(field(value, key)?)?))
+}
+
+fn boxed_stmt(value: &JsonValue, key: &str) -> Result<Box<Stmt>, String> {
+    Ok(Box::new(stmt_from_json(field(value, key)?)?))
+}
+
+fn optional_data_type(value: &JsonValue, key: &str) -> Result<Option<DataType>, String> {
+    optional_field(value, key)
+        .map(data_type_from_json)
+        .transpose()
+}
+
+fn data_type_from_json(value: &JsonValue) -> Result<DataType, String> {
+    if let Some(name) = value.as_str() {
+        return match name {
+            "Integer" => Ok(DataType::Integer),
+            "Float" => Ok(DataType::Float),
+            "String" => Ok(DataType::String),
+            "Boolean" => Ok(DataType::Boolean),
+            "Char" => Ok(DataType::Char),
+            "Tensor" => Ok(DataType::Tensor),
+            "Void" => Ok(DataType::Void),
+            other => Err(format!("Unknown data type '{}'", other)),
+        };
+    }
+    if let Some(name) = value.get("Struct").and_then(JsonValue::as_str) {
+        return Ok(DataType::Struct(name.to_string()));
+    }
+    if let Some(name) = value.get("Enum").and_then(JsonValue::as_str) {
+        return Ok(DataType::Enum(name.to_string()));
+    }
+    if let Some(inner) = value.get("Optional") {
+        return Ok(DataType::Optional(Box::new(data_type_from_json(inner)?)));
+    }
+    if let Some(element) = value.get("Array") {
+        return Ok(DataType::Array(Box::new(data_type_from_json(element)?)));
+    }
+    if let Some(name) = value.get("Parameter").and_then(JsonValue::as_str) {
+        return Ok(DataType::Parameter(name.to_string()));
+    }
+    Err(format!("Invalid data type {}", value))
+}
+
+fn binary_operator_from_json(name: &str) -> Result<BinaryOperator, String> {
+    Ok(match name {
+        "Add" => BinaryOperator::Add,
+        "Subtract" => BinaryOperator::Subtract,
+        "Multiply" => BinaryOperator::Multiply,
+        "Divide" => BinaryOperator::Divide,
+        "MatMul" => BinaryOperator::MatMul,
+        "BitAnd" => BinaryOperator::BitAnd,
+        "BitOr" => BinaryOperator::BitOr,
+        "BitXor" => BinaryOperator::BitXor,
+        "ShiftLeft" => BinaryOperator::ShiftLeft,
+        "ShiftRight" => BinaryOperator::ShiftRight,
+        "Equal" => BinaryOperator::Equal,
+        "NotEqual" => BinaryOperator::NotEqual,
+        "Less" => BinaryOperator::Less,
+        "LessEqual" => BinaryOperator::LessEqual,
+        "Greater" => BinaryOperator::Greater,
+        "GreaterEqual" => BinaryOperator::GreaterEqual,
+        "And" => BinaryOperator::And,
+        "Or" => BinaryOperator::Or,
+        other => return Err(format!("Unknown binary operator '{}'", other)),
+    })
+}
+
+fn unary_operator_from_json(name: &str) -> Result<UnaryOperator, String> {
+    match name {
+        "Negate" => Ok(UnaryOperator::Negate),
+        "Not" => Ok(UnaryOperator::Not),
+        other => Err(format!("Unknown unary operator '{}'", other)),
+    }
+}
+
+fn parameters_from_json(value: &JsonValue, key: &str) -> Result<Vec<ParameterNode>, String> {
+    array_field(value, key)?
+        .iter()
+        .map(|parameter| {
+            Ok(ParameterNode::new(
+                string_field(parameter, "name")?,
+                optional_data_type(parameter, "data_type")?,
+            ))
+        })
+        .collect()
+}
+
+fn pattern_from_json(value: &JsonValue) -> Result<Pattern, String> {
+    match kind(value)? {
+        "Wildcard" => Ok(Pattern::Wildcard),
+        _ => Ok(Pattern::Literal(expr_from_json(value)?)),
+    }
+}
+
+fn expr_from_json(value: &JsonValue) -> Result<Expr, String> {
+    let mut expr = expr_node_from_json(value)?;
+    if let Some(comments) = comments_from_json(value)? {
+        *expr.comments_mut() = comments;
+    }
+    Ok(expr)
+}
+
+fn stmt_from_json(value: &JsonValue) -> Result<Stmt, String> {
+    let mut stmt = stmt_node_from_json(value)?;
+    if let Some(comments) = comments_from_json(value)? {
+        if let Some(slot) = stmt.comments_mut() {
+            *slot = comments;
+        }
+    }
+    Ok(stmt)
+}
+
+fn comments_from_json(value: &JsonValue) -> Result<Option<Comments>, String> {
+    let Some(comments) = optional_field(value, "comments") else {
+        return Ok(None);
+    };
+    let list = |key| -> Result<Vec<CommentNode>, String> {
+        array_field(comments, key)?
+            .iter()
+            .map(comment_from_json)
+            .collect()
+    };
+    Ok(Some(Comments {
+        leading: list("leading")?,
+        trailing: list("trailing")?,
+    }))
+}
+
+fn comment_from_json(value: &JsonValue) -> Result<CommentNode, String> {
+    Ok(CommentNode::new(
+        string_field(value, "text")?,
+        bool_field(value, "same_line")?,
+    ))
+}
+
+fn expr_node_from_json(value: &JsonValue) -> Result<Expr, String> {
+    let expr = match kind(value)? {
+        "Number" => Expr::Number(NumberNode::new(
+            string_field(value, "value")?,
+            data_type_from_json(field(value, "data_type")?)?,
+        )),
+        "String" => Expr::String(StringNode::new(string_field(value, "value")?)),
+        "Char" => {
+            let text = string_field(value, "value")?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Expr::Char(CharNode::new(c)),
+                _ => {
+                    return Err(format!(
+                        "Char value must be one character, found '{}'",
+                        text
+                    ))
+                }
+            }
+        }
+        "Boolean" => Expr::Boolean(BooleanNode::new(bool_field(value, "value")?)),
+        "Null" => Expr::Null(NullNode::default()),
+        "Variable" => Expr::Variable(VariableNode {
+            data_type: optional_data_type(value, "data_type")?,
+            ..VariableNode::new(string_field(value, "name")?)
+        }),
+        "Assignment" => Expr::Assignment(AssignmentNode {
+            target: boxed_expr(value, "target")?,
+            value: boxed_expr(value, "value")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "CompoundAssignment" => Expr::CompoundAssignment(CompoundAssignmentNode {
+            target: boxed_expr(value, "target")?,
+            operator: binary_operator_from_json(&string_field(value, "operator")?)?,
+            value: boxed_expr(value, "value")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Binary" => Expr::Binary(BinaryOperationNode {
+            left: boxed_expr(value, "left")?,
+            operator: binary_operator_from_json(&string_field(value, "operator")?)?,
+            right: boxed_expr(value, "right")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Unary" => Expr::Unary(UnaryOperationNode {
+            operator: unary_operator_from_json(&string_field(value, "operator")?)?,
+            operand: boxed_expr(value, "operand")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Cast" => Expr::Cast(CastNode {
+            expression: boxed_expr(value, "expression")?,
+            data_type: data_type_from_json(field(value, "data_type")?)?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Parenthesis" => Expr::Parenthesis(ParenthesisNode {
+            expression: boxed_expr(value, "expression")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Conditional" => Expr::Conditional(ConditionalExpressionNode {
+            condition: boxed_expr(value, "condition")?,
+            then_expression: boxed_expr(value, "then_expression")?,
+            else_expression: boxed_expr(value, "else_expression")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Range" => Expr::Range(RangeNode {
+            start: boxed_expr(value, "start")?,
+            end: boxed_expr(value, "end")?,
+            inclusive: bool_field(value, "inclusive")?,
+            step: optional_field(value, "step")
+                .map(|step| expr_from_json(step).map(Box::new))
+                .transpose()?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Call" => Expr::Call(CallNode {
+            callee: boxed_expr(value, "callee")?,
+            arguments: exprs_from_json(value, "arguments")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "MethodCall" => Expr::MethodCall(MethodCallNode {
+            object: boxed_expr(value, "object")?,
+            method: string_field(value, "method")?,
+            arguments: exprs_from_json(value, "arguments")?,
+            optional: bool_field(value, "optional")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "MemberAccess" => Expr::MemberAccess(MemberAccessNode {
+            object: boxed_expr(value, "object")?,
+            member: string_field(value, "member")?,
+            optional: bool_field(value, "optional")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "Index" => Expr::Index(IndexNode {
+            object: boxed_expr(value, "object")?,
+            index: boxed_expr(value, "index")?,
+            optional: bool_field(value, "optional")?,
+            span: NodeSpan::default(),
+            resolved: ResolvedType::default(),
+            comments: None,
+        }),
+        "EnumVariant" => Expr::EnumVariant(EnumVariantNode::new(
+            string_field(value, "enum_name")?,
+            string_field(value, "variant")?,
+        )),
+        "Cfg" => Expr::Cfg(CfgNode::new(string_field(value, "key")?)),
+        "TensorLiteral" => {
+            let elements = exprs_from_json(value, "elements")?;
+            let shape = array_field(value, "shape")?
+                .iter()
+                .map(|dim| match dim.as_f64() {
+                    Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+                    _ => Err(wrong_type(value, "shape", "an array of sizes")),
+                })
+                .collect::<Result<Vec<usize>, String>>()?;
+            if shape.iter().product::<usize>() != elements.len() {
+                return Err(format!(
+                    "Tensor shape {:?} does not match {} elements",
+                    shape,
+                    elements.len()
+                ));
+            }
+            Expr::TensorLiteral(TensorLiteralNode::new(elements, shape))
+        }
+        "Match" => {
+            let arms = array_field(value, "arms")?
+                .iter()
+                .map(|arm| {
+                    Ok(MatchExpressionArmNode::new(
+                        pattern_from_json(field(arm, "pattern")?)?,
+                        expr_from_json(field(arm, "value")?)?,
+                    ))
+                })
+                .collect::<Result<Vec<MatchExpressionArmNode>, String>>()?;
+            Expr::Match(MatchExpressionNode::new(
+                expr_from_json(field(value, "subject")?)?,
+                arms,
+            ))
+        }
+        "Lambda" => {
+            let body = field(value, "body")?;
+            let body = if let Some(expression) = body.get("expression") {
+                LambdaBody::Expression(Box::new(expr_from_json(expression)?))
+            } else if let Some(block) = body.get("block") {
+                LambdaBody::Block(stmts_from_json(block)?)
+            } else {
+                return Err("Lambda body must have an 'expression' or 'block' field".to_string());
+            };
+            Expr::Lambda(LambdaNode::new(
+                parameters_from_json(value, "parameters")?,
+                optional_data_type(value, "return_type")?,
+                body,
+            ))
+        }
+        "Spawn" => Expr::Spawn(SpawnNode::new(stmts_from_json(field(value, "body")?)?)),
+        other => return Err(format!("Unknown expression type '{}'", other)),
+    };
+    Ok(expr)
+}
+
+fn stmt_node_from_json(value: &JsonValue) -> Result<Stmt, String> {
+    let stmt = match kind(value)? {
+        "Expression" => Stmt::Expression(expr_from_json(field(value, "expression")?)?),
+        "VarDeclaration" => Stmt::VarDeclaration(VarDeclarationNode::new(
+            string_field(value, "name")?,
+            optional_data_type(value, "data_type")?,
+            optional_field(value, "initializer")
+                .map(expr_from_json)
+                .transpose()?,
+        )),
+        "ConstDeclaration" => Stmt::ConstDeclaration(ConstDeclarationNode::new(
+            string_field(value, "name")?,
+            optional_data_type(value, "data_type")?,
+            expr_from_json(field(value, "value")?)?,
+        )),
+        "FunctionDeclaration" => {
+            let mut function = FunctionDeclarationNode::new(
+                string_field(value, "name")?,
+                parameters_from_json(value, "parameters")?,
+                optional_data_type(value, "return_type")?,
+                stmts_from_json(field(value, "body")?)?,
+            );
+            // Absent from trees written before functions could be generic
+            if let Some(names) = optional_field(value, "type_parameters") {
+                function.type_parameters = names
+                    .as_array()
+                    .ok_or_else(|| wrong_type(value, "type_parameters", "an array"))?
+                    .iter()
+                    .map(|name| {
+                        name.as_str().map(str::to_string).ok_or_else(|| {
+                            wrong_type(value, "type_parameters", "an array of strings")
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            Stmt::FunctionDeclaration(function)
+        }
+        "StructDeclaration" => {
+            let fields = array_field(value, "fields")?
+                .iter()
+                .map(|field_value| {
+                    Ok(FieldNode::new(
+                        string_field(field_value, "name")?,
+                        data_type_from_json(field(field_value, "data_type")?)?,
+                    ))
+                })
+                .collect::<Result<Vec<FieldNode>, String>>()?;
+            Stmt::StructDeclaration(StructDeclarationNode::new(
+                string_field(value, "name")?,
+                fields,
+            ))
+        }
+        "EnumDeclaration" => {
+            let variants = array_field(value, "variants")?
+                .iter()
+                .map(|variant| {
+                    variant
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| wrong_type(value, "variants", "an array of strings"))
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            Stmt::EnumDeclaration(EnumDeclarationNode::new(
+                string_field(value, "name")?,
+                variants,
+            ))
+        }
+        "Throw" => Stmt::Throw(ThrowNode::new(expr_from_json(field(value, "value")?)?)),
+        "Return" => Stmt::Return(ReturnNode::new(
+            optional_field(value, "value")
+                .map(expr_from_json)
+                .transpose()?,
+        )),
+        // `print` used to be a statement; older files load it as a call
+        "Print" => Stmt::Expression(Expr::Call(CallNode::new(
+            Expr::Variable(VariableNode::new("print".to_string())),
+            exprs_from_json(value, "arguments")?,
+        ))),
+        "Breakpoint" => Stmt::Breakpoint(BreakpointNode::default()),
+        "If" => Stmt::If(IfNode {
+            condition: expr_from_json(field(value, "condition")?)?,
+            then_branch: boxed_stmt(value, "then_branch")?,
+            else_branch: optional_field(value, "else_branch")
+                .map(|branch| stmt_from_json(branch).map(Box::new))
+                .transpose()?,
+            comments: None,
+        }),
+        "While" => Stmt::While(WhileNode {
+            condition: expr_from_json(field(value, "condition")?)?,
+            body: boxed_stmt(value, "body")?,
+            comments: None,
+        }),
+        "DoWhile" => Stmt::DoWhile(DoWhileNode {
+            body: boxed_stmt(value, "body")?,
+            condition: expr_from_json(field(value, "condition")?)?,
+            comments: None,
+        }),
+        "For" => Stmt::For(ForNode {
+            initializer: optional_field(value, "initializer")
+                .map(|init| stmt_from_json(init).map(Box::new))
+                .transpose()?,
+            condition: optional_field(value, "condition")
+                .map(expr_from_json)
+                .transpose()?,
+            increment: optional_field(value, "increment")
+                .map(expr_from_json)
+                .transpose()?,
+            body: boxed_stmt(value, "body")?,
+            comments: None,
+        }),
+        "ForIn" => Stmt::ForIn(ForInNode {
+            variable: string_field(value, "variable")?,
+            iterable: expr_from_json(field(value, "iterable")?)?,
+            body: boxed_stmt(value, "body")?,
+            comments: None,
+        }),
+        "Block" => Stmt::Block(BlockNode::new(stmts_from_json(field(
+            value,
+            "statements",
+        )?)?)),
+        "Match" => {
+            let arms = array_field(value, "arms")?
+                .iter()
+                .map(|arm| {
+                    Ok(MatchArmNode {
+                        pattern: pattern_from_json(field(arm, "pattern")?)?,
+                        body: boxed_stmt(arm, "body")?,
+                    })
+                })
+                .collect::<Result<Vec<MatchArmNode>, String>>()?;
+            Stmt::Match(MatchNode::new(
+                expr_from_json(field(value, "subject")?)?,
+                arms,
+            ))
+        }
+        "Try" => Stmt::Try(TryNode::new(
+            BlockNode::new(stmts_from_json(field(value, "body")?)?),
+            optional_field(value, "error_name")
+                .map(|name| {
+                    name.as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| wrong_type(value, "error_name", "a string"))
+                })
+                .transpose()?,
+            BlockNode::new(stmts_from_json(field(value, "handler")?)?),
+        )),
+        "Import" => {
+            let source = if let Some(path) = value.get("basket") {
+                let segments = path
+                    .as_array()
+                    .and_then(|segments| {
+                        segments
+                            .iter()
+                            .map(|segment| segment.as_str().map(str::to_string))
+                            .collect::<Option<Vec<String>>>()
+                    })
+                    .ok_or_else(|| wrong_type(value, "basket", "an array of strings"))?;
+                ImportSource::Basket(segments)
+            } else {
+                ImportSource::File(string_field(value, "file")?)
+            };
+            Stmt::Import(ImportNode::new(source))
+        }
+        "Comment" => Stmt::Comment(comment_from_json(value)?),
+        other => return Err(format!("Unknown statement type '{}'", other)),
+    };
+    Ok(stmt)
+}
@@ -0,0 +1,304 @@
+//! A compact, one-line Lisp-style dump of the syntax tree, e.g.
+//! `var x = 42 + 5;` becomes `(assign (var x) (+ 42 5))`. It is meant for
+//! tests and snapshots, where it reads far better than matching on nodes.
+//!
+//! Grouping parentheses and comments are left out: the nesting already
+//! shows how an expression groups, and comments are trivia.
+
+use crate::ast::*;
+use crate::formatter::{binary_symbol, format_type};
+
+impl ProgramNode {
+    pub fn to_sexpr(&self) -> String {
+        stmts(&self.statements)
+    }
+}
+
+impl Stmt {
+    pub fn to_sexpr(&self) -> String {
+        stmt(self)
+    }
+}
+
+impl Expr {
+    pub fn to_sexpr(&self) -> String {
+        expr(self)
+    }
+}
+
+// `(head part part ...)`, skipping empty parts
+fn list(head: &str, parts: Vec<String>) -> String {
+    let mut text = format!("({}", head);
+    for part in parts.into_iter().filter(|part| !part.is_empty()) {
+        text.push(' ');
+        text.push_str(&part);
+    }
+    text.push(')');
+    text
+}
+
+fn stmts(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .filter(|statement| !matches!(statement, Stmt::Comment(_)))
+        .map(stmt)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn block(statements: &[Stmt]) -> String {
+    list("block", vec![stmts(statements)])
+}
+
+fn exprs(expressions: &[Expr]) -> Vec<String> {
+    expressions.iter().map(expr).collect()
+}
+
+// `name` or `name:type`
+fn typed(name: &str, data_type: Option<&DataType>) -> String {
+    match data_type {
+        Some(data_type) => format!("{}:{}", name, format_type(data_type)),
+        None => name.to_string(),
+    }
+}
+
+fn parameters(parameters: &[ParameterNode]) -> String {
+    let names: Vec<String> = parameters
+        .iter()
+        .map(|parameter| typed(&parameter.name, parameter.data_type.as_ref()))
+        .collect();
+    format!("({})", names.join(" "))
+}
+
+fn return_type(data_type: &Option<DataType>) -> String {
+    match data_type {
+        Some(data_type) => format!("-> {}", format_type(data_type)),
+        None => String::new(),
+    }
+}
+
+fn pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Literal(literal) => expr(literal),
+        Pattern::Wildcard => "_".to_string(),
+    }
+}
+
+fn stmt(statement: &Stmt) -> String {
+    match statement {
+        Stmt::Expression(expression) => expr(expression),
+        Stmt::VarDeclaration(declaration) => {
+            let variable = list(
+                "var",
+                vec![typed(&declaration.name, declaration.data_type.as_ref())],
+            );
+            match &declaration.initializer {
+                Some(value) => list("assign", vec![variable, expr(value)]),
+                None => variable,
+            }
+        }
+        Stmt::ConstDeclaration(declaration) => list(
+            "assign",
+            vec![
+                list(
+                    "const",
+                    vec![typed(&declaration.name, declaration.data_type.as_ref())],
+                ),
+                expr(&declaration.value),
+            ],
+        ),
+        Stmt::FunctionDeclaration(function) => list(
+            "fn",
+            vec![
+                match function.type_parameters.as_slice() {
+                    [] => function.name.clone(),
+                    names => format!("{}<{}>", function.name, names.join(" ")),
+                },
+                parameters(&function.parameters),
+                return_type(&function.return_type),
+                block(&function.body),
+            ],
+        ),
+        Stmt::StructDeclaration(declaration) => {
+            let fields: Vec<String> = declaration
+                .fields
+                .iter()
+                .map(|field| typed(&field.name, Some(&field.data_type)))
+                .collect();
+            list(
+                "struct",
+                vec![declaration.name.clone(), format!("({})", fields.join(" "))],
+            )
+        }
+        Stmt::EnumDeclaration(declaration) => {
+            let mut parts = vec![declaration.name.clone()];
+            parts.extend(declaration.variants.iter().cloned());
+            list("enum", parts)
+        }
+        Stmt::Return(node) => list("return", node.value.iter().map(expr).collect()),
+        Stmt::Throw(node) => list("throw", vec![expr(&node.value)]),
+        Stmt::Breakpoint(_) => list("breakpoint", Vec::new()),
+        Stmt::If(node) => list(
+            "if",
+            vec![
+                expr(&node.condition),
+                stmt(&node.then_branch),
+                node.else_branch.as_deref().map(stmt).unwrap_or_default(),
+            ],
+        ),
+        Stmt::While(node) => list("while", vec![expr(&node.condition), stmt(&node.body)]),
+        Stmt::DoWhile(node) => list("do", vec![stmt(&node.body), expr(&node.condition)]),
+        // Absent clauses print as `()` so the remaining ones keep their place
+        Stmt::For(node) => list(
+            "for",
+            vec![
+                node.initializer
+                    .as_deref()
+                    .map_or_else(|| "()".to_string(), stmt),
+                node.condition
+                    .as_ref()
+                    .map_or_else(|| "()".to_string(), expr),
+                node.increment
+                    .as_ref()
+                    .map_or_else(|| "()".to_string(), expr),
+                stmt(&node.body),
+            ],
+        ),
+        Stmt::ForIn(node) => list(
+            "for-in",
+            vec![
+                node.variable.clone(),
+                expr(&node.iterable),
+                stmt(&node.body),
+            ],
+        ),
+        Stmt::Block(node) => block(&node.statements),
+        Stmt::Match(node) => {
+            let mut parts = vec![expr(&node.subject)];
+            parts.extend(
+                node.arms
+                    .iter()
+                    .map(|arm| format!("({} {})", pattern(&arm.pattern), stmt(&arm.body))),
+            );
+            list("match", parts)
+        }
+        Stmt::Try(node) => list(
+            "try",
+            vec![
+                block(&node.body.statements),
+                list(
+                    "catch",
+                    vec![
+                        node.error_name.clone().unwrap_or_default(),
+                        block(&node.handler.statements),
+                    ],
+                ),
+            ],
+        ),
+        Stmt::Import(node) => match &node.source {
+            ImportSource::Basket(path) => list("use", vec![path.join("::")]),
+            ImportSource::File(path) => list("import", vec![format!("{:?}", path)]),
+        },
+        Stmt::Comment(_) => String::new(),
+    }
+}
+
+fn expr(expression: &Expr) -> String {
+    match expression {
+        Expr::Number(number) => number.value.clone(),
+        Expr::String(string) => format!("{:?}", string.value),
+        Expr::Char(char_node) => format!("{:?}", char_node.value),
+        Expr::Boolean(boolean) => boolean.value.to_string(),
+        Expr::Null(_) => "null".to_string(),
+        Expr::Variable(variable) => variable.name.clone(),
+        Expr::Assignment(assignment) => {
+            list("=", vec![expr(&assignment.target), expr(&assignment.value)])
+        }
+        Expr::CompoundAssignment(assignment) => list(
+            &format!("{}=", binary_symbol(assignment.operator)),
+            vec![expr(&assignment.target), expr(&assignment.value)],
+        ),
+        Expr::Binary(binary) => list(
+            binary_symbol(binary.operator),
+            vec![expr(&binary.left), expr(&binary.right)],
+        ),
+        Expr::Unary(unary) => {
+            let symbol = match unary.operator {
+                UnaryOperator::Negate => "-",
+                UnaryOperator::Not => "!",
+            };
+            list(symbol, vec![expr(&unary.operand)])
+        }
+        Expr::Cast(cast) => list(
+            "as",
+            vec![expr(&cast.expression), format_type(&cast.data_type)],
+        ),
+        Expr::Parenthesis(paren) => expr(&paren.expression),
+        Expr::Conditional(conditional) => list(
+            "?",
+            vec![
+                expr(&conditional.condition),
+                expr(&conditional.then_expression),
+                expr(&conditional.else_expression),
+            ],
+        ),
+        Expr::Range(range) => list(
+            if range.inclusive { "..=" } else { ".." },
+            vec![
+                expr(&range.start),
+                expr(&range.end),
+                range.step.as_deref().map(expr).unwrap_or_default(),
+            ],
+        ),
+        Expr::Call(call) => {
+            let mut parts = vec![expr(&call.callee)];
+            parts.extend(exprs(&call.arguments));
+            list("call", parts)
+        }
+        Expr::MethodCall(call) => {
+            let mut parts = vec![call.method.clone(), expr(&call.object)];
+            parts.extend(exprs(&call.arguments));
+            list(if call.optional { "method?" } else { "method" }, parts)
+        }
+        Expr::MemberAccess(member) => list(
+            if member.optional { "?." } else { "." },
+            vec![expr(&member.object), member.member.clone()],
+        ),
+        Expr::Index(index) => list(
+            if index.optional { "index?" } else { "index" },
+            vec![expr(&index.object), expr(&index.index)],
+        ),
+        Expr::EnumVariant(variant) => format!("{}::{}", variant.enum_name, variant.variant),
+        Expr::Cfg(cfg) => list("cfg", vec![format!("{:?}", cfg.key)]),
+        Expr::TensorLiteral(tensor) => {
+            let shape: Vec<String> = tensor.shape.iter().map(usize::to_string).collect();
+            let mut parts = vec![format!("[{}]", shape.join(" "))];
+            parts.extend(exprs(&tensor.elements));
+            list("tensor", parts)
+        }
+        Expr::Spawn(spawn) => list("spawn", vec![block(&spawn.body)]),
+        Expr::Lambda(lambda) => {
+            let body = match &lambda.body {
+                LambdaBody::Expression(body) => expr(body),
+                LambdaBody::Block(statements) => block(statements),
+            };
+            list(
+                "lambda",
+                vec![
+                    parameters(&lambda.parameters),
+                    return_type(&lambda.return_type),
+                    body,
+                ],
+            )
+        }
+        Expr::Match(node) => {
+            let mut parts = vec![expr(&node.subject)];
+            parts.extend(
+                node.arms
+                    .iter()
+                    .map(|arm| format!("({} {})", pattern(&arm.pattern), expr(&arm.value))),
+            );
+            list("match", parts)
+        }
+    }
+}
@@ -0,0 +1,426 @@
+//! Functions implemented in Rust and callable from PUT code. They live in
+//! the interpreter's global scope under their names, alongside functions
+//! declared in PUT, so a script can shadow or pass them around like any
+//! other value. Embedders add their own with `Interpreter::register_builtin`,
+//! or `Engine::register_fn` to have arguments converted for them.
+//!
+//! Baskets shipped with the interpreter, such as `math`, are native too,
+//! but stay out of scope until a script asks for them with `use`.
+
+use crate::interpreter::{Interpreter, RuntimeError, RuntimeResult, Value};
+use crate::random::Rng;
+use crate::tensor::Tensor;
+use crate::threads::Channel;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often a thread waiting in `recv` checks whether its run was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> RuntimeResult<Value> + Send + Sync;
+
+pub struct NativeFunction {
+    pub name: String,
+    // `None` accepts any number of arguments
+    pub arity: Option<usize>,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: impl Into<String>,
+        arity: Option<usize>,
+        function: impl Fn(&mut Interpreter, Vec<Value>) -> RuntimeResult<Value> + Send + Sync + 'static,
+    ) -> Self {
+        NativeFunction {
+            name: name.into(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> RuntimeResult<Value> {
+        (self.function)(interpreter, arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// Functions are equal only to themselves
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// The builtins every interpreter starts with.
+pub fn standard_builtins() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("print", None, print),
+        NativeFunction::new("len", Some(1), len),
+        NativeFunction::new("abs", Some(1), abs),
+        NativeFunction::new("type_of", Some(1), type_of),
+        NativeFunction::new("split", Some(2), split),
+        NativeFunction::new("join", None, join),
+        NativeFunction::new("zeros", None, zeros),
+        NativeFunction::new("to_upper", Some(1), |_, arguments| {
+            Ok(Value::String(
+                string_argument("to_upper", &arguments[0])?
+                    .to_uppercase()
+                    .into(),
+            ))
+        }),
+        NativeFunction::new("trim", Some(1), |_, arguments| {
+            let text = string_argument("trim", &arguments[0])?;
+            Ok(Value::String(text.trim().into()))
+        }),
+        NativeFunction::new("contains", Some(2), contains),
+        NativeFunction::new("parse_number", Some(1), parse_number),
+        NativeFunction::new("read_file", Some(1), read_file),
+        NativeFunction::new("write_file", Some(2), write_file),
+        NativeFunction::new("read_lines", Some(1), read_lines),
+        NativeFunction::new("random", Some(0), |interpreter, _| {
+            Ok(Value::Float(interpreter.rng().next_f64()))
+        }),
+        NativeFunction::new("rand_int", Some(2), rand_int),
+        NativeFunction::new("seed", Some(1), seed),
+        NativeFunction::new("channel", Some(0), |_, _| {
+            Ok(Value::Channel(Arc::new(Channel::new())))
+        }),
+        NativeFunction::new("send", Some(2), |_, mut arguments| {
+            let value = arguments.pop().unwrap_or(Value::Null);
+            channel_argument("send", &arguments[0])?.send(value);
+            Ok(Value::Null)
+        }),
+        NativeFunction::new("recv", Some(1), recv),
+        NativeFunction::new("try_recv", Some(1), |_, arguments| {
+            let channel = channel_argument("try_recv", &arguments[0])?;
+            Ok(channel.try_recv().unwrap_or(Value::Null))
+        }),
+    ]
+}
+
+/// The names a standard basket brings into scope.
+pub struct Basket {
+    pub functions: Vec<NativeFunction>,
+    pub constants: Vec<(&'static str, Value)>,
+}
+
+/// The basket at `path` (`["math"]` for `use math;`), if the interpreter
+/// ships one there.
+pub fn standard_basket(path: &[String]) -> Option<Basket> {
+    match path.join("::").as_str() {
+        "math" => Some(math_basket()),
+        _ => None,
+    }
+}
+
+fn math_basket() -> Basket {
+    Basket {
+        functions: vec![
+            NativeFunction::new("sin", Some(1), |_, arguments| {
+                Ok(Value::Float(number_argument("sin", &arguments[0])?.sin()))
+            }),
+            NativeFunction::new("cos", Some(1), |_, arguments| {
+                Ok(Value::Float(number_argument("cos", &arguments[0])?.cos()))
+            }),
+            NativeFunction::new("sqrt", Some(1), |_, arguments| {
+                Ok(Value::Float(number_argument("sqrt", &arguments[0])?.sqrt()))
+            }),
+            NativeFunction::new("floor", Some(1), |_, arguments| {
+                Ok(Value::Float(
+                    number_argument("floor", &arguments[0])?.floor(),
+                ))
+            }),
+            NativeFunction::new("ceil", Some(1), |_, arguments| {
+                Ok(Value::Float(number_argument("ceil", &arguments[0])?.ceil()))
+            }),
+            NativeFunction::new("pow", Some(2), pow),
+        ],
+        constants: vec![
+            ("pi", Value::Float(std::f64::consts::PI)),
+            ("e", Value::Float(std::f64::consts::E)),
+        ],
+    }
+}
+
+fn wrong_argument(name: &str, expected: &str, found: &Value) -> RuntimeError {
+    RuntimeError::new(format!(
+        "{}() expects {}, found {}",
+        name,
+        expected,
+        found.type_name()
+    ))
+}
+
+// Writes its arguments separated by spaces, then a newline
+fn print(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let parts: Vec<String> = arguments.iter().map(Value::to_string).collect();
+    interpreter.write_output(&format!("{}\n", parts.join(" ")))?;
+    Ok(Value::Null)
+}
+
+fn len(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    match &arguments[0] {
+        Value::String(value) => Ok(Value::Integer(value.chars().count() as i64)),
+        Value::Array(elements) => Ok(Value::Integer(elements.len() as i64)),
+        Value::Tensor(tensor) => Ok(Value::Integer(
+            tensor.shape().first().copied().unwrap_or(0) as i64
+        )),
+        other => Err(wrong_argument("len", "a string, array or tensor", other)),
+    }
+}
+
+fn abs(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    match &arguments[0] {
+        Value::Integer(value) => value
+            .checked_abs()
+            .map(Value::Integer)
+            .ok_or_else(|| RuntimeError::new("Integer overflow")),
+        Value::Float(value) => Ok(Value::Float(value.abs())),
+        other => Err(wrong_argument("abs", "a number", other)),
+    }
+}
+
+// Integers raised to a non-negative integer power stay integers
+fn pow(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    if let (Value::Integer(base), Value::Integer(exponent)) = (&arguments[0], &arguments[1]) {
+        if let Ok(exponent) = u32::try_from(*exponent) {
+            return base
+                .checked_pow(exponent)
+                .map(Value::Integer)
+                .ok_or_else(|| RuntimeError::new("Integer overflow"));
+        }
+    }
+    let base = number_argument("pow", &arguments[0])?;
+    let exponent = number_argument("pow", &arguments[1])?;
+    Ok(Value::Float(base.powf(exponent)))
+}
+
+fn number_argument(name: &str, value: &Value) -> RuntimeResult<f64> {
+    match value {
+        Value::Integer(value) => Ok(*value as f64),
+        Value::Float(value) => Ok(*value),
+        other => Err(wrong_argument(name, "a number", other)),
+    }
+}
+
+fn string_argument<'a>(name: &str, value: &'a Value) -> RuntimeResult<&'a str> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(wrong_argument(name, "a string", other)),
+    }
+}
+
+fn split(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let text = string_argument("split", &arguments[0])?;
+    let separator = string_argument("split", &arguments[1])?;
+    if separator.is_empty() {
+        return Err(RuntimeError::new("split() separator must not be empty"));
+    }
+    let parts = text
+        .split(separator)
+        .map(|part| Value::String(part.into()))
+        .collect();
+    Ok(Value::Array(Arc::new(parts)))
+}
+
+// `join(thread)` waits for a spawned thread and gives its result, while
+// `join(array, separator)` joins strings. Elements that aren't strings are
+// joined as they would print
+fn join(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    match arguments.as_slice() {
+        [Value::Thread(thread)] => return thread.join(),
+        [other] => return Err(wrong_argument("join", "a thread", other)),
+        [_, _] => {}
+        _ => {
+            return Err(RuntimeError::new(format!(
+                "'join' expects 1 or 2 arguments but got {}",
+                arguments.len()
+            )))
+        }
+    }
+    let elements = match &arguments[0] {
+        Value::Array(elements) => elements,
+        other => return Err(wrong_argument("join", "an array", other)),
+    };
+    let separator = string_argument("join", &arguments[1])?;
+    let parts: Vec<String> = elements.iter().map(Value::to_string).collect();
+    Ok(Value::String(parts.join(separator).into()))
+}
+
+// A tensor of zeros with the given dimensions, as in `zeros(2, 3)`
+fn zeros(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    if arguments.is_empty() {
+        return Err(RuntimeError::new("'zeros' expects at least 1 argument"));
+    }
+    let shape = arguments
+        .iter()
+        .map(|argument| match argument {
+            Value::Integer(size) => usize::try_from(*size).map_err(|_| {
+                RuntimeError::new(format!(
+                    "Tensor dimensions can't be negative, found {}",
+                    size
+                ))
+            }),
+            other => Err(wrong_argument("zeros", "int dimensions", other)),
+        })
+        .collect::<RuntimeResult<Vec<usize>>>()?;
+    let bytes = shape
+        .iter()
+        .try_fold(8usize, |bytes, &size| bytes.checked_mul(size))
+        .ok_or_else(|| RuntimeError::new(format!("A tensor of shape {:?} is too large", shape)))?;
+    interpreter.check_value_size(bytes)?;
+    Ok(Value::Tensor(Arc::new(Tensor::zeros(shape))))
+}
+
+// Whether a string holds a substring, or an array an element
+fn contains(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let found = match (&arguments[0], &arguments[1]) {
+        (Value::String(text), Value::String(part)) => text.contains(&**part),
+        (Value::String(text), Value::Char(c)) => text.contains(*c),
+        (Value::String(_), other) => {
+            return Err(wrong_argument(
+                "contains",
+                "a string or char to find",
+                other,
+            ))
+        }
+        (Value::Array(elements), value) => elements.contains(value),
+        (other, _) => return Err(wrong_argument("contains", "a string or array", other)),
+    };
+    Ok(Value::Boolean(found))
+}
+
+// Whole numbers become ints and anything else floats, as in source code
+fn parse_number(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let text = string_argument("parse_number", &arguments[0])?.trim();
+    if let Ok(value) = text.parse::<i64>() {
+        return Ok(Value::Integer(value));
+    }
+    match text.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(Value::Float(value)),
+        _ => Err(RuntimeError::new(format!(
+            "parse_number() cannot read '{}' as a number",
+            text
+        ))),
+    }
+}
+
+fn type_of(_: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    Ok(Value::String(arguments[0].type_name().into()))
+}
+
+// The path argument of a file builtin, once file access is known to be on
+fn file_path(interpreter: &Interpreter, name: &str, path: &Value) -> RuntimeResult<String> {
+    if !interpreter.file_access_allowed() {
+        return Err(RuntimeError::new(format!(
+            "{}() needs file access, which this interpreter does not allow",
+            name
+        )));
+    }
+    match path {
+        Value::String(path) => Ok(path.to_string()),
+        other => Err(wrong_argument(name, "a string path", other)),
+    }
+}
+
+fn read_to_string(path: &str) -> RuntimeResult<String> {
+    fs::read_to_string(path)
+        .map_err(|error| RuntimeError::new(format!("Cannot read '{}': {}", path, error)))
+}
+
+fn read_file(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let path = file_path(interpreter, "read_file", &arguments[0])?;
+    Ok(Value::String(read_to_string(&path)?.into()))
+}
+
+// Replaces the file's contents with `text`, creating it if need be
+fn write_file(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let path = file_path(interpreter, "write_file", &arguments[0])?;
+    let text = match &arguments[1] {
+        Value::String(text) => text,
+        other => return Err(wrong_argument("write_file", "string contents", other)),
+    };
+    fs::write(&path, &**text)
+        .map_err(|error| RuntimeError::new(format!("Cannot write '{}': {}", path, error)))?;
+    Ok(Value::Null)
+}
+
+// The file's lines, without their line endings
+fn read_lines(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let path = file_path(interpreter, "read_lines", &arguments[0])?;
+    let lines = read_to_string(&path)?
+        .lines()
+        .map(|line| Value::String(line.into()))
+        .collect();
+    Ok(Value::Array(Arc::new(lines)))
+}
+
+fn channel_argument<'a>(name: &str, value: &'a Value) -> RuntimeResult<&'a Channel> {
+    match value {
+        Value::Channel(channel) => Ok(channel),
+        other => Err(wrong_argument(name, "a channel", other)),
+    }
+}
+
+// Blocks until a value arrives, the run's time is up or it is cancelled.
+// Cancelling doesn't wake the waiting thread, so it wakes itself now and
+// then to check
+fn recv(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let channel = channel_argument("recv", &arguments[0])?;
+    loop {
+        let wake = Instant::now() + CANCEL_POLL_INTERVAL;
+        let until = interpreter
+            .deadline()
+            .map_or(wake, |deadline| deadline.min(wake));
+        if let Some(value) = channel.recv(Some(until)) {
+            return Ok(value);
+        }
+        interpreter.check_cancelled()?;
+        if interpreter
+            .deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(interpreter.time_limit_exceeded());
+        }
+    }
+}
+
+// An integer between `lo` and `hi`, both included
+fn rand_int(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    let (lo, hi) = match (&arguments[0], &arguments[1]) {
+        (Value::Integer(lo), Value::Integer(hi)) => (*lo, *hi),
+        (Value::Integer(_), other) | (other, _) => {
+            return Err(wrong_argument("rand_int", "int bounds", other))
+        }
+    };
+    if lo > hi {
+        return Err(RuntimeError::new(format!(
+            "rand_int() bounds are empty: {} is greater than {}",
+            lo, hi
+        )));
+    }
+    Ok(Value::Integer(interpreter.rng().range(lo, hi)))
+}
+
+// Restarts the generator, so the numbers that follow repeat on every run
+fn seed(interpreter: &mut Interpreter, arguments: Vec<Value>) -> RuntimeResult<Value> {
+    match &arguments[0] {
+        Value::Integer(n) => {
+            *interpreter.rng() = Rng::new(*n as u64);
+            Ok(Value::Null)
+        }
+        other => Err(wrong_argument("seed", "an int", other)),
+    }
+}
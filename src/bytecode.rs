@@ -0,0 +1,190 @@
+//! `.putc` files: a compiled program saved to disk so it can be run again
+//! without lexing, parsing or lowering it. The payload is the same tree
+//! model as the JSON encoding, written in a compact binary form, behind a
+//! header that identifies the file and guards against corruption:
+//!
+//! ```text
+//! "PUTC"  format version (u16)  CRC-32 of payload (u32)  payload length (u64)
+//! ```
+//!
+//! All integers are little-endian. Source positions are not kept, so
+//! runtime errors from a loaded program carry no line numbers.
+
+use crate::ast::ProgramNode;
+use crate::json::JsonValue;
+
+pub const MAGIC: &[u8; 4] = b"PUTC";
+
+/// Bumped whenever the payload layout or the tree it encodes changes, so
+/// a stale file is rejected rather than misread.
+pub const FORMAT_VERSION: u16 = 1;
+
+const HEADER_LENGTH: usize = 4 + 2 + 4 + 8;
+
+// Payload value tags
+const NULL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const NUMBER: u8 = 3;
+const STRING: u8 = 4;
+const ARRAY: u8 = 5;
+const OBJECT: u8 = 6;
+
+impl ProgramNode {
+    pub fn to_putc(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        write_value(&mut payload, &self.to_json_value());
+
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    pub fn from_putc(bytes: &[u8]) -> Result<ProgramNode, String> {
+        if bytes.len() < HEADER_LENGTH || &bytes[..4] != MAGIC {
+            return Err("Not a .putc file".to_string());
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "Unsupported .putc format version {} (expected {})",
+                version, FORMAT_VERSION
+            ));
+        }
+        let checksum = u32::from_le_bytes(bytes[6..10].try_into().unwrap());
+        let length = u64::from_le_bytes(bytes[10..18].try_into().unwrap());
+        let payload = &bytes[HEADER_LENGTH..];
+        if payload.len() as u64 != length {
+            return Err(format!(
+                "Corrupt .putc file: expected {} bytes of program, found {}",
+                length,
+                payload.len()
+            ));
+        }
+        if crc32(payload) != checksum {
+            return Err("Corrupt .putc file: checksum mismatch".to_string());
+        }
+
+        let mut reader = Reader {
+            bytes: payload,
+            position: 0,
+        };
+        let value = reader.read_value()?;
+        if reader.position != payload.len() {
+            return Err("Corrupt .putc file: trailing bytes after program".to_string());
+        }
+        ProgramNode::from_json_value(&value)
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &JsonValue) {
+    match value {
+        JsonValue::Null => out.push(NULL),
+        JsonValue::Bool(false) => out.push(FALSE),
+        JsonValue::Bool(true) => out.push(TRUE),
+        JsonValue::Number(n) => {
+            out.push(NUMBER);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        JsonValue::String(text) => {
+            out.push(STRING);
+            write_str(out, text);
+        }
+        JsonValue::Array(items) => {
+            out.push(ARRAY);
+            out.extend_from_slice(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        JsonValue::Object(entries) => {
+            out.push(OBJECT);
+            out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+            for (key, value) in entries {
+                write_str(out, key);
+                write_value(out, value);
+            }
+        }
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, text: &str) {
+    out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Corrupt .putc file: unexpected end of program")?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn read_u32(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    fn read_str(&mut self) -> Result<String, String> {
+        let length = self.read_u32()?;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| "Corrupt .putc file: invalid UTF-8 in string".to_string())
+    }
+
+    fn read_value(&mut self) -> Result<JsonValue, String> {
+        let tag = self.take(1)?[0];
+        Ok(match tag {
+            NULL => JsonValue::Null,
+            FALSE => JsonValue::Bool(false),
+            TRUE => JsonValue::Bool(true),
+            NUMBER => JsonValue::Number(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            STRING => JsonValue::String(self.read_str()?),
+            ARRAY => {
+                let length = self.read_u32()?;
+                let mut items = Vec::new();
+                for _ in 0..length {
+                    items.push(self.read_value()?);
+                }
+                JsonValue::Array(items)
+            }
+            OBJECT => {
+                let length = self.read_u32()?;
+                let mut entries = Vec::new();
+                for _ in 0..length {
+                    let key = self.read_str()?;
+                    entries.push((key, self.read_value()?));
+                }
+                JsonValue::Object(entries)
+            }
+            other => return Err(format!("Corrupt .putc file: unknown value tag {}", other)),
+        })
+    }
+}
+
+// CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
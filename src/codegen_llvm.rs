@@ -0,0 +1,1189 @@
+//! Lowers a PUT program to textual LLVM IR, and from there to a native
+//! object file with `llc`, for ahead-of-time compilation. Only built with
+//! the `llvm` feature.
+//!
+//! This deliberately doesn't use inkwell. Binding LLVM as a library ties
+//! the build to the development headers and libraries of one exact LLVM
+//! version, which few machines have installed; text IR builds anywhere and
+//! only `build` needs an `llc` on the `PATH`.
+//!
+//! The IR uses opaque pointers and calls `printf` and friends from the C
+//! library, so an object file links into an executable with
+//! `cc script.o -o script -lm`. Variables live in stack slots and are
+//! left to LLVM's mem2reg to promote.
+//!
+//! The supported subset is numeric code: ints, floats and booleans,
+//! functions with annotated parameters, the control flow statements and
+//! the math builtins. String literals may only be stored and printed.
+//! As in the Rust backend, functions see only their own parameters and
+//! locals. Runtime errors the interpreter reports (division by zero,
+//! integer overflow, out of range shifts) print a message and exit with
+//! status 1.
+
+use crate::ast::*;
+use crate::formatter::{binary_symbol, format_type};
+use crate::type_checker::{self, TypeChecker};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The LLVM IR module for `program`, or why it can't be lowered.
+pub fn emit_llvm(program: &ProgramNode) -> Result<String, String> {
+    TypeChecker::check_program(program).map_err(|errors| type_checker::report(&errors))?;
+
+    let mut emitter = LlvmEmitter::default();
+    // Signatures first, so calls may come before declarations
+    for stmt in &program.statements {
+        if let Stmt::FunctionDeclaration(function) = stmt {
+            emitter.signature(function)?;
+        }
+    }
+
+    let mut definitions = Vec::new();
+    let mut main_body = Vec::new();
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::FunctionDeclaration(function) => definitions.push(emitter.function(function)?),
+            stmt => main_body.push(stmt.clone()),
+        }
+    }
+    emitter.begin_function(None);
+    emitter.statements(&main_body)?;
+    emitter.jump_end("ret i32 0");
+    definitions.push(format!(
+        "define i32 @main() {{\n{}}}\n",
+        emitter.finish_function()
+    ));
+
+    let mut output = String::from("; Generated from PUT source by put-lang.\n\n");
+    for global in &emitter.globals {
+        output.push_str(global);
+        output.push('\n');
+    }
+    if !emitter.globals.is_empty() {
+        output.push('\n');
+    }
+    for declaration in &emitter.declarations {
+        output.push_str(declaration);
+        output.push('\n');
+    }
+    output.push('\n');
+    for helper in &emitter.helpers {
+        output.push_str(helper_definition(helper));
+        output.push('\n');
+    }
+    output.push_str(&definitions.join("\n"));
+    Ok(output)
+}
+
+/// Lowers `program` and has `llc` write it to `output` as an object file.
+pub fn compile_object(program: &ProgramNode, output: &Path) -> Result<(), String> {
+    let ir = emit_llvm(program)?;
+    let mut command = Command::new("llc");
+    command
+        .args(["-filetype=obj", "-relocation-model=pic", "-o"])
+        .arg(output)
+        .arg("-");
+    // Opaque pointers are the default from LLVM 15
+    if llc_major_version()? < 15 {
+        command.arg("-opaque-pointers");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Cannot run llc: {}", error))?;
+    child
+        .stdin
+        .take()
+        .expect("llc has a piped stdin")
+        .write_all(ir.as_bytes())
+        .map_err(|error| format!("Cannot run llc: {}", error))?;
+    let status = child
+        .wait()
+        .map_err(|error| format!("Cannot run llc: {}", error))?;
+    if !status.success() {
+        return Err(format!("llc failed with {}", status));
+    }
+    Ok(())
+}
+
+fn llc_major_version() -> Result<u32, String> {
+    let output = Command::new("llc")
+        .arg("--version")
+        .output()
+        .map_err(|error| format!("Cannot run llc: {}", error))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.split("LLVM version ")
+        .nth(1)
+        .and_then(|rest| rest.split('.').next())
+        .and_then(|major| major.trim().parse().ok())
+        .ok_or_else(|| "Cannot tell which LLVM version llc is from".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+    Bool,
+    // A pointer to a string constant
+    Str,
+    Void,
+}
+
+impl Ty {
+    fn from_data_type(data_type: &DataType) -> Result<Ty, String> {
+        Ok(match data_type {
+            DataType::Integer => Ty::Int,
+            DataType::Float => Ty::Float,
+            DataType::Boolean => Ty::Bool,
+            DataType::String => Ty::Str,
+            DataType::Void => Ty::Void,
+            other => {
+                return Err(unsupported(&format!(
+                    "values of type {}",
+                    format_type(other)
+                )))
+            }
+        })
+    }
+
+    fn llvm(self) -> &'static str {
+        match self {
+            Ty::Int => "i64",
+            Ty::Float => "double",
+            Ty::Bool => "i1",
+            Ty::Str => "ptr",
+            Ty::Void => "void",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Ty::Int => "int",
+            Ty::Float => "float",
+            Ty::Bool => "bool",
+            Ty::Str => "string",
+            Ty::Void => "void",
+        }
+    }
+}
+
+// Functions defined alongside the program when it uses them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    PrintFloat,
+    Fail,
+}
+
+fn helper_definition(helper: &Helper) -> &'static str {
+    match helper {
+        // Whole floats keep their ".0", as the interpreter prints them, and
+        // others get the fewest digits that read back as the same number
+        Helper::PrintFloat => {
+            "define private void @put_print_float(double %x) {\n\
+             entry:\n  \
+               %whole = call double @llvm.floor.f64(double %x)\n  \
+               %is_whole = fcmp oeq double %whole, %x\n  \
+               %magnitude = call double @llvm.fabs.f64(double %x)\n  \
+               %small = fcmp olt double %magnitude, 1.0e16\n  \
+               %plain = and i1 %is_whole, %small\n  \
+               br i1 %plain, label %whole_number, label %fraction\n\
+             whole_number:\n  \
+               call i32 (ptr, ...) @printf(ptr @.fmt.whole, double %x)\n  \
+               ret void\n\
+             fraction:\n  \
+               %buffer = alloca [32 x i8]\n  \
+               br label %attempt\n\
+             attempt:\n  \
+               %precision = phi i32 [ 15, %fraction ], [ %more, %retry ]\n  \
+               call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.fmt.fraction, i32 %precision, double %x)\n  \
+               %back = call double @strtod(ptr %buffer, ptr null)\n  \
+               %exact = fcmp oeq double %back, %x\n  \
+               %last = icmp eq i32 %precision, 17\n  \
+               %done = or i1 %exact, %last\n  \
+               br i1 %done, label %print, label %retry\n\
+             retry:\n  \
+               %more = add i32 %precision, 1\n  \
+               br label %attempt\n\
+             print:\n  \
+               call i32 (ptr, ...) @printf(ptr @.fmt.str, ptr %buffer)\n  \
+               ret void\n\
+             }\n"
+        }
+        Helper::Fail => {
+            "define private void @put_fail(ptr %message) noreturn {\n\
+             entry:\n  \
+               call i32 (ptr, ...) @printf(ptr @.fmt.error, ptr %message)\n  \
+               call void @exit(i32 1)\n  \
+               unreachable\n\
+             }\n"
+        }
+    }
+}
+
+const PRINTF: &str = "declare i32 @printf(ptr, ...)";
+const PUTCHAR: &str = "declare i32 @putchar(i32)";
+const EXIT: &str = "declare void @exit(i32) noreturn";
+
+#[derive(Default)]
+struct LlvmEmitter {
+    // String constants, in the order they were created
+    globals: Vec<String>,
+    strings: HashMap<String, String>,
+    declarations: BTreeSet<String>,
+    helpers: BTreeSet<Helper>,
+    // Parameter and return types of every function in the program
+    functions: HashMap<String, (Vec<Ty>, Ty)>,
+
+    // The function being emitted: its stack slots go in the entry block,
+    // ahead of the body
+    allocas: String,
+    body: String,
+    scopes: Vec<HashMap<String, (String, Ty)>>,
+    // `None` in `main`, which PUT code can't return from
+    return_type: Option<Ty>,
+    // The label of the block being filled, and whether it has ended
+    block: String,
+    terminated: bool,
+    next_id: usize,
+}
+
+fn unsupported(what: &str) -> String {
+    format!("Cannot emit LLVM IR for {}", what)
+}
+
+fn body_statements(stmt: &Stmt) -> &[Stmt] {
+    match stmt {
+        Stmt::Block(block) => &block.statements,
+        other => std::slice::from_ref(other),
+    }
+}
+
+// A double constant; LLVM only takes decimal ones that are exact
+fn float_constant(value: f64) -> String {
+    format!("0x{:016X}", value.to_bits())
+}
+
+impl LlvmEmitter {
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn string_constant(&mut self, value: &str) -> String {
+        if let Some(name) = self.strings.get(value) {
+            return name.clone();
+        }
+        let name = format!("@.str.{}", self.strings.len());
+        let mut bytes = String::new();
+        for byte in value.bytes().chain(std::iter::once(0)) {
+            match byte {
+                b' '..=b'~' if byte != b'"' && byte != b'\\' => bytes.push(byte as char),
+                _ => bytes.push_str(&format!("\\{:02X}", byte)),
+            }
+        }
+        self.globals.push(format!(
+            "{} = private unnamed_addr constant [{} x i8] c\"{}\"",
+            name,
+            value.len() + 1,
+            bytes
+        ));
+        self.strings.insert(value.to_string(), name.clone());
+        name
+    }
+
+    // A format string for `printf`, under its own name
+    fn format_constant(&mut self, name: &str, format: &str) {
+        let global = format!(
+            "@.fmt.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            name,
+            format.len() + 1,
+            format.replace('\n', "\\0A")
+        );
+        if !self.globals.contains(&global) {
+            self.globals.push(global);
+        }
+    }
+
+    fn intrinsic(&mut self, declaration: &str) {
+        self.declarations.insert(declaration.to_string());
+    }
+
+    fn helper(&mut self, helper: Helper) {
+        self.declarations.insert(PRINTF.to_string());
+        match helper {
+            Helper::PrintFloat => {
+                self.intrinsic("declare double @llvm.floor.f64(double)");
+                self.intrinsic("declare double @llvm.fabs.f64(double)");
+                self.declarations
+                    .insert("declare i32 @snprintf(ptr, i64, ptr, ...)".to_string());
+                self.declarations
+                    .insert("declare double @strtod(ptr, ptr)".to_string());
+                self.format_constant("whole", "%.1f");
+                self.format_constant("fraction", "%.*g");
+                self.format_constant("str", "%s");
+            }
+            Helper::Fail => {
+                self.declarations.insert(EXIT.to_string());
+                self.format_constant("error", "Runtime error: %s\n");
+            }
+        }
+        self.helpers.insert(helper);
+    }
+
+    // Appends an instruction to the current block. Code after a `return`
+    // lands in a fresh block that nothing branches to
+    fn emit(&mut self, instruction: &str) {
+        if self.terminated {
+            let dead = self.fresh("dead");
+            self.start_block(&dead);
+        }
+        self.body.push_str(&format!("  {}\n", instruction));
+    }
+
+    // Emits an instruction that produces a value, returning its name
+    fn value(&mut self, instruction: &str) -> String {
+        let name = self.fresh("%t");
+        self.emit(&format!("{} = {}", name, instruction));
+        name
+    }
+
+    fn terminate(&mut self, instruction: &str) {
+        self.emit(instruction);
+        self.terminated = true;
+    }
+
+    // Ends the current block with `instruction` unless it already ended
+    fn jump_end(&mut self, instruction: &str) {
+        if !self.terminated {
+            self.terminate(instruction);
+        }
+    }
+
+    fn jump(&mut self, label: &str) {
+        self.jump_end(&format!("br label %{}", label));
+    }
+
+    // Starts a new block, falling through into it from the current one
+    fn start_block(&mut self, label: &str) {
+        self.jump(label);
+        self.body.push_str(&format!("{}:\n", label));
+        self.block = label.to_string();
+        self.terminated = false;
+    }
+
+    fn begin_function(&mut self, return_type: Option<Ty>) {
+        self.allocas.clear();
+        self.body.clear();
+        self.scopes = vec![HashMap::new()];
+        self.return_type = return_type;
+        self.block = "entry".to_string();
+        self.terminated = false;
+    }
+
+    fn finish_function(&mut self) -> String {
+        format!("entry:\n{}{}", self.allocas, self.body)
+    }
+
+    fn declare(&mut self, name: &str, ty: Ty) -> String {
+        let slot = self.fresh(&format!("%{}.", name));
+        self.allocas
+            .push_str(&format!("  {} = alloca {}\n", slot, ty.llvm()));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (slot.clone(), ty));
+        }
+        slot
+    }
+
+    fn lookup(&self, name: &str) -> Result<(String, Ty), String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .ok_or_else(|| {
+                unsupported(&format!(
+                    "'{}', which is not a parameter or local of this function",
+                    name
+                ))
+            })
+    }
+
+    fn scoped<T>(&mut self, emit: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = emit(self);
+        self.scopes.pop();
+        result
+    }
+
+    // Jumps to a block that reports `message` and exits when `condition`
+    // holds
+    fn fail_if(&mut self, condition: &str, message: &str) {
+        self.helper(Helper::Fail);
+        let message = self.string_constant(message);
+        let fail = self.fresh("fail");
+        let ok = self.fresh("ok");
+        self.terminate(&format!(
+            "br i1 {}, label %{}, label %{}",
+            condition, fail, ok
+        ));
+        self.start_block(&fail);
+        self.emit(&format!("call void @put_fail(ptr {})", message));
+        self.terminate("unreachable");
+        self.start_block(&ok);
+    }
+
+    fn signature(&mut self, function: &FunctionDeclarationNode) -> Result<(), String> {
+        let mut parameters = Vec::new();
+        for parameter in &function.parameters {
+            match &parameter.data_type {
+                Some(data_type) => parameters.push(Ty::from_data_type(data_type)?),
+                None => {
+                    return Err(unsupported(&format!(
+                        "parameter '{}' of '{}' without a type annotation",
+                        parameter.name, function.name
+                    )))
+                }
+            }
+        }
+        let return_type = match &function.return_type {
+            Some(data_type) => Ty::from_data_type(data_type)?,
+            None => Ty::Void,
+        };
+        self.functions
+            .insert(function.name.clone(), (parameters, return_type));
+        Ok(())
+    }
+
+    fn function(&mut self, function: &FunctionDeclarationNode) -> Result<String, String> {
+        let (parameter_types, return_type) = self.functions[&function.name].clone();
+        self.begin_function(Some(return_type));
+        let mut parameters = Vec::new();
+        for (parameter, ty) in function.parameters.iter().zip(parameter_types) {
+            let argument = format!("%arg.{}", parameter.name);
+            parameters.push(format!("{} {}", ty.llvm(), argument));
+            let slot = self.declare(&parameter.name, ty);
+            self.emit(&format!("store {} {}, ptr {}", ty.llvm(), argument, slot));
+        }
+        self.statements(&function.body)?;
+        if return_type == Ty::Void {
+            self.jump_end("ret void");
+        } else if !self.terminated {
+            self.helper(Helper::Fail);
+            let message =
+                self.string_constant(&format!("'{}' ended without returning", function.name));
+            self.emit(&format!("call void @put_fail(ptr {})", message));
+            self.terminate("unreachable");
+        }
+        Ok(format!(
+            "define private {} @put.{}({}) {{\n{}}}\n",
+            return_type.llvm(),
+            function.name,
+            parameters.join(", "),
+            self.finish_function()
+        ))
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        for stmt in statements {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expr(expr)?;
+            }
+            Stmt::VarDeclaration(VarDeclarationNode {
+                name,
+                data_type,
+                initializer,
+                ..
+            }) => {
+                let initializer = initializer
+                    .as_ref()
+                    .ok_or_else(|| unsupported(&format!("'{}' without an initializer", name)))?;
+                self.define(name, data_type.as_ref(), initializer)?;
+            }
+            Stmt::ConstDeclaration(declaration) => {
+                self.define(
+                    &declaration.name,
+                    declaration.data_type.as_ref(),
+                    &declaration.value,
+                )?;
+            }
+            Stmt::FunctionDeclaration(function) => {
+                return Err(unsupported(&format!(
+                    "the nested function '{}'",
+                    function.name
+                )))
+            }
+            Stmt::StructDeclaration(_) | Stmt::EnumDeclaration(_) => {
+                return Err(unsupported("structs and enums"))
+            }
+            Stmt::Return(ret) => {
+                let return_type = self
+                    .return_type
+                    .ok_or_else(|| unsupported("a return from top-level code"))?;
+                match (&ret.value, return_type) {
+                    (None, Ty::Void) => self.terminate("ret void"),
+                    (Some(value), ty) if ty != Ty::Void => {
+                        let value = self.coerce(value, ty)?;
+                        self.terminate(&format!("ret {} {}", ty.llvm(), value));
+                    }
+                    _ => {
+                        return Err(unsupported(
+                            "a return that doesn't match the function's return type",
+                        ))
+                    }
+                }
+            }
+            Stmt::Breakpoint(_) => {}
+            Stmt::If(if_node) => {
+                let condition = self.condition(&if_node.condition)?;
+                let then_label = self.fresh("if.then");
+                let end = self.fresh("if.end");
+                let else_label = match if_node.else_branch {
+                    Some(_) => self.fresh("if.else"),
+                    None => end.clone(),
+                };
+                self.terminate(&format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, then_label, else_label
+                ));
+                self.start_block(&then_label);
+                self.scoped(|emitter| emitter.statements(body_statements(&if_node.then_branch)))?;
+                self.jump(&end);
+                if let Some(else_branch) = &if_node.else_branch {
+                    self.start_block(&else_label);
+                    self.scoped(|emitter| emitter.statements(body_statements(else_branch)))?;
+                }
+                self.start_block(&end);
+            }
+            Stmt::While(while_node) => {
+                self.loop_blocks(Some(&while_node.condition), &while_node.body, None)?
+            }
+            Stmt::DoWhile(do_while) => {
+                let body = self.fresh("do.body");
+                let end = self.fresh("do.end");
+                self.start_block(&body);
+                self.scoped(|emitter| emitter.statements(body_statements(&do_while.body)))?;
+                let condition = self.condition(&do_while.condition)?;
+                self.terminate(&format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, body, end
+                ));
+                self.start_block(&end);
+            }
+            Stmt::For(for_node) => self.scoped(|emitter| {
+                if let Some(initializer) = &for_node.initializer {
+                    emitter.stmt(initializer)?;
+                }
+                emitter.loop_blocks(
+                    for_node.condition.as_ref(),
+                    &for_node.body,
+                    for_node.increment.as_ref(),
+                )
+            })?,
+            Stmt::ForIn(for_in) => {
+                let range = match &for_in.iterable {
+                    Expr::Range(range) => range,
+                    _ => return Err(unsupported("a for loop over anything but a range")),
+                };
+                self.scoped(|emitter| emitter.range_loop(&for_in.variable, range, &for_in.body))?
+            }
+            Stmt::Block(block) => self.scoped(|emitter| emitter.statements(&block.statements))?,
+            Stmt::Match(_) => return Err(unsupported("match statements")),
+            Stmt::Try(_) => return Err(unsupported("try statements")),
+            Stmt::Throw(_) => return Err(unsupported("throw statements")),
+            Stmt::Import(ImportNode {
+                source: ImportSource::Basket(path),
+                ..
+            }) if path.len() == 1 && path[0] == "math" => {}
+            Stmt::Import(_) => return Err(unsupported("imports other than `use math;`")),
+            Stmt::Comment(_) => {}
+        }
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        name: &str,
+        data_type: Option<&DataType>,
+        initializer: &Expr,
+    ) -> Result<(), String> {
+        let (value, ty) = match data_type {
+            Some(data_type) => {
+                let ty = Ty::from_data_type(data_type)?;
+                (self.coerce(initializer, ty)?, ty)
+            }
+            None => self.expr(initializer)?,
+        };
+        if ty == Ty::Void {
+            return Err(unsupported(&format!("'{}', which holds no value", name)));
+        }
+        let slot = self.declare(name, ty);
+        self.emit(&format!("store {} {}, ptr {}", ty.llvm(), value, slot));
+        Ok(())
+    }
+
+    // `while`, and `for` once its initializer has run
+    fn loop_blocks(
+        &mut self,
+        condition: Option<&Expr>,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<(), String> {
+        let head = self.fresh("loop.head");
+        let body_label = self.fresh("loop.body");
+        let end = self.fresh("loop.end");
+        self.start_block(&head);
+        match condition {
+            Some(condition) => {
+                let condition = self.condition(condition)?;
+                self.terminate(&format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, body_label, end
+                ));
+            }
+            None => self.jump(&body_label),
+        }
+        self.start_block(&body_label);
+        self.scoped(|emitter| emitter.statements(body_statements(body)))?;
+        if let Some(increment) = increment {
+            self.expr(increment)?;
+        }
+        self.jump(&head);
+        self.start_block(&end);
+        Ok(())
+    }
+
+    // `for i in start..end step n`, counting up
+    fn range_loop(&mut self, variable: &str, range: &RangeNode, body: &Stmt) -> Result<(), String> {
+        let start = self.coerce(&range.start, Ty::Int)?;
+        let end_value = self.coerce(&range.end, Ty::Int)?;
+        let step = match &range.step {
+            Some(step) => self.coerce(step, Ty::Int)?,
+            None => "1".to_string(),
+        };
+        let slot = self.declare(variable, Ty::Int);
+        self.emit(&format!("store i64 {}, ptr {}", start, slot));
+
+        let head = self.fresh("range.head");
+        let body_label = self.fresh("range.body");
+        let end = self.fresh("range.end");
+        self.start_block(&head);
+        let current = self.value(&format!("load i64, ptr {}", slot));
+        let comparison = if range.inclusive { "sle" } else { "slt" };
+        let more = self.value(&format!(
+            "icmp {} i64 {}, {}",
+            comparison, current, end_value
+        ));
+        self.terminate(&format!(
+            "br i1 {}, label %{}, label %{}",
+            more, body_label, end
+        ));
+        self.start_block(&body_label);
+        self.scoped(|emitter| emitter.statements(body_statements(body)))?;
+        let current = self.value(&format!("load i64, ptr {}", slot));
+        let next = self.value(&format!("add i64 {}, {}", current, step));
+        self.emit(&format!("store i64 {}, ptr {}", next, slot));
+        self.jump(&head);
+        self.start_block(&end);
+        Ok(())
+    }
+
+    fn print(&mut self, arguments: &[Expr]) -> Result<(), String> {
+        self.declarations.insert(PRINTF.to_string());
+        self.declarations.insert(PUTCHAR.to_string());
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                self.value("call i32 @putchar(i32 32)");
+            }
+            let (value, ty) = self.expr(argument)?;
+            match ty {
+                Ty::Int => {
+                    self.format_constant("int", "%lld");
+                    self.value(&format!(
+                        "call i32 (ptr, ...) @printf(ptr @.fmt.int, i64 {})",
+                        value
+                    ));
+                }
+                Ty::Float => {
+                    self.helper(Helper::PrintFloat);
+                    self.emit(&format!("call void @put_print_float(double {})", value));
+                }
+                Ty::Bool | Ty::Str => {
+                    let text = if ty == Ty::Bool {
+                        let (yes, no) =
+                            (self.string_constant("true"), self.string_constant("false"));
+                        self.value(&format!("select i1 {}, ptr {}, ptr {}", value, yes, no))
+                    } else {
+                        value
+                    };
+                    self.format_constant("str", "%s");
+                    self.value(&format!(
+                        "call i32 (ptr, ...) @printf(ptr @.fmt.str, ptr {})",
+                        text
+                    ));
+                }
+                Ty::Void => return Err(unsupported("printing a call that returns nothing")),
+            }
+        }
+        self.value("call i32 @putchar(i32 10)");
+        Ok(())
+    }
+
+    fn condition(&mut self, condition: &Expr) -> Result<String, String> {
+        match self.expr(condition)? {
+            (value, Ty::Bool) => Ok(value),
+            (_, ty) => Err(unsupported(&format!("a {} condition", ty.name()))),
+        }
+    }
+
+    // `expr` as a value of type `ty`, widening ints to floats
+    fn coerce(&mut self, expr: &Expr, ty: Ty) -> Result<String, String> {
+        let (value, found) = self.expr(expr)?;
+        match (found, ty) {
+            (found, ty) if found == ty => Ok(value),
+            (Ty::Int, Ty::Float) => Ok(self.value(&format!("sitofp i64 {} to double", value))),
+            (found, ty) => Err(unsupported(&format!(
+                "a {} where a {} is expected",
+                found.name(),
+                ty.name()
+            ))),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(String, Ty), String> {
+        Ok(match expr {
+            Expr::Number(number) => match number.data_type {
+                DataType::Float => {
+                    let value: f64 = number
+                        .value
+                        .parse()
+                        .map_err(|_| unsupported(&format!("the number {}", number.value)))?;
+                    (float_constant(value), Ty::Float)
+                }
+                _ => (number.value.clone(), Ty::Int),
+            },
+            Expr::Boolean(boolean) => (boolean.value.to_string(), Ty::Bool),
+            Expr::String(string) => (self.string_constant(&string.value), Ty::Str),
+            Expr::Variable(variable) => {
+                let (slot, ty) = self.lookup(&variable.name)?;
+                (self.value(&format!("load {}, ptr {}", ty.llvm(), slot)), ty)
+            }
+            Expr::Assignment(assignment) => self.assign(&assignment.target, &assignment.value)?,
+            Expr::CompoundAssignment(assignment) => {
+                let binary = Expr::Binary(BinaryOperationNode::new(
+                    (*assignment.target).clone(),
+                    assignment.operator,
+                    (*assignment.value).clone(),
+                ));
+                self.assign(&assignment.target, &binary)?
+            }
+            Expr::Binary(binary) => self.binary(binary)?,
+            Expr::Unary(unary) => {
+                let (operand, ty) = self.expr(&unary.operand)?;
+                match (unary.operator, ty) {
+                    (UnaryOperator::Negate, Ty::Int) => {
+                        (self.checked("ssub", "0", &operand), Ty::Int)
+                    }
+                    (UnaryOperator::Negate, Ty::Float) => {
+                        (self.value(&format!("fneg double {}", operand)), Ty::Float)
+                    }
+                    (UnaryOperator::Not, Ty::Bool) => {
+                        (self.value(&format!("xor i1 {}, true", operand)), Ty::Bool)
+                    }
+                    (operator, ty) => {
+                        let symbol = match operator {
+                            UnaryOperator::Negate => "-",
+                            UnaryOperator::Not => "!",
+                        };
+                        return Err(unsupported(&format!("'{}' on {}", symbol, ty.name())));
+                    }
+                }
+            }
+            Expr::Cast(cast) => {
+                let (value, from) = self.expr(&cast.expression)?;
+                let to = Ty::from_data_type(&cast.data_type)?;
+                let value = match (from, to) {
+                    (from, to) if from == to => value,
+                    (Ty::Int, Ty::Float) => self.value(&format!("sitofp i64 {} to double", value)),
+                    (Ty::Bool, Ty::Int) => self.value(&format!("zext i1 {} to i64", value)),
+                    (Ty::Float, Ty::Int) => {
+                        // fptosi is undefined outside -2^63..2^63, and on NaN,
+                        // which neither comparison holds for
+                        let low =
+                            self.value(&format!("fcmp oge double {}, 0xC3E0000000000000", value));
+                        let high =
+                            self.value(&format!("fcmp olt double {}, 0x43E0000000000000", value));
+                        let in_range = self.value(&format!("and i1 {}, {}", low, high));
+                        let out_of_range = self.value(&format!("xor i1 {}, true", in_range));
+                        self.fail_if(&out_of_range, "Cannot cast float to int");
+                        self.value(&format!("fptosi double {} to i64", value))
+                    }
+                    (from, to) => {
+                        return Err(unsupported(&format!(
+                            "casts from {} to {}",
+                            from.name(),
+                            to.name()
+                        )))
+                    }
+                };
+                (value, to)
+            }
+            Expr::Parenthesis(paren) => self.expr(&paren.expression)?,
+            Expr::Conditional(conditional) => {
+                let condition = self.condition(&conditional.condition)?;
+                let then_label = self.fresh("cond.then");
+                let else_label = self.fresh("cond.else");
+                let end = self.fresh("cond.end");
+                self.terminate(&format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, then_label, else_label
+                ));
+                // Both branches' types are needed before either is
+                // emitted, in case one must be widened to a float
+                let then_ty = self.branch_type(&conditional.then_expression)?;
+                let else_ty = self.branch_type(&conditional.else_expression)?;
+                let ty = match (then_ty, else_ty) {
+                    (a, b) if a == b => a,
+                    (Ty::Int, Ty::Float) | (Ty::Float, Ty::Int) => Ty::Float,
+                    (a, b) => {
+                        return Err(unsupported(&format!(
+                            "a conditional choosing between {} and {}",
+                            a.name(),
+                            b.name()
+                        )))
+                    }
+                };
+                self.start_block(&then_label);
+                let then_value = self.coerce(&conditional.then_expression, ty)?;
+                let then_end = self.block.clone();
+                self.jump(&end);
+                self.start_block(&else_label);
+                let else_value = self.coerce(&conditional.else_expression, ty)?;
+                let else_end = self.block.clone();
+                self.jump(&end);
+                self.start_block(&end);
+                let value = self.value(&format!(
+                    "phi {} [ {}, %{} ], [ {}, %{} ]",
+                    ty.llvm(),
+                    then_value,
+                    then_end,
+                    else_value,
+                    else_end
+                ));
+                (value, ty)
+            }
+            Expr::Call(call) => match &*call.callee {
+                Expr::Variable(callee) => self.call(&callee.name, &call.arguments)?,
+                _ => return Err(unsupported("calls to computed functions")),
+            },
+            // `x.f(a)` is `f(x, a)`
+            Expr::MethodCall(method_call) if !method_call.optional => {
+                let mut arguments = vec![(*method_call.object).clone()];
+                arguments.extend(method_call.arguments.iter().cloned());
+                self.call(&method_call.method, &arguments)?
+            }
+            Expr::Char(_) => return Err(unsupported("chars")),
+            Expr::Null(_) => return Err(unsupported("null")),
+            Expr::TensorLiteral(_) => return Err(unsupported("tensors")),
+            Expr::Range(_) => return Err(unsupported("ranges outside a for loop")),
+            Expr::Lambda(_) => return Err(unsupported("closures")),
+            Expr::Spawn(_) => return Err(unsupported("spawn")),
+            Expr::Match(_) => return Err(unsupported("match expressions")),
+            Expr::MethodCall(_) | Expr::MemberAccess(_) | Expr::Index(_) | Expr::EnumVariant(_) => {
+                return Err(unsupported("structs, enums and indexing"))
+            }
+            Expr::Cfg(cfg) => {
+                return Err(unsupported(&format!(
+                    "the unsubstituted setting @cfg({:?})",
+                    cfg.key
+                )))
+            }
+        })
+    }
+
+    // The type of a conditional's branch, without keeping its code
+    fn branch_type(&mut self, expr: &Expr) -> Result<Ty, String> {
+        let saved = (
+            self.body.len(),
+            self.allocas.len(),
+            self.block.clone(),
+            self.terminated,
+        );
+        let (_, ty) = self.expr(expr)?;
+        self.body.truncate(saved.0);
+        self.allocas.truncate(saved.1);
+        self.block = saved.2;
+        self.terminated = saved.3;
+        Ok(ty)
+    }
+
+    fn assign(&mut self, target: &Expr, value: &Expr) -> Result<(String, Ty), String> {
+        let name = match target {
+            Expr::Variable(variable) => &variable.name,
+            _ => return Err(unsupported("assignments to anything but a variable")),
+        };
+        let (slot, ty) = self.lookup(name)?;
+        let value = self.coerce(value, ty)?;
+        self.emit(&format!("store {} {}, ptr {}", ty.llvm(), value, slot));
+        Ok((value, ty))
+    }
+
+    // Integer arithmetic that stops the program on overflow, as the
+    // interpreter does
+    fn checked(&mut self, operation: &str, left: &str, right: &str) -> String {
+        let intrinsic = format!("llvm.{}.with.overflow.i64", operation);
+        self.intrinsic(&format!("declare {{ i64, i1 }} @{}(i64, i64)", intrinsic));
+        let pair = self.value(&format!(
+            "call {{ i64, i1 }} @{}(i64 {}, i64 {})",
+            intrinsic, left, right
+        ));
+        let result = self.value(&format!("extractvalue {{ i64, i1 }} {}, 0", pair));
+        let overflow = self.value(&format!("extractvalue {{ i64, i1 }} {}, 1", pair));
+        self.fail_if(&overflow, "Integer overflow");
+        result
+    }
+
+    fn binary(&mut self, binary: &BinaryOperationNode) -> Result<(String, Ty), String> {
+        use BinaryOperator::*;
+
+        if let And | Or = binary.operator {
+            return self.short_circuit(binary);
+        }
+        let symbol = binary_symbol(binary.operator);
+        let (left, left_ty) = self.expr(&binary.left)?;
+        let (right, right_ty) = self.expr(&binary.right)?;
+        let mismatch = || {
+            unsupported(&format!(
+                "'{}' on {} and {}",
+                symbol,
+                left_ty.name(),
+                right_ty.name()
+            ))
+        };
+
+        match (left_ty, right_ty) {
+            (Ty::Int, Ty::Int) => Ok(match binary.operator {
+                Add => (self.checked("sadd", &left, &right), Ty::Int),
+                Subtract => (self.checked("ssub", &left, &right), Ty::Int),
+                Multiply => (self.checked("smul", &left, &right), Ty::Int),
+                Divide => {
+                    let zero = self.value(&format!("icmp eq i64 {}, 0", right));
+                    self.fail_if(&zero, "Division by zero");
+                    let minimum = self.value(&format!("icmp eq i64 {}, {}", left, i64::MIN));
+                    let minus_one = self.value(&format!("icmp eq i64 {}, -1", right));
+                    let overflow = self.value(&format!("and i1 {}, {}", minimum, minus_one));
+                    self.fail_if(&overflow, "Integer overflow");
+                    (
+                        self.value(&format!("sdiv i64 {}, {}", left, right)),
+                        Ty::Int,
+                    )
+                }
+                BitAnd | BitOr | BitXor => {
+                    let instruction = match binary.operator {
+                        BitAnd => "and",
+                        BitOr => "or",
+                        _ => "xor",
+                    };
+                    (
+                        self.value(&format!("{} i64 {}, {}", instruction, left, right)),
+                        Ty::Int,
+                    )
+                }
+                ShiftLeft | ShiftRight => {
+                    let out_of_range = self.value(&format!("icmp uge i64 {}, 64", right));
+                    self.fail_if(&out_of_range, "Shift amount is out of range");
+                    let instruction = if binary.operator == ShiftLeft {
+                        "shl"
+                    } else {
+                        "ashr"
+                    };
+                    (
+                        self.value(&format!("{} i64 {}, {}", instruction, left, right)),
+                        Ty::Int,
+                    )
+                }
+                Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual => {
+                    let predicate = match binary.operator {
+                        Equal => "eq",
+                        NotEqual => "ne",
+                        Less => "slt",
+                        LessEqual => "sle",
+                        Greater => "sgt",
+                        _ => "sge",
+                    };
+                    (
+                        self.value(&format!("icmp {} i64 {}, {}", predicate, left, right)),
+                        Ty::Bool,
+                    )
+                }
+                _ => return Err(mismatch()),
+            }),
+            (Ty::Int | Ty::Float, Ty::Int | Ty::Float) => {
+                let left = self.widen(left, left_ty);
+                let right = self.widen(right, right_ty);
+                Ok(match binary.operator {
+                    Add | Subtract | Multiply | Divide => {
+                        if binary.operator == Divide {
+                            let zero = self.value(&format!("fcmp oeq double {}, 0.0", right));
+                            self.fail_if(&zero, "Division by zero");
+                        }
+                        let instruction = match binary.operator {
+                            Add => "fadd",
+                            Subtract => "fsub",
+                            Multiply => "fmul",
+                            _ => "fdiv",
+                        };
+                        (
+                            self.value(&format!("{} double {}, {}", instruction, left, right)),
+                            Ty::Float,
+                        )
+                    }
+                    Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual => {
+                        // Ordered comparisons are false for NaN, as in the
+                        // interpreter; `!=` is then true
+                        let predicate = match binary.operator {
+                            Equal => "oeq",
+                            NotEqual => "une",
+                            Less => "olt",
+                            LessEqual => "ole",
+                            Greater => "ogt",
+                            _ => "oge",
+                        };
+                        (
+                            self.value(&format!("fcmp {} double {}, {}", predicate, left, right)),
+                            Ty::Bool,
+                        )
+                    }
+                    _ => return Err(mismatch()),
+                })
+            }
+            (Ty::Bool, Ty::Bool) if matches!(binary.operator, Equal | NotEqual) => {
+                let predicate = if binary.operator == Equal { "eq" } else { "ne" };
+                Ok((
+                    self.value(&format!("icmp {} i1 {}, {}", predicate, left, right)),
+                    Ty::Bool,
+                ))
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    fn widen(&mut self, value: String, ty: Ty) -> String {
+        match ty {
+            Ty::Int => self.value(&format!("sitofp i64 {} to double", value)),
+            _ => value,
+        }
+    }
+
+    // `&&` and `||` evaluate their right side only when they must
+    fn short_circuit(&mut self, binary: &BinaryOperationNode) -> Result<(String, Ty), String> {
+        let is_and = binary.operator == BinaryOperator::And;
+        let left = self.condition(&binary.left)?;
+        let left_end = self.block.clone();
+        let right_label = self.fresh("logic.right");
+        let end = self.fresh("logic.end");
+        let (on_true, on_false) = if is_and {
+            (&right_label, &end)
+        } else {
+            (&end, &right_label)
+        };
+        self.terminate(&format!(
+            "br i1 {}, label %{}, label %{}",
+            left, on_true, on_false
+        ));
+        self.start_block(&right_label);
+        let right = self.condition(&binary.right)?;
+        let right_end = self.block.clone();
+        self.jump(&end);
+        self.start_block(&end);
+        let value = self.value(&format!(
+            "phi i1 [ {}, %{} ], [ {}, %{} ]",
+            !is_and, left_end, right, right_end
+        ));
+        Ok((value, Ty::Bool))
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expr]) -> Result<(String, Ty), String> {
+        if let Some((parameters, return_type)) = self.functions.get(name).cloned() {
+            if parameters.len() != arguments.len() {
+                return Err(format!(
+                    "'{}' expects {} arguments but got {}",
+                    name,
+                    parameters.len(),
+                    arguments.len()
+                ));
+            }
+            let mut values = Vec::new();
+            for (argument, ty) in arguments.iter().zip(parameters) {
+                values.push(format!("{} {}", ty.llvm(), self.coerce(argument, ty)?));
+            }
+            let call = format!(
+                "call {} @put.{}({})",
+                return_type.llvm(),
+                name,
+                values.join(", ")
+            );
+            if return_type == Ty::Void {
+                self.emit(&call);
+                return Ok((String::new(), Ty::Void));
+            }
+            return Ok((self.value(&call), return_type));
+        }
+
+        match (name, arguments) {
+            ("sqrt" | "sin" | "cos" | "floor" | "ceil", [x]) => {
+                let x = self.coerce(x, Ty::Float)?;
+                self.intrinsic(&format!("declare double @llvm.{}.f64(double)", name));
+                Ok((
+                    self.value(&format!("call double @llvm.{}.f64(double {})", name, x)),
+                    Ty::Float,
+                ))
+            }
+            ("pow", [base, exponent]) => {
+                let base = self.coerce(base, Ty::Float)?;
+                let exponent = self.coerce(exponent, Ty::Float)?;
+                self.intrinsic("declare double @llvm.pow.f64(double, double)");
+                Ok((
+                    self.value(&format!(
+                        "call double @llvm.pow.f64(double {}, double {})",
+                        base, exponent
+                    )),
+                    Ty::Float,
+                ))
+            }
+            ("abs", [x]) => match self.expr(x)? {
+                (x, Ty::Int) => {
+                    let negative = self.value(&format!("icmp slt i64 {}, 0", x));
+                    let negated = self.checked("ssub", "0", &x);
+                    Ok((
+                        self.value(&format!(
+                            "select i1 {}, i64 {}, i64 {}",
+                            negative, negated, x
+                        )),
+                        Ty::Int,
+                    ))
+                }
+                (x, Ty::Float) => {
+                    self.intrinsic("declare double @llvm.fabs.f64(double)");
+                    Ok((
+                        self.value(&format!("call double @llvm.fabs.f64(double {})", x)),
+                        Ty::Float,
+                    ))
+                }
+                (_, ty) => Err(unsupported(&format!("abs() of {}", ty.name()))),
+            },
+            ("print", arguments) => {
+                self.print(arguments)?;
+                Ok((String::new(), Ty::Void))
+            }
+            _ => Err(unsupported(&format!("a call to '{}'", name))),
+        }
+    }
+}
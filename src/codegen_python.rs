@@ -0,0 +1,771 @@
+//! Transpiles a PUT program into a Python script, with tensors as NumPy
+//! arrays, so PUT code can be dropped into existing Python pipelines.
+//! Builtins and tensor operations are translated through `PYTHON_CALLS`,
+//! a table of call templates, so supporting another one is a new row.
+//!
+//! Python has no block scope, so PUT blocks are flattened into the
+//! enclosing body. Values print the Python way (`True`, `None`, NumPy's
+//! array layout) rather than as the interpreter prints them.
+
+use crate::ast::*;
+use crate::formatter::{binary_symbol, format_type};
+use crate::type_checker::{self, TypeChecker};
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+/// How each builtin, and `@`, is written in Python: `{0}`, `{1}` stand
+/// for the arguments, in order. Functions not listed here are assumed to
+/// be declared by the script itself.
+const PYTHON_CALLS: &[(&str, &str)] = &[
+    ("matmul", "np.matmul({0}, {1})"),
+    ("sqrt", "np.sqrt({0})"),
+    ("sin", "np.sin({0})"),
+    ("cos", "np.cos({0})"),
+    ("floor", "np.floor({0})"),
+    ("ceil", "np.ceil({0})"),
+    ("pow", "np.power({0}, {1})"),
+    ("abs", "np.abs({0})"),
+    ("len", "len({0})"),
+    ("type_of", "type({0}).__name__"),
+    ("random", "np.random.random()"),
+    ("rand_int", "int(np.random.randint({0}, {1} + 1))"),
+    ("seed", "np.random.seed({0})"),
+    ("split", "{0}.split({1})"),
+    ("join", "{1}.join(str(part) for part in {0})"),
+    ("to_upper", "{0}.upper()"),
+    ("trim", "{0}.strip()"),
+    ("contains", "({1} in {0})"),
+    ("parse_number", "_parse_number({0})"),
+    ("read_file", "open({0}).read()"),
+    ("write_file", "open({0}, \"w\").write({1})"),
+    ("read_lines", "open({0}).read().splitlines()"),
+];
+
+// Runtime helpers for PUT semantics Python lacks, emitted only when used
+const HELPERS: &[(&str, &str)] = &[
+    (
+        "_div",
+        "def _div(a, b):\n\
+         \x20   # Integer division truncates toward zero, as in PUT\n\
+         \x20   if isinstance(a, int) and isinstance(b, int):\n\
+         \x20       quotient = abs(a) // abs(b)\n\
+         \x20       return quotient if (a < 0) == (b < 0) else -quotient\n\
+         \x20   return a / b\n",
+    ),
+    (
+        "_parse_number",
+        "def _parse_number(text):\n\
+         \x20   try:\n\
+         \x20       return int(text)\n\
+         \x20   except ValueError:\n\
+         \x20       return float(text)\n",
+    ),
+    (
+        "_no_match",
+        "def _no_match(value):\n\
+         \x20   raise ValueError(f\"No match arm matches the value {value}\")\n",
+    ),
+];
+
+/// The Python source for `program`, or why it can't be translated.
+pub fn emit_python(program: &ProgramNode) -> Result<String, String> {
+    // Emitted from the checked tree, which knows each expression's type
+    let mut program = program.clone();
+    TypeChecker::infer_types(&mut program).map_err(|errors| type_checker::report(&errors))?;
+
+    let mut emitter = PythonEmitter::default();
+    // Functions may be called above their declaration
+    for stmt in &program.statements {
+        emitter.declare(stmt);
+    }
+    let body = emitter.lines(&program.statements, 0)?;
+
+    let mut output = String::from("# Generated from PUT source by put-lang.\nimport numpy as np\n");
+    if emitter.uses_dataclass {
+        output.push_str("from dataclasses import dataclass\n");
+    }
+    if emitter.uses_enum {
+        output.push_str("from enum import Enum\n");
+    }
+    for (name, helper) in HELPERS {
+        if emitter.helpers.contains(name) {
+            output.push('\n');
+            output.push_str(helper);
+        }
+    }
+    output.push('\n');
+    output.push_str(&body);
+    Ok(output)
+}
+
+#[derive(Default)]
+struct PythonEmitter {
+    helpers: Vec<&'static str>,
+    uses_dataclass: bool,
+    uses_enum: bool,
+    // The parameter types of the functions and struct constructors
+    // declared, which arguments are converted to
+    parameters: HashMap<String, Vec<Option<DataType>>>,
+    // The return type of the function being emitted
+    return_type: Option<DataType>,
+}
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+fn unsupported(what: &str) -> String {
+    format!("Cannot emit Python for {}", what)
+}
+
+// A Python string literal holding `value`
+fn quote(value: &str) -> String {
+    let mut text = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if c.is_control() => text.push_str(&format!("\\x{:02x}", c as u32)),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
+
+fn statements_of(stmt: &Stmt) -> &[Stmt] {
+    match stmt {
+        Stmt::Block(block) => &block.statements,
+        other => std::slice::from_ref(other),
+    }
+}
+
+// Drops one pair of parentheses wrapping the whole of `text`
+fn strip_parens(text: String) -> String {
+    let inner = match text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) => inner,
+        None => return text,
+    };
+    let mut depth = 0;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return text,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    inner.to_string()
+}
+
+impl PythonEmitter {
+    fn declare(&mut self, stmt: &Stmt) {
+        let parameters = match stmt {
+            Stmt::FunctionDeclaration(function) => function
+                .parameters
+                .iter()
+                .map(|parameter| parameter.data_type.clone())
+                .collect(),
+            Stmt::StructDeclaration(declaration) => declaration
+                .fields
+                .iter()
+                .map(|field| Some(field.data_type.clone()))
+                .collect(),
+            _ => return,
+        };
+        let name = match stmt {
+            Stmt::FunctionDeclaration(function) => &function.name,
+            Stmt::StructDeclaration(declaration) => &declaration.name,
+            _ => return,
+        };
+        self.parameters.insert(name.clone(), parameters);
+    }
+
+    fn helper(&mut self, name: &'static str) {
+        if !self.helpers.contains(&name) {
+            self.helpers.push(name);
+        }
+    }
+
+    fn lines(&mut self, statements: &[Stmt], indent: usize) -> Result<String, String> {
+        let is_item = |stmt: &Stmt| {
+            matches!(
+                stmt,
+                Stmt::FunctionDeclaration(_)
+                    | Stmt::StructDeclaration(_)
+                    | Stmt::EnumDeclaration(_)
+            )
+        };
+        let mut text = String::new();
+        for (i, stmt) in statements.iter().enumerate() {
+            // Top-level definitions are set apart by a blank line
+            if indent == 0 && i > 0 && (is_item(stmt) || is_item(&statements[i - 1])) {
+                text.push('\n');
+            }
+            text.push_str(&self.commented_stmt(stmt, indent)?);
+        }
+        Ok(text)
+    }
+
+    // A statement with its comments on lines of their own around it
+    fn commented_stmt(&mut self, stmt: &Stmt, indent: usize) -> Result<String, String> {
+        let text = self.stmt(stmt, indent)?;
+        let Some(comments) = stmt.comments() else {
+            return Ok(text);
+        };
+        let p = pad(indent);
+        let lines = |comments: &[CommentNode]| -> String {
+            comments
+                .iter()
+                .map(|c| comment_lines(&c.text, &p))
+                .collect()
+        };
+        Ok(format!(
+            "{}{}{}",
+            lines(&comments.leading),
+            text,
+            lines(&comments.trailing)
+        ))
+    }
+
+    // An indented suite, which Python won't accept empty
+    fn suite(&mut self, statements: &[Stmt], indent: usize) -> Result<String, String> {
+        let text = self.lines(statements, indent)?;
+        if text.trim().is_empty() || text.lines().all(|line| line.trim().starts_with('#')) {
+            return Ok(format!("{}{}pass\n", text, pad(indent)));
+        }
+        Ok(text)
+    }
+
+    // Each statement as one or more complete lines
+    fn stmt(&mut self, stmt: &Stmt, indent: usize) -> Result<String, String> {
+        let p = pad(indent);
+        Ok(match stmt {
+            Stmt::Expression(expr) => format!("{}{}\n", p, self.statement_expr(expr)?),
+            Stmt::VarDeclaration(declaration) => {
+                let value = match &declaration.initializer {
+                    Some(initializer) => {
+                        strip_parens(self.converted(initializer, declaration.data_type.as_ref())?)
+                    }
+                    None => "None".to_string(),
+                };
+                format!("{}{} = {}\n", p, declaration.name, value)
+            }
+            Stmt::ConstDeclaration(declaration) => format!(
+                "{}{} = {}\n",
+                p,
+                declaration.name,
+                strip_parens(self.converted(&declaration.value, declaration.data_type.as_ref())?)
+            ),
+            Stmt::FunctionDeclaration(function) => {
+                self.declare(stmt);
+                let parameters: Vec<&str> = function
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.name.as_str())
+                    .collect();
+                let enclosing =
+                    std::mem::replace(&mut self.return_type, function.return_type.clone());
+                let body = self.suite(&function.body, indent + 1);
+                self.return_type = enclosing;
+                format!(
+                    "{}def {}({}):\n{}",
+                    p,
+                    function.name,
+                    parameters.join(", "),
+                    body?
+                )
+            }
+            Stmt::StructDeclaration(declaration) => {
+                self.uses_dataclass = true;
+                let mut text = format!("{}@dataclass\n{}class {}:\n", p, p, declaration.name);
+                for field in &declaration.fields {
+                    text.push_str(&format!(
+                        "{}{}: {}\n",
+                        pad(indent + 1),
+                        field.name,
+                        python_type(&field.data_type)
+                    ));
+                }
+                if declaration.fields.is_empty() {
+                    text.push_str(&format!("{}pass\n", pad(indent + 1)));
+                }
+                text
+            }
+            Stmt::EnumDeclaration(declaration) => {
+                self.uses_enum = true;
+                let mut text = format!("{}class {}(Enum):\n", p, declaration.name);
+                for (i, variant) in declaration.variants.iter().enumerate() {
+                    text.push_str(&format!("{}{} = {}\n", pad(indent + 1), variant, i + 1));
+                }
+                if declaration.variants.is_empty() {
+                    text.push_str(&format!("{}pass\n", pad(indent + 1)));
+                }
+                text
+            }
+            Stmt::Return(ret) => match &ret.value {
+                Some(value) => {
+                    let return_type = self.return_type.clone();
+                    let value = self.converted(value, return_type.as_ref())?;
+                    format!("{}return {}\n", p, strip_parens(value))
+                }
+                None => format!("{}return\n", p),
+            },
+            Stmt::Throw(throw) => format!(
+                "{}raise Exception({})\n",
+                p,
+                strip_parens(self.expr(&throw.value)?)
+            ),
+            Stmt::Breakpoint(_) => format!("{}breakpoint()\n", p),
+            Stmt::If(if_node) => {
+                let mut text = format!(
+                    "{}if {}:\n{}",
+                    p,
+                    strip_parens(self.expr(&if_node.condition)?),
+                    self.suite(statements_of(&if_node.then_branch), indent + 1)?
+                );
+                let mut else_branch = if_node.else_branch.as_deref();
+                // `else if` chains become `elif`
+                while let Some(Stmt::If(elif)) = else_branch {
+                    text.push_str(&format!(
+                        "{}elif {}:\n{}",
+                        p,
+                        strip_parens(self.expr(&elif.condition)?),
+                        self.suite(statements_of(&elif.then_branch), indent + 1)?
+                    ));
+                    else_branch = elif.else_branch.as_deref();
+                }
+                if let Some(else_branch) = else_branch {
+                    text.push_str(&format!(
+                        "{}else:\n{}",
+                        p,
+                        self.suite(statements_of(else_branch), indent + 1)?
+                    ));
+                }
+                text
+            }
+            Stmt::While(while_node) => format!(
+                "{}while {}:\n{}",
+                p,
+                strip_parens(self.expr(&while_node.condition)?),
+                self.suite(statements_of(&while_node.body), indent + 1)?
+            ),
+            Stmt::DoWhile(do_while) => format!(
+                "{}while True:\n{}{}if not ({}):\n{}break\n",
+                p,
+                self.lines(statements_of(&do_while.body), indent + 1)?,
+                pad(indent + 1),
+                strip_parens(self.expr(&do_while.condition)?),
+                pad(indent + 2)
+            ),
+            Stmt::For(for_node) => {
+                let mut text = String::new();
+                if let Some(initializer) = &for_node.initializer {
+                    text.push_str(&self.stmt(initializer, indent)?);
+                }
+                let condition = match &for_node.condition {
+                    Some(condition) => strip_parens(self.expr(condition)?),
+                    None => "True".to_string(),
+                };
+                let mut body = self.lines(statements_of(&for_node.body), indent + 1)?;
+                if let Some(increment) = &for_node.increment {
+                    body.push_str(&format!(
+                        "{}{}\n",
+                        pad(indent + 1),
+                        self.statement_expr(increment)?
+                    ));
+                }
+                if body.is_empty() {
+                    body = format!("{}pass\n", pad(indent + 1));
+                }
+                text.push_str(&format!("{}while {}:\n{}", p, condition, body));
+                text
+            }
+            Stmt::ForIn(for_in) => format!(
+                "{}for {} in {}:\n{}",
+                p,
+                for_in.variable,
+                self.expr(&for_in.iterable)?,
+                self.suite(statements_of(&for_in.body), indent + 1)?
+            ),
+            Stmt::Block(block) => self.lines(&block.statements, indent)?,
+            Stmt::Match(match_node) => {
+                let mut text = format!(
+                    "{}match {}:\n",
+                    p,
+                    strip_parens(self.expr(&match_node.subject)?)
+                );
+                for arm in &match_node.arms {
+                    text.push_str(&format!(
+                        "{}case {}:\n{}",
+                        pad(indent + 1),
+                        self.pattern(&arm.pattern)?,
+                        self.suite(statements_of(&arm.body), indent + 2)?
+                    ));
+                }
+                text
+            }
+            Stmt::Try(try_node) => {
+                let handler = match &try_node.error_name {
+                    Some(name) => format!("except Exception as {}:", name),
+                    None => "except Exception:".to_string(),
+                };
+                format!(
+                    "{}try:\n{}{}{}\n{}",
+                    p,
+                    self.suite(&try_node.body.statements, indent + 1)?,
+                    p,
+                    handler,
+                    self.suite(&try_node.handler.statements, indent + 1)?
+                )
+            }
+            // NumPy stands in for the math basket
+            Stmt::Import(ImportNode {
+                source: ImportSource::Basket(_),
+                ..
+            }) => String::new(),
+            Stmt::Import(_) => return Err(unsupported("file imports")),
+            Stmt::Comment(comment) => comment_lines(&comment.text, &p),
+        })
+    }
+
+    // Assignments are statements in Python, not expressions
+    fn statement_expr(&mut self, expr: &Expr) -> Result<String, String> {
+        Ok(match expr {
+            Expr::Assignment(assignment) => format!(
+                "{} = {}",
+                self.expr(&assignment.target)?,
+                strip_parens(
+                    self.converted(&assignment.value, assignment.target.resolved().data_type())?
+                )
+            ),
+            Expr::CompoundAssignment(assignment)
+                if assignment.operator != BinaryOperator::Divide =>
+            {
+                format!(
+                    "{} {}= {}",
+                    self.expr(&assignment.target)?,
+                    binary_symbol(assignment.operator),
+                    strip_parens(self.expr(&assignment.value)?)
+                )
+            }
+            Expr::CompoundAssignment(assignment) => {
+                let target = self.expr(&assignment.target)?;
+                let value = self.divide(&assignment.target, &assignment.value)?;
+                format!("{} = {}", target, value)
+            }
+            other => strip_parens(self.expr(other)?),
+        })
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Result<String, String> {
+        match pattern {
+            Pattern::Wildcard => Ok("_".to_string()),
+            Pattern::Literal(literal) => self.expr(literal),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<String, String> {
+        Ok(match expr {
+            Expr::Number(number) => number.value.clone(),
+            Expr::String(string) => quote(&string.value),
+            Expr::Char(char_node) => quote(&char_node.value.to_string()),
+            Expr::Boolean(boolean) => if boolean.value { "True" } else { "False" }.to_string(),
+            Expr::Null(_) => "None".to_string(),
+            Expr::Variable(variable) => variable.name.clone(),
+            Expr::Assignment(_) | Expr::CompoundAssignment(_) => {
+                return Err(unsupported("an assignment inside an expression"))
+            }
+            Expr::Binary(binary) => match binary.operator {
+                BinaryOperator::And | BinaryOperator::Or => {
+                    let keyword = if binary.operator == BinaryOperator::And {
+                        "and"
+                    } else {
+                        "or"
+                    };
+                    format!(
+                        "({} {} {})",
+                        self.expr(&binary.left)?,
+                        keyword,
+                        self.expr(&binary.right)?
+                    )
+                }
+                BinaryOperator::MatMul => {
+                    self.call("matmul", &[(*binary.left).clone(), (*binary.right).clone()])?
+                }
+                BinaryOperator::Divide => self.divide(&binary.left, &binary.right)?,
+                operator => format!(
+                    "({} {} {})",
+                    self.expr(&binary.left)?,
+                    binary_symbol(operator),
+                    self.expr(&binary.right)?
+                ),
+            },
+            Expr::Unary(unary) => match unary.operator {
+                UnaryOperator::Negate => format!("(-{})", self.expr(&unary.operand)?),
+                UnaryOperator::Not => format!("(not {})", self.expr(&unary.operand)?),
+            },
+            Expr::Cast(cast) => {
+                let value = strip_parens(self.expr(&cast.expression)?);
+                let inner = match &cast.data_type {
+                    DataType::Optional(inner) => inner,
+                    data_type => data_type,
+                };
+                match (inner, &*cast.expression) {
+                    // Python has no chars, only one-letter strings
+                    (DataType::Integer, Expr::Char(_)) => format!("ord({})", value),
+                    (DataType::Integer, _) => format!("int({})", value),
+                    (DataType::Float, _) => format!("float({})", value),
+                    (DataType::String, _) => format!("str({})", value),
+                    (DataType::Char, _) => format!("chr({})", value),
+                    (other, _) => {
+                        return Err(unsupported(&format!("casts to {}", format_type(other))))
+                    }
+                }
+            }
+            Expr::Parenthesis(paren) => {
+                format!("({})", strip_parens(self.expr(&paren.expression)?))
+            }
+            Expr::Conditional(conditional) => format!(
+                "({} if {} else {})",
+                self.converted(&conditional.then_expression, expr.resolved().data_type())?,
+                strip_parens(self.expr(&conditional.condition)?),
+                self.converted(&conditional.else_expression, expr.resolved().data_type())?
+            ),
+            Expr::Range(range) => {
+                let end = self.expr(&range.end)?;
+                let end = if range.inclusive {
+                    format!("{} + 1", end)
+                } else {
+                    strip_parens(end)
+                };
+                match &range.step {
+                    Some(step) => format!(
+                        "range({}, {}, {})",
+                        strip_parens(self.expr(&range.start)?),
+                        end,
+                        strip_parens(self.expr(step)?)
+                    ),
+                    None => format!("range({}, {})", strip_parens(self.expr(&range.start)?), end),
+                }
+            }
+            Expr::Call(call) => match &*call.callee {
+                Expr::Variable(callee) => self.call(&callee.name, &call.arguments)?,
+                callee => {
+                    let callee = self.expr(callee)?;
+                    format!(
+                        "{}({})",
+                        callee,
+                        self.arguments(&call.arguments)?.join(", ")
+                    )
+                }
+            },
+            // `x.f(a)` is `f(x, a)`
+            Expr::MethodCall(method_call) => {
+                let mut arguments = vec![(*method_call.object).clone()];
+                arguments.extend(method_call.arguments.iter().cloned());
+                let call = self.call(&method_call.method, &arguments)?;
+                if method_call.optional {
+                    let object = self.expr(&method_call.object)?;
+                    format!("(None if {} is None else {})", object, call)
+                } else {
+                    call
+                }
+            }
+            Expr::MemberAccess(member) => {
+                let object = self.expr(&member.object)?;
+                let access = format!("{}.{}", object, member.member);
+                if member.optional {
+                    format!("(None if {} is None else {})", object, access)
+                } else {
+                    access
+                }
+            }
+            Expr::Index(index) => {
+                let object = self.expr(&index.object)?;
+                let access = format!("{}[{}]", object, strip_parens(self.expr(&index.index)?));
+                if index.optional {
+                    format!("(None if {} is None else {})", object, access)
+                } else {
+                    access
+                }
+            }
+            Expr::EnumVariant(variant) => format!("{}.{}", variant.enum_name, variant.variant),
+            Expr::TensorLiteral(tensor) => {
+                let elements = self.arguments(&tensor.elements)?;
+                format!(
+                    "np.array([{}], dtype=float).reshape({:?})",
+                    elements.join(", "),
+                    tensor.shape
+                )
+            }
+            Expr::Spawn(_) => return Err(unsupported("spawn")),
+            Expr::Lambda(lambda) => {
+                let parameters: Vec<&str> = lambda
+                    .parameters
+                    .iter()
+                    .map(|parameter| parameter.name.as_str())
+                    .collect();
+                let body = match &lambda.body {
+                    LambdaBody::Expression(body) => {
+                        strip_parens(self.converted(body, lambda.return_type.as_ref())?)
+                    }
+                    LambdaBody::Block(_) => return Err(unsupported("closures with a block body")),
+                };
+                let separator = if parameters.is_empty() { "" } else { " " };
+                format!("(lambda{}{}: {})", separator, parameters.join(", "), body)
+            }
+            // Python's match is a statement, so this is a chain of
+            // conditionals over the subject bound to `_s`
+            Expr::Match(match_node) => {
+                let subject = strip_parens(self.expr(&match_node.subject)?);
+                let mut chain = String::new();
+                let mut fallback = None;
+                for arm in &match_node.arms {
+                    let value = self.expr(&arm.value)?;
+                    match &arm.pattern {
+                        Pattern::Wildcard => {
+                            fallback = Some(value);
+                            break;
+                        }
+                        Pattern::Literal(literal) => chain.push_str(&format!(
+                            "{} if _s == {} else ",
+                            value,
+                            self.expr(literal)?
+                        )),
+                    }
+                }
+                let fallback = match fallback {
+                    Some(value) => value,
+                    None => {
+                        self.helper("_no_match");
+                        "_no_match(_s)".to_string()
+                    }
+                };
+                format!("(lambda _s: {}{})({})", chain, fallback, subject)
+            }
+            Expr::Cfg(cfg) => {
+                return Err(unsupported(&format!(
+                    "the unsubstituted setting @cfg({})",
+                    quote(&cfg.key)
+                )))
+            }
+        })
+    }
+
+    // Float division stays `/`; anything that might be two integers goes
+    // through `_div` so it truncates as PUT does
+    fn divide(&mut self, left: &Expr, right: &Expr) -> Result<String, String> {
+        let is_float = |expr: &Expr| expr.resolved().data_type() == Some(&DataType::Float);
+        let (left_text, right_text) = (self.expr(left)?, self.expr(right)?);
+        if is_float(left) || is_float(right) {
+            return Ok(format!("({} / {})", left_text, right_text));
+        }
+        self.helper("_div");
+        Ok(format!(
+            "_div({}, {})",
+            strip_parens(left_text),
+            strip_parens(right_text)
+        ))
+    }
+
+    // `expr` as a value of `data_type`: an int where a float is expected
+    // is converted, as PUT converts it
+    fn converted(&mut self, expr: &Expr, data_type: Option<&DataType>) -> Result<String, String> {
+        let text = self.expr(expr)?;
+        let float = match data_type {
+            Some(DataType::Optional(inner)) => **inner == DataType::Float,
+            data_type => data_type == Some(&DataType::Float),
+        };
+        if float && expr.resolved().data_type() == Some(&DataType::Integer) {
+            Ok(format!("float({})", strip_parens(text)))
+        } else {
+            Ok(text)
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Expr]) -> Result<Vec<String>, String> {
+        arguments
+            .iter()
+            .map(|argument| Ok(strip_parens(self.expr(argument)?)))
+            .collect()
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expr]) -> Result<String, String> {
+        if let Some(parameters) = self.parameters.get(name).cloned() {
+            let values = arguments
+                .iter()
+                .zip(&parameters)
+                .map(|(argument, parameter)| {
+                    Ok(strip_parens(self.converted(argument, parameter.as_ref())?))
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            return Ok(format!("{}({})", name, values.join(", ")));
+        }
+        let values = self.arguments(arguments)?;
+        if name == "print" {
+            return Ok(format!("print({})", values.join(", ")));
+        }
+        if name == "zeros" {
+            return Ok(format!("np.zeros(({},))", values.join(", ")));
+        }
+        let template = match PYTHON_CALLS.iter().find(|(call, _)| *call == name) {
+            Some((_, template)) => template,
+            None => return Ok(format!("{}({})", name, values.join(", "))),
+        };
+        let arity = (0..)
+            .take_while(|i| template.contains(&format!("{{{}}}", i)))
+            .count();
+        if values.len() != arity {
+            return Err(format!(
+                "'{}' expects {} argument{} but got {}",
+                name,
+                arity,
+                if arity == 1 { "" } else { "s" },
+                values.len()
+            ));
+        }
+        if name == "parse_number" {
+            self.helper("_parse_number");
+        }
+        let mut text = template.to_string();
+        for (i, value) in values.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", i), value);
+        }
+        Ok(text)
+    }
+}
+
+fn python_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Integer => "int".to_string(),
+        DataType::Float => "float".to_string(),
+        DataType::String | DataType::Char => "str".to_string(),
+        DataType::Boolean => "bool".to_string(),
+        DataType::Tensor => "np.ndarray".to_string(),
+        DataType::Void => "None".to_string(),
+        DataType::Struct(name) | DataType::Enum(name) => format!("\"{}\"", name),
+        DataType::Optional(inner) => format!("{} | None", python_type(inner)),
+        DataType::Array(element) => format!("list[{}]", python_type(element)),
+        DataType::Parameter(_) => "object".to_string(),
+    }
+}
+
+// `// text` and `/* text */` as `#` lines
+fn comment_lines(text: &str, pad: &str) -> String {
+    let body = text
+        .strip_prefix("//")
+        .or_else(|| {
+            text.strip_prefix("/*")
+                .map(|t| t.strip_suffix("*/").unwrap_or(t))
+        })
+        .unwrap_or(text);
+    body.trim()
+        .lines()
+        .map(|line| format!("{}# {}\n", pad, line.trim()).replace("# \n", "#\n"))
+        .collect()
+}
@@ -0,0 +1,923 @@
+//! Transpiles a PUT program into a standalone Rust source file, so numeric
+//! scripts can be compiled with rustc instead of interpreted. Tensors
+//! become this crate's `Tensor`, so the output depends on `put_lang`.
+//!
+//! Functions, structs and enums become top-level items and every other
+//! top-level statement goes into `fn main`. As in Rust, a function body
+//! therefore cannot see the script's top-level variables. Function
+//! parameters need type annotations; local types are worked out from
+//! literals, annotations and function signatures as the code is emitted.
+//! Constructs with no Rust counterpart (closures, `try`, file imports,
+//! most builtins) are reported as errors rather than emitted half-right.
+
+use crate::ast::*;
+use crate::type_checker::{self, TypeChecker};
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "crate", "dyn", "extern", "impl", "in", "loop", "mod", "move",
+    "mut", "pub", "ref", "self", "static", "super", "trait", "type", "unsafe", "where", "yield",
+];
+
+/// The Rust source for `program`, or why it can't be translated.
+pub fn emit_rust(program: &ProgramNode) -> Result<String, String> {
+    // Emitted from the checked tree, which knows each expression's type
+    let mut program = program.clone();
+    TypeChecker::infer_types(&mut program).map_err(|errors| type_checker::report(&errors))?;
+
+    let mut emitter = RustEmitter::default();
+    // The scope of `main`
+    emitter.scopes.push(HashMap::new());
+    let mut items = Vec::new();
+    let mut main_body = String::new();
+    for stmt in &program.statements {
+        match stmt {
+            Stmt::FunctionDeclaration(_)
+            | Stmt::StructDeclaration(_)
+            | Stmt::EnumDeclaration(_) => items.push(emitter.stmt(stmt, 0)?),
+            _ => main_body.push_str(&emitter.line(stmt, 1)?),
+        }
+    }
+
+    let mut output = String::from(
+        "// Generated from PUT source by put-lang.\n\
+         #![allow(unused_mut, unused_variables, unused_parens, unused_imports)]\n\
+         #![allow(unused_assignments, unreachable_patterns, dead_code)]\n\
+         \n\
+         use put_lang::tensor::Tensor;\n\
+         \n",
+    );
+    for item in items {
+        output.push_str(&item);
+        output.push_str("\n\n");
+    }
+    output.push_str(&format!("fn main() {{\n{}}}\n", main_body));
+    Ok(output)
+}
+
+// What the emitter knows of an expression's type
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+    Tensor,
+    Named(String),
+    Optional(Box<Ty>),
+    Vec(Box<Ty>),
+    Unit,
+    Unknown,
+}
+
+impl Ty {
+    fn from_data_type(data_type: &DataType) -> Ty {
+        match data_type {
+            DataType::Integer => Ty::Int,
+            DataType::Float => Ty::Float,
+            DataType::String => Ty::Str,
+            DataType::Boolean => Ty::Bool,
+            DataType::Char => Ty::Char,
+            DataType::Tensor => Ty::Tensor,
+            DataType::Struct(name) | DataType::Enum(name) => Ty::Named(name.clone()),
+            DataType::Void => Ty::Unit,
+            DataType::Optional(inner) => Ty::Optional(Box::new(Ty::from_data_type(inner))),
+            DataType::Array(element) => Ty::Vec(Box::new(Ty::from_data_type(element))),
+            DataType::Parameter(name) => Ty::Named(name.clone()),
+        }
+    }
+
+    fn rust(&self) -> String {
+        match self {
+            Ty::Int => "i64".to_string(),
+            Ty::Float => "f64".to_string(),
+            Ty::Bool => "bool".to_string(),
+            Ty::Char => "char".to_string(),
+            Ty::Str => "String".to_string(),
+            Ty::Tensor => "Tensor".to_string(),
+            Ty::Named(name) => name.clone(),
+            Ty::Optional(inner) => format!("Option<{}>", inner.rust()),
+            Ty::Vec(element) => format!("Vec<{}>", element.rust()),
+            Ty::Unit => "()".to_string(),
+            Ty::Unknown => "_".to_string(),
+        }
+    }
+
+    // Values that Rust moves rather than copies
+    fn is_owned(&self) -> bool {
+        matches!(
+            self,
+            Ty::Str | Ty::Tensor | Ty::Named(_) | Ty::Optional(_) | Ty::Vec(_) | Ty::Unknown
+        )
+    }
+}
+
+#[derive(Default)]
+struct RustEmitter {
+    // Innermost scope last
+    scopes: Vec<HashMap<String, Ty>>,
+    // Parameter and return types of the functions declared so far
+    functions: HashMap<String, (Vec<Ty>, Ty)>,
+    // The return type of the function being emitted
+    return_type: Option<Ty>,
+    structs: HashMap<String, Vec<(String, Ty)>>,
+    // Indentation of the statement being emitted, for expressions that
+    // span lines
+    indent: usize,
+}
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+fn unsupported(what: &str) -> String {
+    format!("Cannot emit Rust for {}", what)
+}
+
+fn identifier(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+impl RustEmitter {
+    fn declare(&mut self, name: &str, ty: Ty) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Ty {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or(Ty::Unknown)
+    }
+
+    // Runs `emit` in a new innermost scope
+    fn scoped<T>(&mut self, emit: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = emit(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn line(&mut self, stmt: &Stmt, indent: usize) -> Result<String, String> {
+        let enclosing = std::mem::replace(&mut self.indent, indent);
+        let text = self.stmt(stmt, indent);
+        self.indent = enclosing;
+        let line = format!("{}{}\n", pad(indent), text?);
+        // PUT comments are already valid Rust comments
+        let Some(comments) = stmt.comments() else {
+            return Ok(line);
+        };
+        let lines = |comments: &[CommentNode]| -> String {
+            comments
+                .iter()
+                .map(|c| format!("{}{}\n", pad(indent), c.text))
+                .collect()
+        };
+        Ok(format!(
+            "{}{}{}",
+            lines(&comments.leading),
+            line,
+            lines(&comments.trailing)
+        ))
+    }
+
+    fn lines(&mut self, statements: &[Stmt], indent: usize) -> Result<String, String> {
+        let mut text = String::new();
+        for stmt in statements {
+            text.push_str(&self.line(stmt, indent)?);
+        }
+        Ok(text)
+    }
+
+    fn block(&mut self, statements: &[Stmt], indent: usize) -> Result<String, String> {
+        if statements.is_empty() {
+            return Ok("{}".to_string());
+        }
+        let body = self.scoped(|emitter| emitter.lines(statements, indent + 1))?;
+        Ok(format!("{{\n{}{}}}", body, pad(indent)))
+    }
+
+    // A loop or branch body, which Rust always wants in braces
+    fn body(&mut self, stmt: &Stmt, indent: usize) -> Result<String, String> {
+        self.block(body_statements(stmt), indent)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, indent: usize) -> Result<String, String> {
+        Ok(match stmt {
+            Stmt::Expression(expr) => format!("{};", strip_parens(self.expr(expr)?.0)),
+            Stmt::VarDeclaration(declaration) => {
+                let declared = declaration.data_type.as_ref().map(Ty::from_data_type);
+                let name = identifier(&declaration.name);
+                match (&declaration.initializer, &declared) {
+                    (Some(initializer), _) => {
+                        let (value, ty) = self.initializer(initializer, declared.as_ref())?;
+                        self.declare(&declaration.name, declared.clone().unwrap_or(ty));
+                        match &declared {
+                            Some(declared) => {
+                                format!("let mut {}: {} = {};", name, declared.rust(), value)
+                            }
+                            None => format!("let mut {} = {};", name, value),
+                        }
+                    }
+                    (None, Some(Ty::Optional(inner))) => {
+                        self.declare(&declaration.name, Ty::Optional(inner.clone()));
+                        format!("let mut {}: Option<{}> = None;", name, inner.rust())
+                    }
+                    (None, Some(declared)) => {
+                        self.declare(&declaration.name, declared.clone());
+                        format!("let mut {}: {};", name, declared.rust())
+                    }
+                    (None, None) => {
+                        return Err(unsupported(&format!(
+                            "'{}' without a type or initializer",
+                            declaration.name
+                        )))
+                    }
+                }
+            }
+            Stmt::ConstDeclaration(declaration) => {
+                let declared = declaration.data_type.as_ref().map(Ty::from_data_type);
+                let (value, ty) = self.initializer(&declaration.value, declared.as_ref())?;
+                let name = identifier(&declaration.name);
+                self.declare(&declaration.name, declared.clone().unwrap_or(ty));
+                match declared {
+                    Some(declared) => format!("let {}: {} = {};", name, declared.rust(), value),
+                    None => format!("let {} = {};", name, value),
+                }
+            }
+            Stmt::FunctionDeclaration(function) => self.function(function, indent)?,
+            Stmt::StructDeclaration(declaration) => {
+                let fields: Vec<(String, Ty)> = declaration
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), Ty::from_data_type(&field.data_type)))
+                    .collect();
+                let mut text = format!(
+                    "#[derive(Debug, Clone, PartialEq)]\n{}struct {} {{\n",
+                    pad(indent),
+                    declaration.name
+                );
+                for (name, ty) in &fields {
+                    text.push_str(&format!(
+                        "{}{}: {},\n",
+                        pad(indent + 1),
+                        identifier(name),
+                        ty.rust()
+                    ));
+                }
+                text.push_str(&format!("{}}}", pad(indent)));
+                self.structs.insert(declaration.name.clone(), fields);
+                text
+            }
+            Stmt::EnumDeclaration(declaration) => {
+                let mut text = format!(
+                    "#[derive(Debug, Clone, Copy, PartialEq)]\n{}enum {} {{\n",
+                    pad(indent),
+                    declaration.name
+                );
+                for variant in &declaration.variants {
+                    text.push_str(&format!("{}{},\n", pad(indent + 1), variant));
+                }
+                text.push_str(&format!("{}}}", pad(indent)));
+                text
+            }
+            Stmt::Return(ret) => match &ret.value {
+                Some(value) => {
+                    let (value, ty) = self.owned(value)?;
+                    let return_type = self.return_type.clone();
+                    format!("return {};", converted(value, ty, return_type.as_ref()).0)
+                }
+                None => "return;".to_string(),
+            },
+            Stmt::Breakpoint(_) => "// breakpoint;".to_string(),
+            Stmt::If(if_node) => {
+                let mut text = format!(
+                    "if {} {}",
+                    self.condition(&if_node.condition)?,
+                    self.body(&if_node.then_branch, indent)?
+                );
+                match if_node.else_branch.as_deref() {
+                    // `else if` chains stay flat
+                    Some(else_branch @ Stmt::If(_)) => {
+                        text.push_str(&format!(" else {}", self.stmt(else_branch, indent)?))
+                    }
+                    Some(else_branch) => {
+                        text.push_str(&format!(" else {}", self.body(else_branch, indent)?))
+                    }
+                    None => {}
+                }
+                text
+            }
+            Stmt::While(while_node) => format!(
+                "while {} {}",
+                self.condition(&while_node.condition)?,
+                self.body(&while_node.body, indent)?
+            ),
+            Stmt::DoWhile(do_while) => {
+                let body = self
+                    .scoped(|emitter| emitter.lines(body_statements(&do_while.body), indent + 1))?;
+                format!(
+                    "loop {{\n{}{inner}if !({}) {{\n{inner}{INDENT}break;\n{inner}}}\n{}}}",
+                    body,
+                    self.condition(&do_while.condition)?,
+                    pad(indent),
+                    inner = pad(indent + 1)
+                )
+            }
+            Stmt::For(for_node) => self.scoped(|emitter| emitter.for_loop(for_node, indent))?,
+            Stmt::ForIn(for_in) => {
+                let range = match &for_in.iterable {
+                    Expr::Range(range) => self.range(range)?,
+                    _ => return Err(unsupported("a for loop over anything but a range")),
+                };
+                self.scoped(|emitter| {
+                    emitter.declare(&for_in.variable, Ty::Int);
+                    Ok::<_, String>(format!(
+                        "for {} in {} {}",
+                        identifier(&for_in.variable),
+                        range,
+                        emitter.body(&for_in.body, indent)?
+                    ))
+                })?
+            }
+            Stmt::Block(block) => self.block(&block.statements, indent)?,
+            Stmt::Match(match_node) => {
+                let (subject, ty) = self.expr(&match_node.subject)?;
+                let arms = match_node
+                    .arms
+                    .iter()
+                    .map(|arm| Ok((arm.pattern.clone(), self.body(&arm.body, indent + 1)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                self.match_text(subject, &ty, arms, indent)?
+            }
+            Stmt::Import(ImportNode {
+                source: ImportSource::Basket(path),
+                ..
+            }) if path.len() == 1 && path[0] == "math" => "// use math;".to_string(),
+            Stmt::Import(_) => return Err(unsupported("imports other than `use math;`")),
+            Stmt::Try(_) => return Err(unsupported("try statements")),
+            Stmt::Throw(_) => return Err(unsupported("throw statements")),
+            Stmt::Comment(comment) => comment.text.clone(),
+        })
+    }
+
+    fn function(
+        &mut self,
+        function: &FunctionDeclarationNode,
+        indent: usize,
+    ) -> Result<String, String> {
+        if !function.type_parameters.is_empty() {
+            return Err(unsupported(&format!(
+                "generic function '{}'",
+                function.name
+            )));
+        }
+        let return_type = function
+            .return_type
+            .as_ref()
+            .map_or(Ty::Unit, Ty::from_data_type);
+        let mut parameters = Vec::new();
+        for parameter in &function.parameters {
+            match &parameter.data_type {
+                Some(data_type) => parameters.push(Ty::from_data_type(data_type)),
+                None => {
+                    return Err(unsupported(&format!(
+                        "parameter '{}' of '{}' without a type annotation",
+                        parameter.name, function.name
+                    )))
+                }
+            }
+        }
+        // Registered first so the body may call itself
+        self.functions.insert(
+            function.name.clone(),
+            (parameters.clone(), return_type.clone()),
+        );
+
+        // A function body sees only its parameters, as in Rust
+        let enclosing = std::mem::take(&mut self.scopes);
+        let enclosing_return = self.return_type.replace(return_type.clone());
+        self.scopes.push(HashMap::new());
+        for (parameter, ty) in function.parameters.iter().zip(&parameters) {
+            self.declare(&parameter.name, ty.clone());
+        }
+        let parameters: Vec<String> = function
+            .parameters
+            .iter()
+            .zip(&parameters)
+            .map(|(parameter, ty)| format!("{}: {}", identifier(&parameter.name), ty.rust()))
+            .collect();
+        let body = self.block(&function.body, indent);
+        self.scopes = enclosing;
+        self.return_type = enclosing_return;
+
+        let signature = match return_type {
+            Ty::Unit => String::new(),
+            ty => format!(" -> {}", ty.rust()),
+        };
+        Ok(format!(
+            "fn {}({}){} {}",
+            identifier(&function.name),
+            parameters.join(", "),
+            signature,
+            body?
+        ))
+    }
+
+    // `for (init; condition; increment)` as a while loop in its own block
+    fn for_loop(&mut self, for_node: &ForNode, indent: usize) -> Result<String, String> {
+        let mut text = String::from("{\n");
+        if let Some(initializer) = &for_node.initializer {
+            text.push_str(&self.line(initializer, indent + 1)?);
+        }
+        let head = match &for_node.condition {
+            Some(condition) => format!("while {}", self.condition(condition)?),
+            None => "loop".to_string(),
+        };
+        let mut body =
+            self.scoped(|emitter| emitter.lines(body_statements(&for_node.body), indent + 2))?;
+        // The increment runs after the body, inside the loop
+        if let Some(increment) = &for_node.increment {
+            body.push_str(&format!(
+                "{}{};\n",
+                pad(indent + 2),
+                strip_parens(self.expr(increment)?.0)
+            ));
+        }
+        text.push_str(&format!(
+            "{inner}{} {{\n{}{inner}}}\n{}}}",
+            head,
+            body,
+            pad(indent),
+            inner = pad(indent + 1)
+        ));
+        Ok(text)
+    }
+
+    fn range(&mut self, range: &RangeNode) -> Result<String, String> {
+        let start = self.expr(&range.start)?.0;
+        let end = self.expr(&range.end)?.0;
+        let operator = if range.inclusive { "..=" } else { ".." };
+        Ok(match &range.step {
+            Some(step) => format!(
+                "({}{}{}).step_by({} as usize)",
+                start,
+                operator,
+                end,
+                self.expr(step)?.0
+            ),
+            None => format!("{}{}{}", start, operator, end),
+        })
+    }
+
+    fn match_text(
+        &mut self,
+        subject: String,
+        ty: &Ty,
+        arms: Vec<(Pattern, String)>,
+        indent: usize,
+    ) -> Result<String, String> {
+        // String patterns are `&str` literals
+        let subject = if *ty == Ty::Str {
+            format!("{}.as_str()", subject)
+        } else {
+            subject
+        };
+        let mut text = format!("match {} {{\n", subject);
+        let mut has_wildcard = false;
+        for (pattern, body) in arms {
+            let pattern = match &pattern {
+                Pattern::Wildcard => {
+                    has_wildcard = true;
+                    "_".to_string()
+                }
+                Pattern::Literal(Expr::String(string)) => format!("{:?}", string.value),
+                Pattern::Literal(literal) => self.expr(literal)?.0,
+            };
+            text.push_str(&format!("{}{} => {},\n", pad(indent + 1), pattern, body));
+        }
+        // Rust insists on exhaustive matches, PUT fails at runtime instead
+        if !has_wildcard {
+            text.push_str(&format!(
+                "{}_ => panic!(\"No match arm matches the value\"),\n",
+                pad(indent + 1)
+            ));
+        }
+        text.push_str(&format!("{}}}", pad(indent)));
+        Ok(text)
+    }
+
+    fn print(&mut self, arguments: &[Expr]) -> Result<String, String> {
+        let mut placeholders = Vec::new();
+        let mut values = Vec::new();
+        for argument in arguments {
+            let (value, ty) = self.expr(argument)?;
+            // Whole floats keep their ".0", as the interpreter prints them
+            placeholders.push(match ty {
+                Ty::Float | Ty::Optional(_) | Ty::Named(_) => "{:?}",
+                _ => "{}",
+            });
+            values.push(value);
+        }
+        if values.is_empty() {
+            return Ok("println!()".to_string());
+        }
+        Ok(format!(
+            "println!(\"{}\", {})",
+            placeholders.join(" "),
+            values.join(", ")
+        ))
+    }
+
+    fn condition(&mut self, condition: &Expr) -> Result<String, String> {
+        let (text, _) = self.expr(condition)?;
+        Ok(strip_parens(text))
+    }
+
+    // An initializer converted to the declared type, if there is one
+    fn initializer(&mut self, value: &Expr, declared: Option<&Ty>) -> Result<(String, Ty), String> {
+        let (text, ty) = self.owned(value)?;
+        Ok(converted(text, ty, declared))
+    }
+
+    // An expression whose value is moved somewhere, so a variable holding
+    // a non-`Copy` value must be cloned
+    fn owned(&mut self, expr: &Expr) -> Result<(String, Ty), String> {
+        let (text, ty) = self.expr(expr)?;
+        if ty.is_owned() && matches!(expr, Expr::Variable(_) | Expr::MemberAccess(_)) {
+            Ok((format!("{}.clone()", text), ty))
+        } else {
+            Ok((text, ty))
+        }
+    }
+
+    // An expression and its type: what the emitter tracked itself, or else
+    // what the type checker resolved
+    fn expr(&mut self, expr: &Expr) -> Result<(String, Ty), String> {
+        let (text, ty) = self.expr_kind(expr)?;
+        match (&ty, expr.resolved().data_type()) {
+            (Ty::Unknown, Some(data_type)) => Ok((text, Ty::from_data_type(data_type))),
+            _ => Ok((text, ty)),
+        }
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> Result<(String, Ty), String> {
+        Ok(match expr {
+            Expr::Number(number) => match number.data_type {
+                DataType::Float => (number.value.clone(), Ty::Float),
+                // Rust would infer i32 for a literal too big for it
+                _ => match number.value.parse::<i32>() {
+                    Ok(_) => (number.value.clone(), Ty::Int),
+                    Err(_) => (format!("{}i64", number.value), Ty::Int),
+                },
+            },
+            Expr::String(string) => (format!("String::from({:?})", string.value), Ty::Str),
+            Expr::Char(char_node) => (format!("{:?}", char_node.value), Ty::Char),
+            Expr::Boolean(boolean) => (boolean.value.to_string(), Ty::Bool),
+            Expr::Null(_) => ("None".to_string(), Ty::Optional(Box::new(Ty::Unknown))),
+            Expr::Variable(variable) => (identifier(&variable.name), self.lookup(&variable.name)),
+            Expr::Assignment(assignment) => {
+                let (target, target_ty) = self.expr(&assignment.target)?;
+                let (value, ty) = self.owned(&assignment.value)?;
+                let (value, _) = converted(value, ty, Some(&target_ty));
+                (format!("{} = {}", target, value), Ty::Unit)
+            }
+            Expr::CompoundAssignment(assignment) => {
+                let (target, _) = self.expr(&assignment.target)?;
+                let binary = BinaryOperationNode::new(
+                    (*assignment.target).clone(),
+                    assignment.operator,
+                    (*assignment.value).clone(),
+                );
+                let (value, _) = self.binary(&binary)?;
+                (format!("{} = {}", target, strip_parens(value)), Ty::Unit)
+            }
+            Expr::Binary(binary) => self.binary(binary)?,
+            Expr::Unary(unary) => {
+                let (operand, ty) = self.expr(&unary.operand)?;
+                match (unary.operator, &ty) {
+                    (UnaryOperator::Negate, Ty::Tensor) => {
+                        (format!("{}.apply(|x| -x)", operand), Ty::Tensor)
+                    }
+                    (UnaryOperator::Negate, _) => (format!("(-{})", operand), ty),
+                    (UnaryOperator::Not, _) => (format!("(!{})", operand), Ty::Bool),
+                }
+            }
+            Expr::Cast(cast) => {
+                let (value, from) = self.expr(&cast.expression)?;
+                let to = Ty::from_data_type(&cast.data_type);
+                let text = match (&from, &to) {
+                    (from, to) if from == to => value,
+                    (Ty::Int | Ty::Bool | Ty::Char, Ty::Int)
+                    | (Ty::Int | Ty::Float, Ty::Float)
+                    | (Ty::Float, Ty::Int) => format!("({} as {})", value, to.rust()),
+                    (Ty::Int, Ty::Char) => format!(
+                        "char::from_u32({} as u32).expect(\"not a character code\")",
+                        value
+                    ),
+                    (Ty::Str, Ty::Int | Ty::Float) => format!(
+                        "{}.trim().parse::<{}>().expect(\"not a number\")",
+                        value,
+                        to.rust()
+                    ),
+                    (Ty::Int | Ty::Float | Ty::Bool | Ty::Char, Ty::Str) => {
+                        format!("{}.to_string()", value)
+                    }
+                    _ => {
+                        return Err(unsupported(&format!(
+                            "casts from {} to {}",
+                            from.rust(),
+                            to.rust()
+                        )))
+                    }
+                };
+                (text, to)
+            }
+            Expr::Parenthesis(paren) => {
+                let (inner, ty) = self.expr(&paren.expression)?;
+                (format!("({})", strip_parens(inner)), ty)
+            }
+            Expr::Conditional(conditional) => {
+                let condition = self.condition(&conditional.condition)?;
+                let (then_text, then_ty) = self.owned(&conditional.then_expression)?;
+                let (else_text, else_ty) = self.owned(&conditional.else_expression)?;
+                let (then_text, else_text, ty) =
+                    unify_numbers(then_text, then_ty, else_text, else_ty);
+                (
+                    format!(
+                        "(if {} {{ {} }} else {{ {} }})",
+                        condition,
+                        strip_parens(then_text),
+                        strip_parens(else_text)
+                    ),
+                    ty,
+                )
+            }
+            Expr::Call(call) => match &*call.callee {
+                Expr::Variable(callee) => self.call(&callee.name, &call.arguments)?,
+                _ => return Err(unsupported("calls to computed functions")),
+            },
+            // `x.f(a)` is `f(x, a)`
+            Expr::MethodCall(method_call) => {
+                if method_call.optional {
+                    return Err(unsupported("optional method calls"));
+                }
+                let mut arguments = vec![(*method_call.object).clone()];
+                arguments.extend(method_call.arguments.iter().cloned());
+                self.call(&method_call.method, &arguments)?
+            }
+            Expr::MemberAccess(member) => {
+                if member.optional {
+                    return Err(unsupported("optional member access"));
+                }
+                let (object, ty) = self.expr(&member.object)?;
+                let field_ty = match &ty {
+                    Ty::Named(name) => self.structs.get(name).and_then(|fields| {
+                        fields
+                            .iter()
+                            .find(|(field, _)| *field == member.member)
+                            .map(|(_, ty)| ty.clone())
+                    }),
+                    _ => None,
+                };
+                (
+                    format!("{}.{}", object, identifier(&member.member)),
+                    field_ty.unwrap_or(Ty::Unknown),
+                )
+            }
+            Expr::EnumVariant(variant) => (
+                format!("{}::{}", variant.enum_name, variant.variant),
+                Ty::Named(variant.enum_name.clone()),
+            ),
+            Expr::TensorLiteral(tensor) => {
+                let elements = tensor
+                    .elements
+                    .iter()
+                    .map(|element| self.float_element(element))
+                    .collect::<Result<Vec<String>, String>>()?;
+                let shape: Vec<String> = tensor.shape.iter().map(usize::to_string).collect();
+                (
+                    format!(
+                        "Tensor::new(vec![{}], vec![{}])",
+                        elements.join(", "),
+                        shape.join(", ")
+                    ),
+                    Ty::Tensor,
+                )
+            }
+            Expr::Match(match_node) => {
+                let (subject, ty) = self.expr(&match_node.subject)?;
+                let mut value_ty = Ty::Unknown;
+                let mut arms = Vec::new();
+                for arm in &match_node.arms {
+                    let (value, ty) = self.owned(&arm.value)?;
+                    if value_ty == Ty::Unknown {
+                        value_ty = ty;
+                    }
+                    arms.push((arm.pattern.clone(), strip_parens(value)));
+                }
+                (self.match_text(subject, &ty, arms, self.indent)?, value_ty)
+            }
+            Expr::Range(_) => return Err(unsupported("ranges outside a for loop")),
+            Expr::Index(_) => return Err(unsupported("indexing")),
+            Expr::Lambda(_) => return Err(unsupported("closures")),
+            Expr::Spawn(_) => return Err(unsupported("spawn")),
+            Expr::Cfg(cfg) => {
+                return Err(unsupported(&format!(
+                    "the unsubstituted setting @cfg({:?})",
+                    cfg.key
+                )))
+            }
+        })
+    }
+
+    fn float_element(&mut self, element: &Expr) -> Result<String, String> {
+        if let Expr::Number(number) = element {
+            if number.data_type == DataType::Integer {
+                return Ok(format!("{}.0", number.value));
+            }
+        }
+        if let Expr::Unary(unary) = element {
+            if let (UnaryOperator::Negate, Expr::Number(_)) = (unary.operator, &*unary.operand) {
+                return Ok(format!("-{}", self.float_element(&unary.operand)?));
+            }
+        }
+        let (text, ty) = self.expr(element)?;
+        Ok(match ty {
+            Ty::Float => text,
+            _ => format!("({} as f64)", text),
+        })
+    }
+
+    fn binary(&mut self, binary: &BinaryOperationNode) -> Result<(String, Ty), String> {
+        use BinaryOperator::*;
+
+        let (left, left_ty) = self.expr(&binary.left)?;
+        let (right, right_ty) = self.expr(&binary.right)?;
+        let symbol = crate::formatter::binary_symbol(binary.operator);
+
+        if left_ty == Ty::Tensor || right_ty == Ty::Tensor {
+            return match binary.operator {
+                // The crate implements the operators on references
+                Add | Subtract | Multiply => {
+                    Ok((format!("(&{} {} &{})", left, symbol, right), Ty::Tensor))
+                }
+                MatMul => Ok((
+                    format!(
+                        "{}.matmul(&{}).expect(\"matmul shapes do not match\")",
+                        left, right
+                    ),
+                    Ty::Tensor,
+                )),
+                _ => Err(unsupported(&format!("'{}' on tensors", symbol))),
+            };
+        }
+        if binary.operator == MatMul {
+            return Err(unsupported("'@' on anything but tensors"));
+        }
+        if binary.operator == Add && (left_ty == Ty::Str || right_ty == Ty::Str) {
+            return Ok((
+                format!("format!(\"{{}}{{}}\", {}, {})", left, right),
+                Ty::Str,
+            ));
+        }
+
+        let (left, right, ty) = unify_numbers(left, left_ty, right, right_ty);
+        let ty = match binary.operator {
+            Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual | And | Or => Ty::Bool,
+            _ => ty,
+        };
+        Ok((format!("({} {} {})", left, symbol, right), ty))
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expr]) -> Result<(String, Ty), String> {
+        // A struct's constructor takes its fields in declaration order
+        if let Some(fields) = self.structs.get(name).cloned() {
+            let fields = fields
+                .iter()
+                .zip(arguments)
+                .map(|((field, field_ty), argument)| {
+                    let (value, ty) = self.owned(argument)?;
+                    let (value, _) = converted(value, ty, Some(field_ty));
+                    Ok(format!("{}: {}", identifier(field), value))
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            return Ok((
+                format!("{} {{ {} }}", name, fields.join(", ")),
+                Ty::Named(name.to_string()),
+            ));
+        }
+        if let Some((parameters, return_type)) = self.functions.get(name).cloned() {
+            let arguments = arguments
+                .iter()
+                .zip(&parameters)
+                .map(|(argument, parameter)| {
+                    let (value, ty) = self.owned(argument)?;
+                    Ok(converted(value, ty, Some(parameter)).0)
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            return Ok((
+                format!("{}({})", identifier(name), arguments.join(", ")),
+                return_type,
+            ));
+        }
+
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.expr(argument)?);
+        }
+        let float = |(text, ty): &(String, Ty)| match ty {
+            Ty::Float => text.clone(),
+            _ => format!("({} as f64)", text),
+        };
+        Ok(match (name, values.as_slice()) {
+            ("print", _) => (self.print(arguments)?, Ty::Unit),
+            ("sin" | "cos" | "sqrt" | "floor" | "ceil", [x]) => {
+                (format!("{}.{}()", float(x), name), Ty::Float)
+            }
+            ("pow", [base, exponent]) => (
+                format!("{}.powf({})", float(base), float(exponent)),
+                Ty::Float,
+            ),
+            ("abs", [(x, ty)]) => (format!("{}.abs()", x), ty.clone()),
+            ("len", [(x, Ty::Str)]) => (format!("({}.chars().count() as i64)", x), Ty::Int),
+            ("zeros", dimensions) if !dimensions.is_empty() => {
+                let dimensions: Vec<String> = dimensions
+                    .iter()
+                    .map(|(size, _)| {
+                        format!(
+                            "usize::try_from({}).expect(\"tensor dimensions can't be negative\")",
+                            size
+                        )
+                    })
+                    .collect();
+                (
+                    format!("Tensor::zeros(vec![{}])", dimensions.join(", ")),
+                    Ty::Tensor,
+                )
+            }
+            _ => return Err(unsupported(&format!("a call to '{}'", name))),
+        })
+    }
+}
+
+fn body_statements(stmt: &Stmt) -> &[Stmt] {
+    match stmt {
+        Stmt::Block(block) => &block.statements,
+        other => std::slice::from_ref(other),
+    }
+}
+
+// `text`, of type `ty`, as a value of type `to` where that is known: an int
+// widened where a float is expected, and a value wrapped in `Some` where
+// an optional is
+fn converted(text: String, ty: Ty, to: Option<&Ty>) -> (String, Ty) {
+    match to {
+        Some(Ty::Optional(inner)) if !matches!(ty, Ty::Optional(_)) => {
+            let (text, _) = converted(text, ty.clone(), Some(inner));
+            (format!("Some({})", strip_parens(text)), ty)
+        }
+        Some(Ty::Float) if ty == Ty::Int => (format!("({} as f64)", text), Ty::Float),
+        _ => (strip_parens(text), ty),
+    }
+}
+
+// Mixed integer and float arithmetic is carried out in floats, as the
+// interpreter does
+fn unify_numbers(left: String, left_ty: Ty, right: String, right_ty: Ty) -> (String, String, Ty) {
+    match (&left_ty, &right_ty) {
+        (Ty::Int, Ty::Float) => (format!("({} as f64)", left), right, Ty::Float),
+        (Ty::Float, Ty::Int) => (left, format!("({} as f64)", right), Ty::Float),
+        (Ty::Unknown, _) => (left, right, right_ty),
+        _ => (left, right, left_ty),
+    }
+}
+
+// Drops one pair of parentheses wrapping the whole of `text`
+fn strip_parens(text: String) -> String {
+    let inner = match text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) => inner,
+        None => return text,
+    };
+    // `(a) + (b)` starts and ends with parentheses that don't pair up
+    let mut depth = 0;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return text,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    inner.to_string()
+}
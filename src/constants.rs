@@ -0,0 +1,236 @@
+//! Values known before a program runs, and the arithmetic and casts on
+//! them. The optimizer folds constant subtrees with these, and the type
+//! checker works out `const` values with them to reject operations that
+//! are bound to fail.
+
+use crate::ast::*;
+use crate::formatter::format_type;
+
+/// A value known before the program runs, from literals and constants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+}
+
+impl Constant {
+    // Negative numbers are written `-n`, so they appear as a negated literal
+    pub(crate) fn from_expr(expr: &Expr) -> Option<Constant> {
+        match expr {
+            Expr::Number(number) => match number.data_type {
+                DataType::Integer => number.value.parse().ok().map(Constant::Integer),
+                _ => number.value.parse().ok().map(Constant::Float),
+            },
+            Expr::Boolean(boolean) => Some(Constant::Boolean(boolean.value)),
+            Expr::String(string) => Some(Constant::String(string.value.clone())),
+            Expr::Unary(unary) if unary.operator == UnaryOperator::Negate => {
+                match (&*unary.operand, Constant::from_expr(&unary.operand)?) {
+                    (Expr::Number(_), Constant::Integer(value)) => {
+                        value.checked_neg().map(Constant::Integer)
+                    }
+                    (Expr::Number(_), Constant::Float(value)) => Some(Constant::Float(-value)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn into_expr(self) -> Option<Expr> {
+        let number = |text: String, data_type: DataType, negative: bool| {
+            let literal = Expr::Number(NumberNode::new(text, data_type));
+            if negative {
+                Expr::Unary(UnaryOperationNode::new(UnaryOperator::Negate, literal))
+            } else {
+                literal
+            }
+        };
+        match self {
+            Constant::Integer(value) => Some(number(
+                value.unsigned_abs().to_string(),
+                DataType::Integer,
+                value < 0,
+            )),
+            Constant::Float(value) if value.is_finite() => {
+                // Always keep a decimal point so the literal reads back as a float
+                let mut text = value.abs().to_string();
+                if !text.contains('.') {
+                    text.push_str(".0");
+                }
+                Some(number(text, DataType::Float, value.is_sign_negative()))
+            }
+            Constant::Float(_) => None,
+            Constant::Boolean(value) => Some(Expr::Boolean(BooleanNode::new(value))),
+            Constant::String(value) => Some(Expr::String(StringNode::new(value))),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Constant::Integer(_) => "int",
+            Constant::Float(_) => "float",
+            Constant::Boolean(_) => "bool",
+            Constant::String(_) => "string",
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Constant::Integer(value) => Some(*value as f64),
+            Constant::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+// Written as the interpreter prints the value
+impl std::fmt::Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Constant::Integer(value) => write!(f, "{}", value),
+            // `{:?}` keeps the ".0" on whole floats, so 2.0 doesn't print as 2
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::Boolean(value) => write!(f, "{}", value),
+            Constant::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+pub(crate) fn fold_unary(operator: UnaryOperator, operand: Constant) -> Option<Constant> {
+    match (operator, operand) {
+        (UnaryOperator::Negate, Constant::Integer(value)) => {
+            value.checked_neg().map(Constant::Integer)
+        }
+        (UnaryOperator::Negate, Constant::Float(value)) => Some(Constant::Float(-value)),
+        (UnaryOperator::Not, Constant::Boolean(value)) => Some(Constant::Boolean(!value)),
+        _ => None,
+    }
+}
+
+pub(crate) fn fold_binary(
+    left: &Constant,
+    operator: BinaryOperator,
+    right: &Constant,
+) -> Option<Constant> {
+    use BinaryOperator::*;
+
+    match (left, right) {
+        (Constant::Integer(a), Constant::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            let value = match operator {
+                Add => a.checked_add(b)?,
+                Subtract => a.checked_sub(b)?,
+                Multiply => a.checked_mul(b)?,
+                // `checked_div` is None for a zero divisor as well as overflow
+                Divide => a.checked_div(b)?,
+                BitAnd => a & b,
+                BitOr => a | b,
+                BitXor => a ^ b,
+                ShiftLeft => a.checked_shl(u32::try_from(b).ok()?)?,
+                ShiftRight => a.checked_shr(u32::try_from(b).ok()?)?,
+                _ => return compare(operator, a.partial_cmp(&b)?).map(Constant::Boolean),
+            };
+            Some(Constant::Integer(value))
+        }
+        (Constant::Boolean(a), Constant::Boolean(b)) => {
+            let value = match operator {
+                And => *a && *b,
+                Or => *a || *b,
+                Equal => a == b,
+                NotEqual => a != b,
+                _ => return None,
+            };
+            Some(Constant::Boolean(value))
+        }
+        (Constant::String(a), Constant::String(b)) => match operator {
+            Add => Some(Constant::String(format!("{}{}", a, b))),
+            Equal => Some(Constant::Boolean(a == b)),
+            NotEqual => Some(Constant::Boolean(a != b)),
+            _ => None,
+        },
+        // Mixed integer and float arithmetic is carried out in floats
+        _ => {
+            let (a, b) = (left.as_float()?, right.as_float()?);
+            let value = match operator {
+                Add => a + b,
+                Subtract => a - b,
+                Multiply => a * b,
+                Divide if b == 0.0 => return None,
+                Divide => a / b,
+                _ => return compare(operator, a.partial_cmp(&b)?).map(Constant::Boolean),
+            };
+            value.is_finite().then_some(Constant::Float(value))
+        }
+    }
+}
+
+fn compare(operator: BinaryOperator, ordering: std::cmp::Ordering) -> Option<bool> {
+    use std::cmp::Ordering::*;
+
+    Some(match operator {
+        BinaryOperator::Equal => ordering == Equal,
+        BinaryOperator::NotEqual => ordering != Equal,
+        BinaryOperator::Less => ordering == Less,
+        BinaryOperator::LessEqual => ordering != Greater,
+        BinaryOperator::Greater => ordering == Greater,
+        BinaryOperator::GreaterEqual => ordering != Less,
+        _ => return None,
+    })
+}
+
+/// `constant as data_type`, following the interpreter's casts: floats
+/// truncate toward zero on the way to ints, and strings are parsed as
+/// numbers. Gives `None` for a cast that succeeds but has no constant form,
+/// like an int to a char, and the interpreter's error for one that fails.
+pub(crate) fn cast(constant: Constant, data_type: &DataType) -> Result<Option<Constant>, String> {
+    let failed = |constant: &Constant| {
+        let shown = match constant {
+            Constant::String(text) => format!("{:?}", text),
+            other => other.to_string(),
+        };
+        format!("Cannot cast {} to {}", shown, format_type(data_type))
+    };
+    Ok(Some(match (constant, data_type) {
+        (constant, DataType::Optional(inner)) => return cast(constant, inner),
+        (constant, DataType::Parameter(_)) => constant,
+        (constant @ Constant::Integer(_), DataType::Integer)
+        | (constant @ Constant::Float(_), DataType::Float)
+        | (constant @ Constant::Boolean(_), DataType::Boolean)
+        | (constant @ Constant::String(_), DataType::String) => constant,
+        (Constant::Integer(value), DataType::Float) => Constant::Float(value as f64),
+        (Constant::Float(value), DataType::Integer) => {
+            // From -2^63 up to but not including 2^63, which is i64::MAX
+            // rounded up
+            let range = i64::MIN as f64..-(i64::MIN as f64);
+            if !range.contains(&value.trunc()) {
+                return Err(failed(&Constant::Float(value)));
+            }
+            Constant::Integer(value.trunc() as i64)
+        }
+        (Constant::Boolean(value), DataType::Integer) => Constant::Integer(value as i64),
+        (Constant::Integer(value), DataType::Char) => {
+            return match u32::try_from(value).ok().and_then(char::from_u32) {
+                Some(_) => Ok(None),
+                None => Err(failed(&Constant::Integer(value))),
+            }
+        }
+        (Constant::String(text), DataType::Integer) => match text.trim().parse() {
+            Ok(value) => Constant::Integer(value),
+            Err(_) => return Err(failed(&Constant::String(text))),
+        },
+        (Constant::String(text), DataType::Float) => match text.trim().parse() {
+            Ok(value) => Constant::Float(value),
+            Err(_) => return Err(failed(&Constant::String(text))),
+        },
+        (constant, DataType::String) => Constant::String(constant.to_string()),
+        (constant, _) => {
+            return Err(format!(
+                "Cannot cast {} to {}",
+                constant.type_name(),
+                format_type(data_type)
+            ))
+        }
+    }))
+}
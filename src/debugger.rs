@@ -0,0 +1,221 @@
+//! Pausing a running script to look inside it. An interpreter with a
+//! `Debugger` attached stops at every `breakpoint;` statement, and before
+//! statements on the lines the debugger asks for, and hands it a `Pause`
+//! saying where execution is and what is in scope.
+
+use crate::interpreter::{StackFrame, Value};
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseReason {
+    /// A `breakpoint;` statement
+    Breakpoint,
+    /// A statement on a line the debugger breaks at
+    Line,
+    /// The next statement after a `step` or `next`
+    Step,
+}
+
+/// Where execution stopped and what it could see there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pause {
+    pub reason: PauseReason,
+    /// The line of the statement about to run, if it has one
+    pub line: Option<usize>,
+    /// The variables in scope, sorted by name. Shadowed bindings and the
+    /// builtins are left out.
+    pub variables: Vec<(String, Value)>,
+    /// The function calls in progress, outermost first
+    pub backtrace: Vec<StackFrame>,
+}
+
+pub trait Debugger {
+    /// Whether to stop before the statements on `line`, reached `depth`
+    /// function calls deep, and if so why.
+    fn breaks_at(&mut self, line: usize, depth: usize) -> Option<PauseReason>;
+
+    /// Called while execution is stopped; it resumes when this returns.
+    fn pause(&mut self, pause: &Pause);
+}
+
+const HELP: &str = "Commands:
+  step (s)            run to the next statement, entering calls
+  next (n)            run to the next statement in this function
+  continue (c)        run to the next breakpoint
+  print (p) <name>    show a variable
+  variables (v)       show every variable in scope
+  backtrace (bt)      show the calls in progress
+An empty line repeats the last of step, next and continue.";
+
+// How far to run before stopping again
+#[derive(Debug, Clone, Copy)]
+enum Resume {
+    Continue,
+    Step,
+    // Stop at a statement no deeper than this many calls
+    Next(usize),
+}
+
+/// The debugger behind `put debug`: an interactive prompt that reads
+/// commands from `input` and answers on `output`.
+pub struct ConsoleDebugger<R, W> {
+    input: R,
+    output: W,
+    lines: HashSet<usize>,
+    source: Vec<String>,
+    resume: Resume,
+    // Once input runs out the script runs to the end undisturbed
+    detached: bool,
+}
+
+impl<R: BufRead, W: Write> ConsoleDebugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        ConsoleDebugger {
+            input,
+            output,
+            lines: HashSet::new(),
+            source: Vec::new(),
+            resume: Resume::Continue,
+            detached: false,
+        }
+    }
+
+    pub fn break_at_line(mut self, line: usize) -> Self {
+        self.lines.insert(line);
+        self
+    }
+
+    /// Shows the source line at each pause.
+    pub fn show_source(mut self, source: &str) -> Self {
+        self.source = source.lines().map(str::to_string).collect();
+        self
+    }
+
+    // Answers one command, returning whether execution should resume. The
+    // console is best effort, so write errors are ignored
+    fn command(&mut self, command: &str, pause: &Pause) -> bool {
+        let (word, argument) = match command.split_once(' ') {
+            Some((word, argument)) => (word, argument.trim()),
+            None => (command, ""),
+        };
+        match word {
+            "" => {
+                if let Resume::Next(_) = self.resume {
+                    self.resume = Resume::Next(pause.backtrace.len());
+                }
+                return true;
+            }
+            "c" | "continue" => self.resume = Resume::Continue,
+            "s" | "step" => self.resume = Resume::Step,
+            "n" | "next" => self.resume = Resume::Next(pause.backtrace.len()),
+            "p" | "print" if argument.is_empty() => {
+                let _ = writeln!(self.output, "Usage: print <name>");
+                return false;
+            }
+            "p" | "print" => {
+                match pause.variables.iter().find(|(name, _)| name == argument) {
+                    Some((name, value)) => {
+                        let _ = writeln!(self.output, "{} = {}", name, describe(value));
+                    }
+                    None => {
+                        let _ = writeln!(self.output, "No variable named '{}' here", argument);
+                    }
+                }
+                return false;
+            }
+            "v" | "variables" => {
+                for (name, value) in &pause.variables {
+                    let _ = writeln!(self.output, "{} = {}", name, describe(value));
+                }
+                return false;
+            }
+            "bt" | "backtrace" => {
+                let mut line = pause.line;
+                for (i, frame) in pause.backtrace.iter().rev().enumerate() {
+                    let _ = writeln!(self.output, "#{} {}(){}", i, frame.function, at(line));
+                    line = frame.span.map(|span| span.line);
+                }
+                let _ = writeln!(
+                    self.output,
+                    "#{} <script>{}",
+                    pause.backtrace.len(),
+                    at(line)
+                );
+                return false;
+            }
+            "h" | "help" => {
+                let _ = writeln!(self.output, "{}", HELP);
+                return false;
+            }
+            other => {
+                let _ = writeln!(
+                    self.output,
+                    "Unknown command '{}'; type help for the list",
+                    other
+                );
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for ConsoleDebugger<R, W> {
+    fn breaks_at(&mut self, line: usize, depth: usize) -> Option<PauseReason> {
+        if self.detached {
+            return None;
+        }
+        if self.lines.contains(&line) {
+            return Some(PauseReason::Line);
+        }
+        match self.resume {
+            Resume::Step => Some(PauseReason::Step),
+            Resume::Next(limit) if depth <= limit => Some(PauseReason::Step),
+            _ => None,
+        }
+    }
+
+    fn pause(&mut self, pause: &Pause) {
+        if self.detached {
+            return;
+        }
+        let heading = match pause.reason {
+            PauseReason::Breakpoint => "Paused at breakpoint",
+            PauseReason::Line => "Paused at line breakpoint",
+            PauseReason::Step => "Stepped",
+        };
+        let _ = writeln!(self.output, "{}{}", heading, at(pause.line));
+        if let Some(text) = pause.line.and_then(|line| self.source.get(line - 1)) {
+            let _ = writeln!(self.output, "{} | {}", pause.line.unwrap_or(0), text);
+        }
+        loop {
+            let _ = write!(self.output, "(put) ");
+            let _ = self.output.flush();
+            let mut command = String::new();
+            if !matches!(self.input.read_line(&mut command), Ok(read) if read > 0) {
+                self.detached = true;
+                return;
+            }
+            if self.command(command.trim(), pause) {
+                return;
+            }
+        }
+    }
+}
+
+fn at(line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!(" at line {}", line),
+        None => String::new(),
+    }
+}
+
+// A value as it would be written in source, so strings are quoted
+pub(crate) fn describe(value: &Value) -> String {
+    match value {
+        Value::String(text) => format!("{:?}", text),
+        Value::Char(c) => format!("{:?}", c),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,52 @@
+//! Lowering of surface syntax into a smaller core, so that later passes
+//! have fewer forms to handle:
+//!
+//! - `(e)` becomes `e`; grouping is already captured by the tree's shape
+//! - `target op= value` becomes `target = target op value`
+//! - `for (init; cond; step) body` becomes a block with a `while` loop
+//!
+//! A compound assignment's target is duplicated, so any side effects in it
+//! (a call inside an index, say) run twice after lowering.
+
+use crate::ast::*;
+use crate::visitor::{walk_expr_mut, walk_stmt_mut, MutVisitor};
+
+/// Lowers every construct of `program` in place.
+pub fn desugar(program: &mut ProgramNode) {
+    Desugarer.visit_program_mut(program);
+}
+
+struct Desugarer;
+
+impl MutVisitor for Desugarer {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+        if let Stmt::For(_) = stmt {
+            let placeholder = Stmt::Block(BlockNode::new(Vec::new()));
+            if let Stmt::For(for_node) = std::mem::replace(stmt, placeholder) {
+                *stmt = Stmt::Block(for_node.into_while());
+            }
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        *expr = match std::mem::replace(expr, Expr::Null(NullNode::default())) {
+            Expr::Parenthesis(paren) => *paren.expression,
+            Expr::CompoundAssignment(assignment) => {
+                let target = *assignment.target;
+                // Errors in either half point back at the `+=`
+                let mut value = BinaryOperationNode::new(
+                    target.clone(),
+                    assignment.operator,
+                    *assignment.value,
+                );
+                value.span = assignment.span;
+                let mut lowered = AssignmentNode::new(target, Expr::Binary(value));
+                lowered.span = assignment.span;
+                Expr::Assignment(lowered)
+            }
+            other => other,
+        };
+    }
+}
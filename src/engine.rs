@@ -0,0 +1,244 @@
+//! The API for running PUT from other Rust programs. An `Engine` takes
+//! source text through the same steps as `put run` and keeps its globals
+//! from one `eval` to the next, so a host can define functions in one
+//! script and call them from another:
+//!
+//! ```
+//! use put_lang::{Engine, Value};
+//!
+//! let mut engine = Engine::new();
+//! engine.eval("fn square(n: int) -> int { return n * n; }").unwrap();
+//! assert_eq!(engine.eval("square(7);").unwrap(), Value::Integer(49));
+//! ```
+
+use crate::ast::ProgramNode;
+use crate::desugar::desugar;
+use crate::host::HostFunction;
+use crate::interpreter::{CancelHandle, Interpreter, Limits, RuntimeError, Value};
+use crate::lexer::Lexer;
+use crate::optimizer::{fold_constants, fold_typed};
+use crate::parser::{ParseError, Parser};
+use crate::preprocessor::substitute_cfg;
+use crate::symbol_table::{Signature, Symbol};
+use crate::type_checker::{self, TypeChecker, TypeError};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Why `Engine::eval` failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    Parse(ParseError),
+    /// An `@cfg` naming a setting the engine doesn't have
+    Config(String),
+    /// Every error the type checker found
+    Type(Vec<TypeError>),
+    Runtime(RuntimeError),
+}
+
+impl EngineError {
+    /// The error with the source line it points at, for showing to users.
+    pub fn render(&self, source: &str) -> String {
+        match self {
+            EngineError::Runtime(error) => error.render(source),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Parse(error) => error.fmt(f),
+            EngineError::Config(message) => f.write_str(message),
+            EngineError::Type(errors) => f.write_str(&type_checker::report(errors)),
+            EngineError::Runtime(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<ParseError> for EngineError {
+    fn from(error: ParseError) -> Self {
+        EngineError::Parse(error)
+    }
+}
+
+impl From<RuntimeError> for EngineError {
+    fn from(error: RuntimeError) -> Self {
+        EngineError::Runtime(error)
+    }
+}
+
+pub struct Engine {
+    interpreter: Interpreter,
+    // What each `@cfg("name")` stands for
+    settings: HashMap<String, String>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// An engine whose scripts print to standard output and cannot touch
+    /// files.
+    pub fn new() -> Self {
+        Self::from_interpreter(Interpreter::new())
+    }
+
+    /// An engine whose scripts print to `output`.
+    pub fn with_output(output: impl Write + Send + 'static) -> Self {
+        Self::from_interpreter(Interpreter::with_output(output))
+    }
+
+    /// An engine for untrusted scripts: they cannot touch files, what
+    /// they print is thrown away, and each `eval` is held to `limits`.
+    pub fn sandboxed(limits: Limits) -> Self {
+        Self::with_output(io::sink()).with_limits(limits)
+    }
+
+    /// Wraps an interpreter set up by hand, e.g. with a debugger attached.
+    pub fn from_interpreter(interpreter: Interpreter) -> Self {
+        Engine {
+            interpreter,
+            settings: HashMap::new(),
+        }
+    }
+
+    /// Lets scripts use the file builtins. Off by default.
+    pub fn allow_file_access(mut self, allowed: bool) -> Self {
+        self.interpreter = self.interpreter.allow_file_access(allowed);
+        self
+    }
+
+    /// Holds each `eval` to `limits`, failing it with a runtime error once
+    /// the script goes past them.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.interpreter = self.interpreter.limit(limits);
+        self
+    }
+
+    /// How deeply calls to PUT functions may nest; see
+    /// `Interpreter::max_call_depth`.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.interpreter = self.interpreter.max_call_depth(depth);
+        self
+    }
+
+    /// Gives `@cfg("name")` the value `value` in the scripts evaluated
+    /// from now on, as a setting in project.zom would.
+    pub fn set_cfg(mut self, name: &str, value: &str) -> Self {
+        self.settings.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Runs `source` and returns the value of its last statement if that
+    /// is an expression statement, or null otherwise. Globals it defines
+    /// stay defined for later calls. It is type checked first, knowing
+    /// the globals earlier scripts and the host defined; the checker's
+    /// warnings are not reported.
+    pub fn eval(&mut self, source: &str) -> Result<Value, EngineError> {
+        let mut program = Parser::new(Lexer::new(source)).parse()?;
+        substitute_cfg(&mut program, &self.settings).map_err(EngineError::Config)?;
+        desugar(&mut program);
+        self.check(&mut program)?;
+        fold_constants(&mut program);
+        fold_typed(&mut program);
+        self.run(&program)
+    }
+
+    // Type checks `program`, taking the globals already defined as
+    // declared around it. Only functions keep their types; a variable
+    // may hold a value of any type
+    fn check(&self, program: &mut ProgramNode) -> Result<(), EngineError> {
+        let mut checker = TypeChecker::new();
+        for (name, arity) in self.interpreter.builtins() {
+            checker.add_builtin(&name, arity);
+        }
+        for (name, value) in self.interpreter.globals() {
+            let symbol = match &value {
+                Value::Function(function) => Symbol::function(Signature::of(&function.declaration)),
+                _ if self.is_constant(&name) => Symbol::constant(None),
+                _ => Symbol::variable(None),
+            };
+            checker.predefine(&name, symbol);
+        }
+        checker
+            .check(program)
+            .map(|_| ())
+            .map_err(EngineError::Type)
+    }
+
+    /// Runs a program the host has parsed and lowered itself, returning
+    /// what `eval` would.
+    pub fn run(&mut self, program: &ProgramNode) -> Result<Value, EngineError> {
+        Ok(self.interpreter.evaluate_program(program)?)
+    }
+
+    /// A handle another thread can use to stop the `eval` in progress,
+    /// which then fails with a "Script cancelled" runtime error:
+    ///
+    /// ```
+    /// # use put_lang::Engine;
+    /// let mut engine = Engine::new();
+    /// let cancel = engine.cancel_handle();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(std::time::Duration::from_millis(50));
+    ///     cancel.cancel();
+    /// });
+    /// let error = engine.eval("while (true) {}").unwrap_err();
+    /// assert_eq!(error.to_string(), "Runtime error: Script cancelled");
+    /// ```
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.interpreter.cancel_handle()
+    }
+
+    /// The value a script left in the global `name`, if it defined one:
+    ///
+    /// ```
+    /// # use put_lang::{Engine, Value};
+    /// let mut engine = Engine::new();
+    /// engine.eval("var total = 0; for (var i = 1; i <= 4; i += 1) { total += i; }").unwrap();
+    /// assert_eq!(engine.get("total"), Some(Value::Integer(10)));
+    /// ```
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.interpreter.global(name)
+    }
+
+    /// Every global the scripts so far defined, with its value, sorted by
+    /// name. Builtins, including those from `register_fn`, are left out.
+    pub fn globals(&self) -> impl Iterator<Item = (String, Value)> {
+        self.interpreter.globals()
+    }
+
+    /// Whether the global `name` was declared with `const`.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.interpreter.is_global_constant(name)
+    }
+
+    /// Makes `function` callable from scripts as `name`, replacing any
+    /// global of that name. Arguments are converted with `FromValue` and
+    /// the result with `IntoValue`; a wrong argument, or an `Err` returned
+    /// from `function`, is a runtime error in the script:
+    ///
+    /// ```
+    /// # use put_lang::{Engine, Value};
+    /// let mut engine = Engine::new();
+    /// engine.register_fn("shout", |text: String| text.to_uppercase() + "!");
+    /// let result = engine.eval("shout(\"hi\");").unwrap();
+    /// assert_eq!(result, Value::String("HI!".into()));
+    /// ```
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl HostFunction<Args>) {
+        self.interpreter
+            .register_builtin(function.into_native(name));
+    }
+
+    /// The interpreter underneath, for anything the engine does not cover.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
@@ -0,0 +1,135 @@
+//! Lexical scopes for the interpreter. Each block, loop and function call
+//! gets its own `Environment` whose parent is the scope it appears in, so
+//! lookups and assignments walk outwards until they find the name.
+
+use crate::interpreter::{RuntimeError, RuntimeResult, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A scope shared by everything that can see it: the code running in it,
+/// the closures created there and the threads spawned from it.
+#[derive(Clone, Default)]
+pub struct Scope(Arc<Mutex<Environment>>);
+
+impl Scope {
+    pub fn new(environment: Environment) -> Self {
+        Scope(Arc::new(Mutex::new(environment)))
+    }
+
+    /// The environment, once no other thread is using it. Every update
+    /// leaves it consistent, so a thread that panicked while holding it
+    /// does not make it unusable.
+    pub fn lock(&self) -> MutexGuard<'_, Environment> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    // Names bound by `const` in this scope, which may never be rebound
+    constants: HashSet<String>,
+    parent: Option<Scope>,
+}
+
+impl Environment {
+    /// An outermost scope, with no parent.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A scope nested inside `parent`.
+    pub fn with_parent(parent: Scope) -> Self {
+        Environment {
+            parent: Some(parent),
+            ..Self::default()
+        }
+    }
+
+    /// Binds `name` in this scope, shadowing any binding of it further out.
+    /// Redeclaring a name within one scope replaces it, unless it is a
+    /// constant.
+    pub fn define(&mut self, name: &str, value: Value) -> RuntimeResult<()> {
+        if self.constants.contains(name) {
+            return Err(RuntimeError::new(format!(
+                "Cannot redeclare constant '{}'",
+                name
+            )));
+        }
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    pub fn define_constant(&mut self, name: &str, value: Value) -> RuntimeResult<()> {
+        self.define(name, value)?;
+        self.constants.insert(name.to_string());
+        Ok(())
+    }
+
+    /// The value of the innermost binding of `name`.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.lock().get(name),
+        }
+    }
+
+    /// The value bound to `name` in this scope itself.
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    /// Whether `name` was bound by `const` in this scope itself.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains(name)
+    }
+
+    /// Every name visible from this scope, with the value of its innermost
+    /// binding, in no particular order.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings = match &self.parent {
+            Some(parent) => parent.lock().bindings(),
+            None => Vec::new(),
+        };
+        bindings.retain(|(name, _)| !self.values.contains_key(name));
+        bindings.extend(
+            self.values
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        bindings
+    }
+
+    /// Rebinds the innermost existing binding of `name`, in whichever
+    /// scope that is.
+    pub fn assign(&mut self, name: &str, value: Value) -> RuntimeResult<()> {
+        self.update(name, |slot| {
+            *slot = value;
+            Ok(())
+        })
+    }
+
+    /// Changes the innermost binding of `name` in place, under the same
+    /// rules as `assign`. Changing a value in place rather than assigning
+    /// a changed copy lets `Arc::make_mut` skip the copy when nothing else
+    /// holds the value.
+    pub fn update<T>(
+        &mut self,
+        name: &str,
+        change: impl FnOnce(&mut Value) -> RuntimeResult<T>,
+    ) -> RuntimeResult<T> {
+        if let Some(slot) = self.values.get_mut(name) {
+            if self.constants.contains(name) {
+                return Err(RuntimeError::new(format!(
+                    "Cannot assign to constant '{}'",
+                    name
+                )));
+            }
+            return change(slot);
+        }
+        match &self.parent {
+            Some(parent) => parent.lock().update(name, change),
+            None => Err(RuntimeError::new(format!("Undefined variable '{}'", name))),
+        }
+    }
+}
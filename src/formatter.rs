@@ -0,0 +1,719 @@
+//! Pretty-printer that turns a syntax tree back into canonical PUT source:
+//! four-space indentation, one statement per line, single spaces around
+//! binary operators, and argument lists wrapped one per line once a call
+//! grows past `MAX_WIDTH` columns. Formatting parsed source and parsing the
+//! result yields the same tree.
+
+use crate::ast::*;
+use crate::parser::{
+    binary_precedence, ASSIGNMENT_PRECEDENCE, CAST_PRECEDENCE, CONDITIONAL_PRECEDENCE,
+    POSTFIX_PRECEDENCE, PREFIX_PRECEDENCE, RANGE_PRECEDENCE,
+};
+
+const INDENT: &str = "    ";
+const MAX_WIDTH: usize = 100;
+
+// Binds tighter than any operator: literals, variables, parenthesized and
+// bracketed forms
+const PRIMARY_PRECEDENCE: u8 = POSTFIX_PRECEDENCE + 1;
+
+pub fn format_program(program: &ProgramNode) -> String {
+    format_statements(&program.statements, 0, true)
+}
+
+/// Formats a single statement as it would appear at the top level.
+pub fn format_statement(stmt: &Stmt) -> String {
+    format_stmt(stmt, 0)
+}
+
+/// Formats a single expression as it would appear at the top level.
+pub fn format_expr(expr: &Expr) -> String {
+    expr_text(expr, 0)
+}
+
+// Top-level items get a blank line around them, and imports are kept
+// together in a group of their own
+fn needs_blank_line(previous: &Stmt, next: &Stmt) -> bool {
+    let is_item = |stmt: &Stmt| {
+        matches!(
+            stmt,
+            Stmt::FunctionDeclaration(_) | Stmt::StructDeclaration(_) | Stmt::EnumDeclaration(_)
+        )
+    };
+    let is_import = |stmt: &Stmt| matches!(stmt, Stmt::Import(_));
+    is_item(previous) || is_item(next) || (is_import(previous) && !is_import(next))
+}
+
+fn pad(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+// One statement per line, each with its comments. At the top level blank
+// lines separate items.
+fn format_statements(statements: &[Stmt], indent: usize, top_level: bool) -> String {
+    let mut output = String::new();
+    let mut previous: Option<&Stmt> = None;
+    for stmt in statements {
+        if top_level && previous.is_some_and(|previous| needs_blank_line(previous, stmt)) {
+            output.push('\n');
+        }
+        output.push_str(&pad(indent));
+        output.push_str(&format_stmt(stmt, indent));
+        output.push('\n');
+        previous = Some(stmt);
+    }
+    output
+}
+
+fn format_block(statements: &[Stmt], indent: usize) -> String {
+    let Some((first, rest)) = statements.split_first() else {
+        return "{}".to_string();
+    };
+    let rest = format_statements(rest, indent + 1, false);
+    match opening_comment(first) {
+        Some((comment, first)) => {
+            let first = first
+                .map(|first| format_statements(std::slice::from_ref(&first), indent + 1, false))
+                .unwrap_or_default();
+            format!("{{ {}\n{}{}{}}}", comment, first, rest, pad(indent))
+        }
+        None => format!(
+            "{{\n{}{}{}}}",
+            format_statements(std::slice::from_ref(first), indent + 1, false),
+            rest,
+            pad(indent)
+        ),
+    }
+}
+
+// A comment written right after a block's `{`, which stays on its line,
+// and the block's first statement without it
+fn opening_comment(first: &Stmt) -> Option<(&str, Option<Stmt>)> {
+    if let Stmt::Comment(comment) = first {
+        return comment.same_line.then_some((comment.text.as_str(), None));
+    }
+    let comment = first.comments()?.leading.first()?;
+    if !comment.same_line {
+        return None;
+    }
+    let mut rest = first.clone();
+    if let Some(comments) = rest.comments_mut() {
+        comments.leading.remove(0);
+    }
+    Some((comment.text.as_str(), Some(rest)))
+}
+
+pub(crate) fn format_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Integer => "int".to_string(),
+        DataType::Float => "float".to_string(),
+        DataType::String => "string".to_string(),
+        DataType::Boolean => "bool".to_string(),
+        DataType::Char => "char".to_string(),
+        DataType::Tensor => "tensor".to_string(),
+        DataType::Void => "void".to_string(),
+        DataType::Struct(name) | DataType::Enum(name) => name.clone(),
+        DataType::Optional(inner) => format!("{}?", format_type(inner)),
+        DataType::Array(element) => format!("[{}]", format_type(element)),
+        DataType::Parameter(name) => name.clone(),
+    }
+}
+
+fn annotation(data_type: &Option<DataType>) -> String {
+    match data_type {
+        Some(data_type) => format!(": {}", format_type(data_type)),
+        None => String::new(),
+    }
+}
+
+fn return_annotation(return_type: &Option<DataType>) -> String {
+    match return_type {
+        Some(data_type) => format!(" -> {}", format_type(data_type)),
+        None => String::new(),
+    }
+}
+
+// `<T, U>`, or nothing for a function that isn't generic
+fn format_type_parameters(names: &[String]) -> String {
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", names.join(", "))
+    }
+}
+
+fn format_parameters(parameters: &[ParameterNode]) -> String {
+    parameters
+        .iter()
+        .map(|parameter| format!("{}{}", parameter.name, annotation(&parameter.data_type)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Formats a statement starting at the current position, with the comments
+// leading into it on the lines before and those trailing it after; nested
+// lines are indented relative to `indent` and the text carries no trailing
+// newline
+fn format_stmt(stmt: &Stmt, indent: usize) -> String {
+    let text = stmt_text(stmt, indent);
+    let Some(comments) = stmt.comments() else {
+        return text;
+    };
+    let mut output = String::new();
+    for comment in &comments.leading {
+        output.push_str(&comment.text);
+        output.push('\n');
+        output.push_str(&pad(indent));
+    }
+    output.push_str(&text);
+    for comment in &comments.trailing {
+        if comment.same_line {
+            output.push(' ');
+        } else {
+            output.push('\n');
+            output.push_str(&pad(indent));
+        }
+        output.push_str(&comment.text);
+    }
+    output
+}
+
+// A statement without its comments
+fn stmt_text(stmt: &Stmt, indent: usize) -> String {
+    match stmt {
+        // An expression statement's comments are the statement's, written
+        // by `format_stmt`. A leading `match` would be read back as a match
+        // statement
+        Stmt::Expression(expr @ Expr::Match(_)) => format!("({});", bare_expr_text(expr, indent)),
+        Stmt::Expression(expr) => format!("{};", bare_expr_text(expr, indent)),
+        Stmt::VarDeclaration(declaration) => {
+            let initializer = match &declaration.initializer {
+                Some(value) => format!(" = {}", expr_text(value, indent)),
+                None => String::new(),
+            };
+            format!(
+                "var {}{}{};",
+                declaration.name,
+                annotation(&declaration.data_type),
+                initializer
+            )
+        }
+        Stmt::ConstDeclaration(declaration) => format!(
+            "const {}{} = {};",
+            declaration.name,
+            annotation(&declaration.data_type),
+            expr_text(&declaration.value, indent)
+        ),
+        Stmt::FunctionDeclaration(function) => format!(
+            "fn {}{}({}){} {}",
+            function.name,
+            format_type_parameters(&function.type_parameters),
+            format_parameters(&function.parameters),
+            return_annotation(&function.return_type),
+            format_block(&function.body, indent)
+        ),
+        Stmt::StructDeclaration(struct_node) => {
+            if struct_node.fields.is_empty() {
+                return format!("struct {} {{}}", struct_node.name);
+            }
+            let mut text = format!("struct {} {{\n", struct_node.name);
+            for field in &struct_node.fields {
+                text.push_str(&format!(
+                    "{}{}: {},\n",
+                    pad(indent + 1),
+                    field.name,
+                    format_type(&field.data_type)
+                ));
+            }
+            text.push_str(&pad(indent));
+            text.push('}');
+            text
+        }
+        Stmt::EnumDeclaration(enum_node) => {
+            let inline = format!(
+                "enum {} {{ {} }}",
+                enum_node.name,
+                enum_node.variants.join(", ")
+            );
+            if enum_node.variants.is_empty() {
+                return format!("enum {} {{}}", enum_node.name);
+            }
+            if fits(indent, &inline) {
+                return inline;
+            }
+            let mut text = format!("enum {} {{\n", enum_node.name);
+            for variant in &enum_node.variants {
+                text.push_str(&format!("{}{},\n", pad(indent + 1), variant));
+            }
+            text.push_str(&pad(indent));
+            text.push('}');
+            text
+        }
+        Stmt::Return(return_node) => match &return_node.value {
+            Some(value) => format!("return {};", expr_text(value, indent)),
+            None => "return;".to_string(),
+        },
+        Stmt::Throw(throw) => format!("throw {};", expr_text(&throw.value, indent)),
+        Stmt::Breakpoint(_) => "breakpoint;".to_string(),
+        Stmt::If(if_node) => {
+            // An else-less `if` as the then-branch would capture our `else`
+            // when re-parsed, so it is braced
+            let then_branch = match (&*if_node.then_branch, &if_node.else_branch) {
+                (Stmt::If(inner), Some(_)) if inner.else_branch.is_none() => format!(
+                    "{{\n{}{}\n{}}}",
+                    pad(indent + 1),
+                    format_stmt(&if_node.then_branch, indent + 1),
+                    pad(indent)
+                ),
+                (then_branch, _) => format_stmt(then_branch, indent),
+            };
+            let mut text = format!(
+                "if ({}) {}",
+                expr_text(&if_node.condition, indent),
+                then_branch
+            );
+            if let Some(else_branch) = &if_node.else_branch {
+                // Keep `else` on the closing brace's line, or on a fresh
+                // line after a single-statement branch
+                if text.ends_with('}') {
+                    text.push(' ');
+                } else {
+                    text.push('\n');
+                    text.push_str(&pad(indent));
+                }
+                text.push_str("else ");
+                text.push_str(&format_stmt(else_branch, indent));
+            }
+            text
+        }
+        Stmt::While(while_node) => format!(
+            "while ({}) {}",
+            expr_text(&while_node.condition, indent),
+            format_stmt(&while_node.body, indent)
+        ),
+        Stmt::DoWhile(do_while) => {
+            let body = format_stmt(&do_while.body, indent);
+            let separator = if body.ends_with('}') {
+                " ".to_string()
+            } else {
+                format!("\n{}", pad(indent))
+            };
+            format!(
+                "do {}{}while ({});",
+                body,
+                separator,
+                expr_text(&do_while.condition, indent)
+            )
+        }
+        Stmt::For(for_node) => {
+            let mut header = match &for_node.initializer {
+                Some(initializer) => format_stmt(initializer, indent),
+                None => ";".to_string(),
+            };
+            if let Some(condition) = &for_node.condition {
+                header.push(' ');
+                header.push_str(&expr_text(condition, indent));
+            }
+            header.push(';');
+            if let Some(increment) = &for_node.increment {
+                header.push(' ');
+                header.push_str(&expr_text(increment, indent));
+            }
+            format!("for ({}) {}", header, format_stmt(&for_node.body, indent))
+        }
+        Stmt::ForIn(for_in) => format!(
+            "for {} in {} {}",
+            for_in.variable,
+            expr_text(&for_in.iterable, indent),
+            format_stmt(&for_in.body, indent)
+        ),
+        Stmt::Block(block) => format_block(&block.statements, indent),
+        Stmt::Match(match_node) => {
+            if match_node.arms.is_empty() {
+                return format!("match {} {{}}", expr_text(&match_node.subject, indent));
+            }
+            let mut text = format!("match {} {{\n", expr_text(&match_node.subject, indent));
+            for arm in &match_node.arms {
+                let pattern = pattern_text(&arm.pattern, indent + 1);
+                // Arm bodies are an expression or a block; anything else is
+                // wrapped in a block so it can be read back
+                let body = match &*arm.body {
+                    Stmt::Expression(expr) => format!("{},", expr_text(expr, indent + 1)),
+                    Stmt::Block(block) => format_block(&block.statements, indent + 1),
+                    other => format_block(std::slice::from_ref(other), indent + 1),
+                };
+                text.push_str(&format!("{}{} => {}\n", pad(indent + 1), pattern, body));
+            }
+            text.push_str(&pad(indent));
+            text.push('}');
+            text
+        }
+        Stmt::Try(try_node) => {
+            let catch = match &try_node.error_name {
+                Some(name) => format!("catch ({})", name),
+                None => "catch".to_string(),
+            };
+            format!(
+                "try {} {} {}",
+                format_block(&try_node.body.statements, indent),
+                catch,
+                format_block(&try_node.handler.statements, indent)
+            )
+        }
+        Stmt::Import(import) => match &import.source {
+            ImportSource::Basket(path) => format!("use {};", path.join("::")),
+            ImportSource::File(path) => format!("import {};", quote_string(path)),
+        },
+        Stmt::Comment(comment) => comment.text.clone(),
+    }
+}
+
+fn pattern_text(pattern: &Pattern, indent: usize) -> String {
+    match pattern {
+        Pattern::Literal(literal) => expr_text(literal, indent),
+        Pattern::Wildcard => "_".to_string(),
+    }
+}
+
+// Measures only up to the first line break, since anything after it (a
+// block lambda's body, say) is laid out on lines of its own
+fn fits(indent: usize, text: &str) -> bool {
+    let first_line = text.split('\n').next().unwrap_or_default();
+    indent * INDENT.len() + first_line.chars().count() <= MAX_WIDTH
+}
+
+// `(a, b)`, or one argument per line when that would run past MAX_WIDTH
+fn format_arguments(callee: &str, arguments: &[Expr], indent: usize) -> String {
+    let inline: Vec<String> = arguments.iter().map(|a| expr_text(a, indent)).collect();
+    let inline = format!("({})", inline.join(", "));
+    let callee_end = callee.rsplit('\n').next().unwrap_or_default();
+    let commented = arguments.iter().any(has_line_comment);
+    if arguments.is_empty() || (!commented && fits(indent, &format!("{}{}", callee_end, inline))) {
+        return inline;
+    }
+    let wrapped: Vec<String> = arguments
+        .iter()
+        .enumerate()
+        .map(|(i, argument)| {
+            let (text, comments) = list_item(argument, indent + 1);
+            let separator = if i + 1 < arguments.len() { "," } else { "" };
+            format!("{}{}{}{}", pad(indent + 1), text, separator, comments)
+        })
+        .collect();
+    format!("(\n{}\n{})", wrapped.join("\n"), pad(indent))
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Assignment(_) | Expr::CompoundAssignment(_) => ASSIGNMENT_PRECEDENCE,
+        Expr::Conditional(_) => CONDITIONAL_PRECEDENCE,
+        Expr::Range(_) => RANGE_PRECEDENCE,
+        Expr::Binary(binary) => binary_precedence(binary.operator),
+        Expr::Unary(_) => PREFIX_PRECEDENCE,
+        Expr::Cast(_) => CAST_PRECEDENCE,
+        Expr::Call(_) | Expr::MethodCall(_) | Expr::MemberAccess(_) | Expr::Index(_) => {
+            POSTFIX_PRECEDENCE
+        }
+        // An expression-bodied closure extends as far right as it can, so it
+        // needs parentheses inside any operator
+        Expr::Lambda(lambda) if matches!(lambda.body, LambdaBody::Expression(_)) => {
+            ASSIGNMENT_PRECEDENCE
+        }
+        _ => PRIMARY_PRECEDENCE,
+    }
+}
+
+// Formats an operand, parenthesizing it if it binds more loosely than its
+// position requires (possible in trees that were built rather than parsed)
+fn operand(expr: &Expr, min_precedence: u8, indent: usize) -> String {
+    let text = expr_text(expr, indent);
+    if precedence(expr) < min_precedence {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+pub(crate) fn binary_symbol(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::MatMul => "@",
+        BinaryOperator::BitAnd => "&",
+        BinaryOperator::BitOr => "|",
+        BinaryOperator::BitXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+    }
+}
+
+fn escape_char(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c if c.is_control() => format!("\\u{{{:x}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+fn quote_string(value: &str) -> String {
+    let escaped: String = value.chars().map(|c| escape_char(c, '"')).collect();
+    format!("\"{}\"", escaped)
+}
+
+// An expression with its comments: block comments inline, and a line
+// comment followed by a line break, since it runs to the end of the line
+fn expr_text(expr: &Expr, indent: usize) -> String {
+    let text = bare_expr_text(expr, indent);
+    let Some(comments) = expr.comments() else {
+        return text;
+    };
+    let mut output = String::new();
+    for comment in &comments.leading {
+        output.push_str(&comment.text);
+        if comment.is_line_comment() {
+            output.push('\n');
+            output.push_str(&pad(indent));
+        } else {
+            output.push(' ');
+        }
+    }
+    output.push_str(&text);
+    // What follows a trailing line comment continues the expression, so
+    // it is indented one step further
+    for comment in &comments.trailing {
+        output.push(' ');
+        output.push_str(&comment.text);
+        if comment.is_line_comment() {
+            output.push('\n');
+            output.push_str(&pad(indent + 1));
+        }
+    }
+    output
+}
+
+// An expression in a list, laid out one item per line: its text, and the
+// line comment trailing it, which goes after the separator
+fn list_item(expr: &Expr, indent: usize) -> (String, String) {
+    let trailing = expr.comments().map_or(&[][..], |c| &c.trailing[..]);
+    let Some(first) = trailing.iter().position(CommentNode::is_line_comment) else {
+        return (expr_text(expr, indent), String::new());
+    };
+    let after = trailing[first..]
+        .iter()
+        .map(|comment| format!(" {}", comment.text))
+        .collect();
+    let mut bare = expr.clone();
+    bare.comments_mut().trailing.truncate(first);
+    (expr_text(&bare, indent), after)
+}
+
+// Whether an expression has a line comment of its own, which its list can
+// only hold one item per line
+fn has_line_comment(expr: &Expr) -> bool {
+    expr.comments().is_some_and(|comments| {
+        comments
+            .leading
+            .iter()
+            .chain(&comments.trailing)
+            .any(CommentNode::is_line_comment)
+    })
+}
+
+fn bare_expr_text(expr: &Expr, indent: usize) -> String {
+    match expr {
+        Expr::Number(number) => number.value.clone(),
+        Expr::String(string) => quote_string(&string.value),
+        Expr::Char(char_node) => format!("'{}'", escape_char(char_node.value, '\'')),
+        Expr::Boolean(boolean) => boolean.value.to_string(),
+        Expr::Null(_) => "null".to_string(),
+        Expr::Variable(variable) => variable.name.clone(),
+        Expr::Assignment(assignment) => format!(
+            "{} = {}",
+            operand(&assignment.target, POSTFIX_PRECEDENCE, indent),
+            operand(&assignment.value, ASSIGNMENT_PRECEDENCE, indent)
+        ),
+        Expr::CompoundAssignment(assignment) => format!(
+            "{} {}= {}",
+            operand(&assignment.target, POSTFIX_PRECEDENCE, indent),
+            binary_symbol(assignment.operator),
+            operand(&assignment.value, ASSIGNMENT_PRECEDENCE, indent)
+        ),
+        Expr::Binary(binary) => {
+            let precedence = binary_precedence(binary.operator);
+            format!(
+                "{} {} {}",
+                operand(&binary.left, precedence, indent),
+                binary_symbol(binary.operator),
+                operand(&binary.right, precedence + 1, indent)
+            )
+        }
+        Expr::Unary(unary) => {
+            let symbol = match unary.operator {
+                UnaryOperator::Negate => "-",
+                UnaryOperator::Not => "!",
+            };
+            format!(
+                "{}{}",
+                symbol,
+                operand(&unary.operand, PREFIX_PRECEDENCE, indent)
+            )
+        }
+        Expr::Cast(cast) => format!(
+            "{} as {}",
+            operand(&cast.expression, CAST_PRECEDENCE, indent),
+            format_type(&cast.data_type)
+        ),
+        Expr::Parenthesis(paren) => format!("({})", expr_text(&paren.expression, indent)),
+        Expr::Conditional(conditional) => format!(
+            "{} ? {} : {}",
+            operand(&conditional.condition, RANGE_PRECEDENCE, indent),
+            operand(&conditional.then_expression, ASSIGNMENT_PRECEDENCE, indent),
+            operand(&conditional.else_expression, ASSIGNMENT_PRECEDENCE, indent)
+        ),
+        Expr::Range(range) => {
+            let mut text = format!(
+                "{}{}{}",
+                operand(&range.start, RANGE_PRECEDENCE + 1, indent),
+                if range.inclusive { "..=" } else { ".." },
+                operand(&range.end, RANGE_PRECEDENCE + 1, indent)
+            );
+            if let Some(step) = &range.step {
+                text.push_str(" step ");
+                text.push_str(&operand(step, RANGE_PRECEDENCE + 1, indent));
+            }
+            text
+        }
+        Expr::Call(call) => {
+            let callee = operand(&call.callee, POSTFIX_PRECEDENCE, indent);
+            let arguments = format_arguments(&callee, &call.arguments, indent);
+            format!("{}{}", callee, arguments)
+        }
+        Expr::MethodCall(method_call) => {
+            let callee = format!(
+                "{}{}{}",
+                operand(&method_call.object, POSTFIX_PRECEDENCE, indent),
+                if method_call.optional { "?." } else { "." },
+                method_call.method
+            );
+            let arguments = format_arguments(&callee, &method_call.arguments, indent);
+            format!("{}{}", callee, arguments)
+        }
+        Expr::MemberAccess(member) => format!(
+            "{}{}{}",
+            operand(&member.object, POSTFIX_PRECEDENCE, indent),
+            if member.optional { "?." } else { "." },
+            member.member
+        ),
+        Expr::Index(index) => format!(
+            "{}{}[{}]",
+            operand(&index.object, POSTFIX_PRECEDENCE, indent),
+            if index.optional { "?." } else { "" },
+            expr_text(&index.index, indent)
+        ),
+        Expr::EnumVariant(variant) => format!("{}::{}", variant.enum_name, variant.variant),
+        Expr::Cfg(cfg) => format!("@cfg({})", quote_string(&cfg.key)),
+        Expr::Match(match_node) => {
+            let mut text = format!("match {} {{\n", expr_text(&match_node.subject, indent));
+            for arm in &match_node.arms {
+                let (value, comments) = list_item(&arm.value, indent + 1);
+                text.push_str(&format!(
+                    "{}{} => {},{}\n",
+                    pad(indent + 1),
+                    pattern_text(&arm.pattern, indent + 1),
+                    value,
+                    comments
+                ));
+            }
+            text.push_str(&pad(indent));
+            text.push('}');
+            text
+        }
+        Expr::TensorLiteral(tensor) => {
+            let elements: Vec<String> = tensor
+                .elements
+                .iter()
+                .map(|element| expr_text(element, indent))
+                .collect();
+            let text = format_tensor(&elements, &tensor.shape);
+            let commented = tensor.elements.iter().any(has_line_comment);
+            if !commented && (fits(indent, &text) || tensor.shape.len() < 2) {
+                return text;
+            }
+            // Too wide for one line, or holding line comments: put each
+            // outermost row on its own, with its comment after the comma
+            let row_len = tensor.elements.len() / tensor.shape[0].max(1);
+            let rows: Vec<&[Expr]> = tensor.elements.chunks(row_len.max(1)).collect();
+            let lines: Vec<String> = rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| {
+                    let (last, comments) = list_item(&row[row.len() - 1], indent + 1);
+                    let mut texts: Vec<String> = row[..row.len() - 1]
+                        .iter()
+                        .map(|element| expr_text(element, indent + 1))
+                        .collect();
+                    texts.push(last);
+                    let row = match &tensor.shape[1..] {
+                        [] => texts.concat(),
+                        inner => format_tensor(&texts, inner),
+                    };
+                    let separator = if i + 1 < rows.len() { "," } else { "" };
+                    format!("{}{}{}{}", pad(indent + 1), row, separator, comments)
+                })
+                .collect();
+            format!("[\n{}\n{}]", lines.join("\n"), pad(indent))
+        }
+        Expr::Spawn(spawn) => format!("spawn {}", format_block(&spawn.body, indent)),
+        Expr::Lambda(lambda) => match &lambda.body {
+            LambdaBody::Expression(body) => {
+                let parameters = if lambda.parameters.is_empty() {
+                    "||".to_string()
+                } else {
+                    format!("|{}|", format_parameters(&lambda.parameters))
+                };
+                format!(
+                    "{}{} {}",
+                    parameters,
+                    return_annotation(&lambda.return_type),
+                    expr_text(body, indent)
+                )
+            }
+            LambdaBody::Block(body) => format!(
+                "fn({}){} {}",
+                format_parameters(&lambda.parameters),
+                return_annotation(&lambda.return_type),
+                format_block(body, indent)
+            ),
+        },
+    }
+}
+
+// Re-nests flattened, already-formatted elements according to `shape`
+fn format_tensor(elements: &[String], shape: &[usize]) -> String {
+    match shape.split_first() {
+        Some((&rows, inner)) if !inner.is_empty() => {
+            let row_len = elements.len() / rows.max(1);
+            let rows: Vec<String> = elements
+                .chunks(row_len.max(1))
+                .map(|row| format_tensor(row, inner))
+                .collect();
+            format!("[{}]", rows.join(", "))
+        }
+        _ => format!("[{}]", elements.join(", ")),
+    }
+}
@@ -0,0 +1,244 @@
+//! Calling Rust from PUT. Any closure whose arguments implement
+//! `FromValue` and whose result implements `IntoResult` can be registered
+//! as a builtin with `Engine::register_fn`; its arguments are converted
+//! from PUT values on each call and its result converted back.
+
+use crate::builtins::NativeFunction;
+use crate::interpreter::{RuntimeError, RuntimeResult, Value};
+use crate::tensor::Tensor;
+use std::fmt;
+use std::sync::Arc;
+
+/// A Rust type that PUT values can be converted to.
+pub trait FromValue: Sized {
+    /// Converts `value`, or describes the mismatch, as in "a string, found
+    /// int".
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+/// A Rust type that converts to a PUT value.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+fn mismatch(expected: &str, found: &Value) -> String {
+    format!("{}, found {}", expected, found.type_name())
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Integer(value) => Ok(value),
+            other => Err(mismatch("an int", &other)),
+        }
+    }
+}
+
+// Ints are accepted too, as they are by the numeric builtins
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Integer(value) => Ok(value as f64),
+            Value::Float(value) => Ok(value),
+            other => Err(mismatch("a number", &other)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Boolean(value) => Ok(value),
+            other => Err(mismatch("a bool", &other)),
+        }
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Char(value) => Ok(value),
+            other => Err(mismatch("a char", &other)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::String(value) => Ok(value.to_string()),
+            other => Err(mismatch("a string", &other)),
+        }
+    }
+}
+
+impl FromValue for Tensor {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Tensor(tensor) => Ok(Arc::unwrap_or_clone(tensor)),
+            other => Err(mismatch("a tensor", &other)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Array(elements) => Arc::unwrap_or_clone(elements)
+                .into_iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    T::from_value(element).map_err(|error| format!("{} at index {}", error, i))
+                })
+                .collect(),
+            other => Err(mismatch("an array", &other)),
+        }
+    }
+}
+
+/// Null converts to `None`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, String> {
+        match value {
+            Value::Null => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Null
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Integer(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl IntoValue for char {
+    fn into_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for Tensor {
+    fn into_value(self) -> Value {
+        Value::Tensor(Arc::new(self))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::Array(Arc::new(
+            self.into_iter().map(IntoValue::into_value).collect(),
+        ))
+    }
+}
+
+/// `None` converts to null.
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+/// What a host function can return: any `IntoValue`, or a `Result` whose
+/// error becomes a runtime error in the calling script.
+pub trait IntoResult {
+    fn into_result(self) -> RuntimeResult<Value>;
+}
+
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> RuntimeResult<Value> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue, E: fmt::Display> IntoResult for Result<T, E> {
+    fn into_result(self) -> RuntimeResult<Value> {
+        self.map(IntoValue::into_value)
+            .map_err(|error| RuntimeError::new(error.to_string()))
+    }
+}
+
+/// A Rust function of up to six arguments that can be called from PUT.
+/// `Args` is the tuple of its argument types, which keeps the
+/// implementations for each arity apart.
+pub trait HostFunction<Args> {
+    /// The function as a builtin named `name`.
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! host_function {
+    ($arity:expr $(, $arg:ident)*) => {
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoResult,
+            $($arg: FromValue,)*
+        {
+            // Each argument is bound to a variable named after its type
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let function = name.to_string();
+                NativeFunction::new(name, Some($arity), move |_, arguments| {
+                    let mut arguments = arguments.into_iter();
+                    $(
+                        let $arg = $arg::from_value(arguments.next().unwrap_or(Value::Null))
+                            .map_err(|error| {
+                                RuntimeError::new(format!("{}() expects {}", function, error))
+                            })?;
+                    )*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+host_function!(0);
+host_function!(1, A);
+host_function!(2, A, B);
+host_function!(3, A, B, C);
+host_function!(4, A, B, C, D);
+host_function!(5, A, B, C, D, E);
+host_function!(6, A, B, C, D, E, G);
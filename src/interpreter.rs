@@ -0,0 +1,2049 @@
+//! Tree-walking evaluation of a parsed program: statements are executed
+//! and expressions evaluated directly on the AST, with no compilation step
+//! in between.
+
+use crate::ast::*;
+use crate::builtins::{standard_basket, standard_builtins, NativeFunction};
+use crate::debugger::{describe, Debugger, Pause, PauseReason};
+use crate::environment::{Environment, Scope};
+use crate::formatter::{binary_symbol, format_statement, format_type};
+use crate::lexer::Lexer;
+use crate::modules::{display_name, lower, Module, Modules};
+use crate::profiler::Profile;
+use crate::random::Rng;
+use crate::tensor::{broadcast_shape, Tensor};
+use crate::threads::{Channel, ThreadHandle};
+use crate::token::{Span, Token, TokenType};
+use crate::visitor::{walk_expr, walk_stmt, Visitor};
+use crate::zom_parser::ProjectConfig;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A value produced by evaluating an expression. Strings, tensors,
+/// arrays and functions are reference counted, so copying a value into
+/// another variable or argument never copies what it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Char(char),
+    String(Arc<str>),
+    Function(Arc<Function>),
+    NativeFunction(Arc<NativeFunction>),
+    Tensor(Arc<Tensor>),
+    // Produced by builtins such as `read_lines`; there is no literal syntax
+    Array(Arc<Vec<Value>>),
+    Thread(Arc<ThreadHandle>),
+    Channel(Arc<Channel>),
+    // The namespace an `import` binds
+    Module(Arc<Module>),
+    Enum(Arc<EnumValue>),
+    Struct(Arc<StructValue>),
+}
+
+impl Value {
+    /// The PUT name of the value's type, as written in annotations.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Integer(_) => "int",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "bool",
+            Value::Char(_) => "char",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+            Value::Tensor(_) => "tensor",
+            Value::Array(_) => "array",
+            Value::Thread(_) => "thread",
+            Value::Channel(_) => "channel",
+            Value::Module(_) => "module",
+            Value::Enum(_) => "enum",
+            Value::Struct(_) => "struct",
+        }
+    }
+
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Integer(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Integer(value) => write!(f, "{}", value),
+            // `{:?}` keeps the ".0" on whole floats, so 2.0 doesn't print as 2
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Function(function) => write!(f, "{:?}", function),
+            Value::NativeFunction(function) => write!(f, "{:?}", function),
+            Value::Tensor(tensor) => write!(f, "{}", tensor),
+            Value::Array(elements) => {
+                let parts: Vec<String> = elements.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", parts.join(", "))
+            }
+            Value::Thread(_) => write!(f, "<thread>"),
+            Value::Channel(_) => write!(f, "<channel>"),
+            Value::Module(module) => write!(f, "{:?}", module),
+            Value::Enum(value) => write!(f, "{}::{}", value.enum_name, value.variant),
+            Value::Struct(value) => {
+                let fields: Vec<String> = value
+                    .fields
+                    .iter()
+                    .map(|(name, field)| format!("{}: {}", name, field))
+                    .collect();
+                write!(f, "{} {{ {} }}", value.name, fields.join(", "))
+            }
+        }
+    }
+}
+
+/// A variant of a declared enum, like `Color::Red`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    pub enum_name: String,
+    pub variant: String,
+}
+
+/// An instance of a declared struct, made by calling the constructor its
+/// declaration defines: `Point(1.0, 2.0)`. Fields keep their declared
+/// order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructValue {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl StructValue {
+    fn field(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.fields
+            .iter_mut()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// A function declared in PUT code, together with the scope it was
+/// declared in, which its body sees when it runs.
+pub struct Function {
+    pub declaration: FunctionDeclarationNode,
+    closure: Scope,
+}
+
+// Written by hand: the closure can hold the function itself
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name)
+    }
+}
+
+// Functions are equal only to themselves
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for ThreadHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<thread>")
+    }
+}
+
+// Threads and channels, like functions, are equal only to themselves
+impl PartialEq for ThreadHandle {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<channel>")
+    }
+}
+
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// A function call in progress, when a runtime error occurred or a
+/// debugger paused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    // Where the call was made from
+    pub span: Option<Span>,
+}
+
+/// How deeply PUT function calls may nest unless `max_call_depth` says
+/// otherwise. Each call takes tens of kilobytes of the Rust stack in a
+/// debug build, so this fits within a main thread's 8 MiB.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 128;
+
+// The Rust stack a spawned thread gets for each PUT call it may nest
+const STACK_PER_CALL: usize = 64 << 10;
+
+/// Bounds on the work a script may do, so that untrusted code can neither
+/// run forever nor build values that exhaust memory. Each applies to one
+/// run of a program, and each is off by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// The most statements and expressions the run may evaluate
+    pub max_instructions: Option<u64>,
+    /// The most bytes any one string, array or tensor may take up. This
+    /// bounds each value, not what the script holds in total: values are
+    /// measured as they are produced, and joins and builtins that would
+    /// allocate a lot at once are refused before they do
+    pub max_value_size: Option<usize>,
+    /// How long the run may take
+    pub timeout: Option<Duration>,
+}
+
+/// Stops a running script from another thread, say when the user of a
+/// host application presses a stop button. The interpreter checks it
+/// before every statement and expression, so the script stops with a
+/// runtime error soon after `cancel` is called, whatever it is doing.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    // Bumped by each `cancel`; a run is cancelled once this differs from
+    // its value when the run started
+    cancels: Arc<AtomicU64>,
+}
+
+impl CancelHandle {
+    /// Stops the run in progress, along with the threads it spawned. Runs
+    /// started afterwards are not affected.
+    pub fn cancel(&self) {
+        self.cancels.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cancels(&self) -> u64 {
+        self.cancels.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    // The expression that failed, once the interpreter has unwound to it
+    pub span: Option<Span>,
+    // The calls that led to the error, innermost first
+    pub trace: Vec<StackFrame>,
+    // The value of the `throw` statement that raised the error, if any
+    pub thrown: Option<Value>,
+    // Whether a `try` may catch the error. Going past a limit must stop
+    // the script, so those errors can't be caught
+    pub catchable: bool,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            span: None,
+            trace: Vec::new(),
+            thrown: None,
+            catchable: true,
+        }
+    }
+
+    /// The error a `throw` of `value` raises until something catches it.
+    pub fn thrown(value: Value) -> Self {
+        RuntimeError {
+            thrown: Some(value.clone()),
+            ..RuntimeError::new(format!("Uncaught exception: {}", describe(&value)))
+        }
+    }
+
+    // An error no `try` can catch
+    fn uncatchable(mut self) -> Self {
+        self.catchable = false;
+        self
+    }
+
+    // What a `catch (e)` binds `e` to: the thrown value, or the message of
+    // an error the interpreter raised
+    fn caught_value(self) -> Value {
+        self.thrown
+            .unwrap_or_else(|| Value::String(self.message.into()))
+    }
+
+    // Errors are located at the innermost expression that knows its span
+    fn located(mut self, span: Option<Span>) -> Self {
+        if self.span.is_none() {
+            self.span = span;
+        }
+        self
+    }
+
+    /// The error with the offending line of `source` underlined, followed
+    /// by the calls it happened inside.
+    pub fn render(&self, source: &str) -> String {
+        let mut text = self.to_string();
+        if let Some(span) = self.span {
+            text.push_str(&format!(" at line {}, column {}", span.line, span.column));
+            if let Some(line) = source.lines().nth(span.line.saturating_sub(1)) {
+                let gutter = span.line.to_string();
+                text.push_str(&format!(
+                    "\n{} | {}\n{} | {}{}",
+                    gutter,
+                    line,
+                    " ".repeat(gutter.len()),
+                    " ".repeat(span.column.saturating_sub(1)),
+                    "^".repeat(span.length.max(1))
+                ));
+            }
+        }
+        // Runs of the same frame, as in deep recursion, are shown once
+        let mut frames = self.trace.iter().peekable();
+        while let Some(frame) = frames.next() {
+            text.push_str(&format!("\n    in {}()", frame.function));
+            if let Some(span) = frame.span {
+                text.push_str(&format!(" called at line {}", span.line));
+            }
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                text.push_str(&format!("\n    ... repeated {} more times", repeats));
+            }
+        }
+        text
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Runtime error: {}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+// How a statement finished: normally, or by returning from its function
+enum Flow {
+    Normal,
+    Return(Value),
+}
+
+pub struct Interpreter {
+    globals: Scope,
+    // The innermost scope at the statement being executed
+    environment: Scope,
+    // Where `print` writes, shared with spawned threads
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    // Whether scripts may read and write files
+    file_access: bool,
+    // Shared by the random builtins; `seed` resets it
+    rng: Rng,
+    modules: Modules,
+    debugger: Option<Box<dyn Debugger>>,
+    // The calls to PUT functions in progress, outermost first
+    calls: Vec<StackFrame>,
+    profile: Option<Profile>,
+    // Where each statement is logged as it runs, if anywhere
+    trace: Option<Box<dyn Write>>,
+    // The script the trace quotes statements from, if it was given
+    trace_source: Option<TraceSource>,
+    // The lines of the statements being executed, innermost last; only
+    // kept while a debugger is attached or profiling is on
+    executing: Vec<Option<usize>>,
+    // Where the innermost call's statements start in `executing`
+    frame_start: usize,
+    // What the most recent expression statement evaluated to
+    last_value: Value,
+    limits: Limits,
+    max_call_depth: usize,
+    // Statements and expressions evaluated in this run
+    instructions: u64,
+    // When this run's time is up, if it has a timeout
+    deadline: Option<Instant>,
+    cancel: CancelHandle,
+    // How often `cancel` had been cancelled before this run started
+    cancels_before: u64,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter whose `print` statements write to standard output.
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+
+    pub fn with_output(output: impl Write + Send + 'static) -> Self {
+        let globals = Scope::new(Environment::new());
+        let mut interpreter = Interpreter {
+            environment: globals.clone(),
+            globals,
+            output: Arc::new(Mutex::new(Box::new(output))),
+            file_access: false,
+            rng: Rng::from_time(),
+            modules: Modules::default(),
+            debugger: None,
+            calls: Vec::new(),
+            profile: None,
+            trace: None,
+            trace_source: None,
+            executing: Vec::new(),
+            frame_start: 0,
+            last_value: Value::Null,
+            limits: Limits::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            instructions: 0,
+            deadline: None,
+            cancel: CancelHandle::default(),
+            cancels_before: 0,
+        };
+        for builtin in standard_builtins() {
+            interpreter.register_builtin(builtin);
+        }
+        interpreter
+    }
+
+    /// Lets scripts use the file builtins (`read_file` and friends), which
+    /// otherwise fail. Off by default, so running an untrusted script
+    /// cannot touch the filesystem.
+    pub fn allow_file_access(mut self, allowed: bool) -> Self {
+        self.file_access = allowed;
+        self
+    }
+
+    pub fn file_access_allowed(&self) -> bool {
+        self.file_access
+    }
+
+    /// Resolves the paths in `import` statements against `directory`,
+    /// normally the one holding the script. The working directory unless
+    /// set.
+    pub fn import_from(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.modules.directory = directory.into();
+        self
+    }
+
+    /// Follows `config`, the project.zom found in `root`, when importing:
+    /// `use` loads the baskets it declares from `root/baskets`, and
+    /// imported files are parsed with its settings.
+    pub fn project(mut self, config: ProjectConfig, root: impl Into<PathBuf>) -> Self {
+        self.modules.project = Some(Arc::new((config, root.into())));
+        self
+    }
+
+    /// Attaches `debugger`, which is paused at every `breakpoint;`
+    /// statement and wherever else it asks to be.
+    pub fn attach_debugger(mut self, debugger: impl Debugger + 'static) -> Self {
+        self.debugger = Some(Box::new(debugger));
+        self
+    }
+
+    /// Stops each run of a program with a runtime error once it goes past
+    /// `limits`.
+    pub fn limit(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Fails a call to a PUT function with a stack overflow error once
+    /// `depth` calls are already in progress, before the interpreter can
+    /// run out of Rust stack. Raise it only on a thread whose stack has
+    /// room for the deeper recursion.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Counts the runs of every line and call of every PUT function, and
+    /// times them, for `profile` to report.
+    pub fn enable_profiling(mut self) -> Self {
+        self.profile = Some(Profile::new());
+        self
+    }
+
+    /// What the script has done so far, if profiling is on.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Logs each simple statement to `output` as it runs, with its line
+    /// and the value it produced: the value of an expression statement,
+    /// the new variable or constant, or the returned value. Statements
+    /// holding others, like loops, are not logged themselves, but the
+    /// statements in them are.
+    pub fn trace_to(mut self, output: impl Write + 'static) -> Self {
+        self.trace = Some(Box::new(output));
+        self
+    }
+
+    /// Has the trace quote each statement from `source`, the script the
+    /// program was parsed from, as it was written. Without it, statements
+    /// are shown as the formatter writes the tree, after desugaring has
+    /// turned `i += 1;` into `i = i + 1;`.
+    pub fn trace_source(mut self, source: &str) -> Self {
+        self.trace_source = Some(TraceSource::new(source));
+        self
+    }
+
+    /// The generator behind `random`, `rand_int` and `seed`.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Makes a native function callable by name from PUT code, replacing
+    /// any global of the same name (including a standard builtin).
+    pub fn register_builtin(&mut self, function: NativeFunction) {
+        let name = function.name.clone();
+        // Only constants can refuse a redefinition, and builtins are
+        // registered before any script runs
+        let _ = self
+            .globals
+            .lock()
+            .define(&name, Value::NativeFunction(Arc::new(function)));
+    }
+
+    /// Writes `text` wherever this interpreter's `print` output goes.
+    pub fn write_output(&mut self, text: &str) -> RuntimeResult<()> {
+        self.output
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write_all(text.as_bytes())
+            .map_err(|error| RuntimeError::new(format!("Cannot print: {}", error)))
+    }
+
+    /// Runs every statement in order, stopping at the first runtime error.
+    pub fn interpret(&mut self, program: &ProgramNode) -> RuntimeResult<()> {
+        self.evaluate_program(program).map(|_| ())
+    }
+
+    /// Runs the program like `interpret` and returns the value of its last
+    /// statement if that is an expression statement, or null otherwise.
+    pub fn evaluate_program(&mut self, program: &ProgramNode) -> RuntimeResult<Value> {
+        self.instructions = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.cancels_before = self.cancel.cancels();
+        // Comments after the last statement don't make it any less last
+        let end = program
+            .statements
+            .iter()
+            .rposition(|statement| !matches!(statement, Stmt::Comment(_)))
+            .map_or(0, |last| last + 1);
+        for statement in &program.statements[..end] {
+            if let Flow::Return(_) = self.execute(statement)? {
+                return Err(RuntimeError::new("Cannot return from top-level code"));
+            }
+        }
+        // The last statement goes through `execute` like the rest, so it is
+        // traced, profiled and can be stopped at; only an expression
+        // statement leaves a value behind
+        Ok(match program.statements[..end].last() {
+            Some(Stmt::Expression(_)) => std::mem::replace(&mut self.last_value, Value::Null),
+            _ => Value::Null,
+        })
+    }
+
+    /// The current value of a top-level variable or constant.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.lock().get(name)
+    }
+
+    /// Every top-level variable, constant and function with its current
+    /// value, sorted by name. Builtins are left out.
+    pub fn globals(&self) -> impl Iterator<Item = (String, Value)> {
+        let mut globals = self.globals.lock().bindings();
+        globals.retain(|(_, value)| !matches!(value, Value::NativeFunction(_)));
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals.into_iter()
+    }
+
+    /// Every builtin scripts can call, including those from
+    /// `register_builtin`, with the number of arguments it takes if that
+    /// is fixed.
+    pub fn builtins(&self) -> impl Iterator<Item = (String, Option<usize>)> {
+        let globals = self.globals.lock().bindings();
+        globals.into_iter().filter_map(|(name, value)| match value {
+            Value::NativeFunction(function) => Some((name, function.arity)),
+            _ => None,
+        })
+    }
+
+    /// Whether the global `name` was declared with `const`.
+    pub fn is_global_constant(&self, name: &str) -> bool {
+        self.globals.lock().is_constant(name)
+    }
+
+    /// Binds `name` at the top level to `value`, as `var`, or `const` if
+    /// `constant`, would there.
+    pub fn define_global(&mut self, name: &str, value: Value, constant: bool) -> RuntimeResult<()> {
+        let mut globals = self.globals.lock();
+        if constant {
+            globals.define_constant(name, value)
+        } else {
+            globals.define(name, value)
+        }
+    }
+
+    /// The function `declaration` declares, as if it appeared at the top
+    /// level: its body sees the globals and nothing else.
+    pub fn global_function(&self, declaration: FunctionDeclarationNode) -> Value {
+        Value::Function(Arc::new(Function {
+            declaration,
+            closure: self.globals.clone(),
+        }))
+    }
+
+    // Runs `statements` in a new scope nested in `parent`
+    fn execute_block(&mut self, statements: &[Stmt], parent: Scope) -> RuntimeResult<Flow> {
+        let scope = Scope::new(Environment::with_parent(parent));
+        self.with_environment(scope, |interpreter| {
+            for statement in statements {
+                if let Flow::Return(value) = interpreter.execute(statement)? {
+                    return Ok(Flow::Return(value));
+                }
+            }
+            Ok(Flow::Normal)
+        })
+    }
+
+    // Makes `environment` the current scope while `run` runs
+    fn with_environment<T>(
+        &mut self,
+        environment: Scope,
+        run: impl FnOnce(&mut Self) -> RuntimeResult<T>,
+    ) -> RuntimeResult<T> {
+        let enclosing = std::mem::replace(&mut self.environment, environment);
+        let result = run(self);
+        self.environment = enclosing;
+        result
+    }
+
+    // A new scope nested in the current one
+    fn nested_scope(&self) -> Scope {
+        Scope::new(Environment::with_parent(self.environment.clone()))
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> RuntimeResult<Flow> {
+        self.charge()?;
+        if self.debugger.is_none() && self.profile.is_none() && self.trace.is_none() {
+            return self.execute_kind(stmt);
+        }
+        let line = stmt.line();
+        // A line is visited once per call, not again for the statements
+        // nested in the one that started the visit
+        let enclosing = self.executing[self.frame_start..]
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .copied();
+        let visit = line.filter(|&line| enclosing != Some(line));
+        self.pause_before(stmt, visit);
+        let started = self.profile.as_ref().map(|_| Instant::now());
+        self.executing.push(line);
+        let flow = self.execute_traced(stmt, line);
+        self.executing.pop();
+        if let (Some(line), Some(started)) = (visit, started) {
+            // A line already running further out, through a recursive
+            // call, is timed there
+            let time = if self.executing.contains(&Some(line)) {
+                Duration::ZERO
+            } else {
+                started.elapsed()
+            };
+            if let Some(profile) = &mut self.profile {
+                profile.record_line(line, time);
+            }
+        }
+        flow
+    }
+
+    // Runs `stmt`, logging it to the trace if there is one
+    fn execute_traced(&mut self, stmt: &Stmt, line: Option<usize>) -> RuntimeResult<Flow> {
+        if self.trace.is_none() {
+            return self.execute_kind(stmt);
+        }
+        let (flow, value) = match stmt {
+            Stmt::Expression(expr) => {
+                self.last_value = self.evaluate(expr)?;
+                (Flow::Normal, Some(self.last_value.clone()))
+            }
+            Stmt::VarDeclaration(VarDeclarationNode { name, .. })
+            | Stmt::ConstDeclaration(ConstDeclarationNode { name, .. }) => {
+                let flow = self.execute_kind(stmt)?;
+                (flow, self.environment.lock().get(name))
+            }
+            Stmt::Return(_) => match self.execute_kind(stmt)? {
+                Flow::Return(value) => (Flow::Return(value.clone()), Some(value)),
+                Flow::Normal => (Flow::Normal, None),
+            },
+            _ => return self.execute_kind(stmt),
+        };
+        let text = self
+            .trace_source
+            .as_ref()
+            .and_then(|source| source.statement(stmt))
+            .unwrap_or_else(|| format_statement(stmt));
+        let mut entry = match line {
+            Some(line) => format!("[line {}] {}", line, text),
+            None => text,
+        };
+        if let Some(value) = value.filter(|value| !matches!(value, Value::Null)) {
+            entry.push_str(&format!(" => {}", describe(&value)));
+        }
+        if let Some(trace) = &mut self.trace {
+            writeln!(trace, "{}", entry)
+                .map_err(|error| RuntimeError::new(format!("Cannot write the trace: {}", error)))?;
+        }
+        Ok(flow)
+    }
+
+    // Hands control to the debugger if it should stop before `stmt`, which
+    // starts a visit to `line`
+    fn pause_before(&mut self, stmt: &Stmt, line: Option<usize>) {
+        let debugger = match &mut self.debugger {
+            Some(debugger) => debugger,
+            None => return,
+        };
+        let reason = match (stmt, line) {
+            (Stmt::Breakpoint(_), _) => PauseReason::Breakpoint,
+            (_, Some(line)) => match debugger.breaks_at(line, self.calls.len()) {
+                Some(reason) => reason,
+                None => return,
+            },
+            _ => return,
+        };
+        let mut variables: Vec<(String, Value)> = self
+            .environment
+            .lock()
+            .bindings()
+            .into_iter()
+            .filter(|(_, value)| !matches!(value, Value::NativeFunction(_)))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        debugger.pause(&Pause {
+            reason,
+            line,
+            variables,
+            backtrace: self.calls.clone(),
+        });
+    }
+
+    fn execute_kind(&mut self, stmt: &Stmt) -> RuntimeResult<Flow> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.last_value = self.evaluate(expr)?;
+            }
+            Stmt::VarDeclaration(declaration) => {
+                let value = match &declaration.initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Null,
+                };
+                let value = converted(value, declaration.data_type.as_ref());
+                self.environment.lock().define(&declaration.name, value)?;
+            }
+            Stmt::ConstDeclaration(declaration) => {
+                let value = self.evaluate(&declaration.value)?;
+                let value = converted(value, declaration.data_type.as_ref());
+                self.environment
+                    .lock()
+                    .define_constant(&declaration.name, value)?;
+            }
+            Stmt::FunctionDeclaration(declaration) => {
+                let function = Function {
+                    declaration: declaration.clone(),
+                    closure: self.environment.clone(),
+                };
+                self.environment
+                    .lock()
+                    .define(&declaration.name, Value::Function(Arc::new(function)))?;
+            }
+            Stmt::Return(node) => {
+                let value = match &node.value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::If(if_node) => {
+                if self.condition(&if_node.condition)? {
+                    return self.execute(&if_node.then_branch);
+                } else if let Some(else_branch) = &if_node.else_branch {
+                    return self.execute(else_branch);
+                }
+            }
+            Stmt::While(while_node) => {
+                while self.condition(&while_node.condition)? {
+                    if let Flow::Return(value) = self.execute(&while_node.body)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Stmt::DoWhile(do_while) => loop {
+                if let Flow::Return(value) = self.execute(&do_while.body)? {
+                    return Ok(Flow::Return(value));
+                }
+                if !self.condition(&do_while.condition)? {
+                    break;
+                }
+            },
+            // The loop variable is scoped to the loop
+            Stmt::For(for_node) => {
+                let scope = self.nested_scope();
+                return self
+                    .with_environment(scope, |interpreter| interpreter.execute_for(for_node));
+            }
+            Stmt::Block(block) => {
+                let parent = self.environment.clone();
+                return self.execute_block(&block.statements, parent);
+            }
+            Stmt::Match(match_node) => {
+                let subject = self.evaluate(&match_node.subject)?;
+                for arm in &match_node.arms {
+                    if self.matches(&arm.pattern, &subject)? {
+                        return self.execute(&arm.body);
+                    }
+                }
+            }
+            // A struct declaration defines the struct's constructor
+            Stmt::StructDeclaration(declaration) => {
+                let constructor = struct_constructor(declaration);
+                self.environment.lock().define(
+                    &declaration.name,
+                    Value::NativeFunction(Arc::new(constructor)),
+                )?;
+            }
+            // Enum declarations and comments have no runtime effect, and
+            // breakpoints are handled before a statement runs
+            Stmt::EnumDeclaration(_) | Stmt::Comment(_) | Stmt::Breakpoint(_) => {}
+            Stmt::Import(ImportNode {
+                source: ImportSource::Basket(path),
+                ..
+            }) => {
+                let Some(basket) = standard_basket(path) else {
+                    let file = self.modules.basket_file(path).ok_or_else(|| {
+                        RuntimeError::new(format!("Unknown basket '{}'", path.join("::")))
+                    })?;
+                    let name = &path[path.len() - 1];
+                    let module = self.import(&file, name)?;
+                    self.environment.lock().define(name, module)?;
+                    return Ok(Flow::Normal);
+                };
+                let mut environment = self.environment.lock();
+                for function in basket.functions {
+                    let name = function.name.clone();
+                    environment.define(&name, Value::NativeFunction(Arc::new(function)))?;
+                }
+                for (name, value) in basket.constants {
+                    environment.define(name, value)?;
+                }
+            }
+            Stmt::Import(ImportNode {
+                source: ImportSource::File(path),
+                ..
+            }) => {
+                let file = self.modules.directory.join(path);
+                let name = file
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let module = self.import(&file, &name)?;
+                self.environment.lock().define(&name, module)?;
+            }
+            Stmt::Throw(throw) => {
+                let value = self.evaluate(&throw.value)?;
+                return Err(RuntimeError::thrown(value).located(throw.value.span()));
+            }
+            Stmt::Try(try_node) => {
+                let parent = self.environment.clone();
+                match self.execute_block(&try_node.body.statements, parent) {
+                    Err(error) if error.catchable => {
+                        let mut scope = Environment::with_parent(self.environment.clone());
+                        if let Some(name) = &try_node.error_name {
+                            scope.define(name, error.caught_value())?;
+                        }
+                        let parent = Scope::new(scope);
+                        return self.execute_block(&try_node.handler.statements, parent);
+                    }
+                    flow => return flow,
+                }
+            }
+            Stmt::ForIn(for_in) => return self.execute_for_in(for_in),
+        }
+        Ok(Flow::Normal)
+    }
+
+    // Runs the file at `path` in a scope of its own, unless it has run
+    // already, and returns its namespace, named `name`
+    fn import(&mut self, path: &Path, name: &str) -> RuntimeResult<Value> {
+        if !self.file_access {
+            return Err(RuntimeError::new(format!(
+                "Cannot import '{}': this interpreter does not allow file access",
+                display_name(path)
+            )));
+        }
+        let path = fs::canonicalize(path).map_err(|error| {
+            RuntimeError::new(format!("Cannot read '{}': {}", path.display(), error))
+        })?;
+        if let Some(module) = self.modules.loaded.get(&path) {
+            return Ok(module.clone());
+        }
+        if self.modules.loading.contains(&path) {
+            return Err(RuntimeError::new(self.modules.cycle(&path)));
+        }
+        let source = fs::read_to_string(&path).map_err(|error| {
+            RuntimeError::new(format!("Cannot read '{}': {}", path.display(), error))
+        })?;
+        let project = self.modules.project.clone();
+        let program =
+            lower(&source, project.as_deref().map(|(config, _)| config)).map_err(|error| {
+                RuntimeError::new(format!("In '{}': {}", display_name(&path), error))
+            })?;
+
+        // The module sees the builtins, but not the globals of the script
+        // importing it
+        let mut prelude = Environment::new();
+        for (name, value) in self.globals.lock().bindings() {
+            if let Value::NativeFunction(_) = value {
+                prelude.define(&name, value)?;
+            }
+        }
+        let scope = Scope::new(Environment::with_parent(Scope::new(prelude)));
+        let directory = path.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+        let enclosing = std::mem::replace(&mut self.modules.directory, directory);
+        self.modules.loading.push(path.clone());
+        let result = self.with_environment(scope.clone(), |interpreter| {
+            for statement in &program.statements {
+                if let Flow::Return(_) = interpreter.execute(statement)? {
+                    return Err(RuntimeError::new("Cannot return from top-level code"));
+                }
+            }
+            Ok(())
+        });
+        self.modules.loading.pop();
+        self.modules.directory = enclosing;
+        // The error stays catchable, or not, and keeps any thrown value
+        result.map_err(|error| {
+            let line = error.span.map(|span| format!(" at line {}", span.line));
+            RuntimeError {
+                message: format!(
+                    "In '{}'{}: {}",
+                    display_name(&path),
+                    line.unwrap_or_default(),
+                    error.message
+                ),
+                span: None,
+                trace: Vec::new(),
+                ..error
+            }
+        })?;
+
+        let module = Value::Module(Arc::new(Module::new(name.to_string(), scope)));
+        self.modules.loaded.insert(path, module.clone());
+        Ok(module)
+    }
+
+    fn execute_for(&mut self, for_node: &ForNode) -> RuntimeResult<Flow> {
+        if let Some(initializer) = &for_node.initializer {
+            self.execute(initializer)?;
+        }
+        loop {
+            if let Some(condition) = &for_node.condition {
+                if !self.condition(condition)? {
+                    return Ok(Flow::Normal);
+                }
+            }
+            if let Flow::Return(value) = self.execute(&for_node.body)? {
+                return Ok(Flow::Return(value));
+            }
+            if let Some(increment) = &for_node.increment {
+                self.evaluate(increment)?;
+            }
+        }
+    }
+
+    // Each pass runs the body in a scope of its own holding the loop
+    // variable, so closures created in it keep that pass's value
+    fn execute_for_in(&mut self, for_in: &ForInNode) -> RuntimeResult<Flow> {
+        let values: Box<dyn Iterator<Item = Value>> = match &for_in.iterable {
+            Expr::Range(range) => Box::new(self.range(range)?.map(Value::Integer)),
+            iterable => match self.evaluate(iterable)? {
+                Value::Array(elements) => Box::new((*elements).clone().into_iter()),
+                other => {
+                    return Err(RuntimeError::new(format!(
+                        "Cannot loop over {}",
+                        other.type_name()
+                    )))
+                }
+            },
+        };
+        for value in values {
+            let mut scope = Environment::with_parent(self.environment.clone());
+            scope.define(&for_in.variable, value)?;
+            let flow = self.with_environment(Scope::new(scope), |interpreter| {
+                interpreter.execute(&for_in.body)
+            })?;
+            if let Flow::Return(value) = flow {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // The ints a `for` loop's range counts over, up from its start by its
+    // step
+    fn range(&mut self, range: &RangeNode) -> RuntimeResult<Box<dyn Iterator<Item = i64>>> {
+        let mut bound = |expr: &Expr| match self.evaluate(expr)? {
+            Value::Integer(value) => Ok(value),
+            other => Err(RuntimeError::new(format!(
+                "Range bounds and steps must be int, found {}",
+                other.type_name()
+            ))
+            .located(expr.span())),
+        };
+        let start = bound(&range.start)?;
+        let end = bound(&range.end)?;
+        let step = match &range.step {
+            Some(step) => match bound(step)? {
+                step if step > 0 => step as usize,
+                step => {
+                    return Err(RuntimeError::new(format!(
+                        "Range step must be positive, found {}",
+                        step
+                    )))
+                }
+            },
+            None => 1,
+        };
+        Ok(if range.inclusive {
+            Box::new((start..=end).step_by(step))
+        } else {
+            Box::new((start..end).step_by(step))
+        })
+    }
+
+    fn evaluate_arguments(&mut self, arguments: &[Expr]) -> RuntimeResult<Vec<Value>> {
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            values.push(self.evaluate(argument)?);
+        }
+        Ok(values)
+    }
+
+    // `span` is the call site, recorded in the trace if the call fails
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        span: Option<Span>,
+    ) -> RuntimeResult<Value> {
+        let function = match callee {
+            Value::Function(function) => function,
+            Value::NativeFunction(native) => {
+                if let Some(arity) = native.arity {
+                    check_arity(&native.name, arity, arguments.len())?;
+                }
+                return native.call(self, arguments);
+            }
+            other => {
+                return Err(RuntimeError::new(format!(
+                    "Can only call functions, found {}",
+                    other.type_name()
+                )))
+            }
+        };
+        let parameters = &function.declaration.parameters;
+        check_arity(
+            &function.declaration.name,
+            parameters.len(),
+            arguments.len(),
+        )?;
+
+        if self.calls.len() >= self.max_call_depth {
+            return Err(RuntimeError::new(format!(
+                "Stack overflow: calls nested more than {} deep",
+                self.max_call_depth
+            )));
+        }
+
+        let mut scope = Environment::with_parent(function.closure.clone());
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            scope.define(
+                &parameter.name,
+                converted(argument, parameter.data_type.as_ref()),
+            )?;
+        }
+        let scope = Scope::new(scope);
+        let name = &function.declaration.name;
+        let started = self.profile.as_ref().map(|_| Instant::now());
+        self.calls.push(StackFrame {
+            function: name.clone(),
+            span,
+        });
+        let frame_start = std::mem::replace(&mut self.frame_start, self.executing.len());
+        let flow = self.execute_block(&function.declaration.body, scope);
+        self.frame_start = frame_start;
+        self.calls.pop();
+        if let (Some(profile), Some(started)) = (&mut self.profile, started) {
+            let recursive = self.calls.iter().any(|frame| &frame.function == name);
+            let time = if recursive {
+                Duration::ZERO
+            } else {
+                started.elapsed()
+            };
+            profile.record_call(name, time);
+        }
+        let flow = flow.map_err(|mut error| {
+            error.trace.push(StackFrame {
+                function: function.declaration.name.clone(),
+                span,
+            });
+            error
+        })?;
+        match flow {
+            Flow::Return(value) => Ok(converted(value, function.declaration.return_type.as_ref())),
+            Flow::Normal => Ok(Value::Null),
+        }
+    }
+
+    /// Evaluates an expression to its value.
+    pub fn evaluate(&mut self, expr: &Expr) -> RuntimeResult<Value> {
+        self.charge()
+            .and_then(|()| self.evaluate_kind(expr))
+            .and_then(|value| self.within_size_limit(value))
+            .map_err(|error| error.located(expr.span()))
+    }
+
+    // Starts a thread running `body` in a scope nested in the current one.
+    // It shares the globals, output and time limit of this interpreter but
+    // has its own instruction count and random generator, and runs without
+    // the debugger, profiler or trace
+    fn spawn(&mut self, body: &[Stmt]) -> RuntimeResult<Value> {
+        let body = body.to_vec();
+        let globals = self.globals.clone();
+        let environment = self.environment.clone();
+        let output = Arc::clone(&self.output);
+        let file_access = self.file_access;
+        let rng = Rng::new(self.rng.next_u64());
+        let modules = self.modules.clone();
+        let limits = self.limits;
+        let max_call_depth = self.max_call_depth;
+        let deadline = self.deadline;
+        let cancel = self.cancel.clone();
+        let cancels_before = self.cancels_before;
+        let thread = thread::Builder::new()
+            .stack_size((max_call_depth + 1) * STACK_PER_CALL)
+            .spawn(move || {
+                let mut interpreter = Interpreter {
+                    environment: environment.clone(),
+                    globals,
+                    output,
+                    file_access,
+                    rng,
+                    modules,
+                    debugger: None,
+                    calls: Vec::new(),
+                    profile: None,
+                    trace: None,
+                    trace_source: None,
+                    executing: Vec::new(),
+                    frame_start: 0,
+                    last_value: Value::Null,
+                    limits,
+                    max_call_depth,
+                    instructions: 0,
+                    deadline,
+                    cancel,
+                    cancels_before,
+                };
+                match interpreter.execute_block(&body, environment)? {
+                    Flow::Return(value) => Ok(value),
+                    Flow::Normal => Ok(Value::Null),
+                }
+            })
+            .map_err(|error| RuntimeError::new(format!("Cannot start a thread: {}", error)))?;
+        Ok(Value::Thread(Arc::new(ThreadHandle::new(thread))))
+    }
+
+    /// A handle that stops whatever this interpreter is running when
+    /// cancelled, for use from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// The error that stops the run once its cancel handle is cancelled,
+    /// or `Ok` if it hasn't been.
+    pub fn check_cancelled(&self) -> RuntimeResult<()> {
+        if self.cancel.cancels() != self.cancels_before {
+            return Err(RuntimeError::new("Script cancelled").uncatchable());
+        }
+        Ok(())
+    }
+
+    /// When the current run's time is up, if it has a timeout.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The error that stops a run once its time is up.
+    pub fn time_limit_exceeded(&self) -> RuntimeError {
+        RuntimeError::new(format!(
+            "Time limit of {:?} exceeded",
+            self.limits.timeout.unwrap_or_default()
+        ))
+        .uncatchable()
+    }
+
+    // Counts one instruction, failing once the run is over its limits
+    fn charge(&mut self) -> RuntimeResult<()> {
+        self.check_cancelled()?;
+        self.instructions += 1;
+        if let Some(max) = self.limits.max_instructions {
+            if self.instructions > max {
+                return Err(
+                    RuntimeError::new(format!("Instruction limit of {} exceeded", max))
+                        .uncatchable(),
+                );
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(self.time_limit_exceeded());
+            }
+        }
+        Ok(())
+    }
+
+    // Stores `value` in a variable or an array element. An array nothing
+    // else holds is changed in place; a shared one is copied first, so the
+    // change is seen only through this variable
+    fn assign(&mut self, target: &Expr, value: Value) -> RuntimeResult<()> {
+        let name = assignment_target(target)?;
+        if let Expr::MemberAccess(member) = target {
+            return self.environment.lock().update(name, |object| match object {
+                Value::Struct(instance) => {
+                    let instance = Arc::make_mut(instance);
+                    let name = instance.name.clone();
+                    let field = instance
+                        .field_mut(&member.member)
+                        .ok_or_else(|| no_field(&name, &member.member))?;
+                    *field = value;
+                    Ok(())
+                }
+                other => Err(RuntimeError::new(format!(
+                    "Cannot assign to member '{}' of {}",
+                    member.member,
+                    other.type_name()
+                ))),
+            });
+        }
+        let Expr::Index(index) = target else {
+            return self.environment.lock().assign(name, value);
+        };
+        let position = self.evaluate(&index.index)?;
+        self.environment.lock().update(name, |object| match object {
+            Value::Array(elements) => {
+                let i = element_index(elements.len(), &position, "an array")?;
+                Arc::make_mut(elements)[i] = value;
+                Ok(())
+            }
+            other => Err(cannot_index(other)),
+        })
+    }
+
+    fn within_size_limit(&self, value: Value) -> RuntimeResult<Value> {
+        self.check_value_size(heap_size(&value))?;
+        Ok(value)
+    }
+
+    // Fails if a value taking up `bytes` would go over the size limit.
+    // Builtins that allocate a lot at once check before they do
+    pub(crate) fn check_value_size(&self, bytes: usize) -> RuntimeResult<()> {
+        match self.limits.max_value_size {
+            Some(max) if bytes > max => Err(RuntimeError::new(format!(
+                "Value size limit of {} bytes exceeded",
+                max
+            ))
+            .uncatchable()),
+            _ => Ok(()),
+        }
+    }
+
+    fn evaluate_kind(&mut self, expr: &Expr) -> RuntimeResult<Value> {
+        match expr {
+            Expr::Number(number) => number_value(number),
+            Expr::String(string) => Ok(Value::String(string.value.as_str().into())),
+            Expr::Char(char_node) => Ok(Value::Char(char_node.value)),
+            Expr::Boolean(boolean) => Ok(Value::Boolean(boolean.value)),
+            Expr::Null(_) => Ok(Value::Null),
+            Expr::Variable(variable) => self
+                .environment
+                .lock()
+                .get(&variable.name)
+                .ok_or_else(|| undefined_variable(&variable.name)),
+            Expr::Assignment(assignment) => {
+                assignment_target(&assignment.target)?;
+                let value = self.evaluate(&assignment.value)?;
+                // The target's type, where the checker has resolved it
+                let value = converted(value, assignment.target.resolved().data_type());
+                self.assign(&assignment.target, value.clone())?;
+                Ok(value)
+            }
+            Expr::CompoundAssignment(assignment) => {
+                assignment_target(&assignment.target)?;
+                let current = self.evaluate(&assignment.target)?;
+                let operand = self.evaluate(&assignment.value)?;
+                let value = binary_operation(current, assignment.operator, operand)?;
+                self.assign(&assignment.target, value.clone())?;
+                Ok(value)
+            }
+            Expr::Call(call) => {
+                let callee = self.evaluate(&call.callee)?;
+                let arguments = self.evaluate_arguments(&call.arguments)?;
+                self.call(callee, arguments, call.span.0)
+            }
+            Expr::MemberAccess(member) => {
+                let object = self.evaluate(&member.object)?;
+                match object {
+                    Value::Null if member.optional => Ok(Value::Null),
+                    Value::Module(module) => module_member(&module, &member.member),
+                    Value::Struct(value) => value
+                        .field(&member.member)
+                        .cloned()
+                        .ok_or_else(|| no_field(&value.name, &member.member)),
+                    other => Err(RuntimeError::new(format!(
+                        "Cannot access member '{}' of {}",
+                        member.member,
+                        other.type_name()
+                    ))),
+                }
+            }
+            // `x.f(a)` calls the function `f` with `x` as its first argument,
+            // unless `x` is a module, whose function `f` is called with `a`
+            Expr::MethodCall(method_call) => {
+                let object = self.evaluate(&method_call.object)?;
+                if method_call.optional && object == Value::Null {
+                    return Ok(Value::Null);
+                }
+                if let Value::Module(module) = &object {
+                    let callee = module_member(module, &method_call.method)?;
+                    let arguments = self.evaluate_arguments(&method_call.arguments)?;
+                    return self.call(callee, arguments, method_call.span.0);
+                }
+                let callee = self
+                    .environment
+                    .lock()
+                    .get(&method_call.method)
+                    .ok_or_else(|| {
+                        RuntimeError::new(format!(
+                            "Undefined method '{}' on {}",
+                            method_call.method,
+                            object.type_name()
+                        ))
+                    })?;
+                let mut arguments = vec![object];
+                arguments.extend(self.evaluate_arguments(&method_call.arguments)?);
+                self.call(callee, arguments, method_call.span.0)
+            }
+            // `&&` and `||` only evaluate their right side when needed
+            Expr::Binary(binary)
+                if matches!(binary.operator, BinaryOperator::And | BinaryOperator::Or) =>
+            {
+                let left = self.logical_operand(&binary.left, binary.operator)?;
+                if left == (binary.operator == BinaryOperator::Or) {
+                    return Ok(Value::Boolean(left));
+                }
+                let right = self.logical_operand(&binary.right, binary.operator)?;
+                Ok(Value::Boolean(right))
+            }
+            Expr::Binary(binary) => {
+                let left = self.evaluate(&binary.left)?;
+                let right = self.evaluate(&binary.right)?;
+                // A join is measured before it is built
+                if binary.operator == BinaryOperator::Add
+                    && matches!(
+                        (&left, &right),
+                        (Value::String(_), Value::String(_)) | (Value::Array(_), Value::Array(_))
+                    )
+                {
+                    self.check_value_size(heap_size(&left) + heap_size(&right))?;
+                }
+                binary_operation(left, binary.operator, right)
+            }
+            Expr::Unary(unary) => {
+                let operand = self.evaluate(&unary.operand)?;
+                unary_operation(unary.operator, operand)
+            }
+            Expr::Cast(cast) => {
+                let value = self.evaluate(&cast.expression)?;
+                cast_value(value, &cast.data_type)
+            }
+            Expr::Parenthesis(paren) => self.evaluate(&paren.expression),
+            Expr::Conditional(conditional) => {
+                let value = if self.condition(&conditional.condition)? {
+                    self.evaluate(&conditional.then_expression)?
+                } else {
+                    self.evaluate(&conditional.else_expression)?
+                };
+                // An int and a float branch make a float, once checked
+                Ok(converted(value, expr.resolved().data_type()))
+            }
+            Expr::Match(match_node) => {
+                let subject = self.evaluate(&match_node.subject)?;
+                for arm in &match_node.arms {
+                    if self.matches(&arm.pattern, &subject)? {
+                        return self.evaluate(&arm.value);
+                    }
+                }
+                Err(RuntimeError::new(format!(
+                    "No match arm matches the value {}",
+                    subject
+                )))
+            }
+            Expr::Index(index) => {
+                let object = self.evaluate(&index.object)?;
+                if index.optional && object == Value::Null {
+                    return Ok(Value::Null);
+                }
+                let position = self.evaluate(&index.index)?;
+                match &object {
+                    Value::Array(elements) => {
+                        Ok(elements[element_index(elements.len(), &position, "an array")?].clone())
+                    }
+                    Value::Tensor(tensor) => tensor_row(tensor, &position),
+                    other => Err(cannot_index(other)),
+                }
+            }
+            Expr::TensorLiteral(literal) => {
+                let mut data = Vec::with_capacity(literal.elements.len());
+                for element in &literal.elements {
+                    let value = self.evaluate(element)?;
+                    let number = value.as_float().ok_or_else(|| {
+                        RuntimeError::new(format!(
+                            "Tensor elements must be numbers, found {}",
+                            value.type_name()
+                        ))
+                        .located(element.span())
+                    })?;
+                    data.push(number);
+                }
+                let tensor = Tensor::new(data, literal.shape.clone());
+                Ok(Value::Tensor(Arc::new(tensor)))
+            }
+            // A lambda closes over the scope it is evaluated in, sharing
+            // its variables rather than copying them
+            Expr::Lambda(lambda) => {
+                let body = match &lambda.body {
+                    LambdaBody::Expression(value) => {
+                        vec![Stmt::Return(ReturnNode::new(Some((**value).clone())))]
+                    }
+                    LambdaBody::Block(statements) => statements.clone(),
+                };
+                let function = Function {
+                    declaration: FunctionDeclarationNode::new(
+                        "lambda".to_string(),
+                        lambda.parameters.clone(),
+                        lambda.return_type.clone(),
+                        body,
+                    ),
+                    closure: self.environment.clone(),
+                };
+                Ok(Value::Function(Arc::new(function)))
+            }
+            Expr::Spawn(spawn) => self.spawn(&spawn.body),
+            Expr::EnumVariant(variant) => Ok(Value::Enum(Arc::new(EnumValue {
+                enum_name: variant.enum_name.clone(),
+                variant: variant.variant.clone(),
+            }))),
+            // The type checker only lets ranges into a `for` loop's header,
+            // which counts over them without evaluating them
+            Expr::Range(_) => Err(RuntimeError::new(
+                "A range can only be used as what a 'for' loop counts over",
+            )),
+            Expr::Cfg(cfg) => Err(RuntimeError::new(format!(
+                "Configuration setting '{}' was never substituted",
+                cfg.key
+            ))),
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr) -> RuntimeResult<bool> {
+        match self.evaluate(condition)? {
+            Value::Boolean(value) => Ok(value),
+            other => Err(RuntimeError::new(format!(
+                "Condition must be a bool, found {}",
+                other.type_name()
+            ))),
+        }
+    }
+
+    fn logical_operand(&mut self, operand: &Expr, operator: BinaryOperator) -> RuntimeResult<bool> {
+        match self.evaluate(operand)? {
+            Value::Boolean(value) => Ok(value),
+            other => Err(RuntimeError::new(format!(
+                "Operands of '{}' must be bool, found {}",
+                binary_symbol(operator),
+                other.type_name()
+            ))),
+        }
+    }
+
+    fn matches(&mut self, pattern: &Pattern, subject: &Value) -> RuntimeResult<bool> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Literal(literal) => {
+                let value = self.evaluate(literal)?;
+                Ok(values_equal(&value, subject))
+            }
+        }
+    }
+}
+
+fn check_arity(name: &str, expected: usize, found: usize) -> RuntimeResult<()> {
+    if expected == found {
+        return Ok(());
+    }
+    Err(RuntimeError::new(format!(
+        "'{}' expects {} argument{} but got {}",
+        name,
+        expected,
+        if expected == 1 { "" } else { "s" },
+        found
+    )))
+}
+
+// The script a trace quotes statements from, lexed once up front
+struct TraceSource {
+    source: String,
+    // Its tokens, less comments
+    tokens: Vec<Token>,
+    // The byte offset each line starts at
+    line_starts: Vec<usize>,
+}
+
+impl TraceSource {
+    fn new(source: &str) -> Self {
+        let tokens = Lexer::new(source)
+            .tokenize()
+            .into_iter()
+            .filter(|token| token.token_type != TokenType::Comment)
+            .collect();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        TraceSource {
+            source: source.to_string(),
+            tokens,
+            line_starts,
+        }
+    }
+
+    // `stmt` as the script writes it, on one line. The statement starts at
+    // its first node, or the keyword before it, and runs to its ';', the
+    // bracket closing around it, or a line break after its last node
+    fn statement(&self, stmt: &Stmt) -> Option<String> {
+        let (first, last) = span_bounds(stmt)?;
+        let anchor = self
+            .tokens
+            .partition_point(|token| position(token) < (first.line, first.column));
+        let keyword = match stmt {
+            Stmt::VarDeclaration(_) => Some(TokenType::Var),
+            Stmt::ConstDeclaration(_) => Some(TokenType::Const),
+            Stmt::Return(_) => Some(TokenType::Return),
+            _ => None,
+        };
+        let mut start = anchor;
+        match keyword {
+            Some(keyword) => {
+                start = self.tokens[..anchor]
+                    .iter()
+                    .rposition(|token| token.token_type == keyword)?;
+            }
+            // Brackets and prefix operators come before an expression's
+            // first node, but not the '(' of a `for` header
+            None => {
+                while start > 0
+                    && matches!(
+                        self.tokens[start - 1].token_type,
+                        TokenType::LeftParen
+                            | TokenType::LeftBracket
+                            | TokenType::Minus
+                            | TokenType::Bang
+                    )
+                    && !(start > 1 && self.tokens[start - 2].token_type == TokenType::For)
+                {
+                    start -= 1;
+                }
+            }
+        }
+        let mut end = start;
+        let mut depth = 0;
+        for (index, token) in self.tokens.iter().enumerate().skip(start) {
+            let line_break = index > start && token.line > self.tokens[index - 1].end_line();
+            if depth == 0 && line_break && position(token) > (last.line, last.column) {
+                break;
+            }
+            match token.token_type {
+                TokenType::EOF => break,
+                TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                TokenType::Comma if depth == 0 => break,
+                TokenType::Semicolon if depth == 0 => {
+                    end = index;
+                    break;
+                }
+                _ => {}
+            }
+            end = index;
+        }
+        let from = self.offset(&self.tokens[start])?;
+        let to = self.offset(&self.tokens[end])? + self.tokens[end].lexeme.len();
+        let text = self.source.get(from..to)?;
+        Some(text.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+    }
+
+    // Where `token` starts in the source, in bytes
+    fn offset(&self, token: &Token) -> Option<usize> {
+        let start = *self.line_starts.get(token.line.checked_sub(1)?)?;
+        let (offset, _) = self.source[start..]
+            .char_indices()
+            .nth(token.column.checked_sub(1)?)?;
+        Some(start + offset)
+    }
+}
+
+fn position(token: &Token) -> (usize, usize) {
+    (token.line, token.column)
+}
+
+// The first and last of the spans in `stmt` itself, not counting the
+// statements nested in it
+fn span_bounds(stmt: &Stmt) -> Option<(Span, Span)> {
+    struct Bounds(Option<(Span, Span)>);
+
+    impl Bounds {
+        fn add(&mut self, span: Span) {
+            let key = |span: &Span| (span.line, span.column);
+            self.0 = Some(match self.0 {
+                Some((first, last)) => (
+                    if key(&span) < key(&first) {
+                        span
+                    } else {
+                        first
+                    },
+                    if key(&span) > key(&last) { span } else { last },
+                ),
+                None => (span, span),
+            });
+        }
+    }
+
+    impl Visitor for Bounds {
+        fn visit_stmt(&mut self, _: &Stmt) {}
+
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Some(span) = expr.span() {
+                self.add(span);
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    let mut bounds = Bounds(None);
+    match stmt {
+        Stmt::VarDeclaration(VarDeclarationNode { span, .. })
+        | Stmt::ConstDeclaration(ConstDeclarationNode { span, .. }) => {
+            if let Some(span) = span.0 {
+                bounds.add(span);
+            }
+        }
+        _ => {}
+    }
+    walk_stmt(&mut bounds, stmt);
+    bounds.0
+}
+
+// Roughly how many bytes `value` holds beyond its own size, which is what
+// grows with what a script does
+fn heap_size(value: &Value) -> usize {
+    match value {
+        Value::String(text) => text.len(),
+        Value::Array(elements) => elements
+            .iter()
+            .map(|element| std::mem::size_of::<Value>() + heap_size(element))
+            .sum(),
+        Value::Tensor(tensor) => tensor.shape().iter().product::<usize>() * 8,
+        Value::Struct(value) => value
+            .fields
+            .iter()
+            .map(|(name, field)| name.len() + std::mem::size_of::<Value>() + heap_size(field))
+            .sum(),
+        _ => 0,
+    }
+}
+
+// The native function that builds an instance of the declared struct from
+// its fields' values, in declaration order
+fn struct_constructor(declaration: &StructDeclarationNode) -> NativeFunction {
+    let name = declaration.name.clone();
+    let fields = declaration.fields.clone();
+    NativeFunction::new(
+        declaration.name.clone(),
+        Some(fields.len()),
+        move |_, arguments| {
+            Ok(Value::Struct(Arc::new(StructValue {
+                name: name.clone(),
+                fields: fields
+                    .iter()
+                    .zip(arguments)
+                    .map(|(field, value)| {
+                        (field.name.clone(), converted(value, Some(&field.data_type)))
+                    })
+                    .collect(),
+            })))
+        },
+    )
+}
+
+// `value` as a variable, parameter, field or result declared `data_type`
+// holds it: an int becomes a float where one is expected, the only
+// conversion that needs no cast
+fn converted(value: Value, data_type: Option<&DataType>) -> Value {
+    match (value, data_type) {
+        (Value::Integer(value), Some(DataType::Float)) => Value::Float(value as f64),
+        (Value::Integer(value), Some(DataType::Optional(inner))) if **inner == DataType::Float => {
+            Value::Float(value as f64)
+        }
+        (value, _) => value,
+    }
+}
+
+fn no_field(struct_name: &str, field: &str) -> RuntimeError {
+    RuntimeError::new(format!("Struct '{}' has no field '{}'", struct_name, field))
+}
+
+// Where `position` points in `length` elements of `kind`, if it is an int
+// in bounds
+fn element_index(length: usize, position: &Value, kind: &str) -> RuntimeResult<usize> {
+    let i = match position {
+        Value::Integer(i) => *i,
+        other => {
+            return Err(RuntimeError::new(format!(
+                "Array index must be an int, found {}",
+                other.type_name()
+            )))
+        }
+    };
+    usize::try_from(i)
+        .ok()
+        .filter(|&i| i < length)
+        .ok_or_else(|| {
+            RuntimeError::new(format!(
+                "Index {} is out of bounds for {} of length {}",
+                i, kind, length
+            ))
+        })
+}
+
+// Indexing a tensor picks along its first axis, like an array of its rows:
+// a float from a vector, or a tensor one dimension down
+fn tensor_row(tensor: &Tensor, position: &Value) -> RuntimeResult<Value> {
+    let length = tensor.shape().first().copied().unwrap_or(0);
+    let i = element_index(length, position, "a tensor")?;
+    Ok(match tensor.shape() {
+        [_] => Value::Float(tensor.data()[i]),
+        shape => {
+            let rest = &shape[1..];
+            let size: usize = rest.iter().product();
+            let data = tensor.data()[i * size..(i + 1) * size].to_vec();
+            Value::Tensor(Arc::new(Tensor::new(data, rest.to_vec())))
+        }
+    })
+}
+
+fn module_member(module: &Module, name: &str) -> RuntimeResult<Value> {
+    module.get(name).ok_or_else(|| {
+        RuntimeError::new(format!("Module '{}' has no member '{}'", module.name, name))
+    })
+}
+
+fn cannot_index(value: &Value) -> RuntimeError {
+    RuntimeError::new(format!("Cannot index into {}", value.type_name()))
+}
+
+fn undefined_variable(name: &str) -> RuntimeError {
+    RuntimeError::new(format!("Undefined variable '{}'", name))
+}
+
+// The variable an assignment to `target` changes: the variable itself, or
+// the array variable for an element
+fn assignment_target(target: &Expr) -> RuntimeResult<&str> {
+    match target {
+        Expr::Variable(variable) => Ok(&variable.name),
+        Expr::Index(index) if matches!(*index.object, Expr::Variable(_)) => {
+            assignment_target(&index.object)
+        }
+        Expr::MemberAccess(member)
+            if !member.optional && matches!(*member.object, Expr::Variable(_)) =>
+        {
+            assignment_target(&member.object)
+        }
+        other => Err(RuntimeError::new(format!(
+            "Cannot assign to {}",
+            expression_kind(other)
+        ))),
+    }
+}
+
+fn number_value(number: &NumberNode) -> RuntimeResult<Value> {
+    let value = match number.data_type {
+        DataType::Integer => number.value.parse().map(Value::Integer).ok(),
+        _ => number.value.parse().map(Value::Float).ok(),
+    };
+    value.ok_or_else(|| RuntimeError::new(format!("Invalid number literal '{}'", number.value)))
+}
+
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left.as_float(), right.as_float()) {
+        (Some(a), Some(b)) => a == b,
+        _ => left == right,
+    }
+}
+
+fn unary_operation(operator: UnaryOperator, operand: Value) -> RuntimeResult<Value> {
+    match (operator, operand) {
+        (UnaryOperator::Negate, Value::Integer(value)) => value
+            .checked_neg()
+            .map(Value::Integer)
+            .ok_or_else(|| RuntimeError::new("Integer overflow")),
+        (UnaryOperator::Negate, Value::Float(value)) => Ok(Value::Float(-value)),
+        (UnaryOperator::Not, Value::Boolean(value)) => Ok(Value::Boolean(!value)),
+        (operator, operand) => Err(RuntimeError::new(format!(
+            "Cannot apply '{}' to {}",
+            match operator {
+                UnaryOperator::Negate => "-",
+                UnaryOperator::Not => "!",
+            },
+            operand.type_name()
+        ))),
+    }
+}
+
+// `value as data_type`. Floats truncate toward zero on the way to ints,
+// and strings are parsed as numbers
+fn cast_value(value: Value, data_type: &DataType) -> RuntimeResult<Value> {
+    let failed = |value: &Value| {
+        RuntimeError::new(format!(
+            "Cannot cast {} to {}",
+            match value {
+                Value::String(text) => format!("{:?}", text),
+                other => other.to_string(),
+            },
+            format_type(data_type)
+        ))
+    };
+    Ok(match (value, data_type) {
+        (Value::Null, DataType::Optional(_)) => Value::Null,
+        (value, DataType::Optional(inner)) => cast_value(value, inner)?,
+        // Type parameters are checked statically, then erased
+        (value, DataType::Parameter(_)) => value,
+        (value @ Value::Array(_), DataType::Array(_)) => value,
+        (value @ Value::Integer(_), DataType::Integer)
+        | (value @ Value::Float(_), DataType::Float)
+        | (value @ Value::Boolean(_), DataType::Boolean)
+        | (value @ Value::Char(_), DataType::Char)
+        | (value @ Value::String(_), DataType::String)
+        | (value @ Value::Tensor(_), DataType::Tensor) => value,
+        (Value::Integer(value), DataType::Float) => Value::Float(value as f64),
+        (Value::Float(value), DataType::Integer) => {
+            // From -2^63 up to but not including 2^63, which is i64::MAX
+            // rounded up
+            let range = i64::MIN as f64..-(i64::MIN as f64);
+            if !range.contains(&value.trunc()) {
+                return Err(failed(&Value::Float(value)));
+            }
+            Value::Integer(value.trunc() as i64)
+        }
+        (Value::Boolean(value), DataType::Integer) => Value::Integer(value as i64),
+        (Value::Char(value), DataType::Integer) => Value::Integer(value as i64),
+        (Value::Integer(value), DataType::Char) => u32::try_from(value)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Value::Char)
+            .ok_or_else(|| failed(&Value::Integer(value)))?,
+        (Value::String(text), DataType::Integer) => text
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| failed(&Value::String(text.clone())))?,
+        (Value::String(text), DataType::Float) => text
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| failed(&Value::String(text.clone())))?,
+        (
+            value @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Char(_)),
+            DataType::String,
+        ) => Value::String(value.to_string().into()),
+        (value, _) => {
+            return Err(RuntimeError::new(format!(
+                "Cannot cast {} to {}",
+                value.type_name(),
+                format_type(data_type)
+            )))
+        }
+    })
+}
+
+fn binary_operation(left: Value, operator: BinaryOperator, right: Value) -> RuntimeResult<Value> {
+    use BinaryOperator::*;
+
+    let overflow = || RuntimeError::new("Integer overflow");
+    match operator {
+        Equal => return Ok(Value::Boolean(values_equal(&left, &right))),
+        NotEqual => return Ok(Value::Boolean(!values_equal(&left, &right))),
+        _ => {}
+    }
+
+    let value = match (&left, &right) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            match operator {
+                Add => Value::Integer(a.checked_add(b).ok_or_else(overflow)?),
+                Subtract => Value::Integer(a.checked_sub(b).ok_or_else(overflow)?),
+                Multiply => Value::Integer(a.checked_mul(b).ok_or_else(overflow)?),
+                Divide if b == 0 => return Err(RuntimeError::new("Division by zero")),
+                Divide => Value::Integer(a.checked_div(b).ok_or_else(overflow)?),
+                BitAnd => Value::Integer(a & b),
+                BitOr => Value::Integer(a | b),
+                BitXor => Value::Integer(a ^ b),
+                ShiftLeft | ShiftRight => {
+                    let shift = u32::try_from(b)
+                        .ok()
+                        .filter(|shift| *shift < i64::BITS)
+                        .ok_or_else(|| {
+                            RuntimeError::new(format!("Shift amount {} is out of range", b))
+                        })?;
+                    Value::Integer(if operator == ShiftLeft {
+                        a << shift
+                    } else {
+                        a >> shift
+                    })
+                }
+                Less | LessEqual | Greater | GreaterEqual => compare(operator, a.cmp(&b)),
+                _ => return Err(mismatch(&left, operator, &right)),
+            }
+        }
+        (Value::String(a), Value::String(b)) => match operator {
+            Add => Value::String(format!("{}{}", a, b).into()),
+            Less | LessEqual | Greater | GreaterEqual => compare(operator, a.cmp(b)),
+            _ => return Err(mismatch(&left, operator, &right)),
+        },
+        (Value::Tensor(a), Value::Tensor(b)) => match operator {
+            MatMul => Value::Tensor(Arc::new(a.matmul(b).map_err(RuntimeError::new)?)),
+            // The element-wise operators broadcast, and panic on shapes
+            // that can't be broadcast together
+            Add | Subtract | Multiply if broadcast_shape(a.shape(), b.shape()).is_none() => {
+                return Err(RuntimeError::new(format!(
+                    "Cannot apply '{}' to tensors of shapes {:?} and {:?}",
+                    binary_symbol(operator),
+                    a.shape(),
+                    b.shape()
+                )))
+            }
+            Add => Value::Tensor(Arc::new(&**a + &**b)),
+            Subtract => Value::Tensor(Arc::new(&**a - &**b)),
+            Multiply => Value::Tensor(Arc::new(&**a * &**b)),
+            _ => return Err(mismatch(&left, operator, &right)),
+        },
+        (Value::Char(a), Value::Char(b))
+            if matches!(operator, Less | LessEqual | Greater | GreaterEqual) =>
+        {
+            compare(operator, a.cmp(b))
+        }
+        // Mixed integer and float arithmetic is carried out in floats
+        _ => {
+            let (a, b) = match (left.as_float(), right.as_float()) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return Err(mismatch(&left, operator, &right)),
+            };
+            match operator {
+                Add => Value::Float(a + b),
+                Subtract => Value::Float(a - b),
+                Multiply => Value::Float(a * b),
+                Divide if b == 0.0 => return Err(RuntimeError::new("Division by zero")),
+                Divide => Value::Float(a / b),
+                Less | LessEqual | Greater | GreaterEqual => match a.partial_cmp(&b) {
+                    Some(ordering) => compare(operator, ordering),
+                    // NaN is unordered: every comparison with it is false
+                    None => Value::Boolean(false),
+                },
+                _ => return Err(mismatch(&left, operator, &right)),
+            }
+        }
+    };
+    Ok(value)
+}
+
+// Called only with an ordering operator
+fn compare(operator: BinaryOperator, ordering: std::cmp::Ordering) -> Value {
+    use std::cmp::Ordering::*;
+
+    Value::Boolean(match operator {
+        BinaryOperator::Less => ordering == Less,
+        BinaryOperator::LessEqual => ordering != Greater,
+        BinaryOperator::Greater => ordering == Greater,
+        _ => ordering != Less,
+    })
+}
+
+fn mismatch(left: &Value, operator: BinaryOperator, right: &Value) -> RuntimeError {
+    RuntimeError::new(format!(
+        "Cannot apply '{}' to {} and {}",
+        binary_symbol(operator),
+        left.type_name(),
+        right.type_name()
+    ))
+}
+
+fn expression_kind(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::MemberAccess(_) => "member access",
+        Expr::Lambda(_) => "lambda",
+        Expr::Spawn(_) => "spawn",
+        _ => "expression",
+    }
+}
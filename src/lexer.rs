@@ -1,132 +1,681 @@
-use crate::token::{Token, TokenType};
-
-pub struct Lexer {
-    source: String,
-    tokens: Vec<Token>,
-    start: usize,
-    current: usize,
-    line: usize,
-}
-
-impl Lexer {
-    pub fn new(source: &str) -> Self {
-        Lexer {
-            source: source.to_string(),
-            tokens: Vec::new(),
-            start: 0,
-            current: 0,
-            line: 1,
-        }
-    }
-
-    pub fn tokenize(&mut self) -> Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
-        }
-
-        self.tokens
-            .push(Token::new(TokenType::EOF, String::new(), self.line));
-        self.tokens.clone()
-    }
-
-    fn scan_token(&mut self) {
-        let c = self.advance();
-        match c {
-            '(' => self.add_token(TokenType::LeftParen),
-            ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
-            ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
-            ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
-            '=' => self.add_token(TokenType::Assign),
-            '/' => self.add_token(TokenType::Slash),
-            ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
-            _ => {
-                if self.is_digit(c) {
-                    self.number();
-                } else if self.is_alpha(c) {
-                    self.identifier();
-                } else {
-                    eprintln!("Unexpected character: {} at line {}", c, self.line);
-                }
-            }
-        }
-    }
-
-    fn add_token(&mut self, token_type: TokenType) {
-        let text = self.source[self.start..self.current].to_string();
-        self.tokens.push(Token::new(token_type, text, self.line));
-    }
-
-    fn number(&mut self) {
-        while self.is_digit(self.peek()) {
-            self.advance();
-        }
-
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
-            self.advance();
-
-            while self.is_digit(self.peek()) {
-                self.advance();
-            }
-        }
-
-        let num = self.source[self.start..self.current].to_string();
-        self.tokens
-            .push(Token::new(TokenType::Number, num, self.line));
-    }
-
-    fn identifier(&mut self) {
-        while self.is_alphanumeric(self.peek()) {
-            self.advance();
-        }
-
-        let text = &self.source[self.start..self.current];
-        let token_type = match text {
-            "var" => TokenType::Var,
-            "if" => TokenType::If,
-            "else" => TokenType::Else,
-            "while" => TokenType::While,
-            // Add other keywords here
-            _ => TokenType::Identifier,
-        };
-
-        self.add_token(token_type);
-    }
-
-    fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
-        c
-    }
-
-    fn peek(&self) -> char {
-        self.source.chars().nth(self.current).unwrap_or('\0')
-    }
-
-    fn peek_next(&self) -> char {
-        self.source.chars().nth(self.current + 1).unwrap_or('\0')
-    }
-
-    fn is_digit(&self, c: char) -> bool {
-        c.is_ascii_digit()
-    }
-
-    fn is_alpha(&self, c: char) -> bool {
-        c.is_ascii_alphabetic() || c == '_'
-    }
-
-    fn is_alphanumeric(&self, c: char) -> bool {
-        self.is_alpha(c) || self.is_digit(c)
-    }
-
-    fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
-    }
-}
+use crate::token::{Token, TokenType};
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+type CharSource = Box<dyn Iterator<Item = io::Result<char>>>;
+
+/// Produces tokens on demand. `Lexer` is an `Iterator<Item = Token>` that
+/// ends with a single `EOF` token, so the parser can pull tokens lazily;
+/// `tokenize` collects the whole stream when a `Vec` is more convenient.
+///
+/// Characters are pulled from the source as they are needed, keeping only
+/// a few characters of lookahead plus the current lexeme in memory.
+pub struct Lexer {
+    source: CharSource,
+    lookahead: VecDeque<char>,
+    lexeme: String,
+    read_error: Option<io::Error>,
+    line: usize,
+    column: usize,
+    start_column: usize,
+    finished: bool,
+}
+
+impl Lexer {
+    pub fn new(source: &str) -> Self {
+        Self::from_source(Box::new(OwnedChars {
+            text: source.to_string(),
+            offset: 0,
+        }))
+    }
+
+    /// Tokenizes incrementally from a buffered reader, one line at a time.
+    /// A read failure (including invalid UTF-8) ends the stream with an
+    /// `Error` token describing it.
+    pub fn from_reader<R: BufRead + 'static>(reader: R) -> Self {
+        Self::from_source(Box::new(ReaderChars {
+            reader,
+            line: OwnedChars {
+                text: String::new(),
+                offset: 0,
+            },
+            done: false,
+        }))
+    }
+
+    fn from_source(source: CharSource) -> Self {
+        Lexer {
+            source,
+            lookahead: VecDeque::new(),
+            lexeme: String::new(),
+            read_error: None,
+            line: 1,
+            column: 1,
+            start_column: 1,
+            finished: false,
+        }
+    }
+
+    pub fn tokenize(&mut self) -> Vec<Token> {
+        self.by_ref().collect()
+    }
+
+    fn scan_token(&mut self) -> Option<Token> {
+        let c = self.advance();
+        match c {
+            '(' => Some(self.make_token(TokenType::LeftParen)),
+            ')' => Some(self.make_token(TokenType::RightParen)),
+            '{' => Some(self.make_token(TokenType::LeftBrace)),
+            '}' => Some(self.make_token(TokenType::RightBrace)),
+            '[' => Some(self.make_token(TokenType::LeftBracket)),
+            ']' => Some(self.make_token(TokenType::RightBracket)),
+            ',' => Some(self.make_token(TokenType::Comma)),
+            '.' => {
+                let token_type = if self.match_char('.') {
+                    if self.match_char('=') {
+                        TokenType::DotDotEqual
+                    } else {
+                        TokenType::DotDot
+                    }
+                } else {
+                    TokenType::Dot
+                };
+                Some(self.make_token(token_type))
+            }
+            '-' => {
+                let token_type = if self.match_char('>') {
+                    TokenType::Arrow
+                } else if self.match_char('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                Some(self.make_token(token_type))
+            }
+            '+' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                Some(self.make_token(token_type))
+            }
+            ';' => Some(self.make_token(TokenType::Semicolon)),
+            ':' => {
+                let token_type = if self.match_char(':') {
+                    TokenType::ColonColon
+                } else {
+                    TokenType::Colon
+                };
+                Some(self.make_token(token_type))
+            }
+            '!' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::BangEqual
+                } else {
+                    TokenType::Bang
+                };
+                Some(self.make_token(token_type))
+            }
+            '?' => {
+                let token_type = if self.match_char('.') {
+                    TokenType::QuestionDot
+                } else {
+                    TokenType::Question
+                };
+                Some(self.make_token(token_type))
+            }
+            '*' => {
+                let token_type = if self.match_char('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                Some(self.make_token(token_type))
+            }
+            '=' => {
+                let token_type = if self.match_char('>') {
+                    TokenType::FatArrow
+                } else if self.match_char('=') {
+                    TokenType::EqualEqual
+                } else {
+                    TokenType::Assign
+                };
+                Some(self.make_token(token_type))
+            }
+            '/' => {
+                if self.match_char('/') {
+                    return Some(self.line_comment());
+                }
+                if self.match_char('*') {
+                    return Some(self.block_comment());
+                }
+                let token_type = if self.match_char('=') {
+                    TokenType::SlashEqual
+                } else {
+                    TokenType::Slash
+                };
+                Some(self.make_token(token_type))
+            }
+            '&' => {
+                let token_type = if self.match_char('&') {
+                    TokenType::And
+                } else {
+                    TokenType::Ampersand
+                };
+                Some(self.make_token(token_type))
+            }
+            '|' => {
+                let token_type = if self.match_char('|') {
+                    TokenType::Or
+                } else {
+                    TokenType::Pipe
+                };
+                Some(self.make_token(token_type))
+            }
+            '^' => Some(self.make_token(TokenType::Caret)),
+            '@' => Some(self.make_token(TokenType::At)),
+            '<' => {
+                let token_type = if self.match_char('<') {
+                    TokenType::ShiftLeft
+                } else if self.match_char('=') {
+                    TokenType::LessEqual
+                } else {
+                    TokenType::Less
+                };
+                Some(self.make_token(token_type))
+            }
+            '>' => {
+                let token_type = if self.match_char('>') {
+                    TokenType::ShiftRight
+                } else if self.match_char('=') {
+                    TokenType::GreaterEqual
+                } else {
+                    TokenType::Greater
+                };
+                Some(self.make_token(token_type))
+            }
+            '"' => Some(self.string()),
+            '\'' => Some(self.char_literal()),
+            ' ' | '\r' | '\t' => None,
+            '\n' => {
+                self.line += 1;
+                self.column = 1;
+                None
+            }
+            _ => {
+                if Self::is_digit(c) {
+                    Some(self.number())
+                } else if Self::is_alpha(c) {
+                    Some(self.identifier())
+                } else {
+                    Some(self.make_token(TokenType::Error))
+                }
+            }
+        }
+    }
+
+    fn make_token(&self, token_type: TokenType) -> Token {
+        self.token_from(token_type, self.line)
+    }
+
+    // A token whose lexeme may span lines, placed on the line it starts on
+    fn token_from(&self, token_type: TokenType, start_line: usize) -> Token {
+        Token::new(token_type, self.lexeme(), start_line, self.start_column)
+    }
+
+    fn lexeme(&self) -> String {
+        self.lexeme.clone()
+    }
+
+    fn number(&mut self) -> Token {
+        while Self::is_digit(self.peek()) {
+            self.advance();
+        }
+
+        if self.peek() == '.' && Self::is_digit(self.peek_next()) {
+            self.advance();
+
+            while Self::is_digit(self.peek()) {
+                self.advance();
+            }
+        }
+
+        self.make_token(TokenType::Number)
+    }
+
+    /// Scans a string literal. The token keeps the raw source text, quotes
+    /// and escapes included; `string_value` decodes it. An unterminated
+    /// literal becomes an `Error` token. Either way the token is placed
+    /// where the literal starts.
+    fn string(&mut self) -> Token {
+        let start_line = self.line;
+        if self.peek() == '"' && self.peek_next() == '"' {
+            self.advance();
+            self.advance();
+            return self.multiline_string(start_line);
+        }
+
+        while self.peek() != '"' && !self.is_at_end() {
+            let mut c = self.advance();
+            if c == '\\' && !self.is_at_end() {
+                c = self.advance();
+            }
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+
+        if self.is_at_end() {
+            return self.token_from(TokenType::Error, start_line);
+        }
+
+        self.advance();
+        self.token_from(TokenType::String, start_line)
+    }
+
+    /// Scans a triple-quoted `"""..."""` string, which may span lines.
+    fn multiline_string(&mut self, start_line: usize) -> Token {
+        loop {
+            if self.is_at_end() {
+                return self.token_from(TokenType::Error, start_line);
+            }
+            if self.peek() == '"' && self.peek_next() == '"' && self.peek_at(2) == '"' {
+                break;
+            }
+
+            let mut c = self.advance();
+            if c == '\\' && !self.is_at_end() {
+                c = self.advance();
+            }
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+
+        for _ in 0..3 {
+            self.advance();
+        }
+        self.token_from(TokenType::String, start_line)
+    }
+
+    /// Scans a `//` comment up to (not including) the end of the line.
+    fn line_comment(&mut self) -> Token {
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+        self.make_token(TokenType::Comment)
+    }
+
+    /// Scans a `/* ... */` comment, which may span lines; the token is
+    /// placed where the comment starts. An unterminated comment becomes an
+    /// `Error` token.
+    fn block_comment(&mut self) -> Token {
+        let start_line = self.line;
+        loop {
+            if self.is_at_end() {
+                return self.token_from(TokenType::Error, start_line);
+            }
+            if self.peek() == '*' && self.peek_next() == '/' {
+                break;
+            }
+            if self.advance() == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+
+        self.advance();
+        self.advance();
+        self.token_from(TokenType::Comment, start_line)
+    }
+
+    /// Scans a character literal such as `'a'` or `'\n'`. Like strings, the
+    /// token keeps its raw text and is decoded with `char_value`.
+    fn char_literal(&mut self) -> Token {
+        while self.peek() != '\'' && self.peek() != '\n' && !self.is_at_end() {
+            if self.advance() == '\\' && !self.is_at_end() {
+                self.advance();
+            }
+        }
+
+        if self.peek() != '\'' {
+            return self.make_token(TokenType::Error);
+        }
+
+        self.advance();
+        self.make_token(TokenType::Char)
+    }
+
+    fn identifier(&mut self) -> Token {
+        while Self::is_alphanumeric(self.peek()) {
+            self.advance();
+        }
+
+        let text = self.lexeme();
+        let token_type = match text.as_str() {
+            "var" => TokenType::Var,
+            "const" => TokenType::Const,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "while" => TokenType::While,
+            "do" => TokenType::Do,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "struct" => TokenType::Struct,
+            "enum" => TokenType::Enum,
+            "match" => TokenType::Match,
+            "fn" => TokenType::Fun,
+            "return" => TokenType::Return,
+            "breakpoint" => TokenType::Breakpoint,
+            "spawn" => TokenType::Spawn,
+            "try" => TokenType::Try,
+            "throw" => TokenType::Throw,
+            "catch" => TokenType::Catch,
+            "true" => TokenType::True,
+            "false" => TokenType::False,
+            "null" => TokenType::Nil,
+            "use" => TokenType::Use,
+            "as" => TokenType::As,
+            "import" => TokenType::Import,
+            // Add other keywords here
+            _ => TokenType::Identifier,
+        };
+
+        self.make_token(token_type)
+    }
+
+    fn advance(&mut self) -> char {
+        self.fill(1);
+        let c = self.lookahead.pop_front().unwrap_or('\0');
+        self.lexeme.push(c);
+        self.column += 1;
+        c
+    }
+
+    /// Ensures up to `count` characters are buffered in `lookahead`.
+    fn fill(&mut self, count: usize) {
+        while self.lookahead.len() < count && self.read_error.is_none() {
+            match self.source.next() {
+                Some(Ok(c)) => self.lookahead.push_back(c),
+                Some(Err(error)) => self.read_error = Some(error),
+                None => break,
+            }
+        }
+    }
+
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.peek() == expected && !self.is_at_end() {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn peek(&mut self) -> char {
+        self.peek_at(0)
+    }
+
+    fn peek_next(&mut self) -> char {
+        self.peek_at(1)
+    }
+
+    fn peek_at(&mut self, offset: usize) -> char {
+        self.fill(offset + 1);
+        self.lookahead.get(offset).copied().unwrap_or('\0')
+    }
+
+    fn is_digit(c: char) -> bool {
+        c.is_ascii_digit()
+    }
+
+    fn is_alpha(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == '_'
+    }
+
+    fn is_alphanumeric(c: char) -> bool {
+        Self::is_alpha(c) || Self::is_digit(c)
+    }
+
+    fn is_at_end(&mut self) -> bool {
+        self.fill(1);
+        self.lookahead.is_empty()
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while !self.is_at_end() {
+            self.lexeme.clear();
+            self.start_column = self.column;
+            if let Some(token) = self.scan_token() {
+                return Some(token);
+            }
+        }
+
+        if let Some(error) = self.read_error.take() {
+            return Some(Token::new(
+                TokenType::Error,
+                error.to_string(),
+                self.line,
+                self.column,
+            ));
+        }
+
+        if self.finished {
+            return None;
+        }
+        self.finished = true;
+        Some(Token::new(
+            TokenType::EOF,
+            String::new(),
+            self.line,
+            self.column,
+        ))
+    }
+}
+
+/// Walks the characters of an owned string, keeping a byte offset into it
+/// rather than a decoded copy of the text.
+struct OwnedChars {
+    text: String,
+    offset: usize,
+}
+
+impl Iterator for OwnedChars {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<io::Result<char>> {
+        let c = self.text[self.offset..].chars().next()?;
+        self.offset += c.len_utf8();
+        Some(Ok(c))
+    }
+}
+
+/// Adapts a `BufRead` into a stream of characters, reading a line at a time.
+struct ReaderChars<R> {
+    reader: R,
+    line: OwnedChars,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for ReaderChars<R> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<io::Result<char>> {
+        loop {
+            if let Some(c) = self.line.next() {
+                return Some(c);
+            }
+            if self.done {
+                return None;
+            }
+            self.line.text.clear();
+            self.line.offset = 0;
+            match self.reader.read_line(&mut self.line.text) {
+                Ok(0) => self.done = true,
+                Ok(_) => {}
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Describes the problem an `Error` token stands for: an unterminated
+/// literal or comment, a character no token starts with, or a failure to
+/// read the source.
+pub fn error_message(lexeme: &str) -> String {
+    if lexeme.starts_with('"') {
+        "Unterminated string".to_string()
+    } else if lexeme.starts_with('\'') {
+        "Unterminated character literal".to_string()
+    } else if lexeme.starts_with("/*") {
+        "Unterminated comment".to_string()
+    } else if lexeme.chars().count() == 1 {
+        format!("Unexpected character '{}'", lexeme)
+    } else {
+        lexeme.to_string()
+    }
+}
+
+/// Returns the decoded value of a `String` token's lexeme.
+///
+/// Triple-quoted strings keep their text verbatim, unless the opening
+/// `"""` is directly followed by a newline: such text blocks drop that
+/// newline and strip the indentation common to all non-blank lines, with
+/// a whitespace-only closing line counting towards the indentation.
+pub fn string_value(lexeme: &str) -> Result<String, String> {
+    if let Some(body) = lexeme
+        .strip_prefix("\"\"\"")
+        .and_then(|rest| rest.strip_suffix("\"\"\""))
+    {
+        return match body.strip_prefix('\n') {
+            Some(block) => unescape(&dedent(block)),
+            None => unescape(body),
+        };
+    }
+
+    let body = lexeme
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(|| format!("Malformed string literal {}.", lexeme))?;
+    unescape(body)
+}
+
+fn dedent(block: &str) -> String {
+    let lines: Vec<&str> = block.split('\n').collect();
+    let indent_of = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+
+    let closing = lines.last().copied().unwrap_or("");
+    let closing_is_blank = closing.trim().is_empty();
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_of(line))
+        .chain(closing_is_blank.then_some(closing.len()))
+        .min()
+        .unwrap_or(0);
+
+    let mut stripped: Vec<&str> = lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                ""
+            } else {
+                &line[indent..]
+            }
+        })
+        .collect();
+    if closing_is_blank {
+        stripped.pop();
+        stripped.push("");
+    }
+    stripped.join("\n")
+}
+
+/// Returns the decoded value of a `Char` token's lexeme, which must hold
+/// exactly one character after escapes are processed.
+pub fn char_value(lexeme: &str) -> Result<char, String> {
+    let body = lexeme
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+        .ok_or_else(|| format!("Malformed character literal {}.", lexeme))?;
+    let decoded = unescape(body)?;
+    let mut chars = decoded.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        (None, _) => Err("Empty character literal.".to_string()),
+        _ => Err(format!(
+            "Character literal {} must contain exactly one character.",
+            lexeme
+        )),
+    }
+}
+
+/// Decodes the escape sequences in the body of a string literal:
+/// `\n`, `\t`, `\r`, `\0`, `\"`, `\'`, `\\` and `\u{XXXX}`.
+pub fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('"') => result.push('"'),
+            Some('\'') => result.push('\''),
+            Some('\\') => result.push('\\'),
+            Some('u') => {
+                if chars.next() != Some('{') {
+                    return Err("Expect '{' after '\\u' in escape sequence.".to_string());
+                }
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(d) if d.is_ascii_hexdigit() && digits.len() < 6 => digits.push(d),
+                        Some(d) => {
+                            return Err(format!(
+                                "Invalid character '{}' in unicode escape '\\u{{{}'.",
+                                d, digits
+                            ))
+                        }
+                        None => return Err("Unterminated unicode escape.".to_string()),
+                    }
+                }
+                if digits.is_empty() {
+                    return Err("Empty unicode escape '\\u{}'.".to_string());
+                }
+                let code = u32::from_str_radix(&digits, 16).unwrap();
+                match char::from_u32(code) {
+                    Some(decoded) => result.push(decoded),
+                    None => {
+                        return Err(format!(
+                            "Invalid unicode escape '\\u{{{}}}': not a valid code point.",
+                            digits
+                        ))
+                    }
+                }
+            }
+            Some(other) => return Err(format!("Invalid escape sequence '\\{}'.", other)),
+            None => return Err("Unterminated escape sequence.".to_string()),
+        }
+    }
+
+    Ok(result)
+}
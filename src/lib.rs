@@ -1,6 +1,46 @@
-pub mod ast;
-pub mod lexer;
-pub mod parser;
-pub mod tensor;
-pub mod token;
-pub mod zom_parser;
+//! PUT, a programming language for machine learning and scientific
+//! computing.
+//!
+//! Host programs run scripts through [`Engine`], which is the supported way
+//! to embed the language; the other modules expose each stage of the
+//! pipeline (lexer, parser, type checker, interpreter, code generators)
+//! for tools that need them directly.
+
+pub mod ast;
+pub mod ast_json;
+pub mod ast_sexpr;
+pub mod builtins;
+pub mod bytecode;
+#[cfg(feature = "llvm")]
+pub mod codegen_llvm;
+pub mod codegen_python;
+pub mod codegen_rust;
+pub mod constants;
+pub mod debugger;
+pub mod desugar;
+pub mod engine;
+pub mod environment;
+pub mod formatter;
+pub mod host;
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod modules;
+pub mod optimizer;
+pub mod parser;
+pub mod preprocessor;
+pub mod profiler;
+pub mod random;
+pub mod repl;
+pub mod symbol_table;
+pub mod tensor;
+pub mod threads;
+pub mod token;
+pub mod type_checker;
+pub mod visitor;
+pub mod wasm;
+pub mod zom_parser;
+
+pub use engine::{Engine, EngineError};
+pub use host::{FromValue, IntoValue};
+pub use interpreter::{CancelHandle, Limits, Value};
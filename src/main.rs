@@ -1,20 +1,57 @@
-mod ast;
-mod lexer;
-mod parser;
-mod tensor;
-mod token;
-mod zom_parser;
-
-use ast::{
-    AssignmentNode, BinaryOperationNode, NumberNode, ParenthesisNode, StatementNode, VariableNode,
-};
-use lexer::Lexer;
-use parser::Parser;
-use std::fs::File;
-use tensor::Tensor;
-use zom_parser::{parse_zom_file, ProjectConfig};
+use put_lang::ast::{Expr, ImportSource, Pattern, ProgramNode, Stmt};
+use put_lang::builtins::standard_basket;
+#[cfg(feature = "llvm")]
+use put_lang::codegen_llvm::{compile_object, emit_llvm};
+use put_lang::codegen_python::emit_python;
+use put_lang::codegen_rust::emit_rust;
+use put_lang::debugger::ConsoleDebugger;
+use put_lang::desugar::desugar;
+use put_lang::engine::Engine;
+use put_lang::interpreter::Interpreter;
+use put_lang::lexer::Lexer;
+use put_lang::modules;
+use put_lang::optimizer::{fold_constants, fold_typed};
+use put_lang::parser::Parser;
+use put_lang::preprocessor::substitute_cfg;
+use put_lang::repl::Session;
+use put_lang::tensor::Tensor;
+use put_lang::type_checker::{self, TypeChecker};
+use put_lang::visitor::{walk_expr, walk_pattern, walk_stmt, Visitor};
+use put_lang::zom_parser::{parse_zom_file, ProjectConfig};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+const USAGE: &str = "Usage: put compile <source> <output.putc>
+       put run <source or .putc file> [--profile] [--trace]
+       put debug <source> [--break <line>]...
+       put repl [<saved session>]
+       put emit --rust <source>
+       put emit --python <source>
+       put emit --llvm <source>     (with the llvm feature)
+       put build <source> <output.o> (with the llvm feature)";
+
+// Scripts run on a thread with this much stack, so they can recurse
+// `MAX_CALL_DEPTH` calls deep
+const STACK_SIZE: usize = 256 << 20;
+const MAX_CALL_DEPTH: usize = 1000;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        let result = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || run_command(&args))
+            .map_err(|error| format!("Cannot start the interpreter: {}", error))
+            .and_then(|thread| thread.join().unwrap_or_else(|_| std::process::exit(101)));
+        if let Err(message) = result {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Without a command, walk through the pipeline on a sample program
     // Try to parse project.zom file
     let config = match File::open("project.zom") {
         Ok(file) => match parse_zom_file(file) {
@@ -34,8 +71,8 @@ fn main() {
     };
 
     // Use the config to set up your environment if it exists
-    if let Some(cfg) = config {
-        setup_environment(&cfg);
+    if let Some(cfg) = &config {
+        setup_environment(cfg);
     } else {
         println!("Using default environment setup");
     }
@@ -47,24 +84,236 @@ fn main() {
 
     println!("Tokens: {:?}", tokens);
 
-    let mut parser = Parser::new(tokens);
-    let program = parser.parse();
+    let newline_terminated = config
+        .as_ref()
+        .is_some_and(ProjectConfig::newline_terminated);
+    let mut parser = Parser::new(tokens).newline_terminated(newline_terminated);
+    match parser.parse() {
+        Ok(mut program) => {
+            println!("Program: {:?}", program);
 
-    println!("Program: {:?}", program);
+            let settings = config
+                .as_ref()
+                .map(|cfg| cfg.custom_settings.clone())
+                .unwrap_or_default();
+            match substitute_cfg(&mut program, &settings) {
+                Ok(()) => {
+                    desugar(&mut program);
+                    fold_constants(&mut program);
+                    println!("Lowered: {:?}", program);
 
-    println!("\nAST Structure:");
-    if program.statements.is_empty() {
-        println!("Failed to parse any statements.");
-    } else {
-        for statement in program.statements {
-            print_statement(&statement, 0);
+                    let mut interpreter = Interpreter::new();
+                    match interpreter.interpret(&program) {
+                        Ok(()) => {
+                            if let Some(x) = interpreter.global("x") {
+                                println!("x = {}", x);
+                            }
+                        }
+                        Err(error) => eprintln!("{}", error.render(source)),
+                    }
+                }
+                Err(error) => eprintln!("{}", error),
+            }
+
+            if let Err(errors) = TypeChecker::infer_types(&mut program) {
+                eprintln!("{}", type_checker::report(&errors));
+            }
+            println!("\nAST Structure:");
+            AstPrinter { indent: 0 }.visit_program(&program);
+
+            if let Some(cfg) = &config {
+                check_imports(&program, cfg);
+            }
         }
+        Err(error) => eprintln!("{}", error),
     }
 
     // Demonstrate tensor operations
     demo_tensor_operations();
 }
 
+// `put compile` lowers a script once and saves it as a .putc file; `put
+// run` type checks and executes a script, or a .putc file without parsing
+// it again, and with --profile reports where its time went or with --trace logs each
+// statement as it runs; `put debug` runs a script, pausing at breakpoints
+// to step through it and look at its variables;
+// `put emit --rust` and `put emit --python` print the script translated
+// to Rust or Python; with the llvm feature, `put emit --llvm` prints it
+// as LLVM IR and `put build` compiles it to an object file
+fn run_command(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, source_path, output_path] if command == "compile" => {
+            let source = read_source(source_path)?;
+            let program = lower(&source)?;
+            fs::write(output_path, program.to_putc())
+                .map_err(|error| format!("Cannot write '{}': {}", output_path, error))
+        }
+        [command, path, options @ ..] if command == "run" => {
+            let (mut profiling, mut tracing) = (false, false);
+            for option in options {
+                match option.as_str() {
+                    "--profile" => profiling = true,
+                    "--trace" => tracing = true,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            let (mut program, source) = if path.ends_with(".putc") {
+                let bytes =
+                    fs::read(path).map_err(|error| format!("Cannot read '{}': {}", path, error))?;
+                (ProgramNode::from_putc(&bytes)?, String::new())
+            } else {
+                let source = read_source(path)?;
+                (parse_script(&source)?, source)
+            };
+            check(&mut program)?;
+            let mut interpreter = script_interpreter(path)?;
+            if profiling {
+                interpreter = interpreter.enable_profiling();
+            }
+            if tracing {
+                interpreter = interpreter.trace_to(io::stderr());
+                if !source.is_empty() {
+                    interpreter = interpreter.trace_source(&source);
+                }
+            }
+            let result = interpreter.interpret(&program);
+            if let Some(profile) = interpreter.profile() {
+                eprint!("{}", profile.report(&source));
+            }
+            result.map_err(|error| error.render(&source))
+        }
+        [command, path, options @ ..] if command == "debug" => {
+            let mut debugger = ConsoleDebugger::new(io::stdin().lock(), io::stderr());
+            let mut options = options.iter();
+            while let Some(option) = options.next() {
+                let line = match (option.as_str(), options.next()) {
+                    ("--break", Some(line)) => line
+                        .parse()
+                        .map_err(|_| format!("Invalid line number '{}'", line))?,
+                    _ => return Err(USAGE.to_string()),
+                };
+                debugger = debugger.break_at_line(line);
+            }
+            let source = read_source(path)?;
+            let mut program = parse_script(&source)?;
+            check(&mut program)?;
+            script_interpreter(path)?
+                .attach_debugger(debugger.show_source(&source))
+                .interpret(&program)
+                .map_err(|error| error.render(&source))
+        }
+        [command, saved @ ..] if command == "repl" && saved.len() <= 1 => {
+            let engine = Engine::from_interpreter(importing_interpreter(Path::new(""))?);
+            let mut session = Session::new(engine);
+            if let [path] = saved {
+                session.load(path)?;
+            }
+            println!(
+                "PUT {} (type :help for commands)",
+                env!("CARGO_PKG_VERSION")
+            );
+            session.run(io::stdin().lock(), io::stdout());
+            Ok(())
+        }
+        [command, target, path] if command == "emit" && target == "--rust" => {
+            let program = lower(&read_source(path)?)?;
+            print!("{}", emit_rust(&program)?);
+            Ok(())
+        }
+        [command, target, path] if command == "emit" && target == "--python" => {
+            let program = lower(&read_source(path)?)?;
+            print!("{}", emit_python(&program)?);
+            Ok(())
+        }
+        #[cfg(feature = "llvm")]
+        [command, target, path] if command == "emit" && target == "--llvm" => {
+            let program = lower(&read_source(path)?)?;
+            print!("{}", emit_llvm(&program)?);
+            Ok(())
+        }
+        #[cfg(feature = "llvm")]
+        [command, source_path, output_path] if command == "build" => {
+            let program = lower(&read_source(source_path)?)?;
+            compile_object(&program, output_path.as_ref())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+// An interpreter for the script at `path`, importing files relative to it
+fn script_interpreter(path: &str) -> Result<Interpreter, String> {
+    importing_interpreter(Path::new(path).parent().unwrap_or(Path::new("")))
+}
+
+// An interpreter importing files relative to `directory`
+fn importing_interpreter(directory: &Path) -> Result<Interpreter, String> {
+    let mut interpreter = Interpreter::new()
+        .allow_file_access(true)
+        .max_call_depth(MAX_CALL_DEPTH)
+        .import_from(directory);
+    if let Some(config) = project_config()? {
+        interpreter = interpreter.project(config, ".");
+    }
+    Ok(interpreter)
+}
+
+fn read_source(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|error| format!("Cannot read '{}': {}", path, error))
+}
+
+// The project.zom in the working directory, if there is one
+fn project_config() -> Result<Option<ProjectConfig>, String> {
+    match File::open("project.zom") {
+        Ok(file) => parse_zom_file(file)
+            .map(Some)
+            .map_err(|error| format!("Error parsing project.zom: {}", error)),
+        Err(_) => Ok(None),
+    }
+}
+
+// Parses `source` and lowers it to what the interpreter runs, applying
+// project.zom if there is one
+fn lower(source: &str) -> Result<ProgramNode, String> {
+    modules::lower(source, project_config()?.as_ref())
+}
+
+// `lower` without the constant folding, which `check` does afterwards
+fn parse_script(source: &str) -> Result<ProgramNode, String> {
+    modules::parse_script(source, project_config()?.as_ref())
+}
+
+// Type checks a script before it runs, printing any warnings, then folds
+// its constants and what the types it resolved make redundant; the errors
+// fail the command
+fn check(program: &mut ProgramNode) -> Result<(), String> {
+    let warnings = TypeChecker::new()
+        .check(program)
+        .map_err(|errors| type_checker::report(&errors))?;
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+    fold_constants(program);
+    fold_typed(program);
+    Ok(())
+}
+
+// Warns about `use` statements naming baskets that project.zom doesn't
+// declare and the interpreter doesn't ship
+fn check_imports(program: &ProgramNode, config: &ProjectConfig) {
+    for statement in &program.statements {
+        if let Stmt::Import(import) = statement {
+            if let ImportSource::Basket(path) = &import.source {
+                if standard_basket(path).is_none() && config.basket_version(&path[0]).is_none() {
+                    eprintln!(
+                        "Warning: basket '{}' is not declared in project.zom",
+                        path[0]
+                    );
+                }
+            }
+        }
+    }
+}
+
 fn setup_environment(config: &ProjectConfig) {
     println!("Setting up environment based on project.zom:");
     println!(
@@ -90,29 +339,162 @@ fn setup_environment(config: &ProjectConfig) {
     // Apply other configuration settings as needed
 }
 
-fn print_statement(statement: &Box<dyn StatementNode>, indent: usize) {
-    let indent_str = "  ".repeat(indent);
-
-    if let Some(assignment) = statement.as_any().downcast_ref::<AssignmentNode>() {
-        println!("{}AssignmentNode", indent_str);
-        print_statement(&assignment.left, indent + 1);
-        print_statement(&assignment.right, indent + 1);
-    } else if let Some(variable) = statement.as_any().downcast_ref::<VariableNode>() {
-        println!("{}VariableNode: {}", indent_str, variable.name);
-    } else if let Some(number) = statement.as_any().downcast_ref::<NumberNode>() {
-        println!("{}NumberNode: {}", indent_str, number.value);
-    } else if let Some(binary_op) = statement.as_any().downcast_ref::<BinaryOperationNode>() {
-        println!(
-            "{}BinaryOperationNode: {:?}",
-            indent_str, binary_op.operator
-        );
-        print_statement(&binary_op.left, indent + 1);
-        print_statement(&binary_op.right, indent + 1);
-    } else if let Some(paren) = statement.as_any().downcast_ref::<ParenthesisNode>() {
-        println!("{}ParenthesisNode", indent_str);
-        print_statement(&paren.expression, indent + 1);
-    } else {
-        println!("{}Unknown node type", indent_str);
+/// Prints each node on its own line, indented by its depth in the tree.
+struct AstPrinter {
+    indent: usize,
+}
+
+impl AstPrinter {
+    fn line(&self, text: String) {
+        println!("{}{}", "  ".repeat(self.indent), text);
+    }
+
+    // Prints `label`, then the node's children one level deeper
+    fn nested(&mut self, label: String, walk: impl FnOnce(&mut Self)) {
+        self.line(label);
+        self.indent += 1;
+        walk(self);
+        self.indent -= 1;
+    }
+}
+
+impl Visitor for AstPrinter {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        let label = match stmt {
+            // Expression statements print as the expression itself
+            Stmt::Expression(expr) => return self.visit_expr(expr),
+            Stmt::VarDeclaration(declaration) => match &declaration.data_type {
+                Some(data_type) => {
+                    format!("VarDeclarationNode: {}: {:?}", declaration.name, data_type)
+                }
+                None => format!("VarDeclarationNode: {}", declaration.name),
+            },
+            Stmt::ConstDeclaration(declaration) => match &declaration.data_type {
+                Some(data_type) => {
+                    format!(
+                        "ConstDeclarationNode: {}: {:?}",
+                        declaration.name, data_type
+                    )
+                }
+                None => format!("ConstDeclarationNode: {}", declaration.name),
+            },
+            Stmt::FunctionDeclaration(function) => {
+                let parameters: Vec<&str> = function
+                    .parameters
+                    .iter()
+                    .map(|p| p.name.as_str())
+                    .collect();
+                format!(
+                    "FunctionDeclarationNode: {}({})",
+                    function.name,
+                    parameters.join(", ")
+                )
+            }
+            Stmt::StructDeclaration(struct_node) => {
+                return self.nested(
+                    format!("StructDeclarationNode: {}", struct_node.name),
+                    |printer| {
+                        for field in &struct_node.fields {
+                            printer.line(format!("{}: {:?}", field.name, field.data_type));
+                        }
+                    },
+                );
+            }
+            Stmt::EnumDeclaration(enum_node) => format!(
+                "EnumDeclarationNode: {} [{}]",
+                enum_node.name,
+                enum_node.variants.join(", ")
+            ),
+            Stmt::Return(_) => "ReturnNode".to_string(),
+            Stmt::Throw(_) => "ThrowNode".to_string(),
+            Stmt::Breakpoint(_) => "BreakpointNode".to_string(),
+            Stmt::If(_) => "IfNode".to_string(),
+            Stmt::While(_) => "WhileNode".to_string(),
+            Stmt::DoWhile(_) => "DoWhileNode".to_string(),
+            Stmt::For(_) => "ForNode".to_string(),
+            Stmt::ForIn(for_in) => format!("ForInNode: {}", for_in.variable),
+            Stmt::Block(_) => "BlockNode".to_string(),
+            Stmt::Match(_) => "MatchNode".to_string(),
+            Stmt::Try(try_node) => match &try_node.error_name {
+                Some(name) => format!("TryNode: catch ({})", name),
+                None => "TryNode".to_string(),
+            },
+            Stmt::Import(import) => match &import.source {
+                ImportSource::Basket(path) => format!("ImportNode: use {}", path.join("::")),
+                ImportSource::File(path) => format!("ImportNode: import {:?}", path),
+            },
+            Stmt::Comment(comment) => format!("CommentNode: {}", comment.text),
+        };
+        self.nested(label, |printer| walk_stmt(printer, stmt));
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        let label = match expr {
+            Expr::Variable(variable) => format!("VariableNode: {}", variable.name),
+            Expr::Number(number) => format!("NumberNode: {}", number.value),
+            Expr::String(string) => format!("StringNode: {:?}", string.value),
+            Expr::Boolean(boolean) => format!("BooleanNode: {}", boolean.value),
+            Expr::Null(_) => "NullNode".to_string(),
+            Expr::Char(character) => format!("CharNode: {:?}", character.value),
+            Expr::Assignment(_) => "AssignmentNode".to_string(),
+            Expr::CompoundAssignment(assignment) => {
+                format!("CompoundAssignmentNode: {:?}", assignment.operator)
+            }
+            Expr::Binary(binary_op) => format!("BinaryOperationNode: {:?}", binary_op.operator),
+            Expr::Unary(unary) => format!("UnaryOperationNode: {:?}", unary.operator),
+            Expr::Cast(cast) => format!("CastNode: {:?}", cast.data_type),
+            Expr::Parenthesis(_) => "ParenthesisNode".to_string(),
+            Expr::Conditional(_) => "ConditionalExpressionNode".to_string(),
+            Expr::Range(range) => {
+                let kind = if range.inclusive {
+                    "inclusive"
+                } else {
+                    "exclusive"
+                };
+                format!("RangeNode ({})", kind)
+            }
+            Expr::Call(_) => "CallNode".to_string(),
+            Expr::MethodCall(method_call) => {
+                let access = if method_call.optional { "?." } else { "." };
+                format!("MethodCallNode: {}{}()", access, method_call.method)
+            }
+            Expr::MemberAccess(member) => {
+                let access = if member.optional { "?." } else { "." };
+                format!("MemberAccessNode: {}{}", access, member.member)
+            }
+            Expr::Index(index) if index.optional => "IndexNode (optional)".to_string(),
+            Expr::Index(_) => "IndexNode".to_string(),
+            Expr::Lambda(lambda) => {
+                let parameters: Vec<&str> =
+                    lambda.parameters.iter().map(|p| p.name.as_str()).collect();
+                format!("LambdaNode({})", parameters.join(", "))
+            }
+            Expr::Spawn(_) => "SpawnNode".to_string(),
+            Expr::Match(_) => "MatchExpressionNode".to_string(),
+            Expr::Cfg(cfg) => format!("CfgNode: {}", cfg.key),
+            Expr::TensorLiteral(tensor) => format!("TensorLiteralNode: shape {:?}", tensor.shape),
+            Expr::EnumVariant(variant) => {
+                format!(
+                    "EnumVariantNode: {}::{}",
+                    variant.enum_name, variant.variant
+                )
+            }
+        };
+        // With the type the checker resolved, if it has run
+        let label = match expr.resolved().data_type() {
+            Some(data_type) => format!("{} : {:?}", label, data_type),
+            None => label,
+        };
+        self.nested(label, |printer| walk_expr(printer, expr));
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.line("Arm: _".to_string()),
+            Pattern::Literal(_) => {
+                self.nested("Arm:".to_string(), |printer| walk_pattern(printer, pattern))
+            }
+        }
     }
 }
 
@@ -0,0 +1,137 @@
+//! Running the files that `import` and `use` name. `import "utils.put";`
+//! runs utils.put, found next to the importing file, and binds `utils` to
+//! a namespace holding its top-level bindings, so the script can call
+//! `utils.mean(xs)`. `use stats;` does the same for a basket that
+//! project.zom declares but the interpreter doesn't ship, loading it from
+//! `baskets/stats.put` under the project root. Each file runs once per
+//! interpreter however often it is imported, and a file that ends up
+//! importing itself is an error.
+
+use crate::ast::ProgramNode;
+use crate::desugar::desugar;
+use crate::environment::Scope;
+use crate::interpreter::Value;
+use crate::lexer::Lexer;
+use crate::optimizer::fold_constants;
+use crate::parser::Parser;
+use crate::preprocessor::substitute_cfg;
+use crate::zom_parser::ProjectConfig;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The top-level bindings of an imported file.
+pub struct Module {
+    pub name: String,
+    scope: Scope,
+}
+
+impl Module {
+    pub(crate) fn new(name: String, scope: Scope) -> Self {
+        Module { name, scope }
+    }
+
+    /// The value the module's top level bound to `name`.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.scope.lock().get_local(name)
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
+
+// Modules are equal only to themselves
+impl PartialEq for Module {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Parses `source` and lowers it to what the interpreter runs, following
+/// the statement terminator and `@cfg` settings of `config` if given.
+pub fn lower(source: &str, config: Option<&ProjectConfig>) -> Result<ProgramNode, String> {
+    let mut program = parse_script(source, config)?;
+    fold_constants(&mut program);
+    Ok(program)
+}
+
+/// Everything `lower` does but fold constants, for a program to be type
+/// checked first, so that what the checker reports reads as written.
+pub fn parse_script(source: &str, config: Option<&ProjectConfig>) -> Result<ProgramNode, String> {
+    let newline_terminated = config.is_some_and(ProjectConfig::newline_terminated);
+    let mut program = Parser::new(Lexer::new(source))
+        .newline_terminated(newline_terminated)
+        .parse()
+        .map_err(|error| error.to_string())?;
+    let settings = config
+        .map(|config| config.custom_settings.clone())
+        .unwrap_or_default();
+    substitute_cfg(&mut program, &settings)?;
+    desugar(&mut program);
+    Ok(program)
+}
+
+/// Where an interpreter finds imported files, and the ones it has run.
+#[derive(Clone)]
+pub(crate) struct Modules {
+    // What relative `import` paths start from: the directory of the file
+    // being run
+    pub directory: PathBuf,
+    // project.zom and the directory it is in
+    pub project: Option<Arc<(ProjectConfig, PathBuf)>>,
+    // Keyed by canonical path
+    pub loaded: HashMap<PathBuf, Value>,
+    // The files being run, outermost first
+    pub loading: Vec<PathBuf>,
+}
+
+impl Default for Modules {
+    fn default() -> Self {
+        Modules {
+            directory: PathBuf::from("."),
+            project: None,
+            loaded: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+}
+
+impl Modules {
+    /// The file behind `use path;`, if project.zom declares its basket.
+    pub fn basket_file(&self, path: &[String]) -> Option<PathBuf> {
+        let (config, root) = &**self.project.as_ref()?;
+        config.basket_version(&path[0])?;
+        let mut file = root.join("baskets");
+        file.extend(path);
+        file.set_extension("put");
+        Some(file)
+    }
+
+    /// The error for importing `path` while it is still running: every
+    /// file in the cycle, in the order they import each other.
+    pub fn cycle(&self, path: &Path) -> String {
+        let start = self
+            .loading
+            .iter()
+            .position(|loading| loading == path)
+            .unwrap_or(0);
+        let mut files: Vec<String> = self.loading[start..]
+            .iter()
+            .map(|file| display_name(file))
+            .collect();
+        files.push(display_name(path));
+        format!("Import cycle: {}", files.join(" -> "))
+    }
+}
+
+/// The file name of `path`, as errors show it.
+pub(crate) fn display_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
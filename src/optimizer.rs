@@ -0,0 +1,214 @@
+//! AST-level optimizations run between parsing and checking/execution.
+//!
+//! Constant folding collapses operator subtrees whose operands are all
+//! literals (`(42 + 5) * 2` becomes `94`) and substitutes the literal value
+//! of a `const` into later uses of its name, except where a variable,
+//! parameter or other binding of the same name hides it. Anything that
+//! would fail at runtime, such as integer overflow or division by zero, is
+//! left in place so the error is still reported where the program actually
+//! runs.
+//!
+//! Once the type checker has recorded what each expression resolves to,
+//! typed folding drops what those types make redundant: casts to the type
+//! a value already has, and int arithmetic by an identity, like `n + 0` or
+//! `n * 1`. Expressions the checker couldn't type are left as they are.
+
+use crate::ast::*;
+use crate::constants::{fold_binary, fold_unary, Constant};
+use crate::visitor::{walk_expr_mut, walk_stmt_mut, MutVisitor};
+use std::collections::HashMap;
+
+/// Folds every constant subtree of `program` in place.
+pub fn fold_constants(program: &mut ProgramNode) {
+    ConstantFolder {
+        scopes: vec![HashMap::new()],
+    }
+    .visit_program_mut(program);
+}
+
+/// Drops the casts and int arithmetic in `program` that do nothing given
+/// the types `TypeChecker::check` resolved, so run it after checking.
+pub fn fold_typed(program: &mut ProgramNode) {
+    TypedFolder.visit_program_mut(program);
+}
+
+struct ConstantFolder {
+    // The names bound in each enclosing scope so far, innermost last: a
+    // `const` whose value folded down to a literal maps to that literal,
+    // and any other binding to `None`, hiding a constant of the same name
+    scopes: Vec<HashMap<String, Option<Expr>>>,
+}
+
+impl ConstantFolder {
+    fn bind(&mut self, name: &str, value: Option<Expr>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), value);
+        }
+    }
+
+    // Walks a scope of its own, in which `names` are bound from the start
+    fn scoped<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+        walk: impl FnOnce(&mut Self),
+    ) {
+        let scope = names
+            .into_iter()
+            .map(|name| (name.to_string(), None))
+            .collect();
+        self.scopes.push(scope);
+        walk(self);
+        self.scopes.pop();
+    }
+
+    // The literal a name stands for, if its innermost binding is a constant
+    fn constant(&self, name: &str) -> Option<Expr> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))?
+            .clone()
+    }
+
+    fn fold(&self, expr: &Expr) -> Option<Expr> {
+        match expr {
+            Expr::Variable(variable) => self.constant(&variable.name),
+            Expr::Parenthesis(paren) => {
+                Constant::from_expr(&paren.expression).and_then(Constant::into_expr)
+            }
+            // A negated literal is already in its folded form
+            Expr::Unary(unary) if !matches!(*unary.operand, Expr::Number(_)) => {
+                fold_unary(unary.operator, Constant::from_expr(&unary.operand)?)?.into_expr()
+            }
+            Expr::Binary(binary) => {
+                let left = Constant::from_expr(&binary.left)?;
+                let right = Constant::from_expr(&binary.right)?;
+                fold_binary(&left, binary.operator, &right)?.into_expr()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl MutVisitor for ConstantFolder {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::ConstDeclaration(declaration) => {
+                self.visit_expr_mut(&mut declaration.value);
+                let value = Constant::from_expr(&declaration.value).and_then(|constant| {
+                    match (constant, &declaration.data_type) {
+                        // An int declared a float is substituted as one
+                        (Constant::Integer(value), Some(DataType::Float)) => {
+                            Constant::Float(value as f64).into_expr()
+                        }
+                        _ => Some(declaration.value.clone()),
+                    }
+                });
+                self.bind(&declaration.name, value);
+            }
+            // A variable hides a constant from its declaration on; its
+            // initializer still sees the constant
+            Stmt::VarDeclaration(declaration) => {
+                if let Some(initializer) = &mut declaration.initializer {
+                    self.visit_expr_mut(initializer);
+                }
+                self.bind(&declaration.name, None);
+            }
+            Stmt::FunctionDeclaration(function) => {
+                self.bind(&function.name, None);
+                let parameters = function.parameters.clone();
+                let names = parameters.iter().map(|p| p.name.as_str());
+                self.scoped(names, |folder| walk_stmt_mut(folder, stmt));
+            }
+            Stmt::StructDeclaration(declaration) => self.bind(&declaration.name, None),
+            Stmt::Block(_) | Stmt::For(_) => self.scoped([], |folder| walk_stmt_mut(folder, stmt)),
+            Stmt::ForIn(for_in) => {
+                self.visit_expr_mut(&mut for_in.iterable);
+                let variable = for_in.variable.clone();
+                let body = &mut for_in.body;
+                self.scoped([variable.as_str()], |folder| folder.visit_stmt_mut(body));
+            }
+            Stmt::Try(try_node) => {
+                let body = &mut try_node.body.statements;
+                self.scoped([], |folder| {
+                    body.iter_mut().for_each(|s| folder.visit_stmt_mut(s))
+                });
+                let handler = &mut try_node.handler.statements;
+                self.scoped(try_node.error_name.as_deref(), |folder| {
+                    handler.iter_mut().for_each(|s| folder.visit_stmt_mut(s))
+                });
+            }
+            _ => walk_stmt_mut(self, stmt),
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            // The target names a place, not a value, so only an index
+            // inside it is folded
+            Expr::Assignment(AssignmentNode { target, value, .. })
+            | Expr::CompoundAssignment(CompoundAssignmentNode { target, value, .. }) => {
+                if let Expr::Index(index) = &mut **target {
+                    self.visit_expr_mut(&mut index.index);
+                }
+                self.visit_expr_mut(value);
+            }
+            Expr::Lambda(lambda) => {
+                let parameters = lambda.parameters.clone();
+                let names = parameters.iter().map(|p| p.name.as_str());
+                self.scoped(names, |folder| walk_expr_mut(folder, expr));
+            }
+            Expr::Spawn(_) => self.scoped([], |folder| walk_expr_mut(folder, expr)),
+            _ => walk_expr_mut(self, expr),
+        }
+        if let Some(folded) = self.fold(expr) {
+            *expr = folded;
+        }
+    }
+}
+
+struct TypedFolder;
+
+impl TypedFolder {
+    fn fold(expr: &mut Expr) -> Option<Expr> {
+        use BinaryOperator::*;
+
+        let is = |expr: &Expr, data_type: &DataType| expr.resolved().data_type() == Some(data_type);
+        let literal = |expr: &Expr| match Constant::from_expr(expr) {
+            Some(Constant::Integer(value)) => Some(value),
+            _ => None,
+        };
+        match expr {
+            Expr::Cast(cast) if is(&cast.expression, &cast.data_type) => {
+                Some((*cast.expression).clone())
+            }
+            Expr::Binary(binary) if is(&binary.left, &DataType::Integer) => {
+                match (
+                    literal(&binary.left),
+                    binary.operator,
+                    literal(&binary.right),
+                ) {
+                    (_, Add | Subtract, Some(0)) | (_, Multiply | Divide, Some(1)) => {
+                        Some((*binary.left).clone())
+                    }
+                    (Some(0), Add, _) | (Some(1), Multiply, _)
+                        if is(&binary.right, &DataType::Integer) =>
+                    {
+                        Some((*binary.right).clone())
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl MutVisitor for TypedFolder {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let Some(folded) = Self::fold(expr) {
+            *expr = folded;
+        }
+    }
+}
@@ -1,209 +1,1421 @@
-use crate::ast::*;
-use crate::token::{Token, TokenType};
-
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
-}
-
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
-    }
-
-    pub fn parse(&mut self) -> ProgramNode {
-        let mut program = ProgramNode::new();
-
-        while !self.is_at_end() {
-            if let Some(stmt) = self.parse_statement() {
-                program.statements.push(stmt);
-            } else {
-                break;
-            }
-        }
-
-        program
-    }
-
-    fn parse_statement(&mut self) -> Option<Box<dyn StatementNode>> {
-        if self.match_token(TokenType::If) {
-            self.parse_if_statement()
-        } else if self.match_token(TokenType::While) {
-            self.parse_while_statement()
-        } else if self.match_token(TokenType::Var) {
-            self.parse_variable_declaration()
-        } else {
-            self.parse_expression()
-        }
-    }
-
-    fn parse_if_statement(&mut self) -> Option<Box<dyn StatementNode>> {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
-        let condition = self.parse_expression()?;
-        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
-
-        let then_branch = self.parse_statement()?;
-        let mut else_branch = None;
-
-        if self.match_token(TokenType::Else) {
-            else_branch = Some(self.parse_statement()?);
-        }
-
-        Some(Box::new(IfNode::new(condition, then_branch, else_branch)))
-    }
-
-    fn parse_while_statement(&mut self) -> Option<Box<dyn StatementNode>> {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
-        let condition = self.parse_expression()?;
-        self.consume(TokenType::RightParen, "Expect ')' after while condition.")?;
-
-        let body = self.parse_statement()?;
-
-        Some(Box::new(WhileNode::new(condition, body)))
-    }
-
-    fn parse_variable_declaration(&mut self) -> Option<Box<dyn StatementNode>> {
-        let name_token = self.consume(TokenType::Identifier, "Expect variable name.")?;
-        let var_name = name_token.lexeme.clone();
-
-        // Assume data type, modify as necessary to detect actual type
-        let data_type = DataType::Integer; // Default to Integer for example purposes
-
-        let mut initializer = None;
-        if self.match_token(TokenType::Assign) {
-            initializer = Some(self.parse_expression()?);
-        }
-
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after variable declaration.",
-        )?;
-
-        // Use the initializer in constructing the AssignmentNode if it exists
-        if let Some(init) = initializer {
-            Some(Box::new(AssignmentNode::new(
-                Box::new(VariableNode::new(var_name, data_type)),
-                init,
-            )))
-        } else {
-            // If there's no initializer, just create a VariableNode
-            Some(Box::new(VariableNode::new(var_name, data_type)))
-        }
-    }
-
-    fn parse_expression(&mut self) -> Option<Box<dyn StatementNode>> {
-        self.parse_addition()
-    }
-
-    fn parse_addition(&mut self) -> Option<Box<dyn StatementNode>> {
-        let mut expr = self.parse_multiplication()?;
-
-        while self.match_any(&[TokenType::Plus, TokenType::Minus]) {
-            let operator = match self.previous().token_type {
-                TokenType::Plus => BinaryOperator::Add,
-                TokenType::Minus => BinaryOperator::Subtract,
-                _ => unreachable!(),
-            };
-            let right = self.parse_multiplication()?;
-            expr = Box::new(BinaryOperationNode::new(expr, operator, right));
-        }
-
-        Some(expr)
-    }
-
-    fn parse_multiplication(&mut self) -> Option<Box<dyn StatementNode>> {
-        let mut expr = self.parse_primary()?;
-
-        while self.match_any(&[TokenType::Star, TokenType::Slash]) {
-            let operator = match self.previous().token_type {
-                TokenType::Star => BinaryOperator::Multiply,
-                TokenType::Slash => BinaryOperator::Divide,
-                _ => unreachable!(),
-            };
-            let right = self.parse_primary()?;
-            expr = Box::new(BinaryOperationNode::new(expr, operator, right));
-        }
-
-        Some(expr)
-    }
-
-    fn parse_primary(&mut self) -> Option<Box<dyn StatementNode>> {
-        if self.match_token(TokenType::Number) {
-            let token = self.previous();
-            // Determine if the number is an integer or float for correct data type
-            let data_type = if token.lexeme.contains('.') {
-                DataType::Float
-            } else {
-                DataType::Integer
-            };
-            Some(Box::new(NumberNode::new(token.lexeme.clone(), data_type)))
-        } else if self.match_token(TokenType::Identifier) {
-            let token = self.previous();
-            // Default to Integer, adjust based on context or additional checks
-            let data_type = DataType::Integer;
-            Some(Box::new(VariableNode::new(token.lexeme.clone(), data_type)))
-        } else if self.match_token(TokenType::LeftParen) {
-            let expr = self.parse_expression()?;
-            self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
-            Some(Box::new(ParenthesisNode::new(expr)))
-        } else {
-            eprintln!("Unexpected token: {:?}", self.peek());
-            None
-        }
-    }
-
-    fn match_token(&mut self, token_type: TokenType) -> bool {
-        if self.check(token_type) {
-            self.advance();
-            true
-        } else {
-            false
-        }
-    }
-
-    fn match_any(&mut self, token_types: &[TokenType]) -> bool {
-        for &token_type in token_types {
-            if self.check(token_type) {
-                self.advance();
-                return true;
-            }
-        }
-        false
-    }
-
-    fn check(&self, token_type: TokenType) -> bool {
-        if self.is_at_end() {
-            false
-        } else {
-            self.peek().token_type == token_type
-        }
-    }
-
-    fn advance(&mut self) -> &Token {
-        if !self.is_at_end() {
-            self.current += 1;
-        }
-        self.previous()
-    }
-
-    fn is_at_end(&self) -> bool {
-        self.peek().token_type == TokenType::EOF
-    }
-
-    fn peek(&self) -> &Token {
-        &self.tokens[self.current]
-    }
-
-    fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
-    }
-
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Option<&Token> {
-        if self.check(token_type) {
-            Some(self.advance())
-        } else {
-            eprintln!("Parse error: {} at line {}", message, self.peek().line);
-            None
-        }
-    }
-}
+use crate::ast::*;
+use crate::lexer::{char_value, error_message, string_value};
+use crate::token::{Span, Token, TokenType};
+use std::collections::HashSet;
+use std::fmt;
+
+// Binding strength of each operator class; higher binds more tightly.
+pub(crate) const LOWEST_PRECEDENCE: u8 = 0;
+pub(crate) const ASSIGNMENT_PRECEDENCE: u8 = 1;
+pub(crate) const CONDITIONAL_PRECEDENCE: u8 = 2;
+pub(crate) const RANGE_PRECEDENCE: u8 = 3;
+pub(crate) const CAST_PRECEDENCE: u8 = 14;
+pub(crate) const PREFIX_PRECEDENCE: u8 = 15;
+pub(crate) const POSTFIX_PRECEDENCE: u8 = 16;
+
+/// How deeply statements and expressions may nest before parsing stops
+/// with an error, rather than overflowing the stack on hostile input.
+const MAX_NESTING_DEPTH: usize = 200;
+
+/// Left-associative binary operators. Registering a new operator only
+/// takes a row here (plus its token in the lexer).
+const INFIX_OPERATORS: &[(TokenType, BinaryOperator, u8)] = &[
+    (TokenType::Or, BinaryOperator::Or, 4),
+    (TokenType::And, BinaryOperator::And, 5),
+    (TokenType::Pipe, BinaryOperator::BitOr, 6),
+    (TokenType::Caret, BinaryOperator::BitXor, 7),
+    (TokenType::Ampersand, BinaryOperator::BitAnd, 8),
+    (TokenType::EqualEqual, BinaryOperator::Equal, 9),
+    (TokenType::BangEqual, BinaryOperator::NotEqual, 9),
+    (TokenType::Less, BinaryOperator::Less, 10),
+    (TokenType::LessEqual, BinaryOperator::LessEqual, 10),
+    (TokenType::Greater, BinaryOperator::Greater, 10),
+    (TokenType::GreaterEqual, BinaryOperator::GreaterEqual, 10),
+    (TokenType::ShiftLeft, BinaryOperator::ShiftLeft, 11),
+    (TokenType::ShiftRight, BinaryOperator::ShiftRight, 11),
+    (TokenType::Plus, BinaryOperator::Add, 12),
+    (TokenType::Minus, BinaryOperator::Subtract, 12),
+    (TokenType::Star, BinaryOperator::Multiply, 13),
+    (TokenType::Slash, BinaryOperator::Divide, 13),
+    (TokenType::At, BinaryOperator::MatMul, 13),
+];
+
+// `target op= value` for the arithmetic operators
+const COMPOUND_ASSIGNMENT_OPERATORS: &[(TokenType, BinaryOperator)] = &[
+    (TokenType::PlusEqual, BinaryOperator::Add),
+    (TokenType::MinusEqual, BinaryOperator::Subtract),
+    (TokenType::StarEqual, BinaryOperator::Multiply),
+    (TokenType::SlashEqual, BinaryOperator::Divide),
+];
+
+const PREFIX_OPERATORS: &[(TokenType, UnaryOperator)] = &[
+    (TokenType::Minus, UnaryOperator::Negate),
+    (TokenType::Bang, UnaryOperator::Not),
+];
+
+// Call `f(x)`, member access `p.x`, indexing `a[i]` and their optional
+// forms `p?.x` / `a?.[i]`
+const POSTFIX_OPERATORS: &[TokenType] = &[
+    TokenType::LeftParen,
+    TokenType::Dot,
+    TokenType::LeftBracket,
+    TokenType::QuestionDot,
+];
+
+fn infix_operator(token_type: TokenType) -> Option<(BinaryOperator, u8)> {
+    INFIX_OPERATORS
+        .iter()
+        .find(|(candidate, _, _)| *candidate == token_type)
+        .map(|&(_, operator, precedence)| (operator, precedence))
+}
+
+/// The binding strength of a binary operator, for passes that print
+/// expressions back out and must know where parentheses are needed.
+pub(crate) fn binary_precedence(operator: BinaryOperator) -> u8 {
+    INFIX_OPERATORS
+        .iter()
+        .find(|(_, candidate, _)| *candidate == operator)
+        .map(|&(_, _, precedence)| precedence)
+        .expect("every binary operator has an infix table entry")
+}
+
+fn compound_assignment_operator(token_type: TokenType) -> Option<BinaryOperator> {
+    COMPOUND_ASSIGNMENT_OPERATORS
+        .iter()
+        .find(|(candidate, _)| *candidate == token_type)
+        .map(|&(_, operator)| operator)
+}
+
+fn prefix_operator(token_type: TokenType) -> Option<UnaryOperator> {
+    PREFIX_OPERATORS
+        .iter()
+        .find(|(candidate, _)| *candidate == token_type)
+        .map(|&(_, operator)| operator)
+}
+
+/// A syntax error: what the parser wanted, the token it got instead, and
+/// where that token sits in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    // `None` when any of several tokens would have done, e.g. an expression
+    pub expected: Option<TokenType>,
+    pub found: Token,
+    pub span: Span,
+    // Further errors from the same run, in source order: every lexical
+    // error in the input is reported, not only the first
+    pub others: Box<[ParseError]>,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, expected: Option<TokenType>, found: &Token) -> Self {
+        ParseError {
+            message: message.into(),
+            expected,
+            found: found.clone(),
+            span: found.span(),
+            others: Box::default(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Parse error: {} at line {}, column {}",
+            self.message, self.span.line, self.span.column
+        )?;
+        for other in &self.others {
+            write!(f, "\n{}", other)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub type ParseResult<T> = Result<T, ParseError>;
+
+/// Recursive-descent parser that pulls tokens from any token source on
+/// demand, e.g. a `Vec<Token>` or a `Lexer` directly.
+pub struct Parser {
+    tokens: Box<dyn Iterator<Item = Token>>,
+    current: Token,
+    previous: Token,
+    // Enums declared so far, so type annotations naming them resolve to
+    // `DataType::Enum` rather than `DataType::Struct`
+    enum_names: HashSet<String>,
+    // The type parameters of the generic functions being parsed, so
+    // annotations naming them resolve to `DataType::Parameter`
+    type_parameters: Vec<String>,
+    // Comments skipped over since they were last attached to a node
+    comments: Vec<CommentNode>,
+    // Comments set aside for the nodes being parsed, with the depth each
+    // node starts at
+    leading_comments: Vec<(usize, Vec<CommentNode>)>,
+    // Error tokens skipped over so far, each reported as a parse error
+    lexical_errors: Vec<ParseError>,
+    // Whether a line break may stand in for a statement's ';'
+    newline_terminated: bool,
+    // How many parentheses, brackets or match arm lists enclose the current
+    // position; line breaks inside them never end a statement
+    nesting: usize,
+    // Statements and expressions currently being parsed, innermost last
+    depth: usize,
+    // Whether the last expression statement ended at a '}' without a ';'
+    tail_expression: bool,
+}
+
+impl Parser {
+    pub fn new<I>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = Token>,
+        I::IntoIter: 'static,
+    {
+        let start = Token::new(TokenType::EOF, String::new(), 0, 0);
+        let mut parser = Parser {
+            tokens: Box::new(tokens.into_iter()),
+            current: start.clone(),
+            previous: start,
+            enum_names: HashSet::new(),
+            type_parameters: Vec::new(),
+            comments: Vec::new(),
+            leading_comments: Vec::new(),
+            lexical_errors: Vec::new(),
+            newline_terminated: false,
+            nesting: 0,
+            depth: 0,
+            tail_expression: false,
+        };
+        parser.current = parser.next_significant_token();
+        parser.previous = parser.current.clone();
+        parser
+    }
+
+    /// Makes semicolons optional: a statement also ends at a line break,
+    /// unless the break falls inside parentheses or brackets.
+    pub fn newline_terminated(mut self, enabled: bool) -> Self {
+        self.newline_terminated = enabled;
+        self
+    }
+
+    /// Parses the whole token stream, stopping at the first syntax error.
+    /// Lexical errors don't stop it: the rest of the input is still read,
+    /// so the error returned lists every one of them.
+    pub fn parse(&mut self) -> ParseResult<ProgramNode> {
+        let result = self.parse_program();
+        while !self.is_at_end() {
+            self.advance();
+        }
+        if self.lexical_errors.is_empty() {
+            return result;
+        }
+
+        let mut errors = std::mem::take(&mut self.lexical_errors);
+        // A syntax error after the first lexical error is most likely
+        // caused by the token that was skipped, so only an earlier one is
+        // worth reporting as well
+        if let Err(error) = result {
+            let position = |error: &ParseError| (error.span.line, error.span.column);
+            if position(&error) < position(&errors[0]) {
+                errors.insert(0, error);
+            }
+        }
+        let mut first = errors.remove(0);
+        first.others = errors.into_boxed_slice();
+        Err(first)
+    }
+
+    fn parse_program(&mut self) -> ParseResult<ProgramNode> {
+        let mut program = ProgramNode::new();
+
+        while !self.is_at_end() {
+            self.parse_listed_statement(&mut program.statements)?;
+        }
+        self.finish_statement_list(&mut program.statements);
+
+        Ok(program)
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<Stmt> {
+        self.descend(Self::parse_statement_kind)
+    }
+
+    // Parses the next statement of a block or program onto `statements`,
+    // led by the comments before it and trailed by those on the line it
+    // ends on
+    fn parse_listed_statement(&mut self, statements: &mut Vec<Stmt>) -> ParseResult<()> {
+        self.set_leading_comments();
+        statements.push(self.parse_statement()?);
+        let Some(statement) = statements.last_mut() else {
+            return Ok(());
+        };
+        let leading = self.take_leading_comments();
+        let same_line = self.comments.iter().take_while(|c| c.same_line).count();
+        if leading.is_empty() && same_line == 0 {
+            return Ok(());
+        }
+        let trailing = self.comments.drain(..same_line);
+        if let Some(comments) = statement.comments_mut() {
+            comments.leading.splice(0..0, leading);
+            comments.trailing.extend(trailing);
+        }
+        Ok(())
+    }
+
+    // The comments left at the end of a block or program trail its last
+    // statement, or stand on their own if it has none
+    fn finish_statement_list(&mut self, statements: &mut Vec<Stmt>) {
+        let comments = std::mem::take(&mut self.comments);
+        if comments.is_empty() {
+            return;
+        }
+        match statements.last_mut().and_then(Stmt::comments_mut) {
+            Some(last) => last.trailing.extend(comments),
+            None => statements.extend(comments.into_iter().map(Stmt::Comment)),
+        }
+    }
+
+    fn parse_statement_kind(&mut self) -> ParseResult<Stmt> {
+        if self.match_token(TokenType::If) {
+            self.parse_if_statement()
+        } else if self.match_token(TokenType::While) {
+            self.parse_while_statement()
+        } else if self.match_token(TokenType::Do) {
+            self.parse_do_while_statement()
+        } else if self.match_token(TokenType::For) {
+            self.parse_for_statement()
+        } else if self.match_token(TokenType::Var) {
+            self.parse_variable_declaration()
+        } else if self.match_token(TokenType::Const) {
+            self.parse_const_declaration()
+        } else if self.match_token(TokenType::Fun) {
+            if self.check(TokenType::LeftParen) {
+                // An anonymous function used as an expression statement
+                let span = self.previous().span();
+                let mut function = self.parse_function_expression()?;
+                function.set_span(span);
+                let expr = self.parse_infix(function, LOWEST_PRECEDENCE)?;
+                self.finish_expression_statement(expr)
+            } else {
+                self.parse_function_declaration()
+            }
+        } else if self.match_token(TokenType::Struct) {
+            self.parse_struct_declaration()
+        } else if self.match_token(TokenType::Enum) {
+            self.parse_enum_declaration()
+        } else if self.match_token(TokenType::Try) {
+            self.parse_try_statement()
+        } else if self.match_token(TokenType::Throw) {
+            let value = self.parse_expression()?;
+            self.consume_terminator("Expect ';' after thrown value.")?;
+            Ok(Stmt::Throw(ThrowNode::new(value)))
+        } else if self.match_token(TokenType::Match) {
+            self.parse_match_statement()
+        } else if self.match_token(TokenType::Use) {
+            self.parse_use_statement()
+        } else if self.match_token(TokenType::Import) {
+            self.parse_import_statement()
+        } else if self.match_token(TokenType::Breakpoint) {
+            let span = self.previous().span();
+            self.consume_terminator("Expect ';' after 'breakpoint'.")?;
+            Ok(Stmt::Breakpoint(BreakpointNode {
+                span: NodeSpan(Some(span)),
+                ..BreakpointNode::default()
+            }))
+        } else if self.match_token(TokenType::Return) {
+            self.parse_return_statement()
+        } else if self.match_token(TokenType::LeftBrace) {
+            let statements = self.parse_block_statements()?;
+            Ok(Stmt::Block(BlockNode::new(statements)))
+        } else {
+            self.parse_expression_statement()
+        }
+    }
+
+    fn parse_use_statement(&mut self) -> ParseResult<Stmt> {
+        let mut path = Vec::new();
+        loop {
+            let segment = self
+                .consume(TokenType::Identifier, "Expect basket name after 'use'.")?
+                .lexeme
+                .clone();
+            path.push(segment);
+            if !self.match_token(TokenType::ColonColon) {
+                break;
+            }
+        }
+        self.consume_terminator("Expect ';' after use statement.")?;
+        Ok(Stmt::Import(ImportNode::new(ImportSource::Basket(path))))
+    }
+
+    fn parse_import_statement(&mut self) -> ParseResult<Stmt> {
+        let token = self
+            .consume(TokenType::String, "Expect file path string after 'import'.")?
+            .clone();
+        let path = string_value(&token.lexeme)
+            .map_err(|message| ParseError::new(message, None, &token))?;
+        self.consume_terminator("Expect ';' after import statement.")?;
+        Ok(Stmt::Import(ImportNode::new(ImportSource::File(path))))
+    }
+
+    fn parse_return_statement(&mut self) -> ParseResult<Stmt> {
+        let value = if self.at_terminator() {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.consume_terminator("Expect ';' after return value.")?;
+        Ok(Stmt::Return(ReturnNode::new(value)))
+    }
+
+    // Parses the statements of a block whose '{' has already been consumed
+    fn parse_block_statements(&mut self) -> ParseResult<Vec<Stmt>> {
+        // A block inside parentheses (a closure argument, say) holds
+        // statements of its own, which line breaks may end again
+        let nesting = std::mem::replace(&mut self.nesting, 0);
+        let result = self.parse_block_body();
+        self.nesting = nesting;
+        result
+    }
+
+    fn parse_block_body(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut statements = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            self.parse_listed_statement(&mut statements)?;
+        }
+        self.finish_statement_list(&mut statements);
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    fn parse_expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expr = self.parse_expression()?;
+        self.finish_expression_statement(expr)
+    }
+
+    // The last statement in a block may leave out its ';', which in a
+    // function body makes it the function's value
+    fn finish_expression_statement(&mut self, expr: Expr) -> ParseResult<Stmt> {
+        self.tail_expression = self.check(TokenType::RightBrace);
+        if !self.tail_expression {
+            self.consume_terminator("Expect ';' after expression.")?;
+        }
+        Ok(Stmt::Expression(expr))
+    }
+
+    // A function's statements through the closing '}'. A final expression
+    // without its ';' is returned, so `fn(x) { x * 2 }` doubles its argument
+    fn parse_function_body(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut body = self.parse_block_statements()?;
+        let last = body
+            .iter()
+            .rposition(|statement| !matches!(statement, Stmt::Comment(_)));
+        if let Some(last) = last.filter(|_| self.tail_expression) {
+            if let Stmt::Expression(value) = &mut body[last] {
+                let mut value = std::mem::replace(value, Expr::Null(NullNode::default()));
+                // The statement's comments stay with the statement
+                body[last] = Stmt::Return(ReturnNode {
+                    comments: value.take_comments(),
+                    ..ReturnNode::new(Some(value))
+                });
+            }
+        }
+        Ok(body)
+    }
+
+    fn parse_if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.nested(Self::parse_expression)?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = self.parse_statement()?;
+        let mut else_branch = None;
+
+        if self.match_token(TokenType::Else) {
+            else_branch = Some(self.parse_statement()?);
+        }
+
+        Ok(Stmt::If(IfNode::new(condition, then_branch, else_branch)))
+    }
+
+    fn parse_while_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.nested(Self::parse_expression)?;
+        self.consume(TokenType::RightParen, "Expect ')' after while condition.")?;
+
+        let body = self.parse_statement()?;
+
+        Ok(Stmt::While(WhileNode::new(condition, body)))
+    }
+
+    fn parse_do_while_statement(&mut self) -> ParseResult<Stmt> {
+        let body = self.parse_statement()?;
+
+        self.consume(TokenType::While, "Expect 'while' after do-while body.")?;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.nested(Self::parse_expression)?;
+        self.consume(TokenType::RightParen, "Expect ')' after while condition.")?;
+        self.consume_terminator("Expect ';' after do-while loop.")?;
+
+        Ok(Stmt::DoWhile(DoWhileNode::new(body, condition)))
+    }
+
+    // Either `for (init; condition; increment) body` or `for x in iterable body`
+    fn parse_for_statement(&mut self) -> ParseResult<Stmt> {
+        if self.match_token(TokenType::Identifier) {
+            let variable = self.previous().lexeme.clone();
+            self.consume(TokenType::In, "Expect 'in' after loop variable.")?;
+            let iterable = self.parse_expression()?;
+            let body = self.parse_statement()?;
+            return Ok(Stmt::ForIn(ForInNode::new(variable, iterable, body)));
+        }
+
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        let (initializer, condition, increment) = self.nested(Self::parse_for_clauses)?;
+
+        let body = self.parse_statement()?;
+
+        Ok(Stmt::For(ForNode::new(
+            initializer,
+            condition,
+            increment,
+            body,
+        )))
+    }
+
+    fn parse_try_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let body = BlockNode::new(self.parse_block_statements()?);
+
+        self.consume(TokenType::Catch, "Expect 'catch' after try block.")?;
+        let error_name = if self.match_token(TokenType::LeftParen) {
+            let name = self
+                .consume(TokenType::Identifier, "Expect error name in catch.")?
+                .lexeme
+                .clone();
+            self.consume(TokenType::RightParen, "Expect ')' after error name.")?;
+            Some(name)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::LeftBrace, "Expect '{' after catch clause.")?;
+        let handler = BlockNode::new(self.parse_block_statements()?);
+
+        Ok(Stmt::Try(TryNode::new(body, error_name, handler)))
+    }
+
+    fn parse_match_statement(&mut self) -> ParseResult<Stmt> {
+        let subject = self.parse_expression()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' after match subject.")?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let pattern = self.parse_pattern()?;
+            self.consume(TokenType::FatArrow, "Expect '=>' after match pattern.")?;
+
+            let block_body = self.match_token(TokenType::LeftBrace);
+            let body = if block_body {
+                Stmt::Block(BlockNode::new(self.parse_block_statements()?))
+            } else {
+                Stmt::Expression(self.parse_expression()?)
+            };
+            arms.push(MatchArmNode::new(pattern, body));
+
+            // Arms with block bodies, and the last arm, may omit the comma
+            if !self.match_token(TokenType::Comma)
+                && !block_body
+                && !self.check(TokenType::RightBrace)
+            {
+                self.consume(TokenType::Comma, "Expect ',' between match arms.")?;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+
+        Ok(Stmt::Match(MatchNode::new(subject, arms)))
+    }
+
+    // `match subject { pattern => value, ... }` in expression position,
+    // called with 'match' already consumed
+    fn parse_match_expression(&mut self) -> ParseResult<Expr> {
+        let subject = self.parse_expression()?;
+        self.consume(TokenType::LeftBrace, "Expect '{' after match subject.")?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let pattern = self.parse_pattern()?;
+            self.consume(TokenType::FatArrow, "Expect '=>' after match pattern.")?;
+            // Arms are separated by commas, so a value may span lines
+            let mut value = self.nested(Self::parse_expression)?;
+            let comma = self.match_token(TokenType::Comma);
+            self.attach_trailing(&mut value, |c| comma && c.same_line && c.is_line_comment());
+            arms.push(MatchExpressionArmNode::new(pattern, value));
+            if !comma {
+                break;
+            }
+        }
+        if arms.is_empty() {
+            return Err(ParseError::new(
+                "Match expression needs at least one arm.",
+                None,
+                self.peek(),
+            ));
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match arms.")?;
+
+        Ok(Expr::Match(MatchExpressionNode::new(subject, arms)))
+    }
+
+    // `@cfg("key")`, called with the '@' already consumed
+    fn parse_directive(&mut self) -> ParseResult<Expr> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect directive name after '@'.")?
+            .clone();
+        if name.lexeme != "cfg" {
+            let message = format!("Unknown directive '@{}'.", name.lexeme);
+            return Err(ParseError::new(message, None, &name));
+        }
+        self.consume(TokenType::LeftParen, "Expect '(' after '@cfg'.")?;
+        let token = self
+            .consume(TokenType::String, "Expect setting name string in '@cfg'.")?
+            .clone();
+        let key = string_value(&token.lexeme)
+            .map_err(|message| ParseError::new(message, None, &token))?;
+        self.consume(TokenType::RightParen, "Expect ')' after setting name.")?;
+        Ok(Expr::Cfg(CfgNode::new(key)))
+    }
+
+    // Patterns are `_` or a literal (optionally negated), including enum variants
+    fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        if self.check(TokenType::Identifier) && self.peek().lexeme == "_" {
+            self.advance();
+            return Ok(Pattern::Wildcard);
+        }
+        Ok(Pattern::Literal(self.parse_precedence(PREFIX_PRECEDENCE)?))
+    }
+
+    fn parse_variable_declaration(&mut self) -> ParseResult<Stmt> {
+        let name_token = self.consume(TokenType::Identifier, "Expect variable name.")?;
+        let var_name = name_token.lexeme.clone();
+        let span = name_token.span();
+
+        // `var x: float = 1.5;` declares the type; without it the type is
+        // left for the checker to infer from the initializer
+        let data_type = if self.match_token(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        let mut initializer = None;
+        if self.match_token(TokenType::Assign) {
+            initializer = Some(self.parse_expression()?);
+        }
+
+        self.consume_terminator("Expect ';' after variable declaration.")?;
+
+        let mut declaration = VarDeclarationNode::new(var_name, data_type, initializer);
+        declaration.span = NodeSpan(Some(span));
+        Ok(Stmt::VarDeclaration(declaration))
+    }
+
+    fn parse_const_declaration(&mut self) -> ParseResult<Stmt> {
+        let span = self.peek().span();
+        let name = self
+            .consume(TokenType::Identifier, "Expect constant name.")?
+            .lexeme
+            .clone();
+
+        let data_type = if self.match_token(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Assign, "Expect '=' after constant name.")?;
+        let value = self.parse_expression()?;
+        self.consume_terminator("Expect ';' after constant declaration.")?;
+
+        let mut declaration = ConstDeclarationNode::new(name, data_type, value);
+        declaration.span = NodeSpan(Some(span));
+        Ok(Stmt::ConstDeclaration(declaration))
+    }
+
+    fn parse_function_declaration(&mut self) -> ParseResult<Stmt> {
+        let span = self.peek().span();
+        let name = self
+            .consume(TokenType::Identifier, "Expect function name.")?
+            .lexeme
+            .clone();
+        let type_parameters = self.parse_type_parameters()?;
+        // The type parameters are in scope through the end of the body
+        let enclosing = self.type_parameters.len();
+        self.type_parameters.extend(type_parameters.iter().cloned());
+        let function = self.parse_function_rest(name);
+        self.type_parameters.truncate(enclosing);
+
+        let mut function = function?;
+        function.type_parameters = type_parameters;
+        function.span = NodeSpan(Some(span));
+        Ok(Stmt::FunctionDeclaration(function))
+    }
+
+    // `<T, U>` after a function's name, if it is generic
+    fn parse_type_parameters(&mut self) -> ParseResult<Vec<String>> {
+        let mut names = Vec::new();
+        if !self.match_token(TokenType::Less) {
+            return Ok(names);
+        }
+        loop {
+            let name = self.consume(TokenType::Identifier, "Expect type parameter name.")?;
+            names.push(name.lexeme.clone());
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::Greater, "Expect '>' after type parameters.")?;
+        Ok(names)
+    }
+
+    // A function declaration's parameters, return type and body
+    fn parse_function_rest(&mut self, name: String) -> ParseResult<FunctionDeclarationNode> {
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+        let parameters =
+            self.parse_parameters(TokenType::RightParen, "Expect ')' after parameters.")?;
+        let return_type = self.parse_return_type()?;
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.parse_function_body()?;
+        Ok(FunctionDeclarationNode::new(
+            name,
+            parameters,
+            return_type,
+            body,
+        ))
+    }
+
+    // Parses a parameter list up to and including its closing delimiter
+    fn parse_parameters(
+        &mut self,
+        closing: TokenType,
+        message: &str,
+    ) -> ParseResult<Vec<ParameterNode>> {
+        let mut parameters = Vec::new();
+        if !self.check(closing) {
+            loop {
+                parameters.push(self.parse_parameter()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(closing, message)?;
+        Ok(parameters)
+    }
+
+    fn parse_return_type(&mut self) -> ParseResult<Option<DataType>> {
+        if self.match_token(TokenType::Arrow) {
+            Ok(Some(self.parse_type()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // `fn(a, b) -> type { ... }`, called with the 'fn' already consumed
+    fn parse_function_expression(&mut self) -> ParseResult<Expr> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fn'.")?;
+        let parameters =
+            self.parse_parameters(TokenType::RightParen, "Expect ')' after parameters.")?;
+        let return_type = self.parse_return_type()?;
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        let body = LambdaBody::Block(self.parse_function_body()?);
+        Ok(Expr::Lambda(LambdaNode::new(parameters, return_type, body)))
+    }
+
+    // `|a, b| a + b` or `|x| { ... }`, called with the opening '|' (or the
+    // '||' of a parameterless closure) already consumed
+    fn parse_closure(&mut self) -> ParseResult<Expr> {
+        let parameters = if self.previous().token_type == TokenType::Or {
+            Vec::new()
+        } else {
+            self.parse_parameters(TokenType::Pipe, "Expect '|' after closure parameters.")?
+        };
+        let return_type = self.parse_return_type()?;
+
+        let body = if self.match_token(TokenType::LeftBrace) {
+            LambdaBody::Block(self.parse_function_body()?)
+        } else {
+            LambdaBody::Expression(Box::new(self.parse_expression()?))
+        };
+        Ok(Expr::Lambda(LambdaNode::new(parameters, return_type, body)))
+    }
+
+    // The `init; condition; increment)` of a for loop, through its ')'
+    fn parse_for_clauses(&mut self) -> ParseResult<(Option<Stmt>, Option<Expr>, Option<Expr>)> {
+        let initializer = if self.match_token(TokenType::Semicolon) {
+            None
+        } else if self.match_token(TokenType::Var) {
+            Some(self.parse_variable_declaration()?)
+        } else {
+            Some(self.parse_expression_statement()?)
+        };
+
+        let condition = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        Ok((initializer, condition, increment))
+    }
+
+    fn parse_struct_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect struct name.")?
+            .lexeme
+            .clone();
+        self.consume(TokenType::LeftBrace, "Expect '{' after struct name.")?;
+
+        let mut fields = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let field_name = self
+                .consume(TokenType::Identifier, "Expect field name.")?
+                .lexeme
+                .clone();
+            self.consume(TokenType::Colon, "Expect ':' after field name.")?;
+            let data_type = self.parse_type()?;
+            fields.push(FieldNode::new(field_name, data_type));
+
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after struct fields.")?;
+
+        Ok(Stmt::StructDeclaration(StructDeclarationNode::new(
+            name, fields,
+        )))
+    }
+
+    fn parse_enum_declaration(&mut self) -> ParseResult<Stmt> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect enum name.")?
+            .lexeme
+            .clone();
+        self.consume(TokenType::LeftBrace, "Expect '{' after enum name.")?;
+
+        let mut variants = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let variant = self
+                .consume(TokenType::Identifier, "Expect variant name.")?
+                .lexeme
+                .clone();
+            variants.push(variant);
+
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after enum variants.")?;
+
+        self.enum_names.insert(name.clone());
+        Ok(Stmt::EnumDeclaration(EnumDeclarationNode::new(
+            name, variants,
+        )))
+    }
+
+    fn parse_parameter(&mut self) -> ParseResult<ParameterNode> {
+        let span = self.peek().span();
+        let name = self
+            .consume(TokenType::Identifier, "Expect parameter name.")?
+            .lexeme
+            .clone();
+        let data_type = if self.match_token(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let mut parameter = ParameterNode::new(name, data_type);
+        parameter.span = NodeSpan(Some(span));
+        Ok(parameter)
+    }
+
+    fn parse_type(&mut self) -> ParseResult<DataType> {
+        let data_type = if self.match_token(TokenType::LeftBracket) {
+            let element = self.parse_type()?;
+            self.consume(TokenType::RightBracket, "Expect ']' after element type.")?;
+            DataType::Array(Box::new(element))
+        } else {
+            self.parse_type_name()?
+        };
+
+        // `int?` may also hold null
+        if self.match_token(TokenType::Question) {
+            Ok(DataType::Optional(Box::new(data_type)))
+        } else {
+            Ok(data_type)
+        }
+    }
+
+    fn parse_type_name(&mut self) -> ParseResult<DataType> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect type name.")?
+            .lexeme
+            .clone();
+        Ok(match name.as_str() {
+            "int" => DataType::Integer,
+            "float" => DataType::Float,
+            "string" => DataType::String,
+            "bool" => DataType::Boolean,
+            "char" => DataType::Char,
+            "tensor" => DataType::Tensor,
+            "void" => DataType::Void,
+            other if self.type_parameters.iter().any(|name| name == other) => {
+                DataType::Parameter(other.to_string())
+            }
+            // Any other name refers to a user-defined type
+            other if self.enum_names.contains(other) => DataType::Enum(other.to_string()),
+            other => DataType::Struct(other.to_string()),
+        })
+    }
+
+    fn parse_expression(&mut self) -> ParseResult<Expr> {
+        self.parse_precedence(LOWEST_PRECEDENCE)
+    }
+
+    /// Pratt parser core: parses a prefix expression, then keeps folding in
+    /// postfix and infix operators that bind at least as tightly as
+    /// `min_precedence`.
+    fn parse_precedence(&mut self, min_precedence: u8) -> ParseResult<Expr> {
+        self.descend(|parser| {
+            let prefix = parser.parse_prefix()?;
+            parser.parse_infix(prefix, min_precedence)
+        })
+    }
+
+    // Folds the operators following an already-parsed prefix expression
+    fn parse_infix(&mut self, mut expr: Expr, min_precedence: u8) -> ParseResult<Expr> {
+        loop {
+            // An operator at the start of a new line begins the next statement
+            if self.at_line_break() {
+                break;
+            }
+            let token_type = self.peek().token_type;
+            // Operations are located at their operator, which is what a
+            // runtime error points at
+            let span = self.peek().span();
+
+            if POSTFIX_OPERATORS.contains(&token_type) {
+                if POSTFIX_PRECEDENCE < min_precedence {
+                    break;
+                }
+                self.advance();
+                expr = self.parse_postfix(expr)?;
+                expr.set_span(span);
+            } else if token_type == TokenType::Assign
+                || compound_assignment_operator(token_type).is_some()
+            {
+                if ASSIGNMENT_PRECEDENCE < min_precedence {
+                    break;
+                }
+                let assignable = match &expr {
+                    Expr::Variable(_) => true,
+                    Expr::MemberAccess(member) => !member.optional,
+                    Expr::Index(index) => !index.optional,
+                    _ => false,
+                };
+                if !assignable {
+                    return Err(ParseError::new(
+                        "Invalid assignment target.",
+                        None,
+                        self.peek(),
+                    ));
+                }
+                self.advance();
+                // Right-associative: `a = b = c` assigns `b = c` to `a`
+                let value = self.parse_precedence(ASSIGNMENT_PRECEDENCE)?;
+                expr = match compound_assignment_operator(token_type) {
+                    Some(operator) => {
+                        Expr::CompoundAssignment(CompoundAssignmentNode::new(expr, operator, value))
+                    }
+                    None => Expr::Assignment(AssignmentNode::new(expr, value)),
+                };
+                expr.set_span(span);
+            } else if token_type == TokenType::Question {
+                if CONDITIONAL_PRECEDENCE < min_precedence {
+                    break;
+                }
+                self.advance();
+                expr = self.parse_conditional(expr)?;
+                expr.set_span(span);
+            } else if matches!(token_type, TokenType::DotDot | TokenType::DotDotEqual) {
+                if RANGE_PRECEDENCE < min_precedence {
+                    break;
+                }
+                if matches!(expr, Expr::Range(_)) {
+                    return Err(ParseError::new(
+                        "Ranges cannot be chained",
+                        None,
+                        self.peek(),
+                    ));
+                }
+                self.advance();
+                let end = self.parse_precedence(RANGE_PRECEDENCE + 1)?;
+                let inclusive = token_type == TokenType::DotDotEqual;
+                // `step` is contextual so it stays usable as an identifier
+                let step = if self.check(TokenType::Identifier) && self.peek().lexeme == "step" {
+                    self.advance();
+                    Some(self.parse_precedence(RANGE_PRECEDENCE + 1)?)
+                } else {
+                    None
+                };
+                expr = Expr::Range(RangeNode::new(expr, end, inclusive, step));
+                expr.set_span(span);
+            } else if token_type == TokenType::As {
+                if CAST_PRECEDENCE < min_precedence {
+                    break;
+                }
+                self.advance();
+                let data_type = self.parse_type()?;
+                expr = Expr::Cast(CastNode::new(expr, data_type));
+                expr.set_span(span);
+            } else if let Some((operator, precedence)) = infix_operator(token_type) {
+                if precedence < min_precedence {
+                    break;
+                }
+                self.advance();
+                // Left-associative: the right operand must bind more tightly
+                let right = self.parse_precedence(precedence + 1)?;
+                expr = Expr::Binary(BinaryOperationNode::new(expr, operator, right));
+                expr.set_span(span);
+            } else {
+                break;
+            }
+        }
+
+        // Comments before a ';' or the end of a line belong to the
+        // statement, and the statement list attaches them
+        let ends_statement = matches!(
+            self.peek().token_type,
+            TokenType::Semicolon | TokenType::RightBrace | TokenType::EOF
+        ) || self.at_line_break();
+        if !ends_statement {
+            self.attach_trailing(&mut expr, |_| true);
+        }
+        Ok(expr)
+    }
+
+    // Sets the pending comments aside for the node that starts here, until
+    // `take_leading_comments` hands them to it once it is parsed. They wait
+    // on the parser rather than the stack, which deep nesting would use up
+    fn set_leading_comments(&mut self) {
+        if !self.comments.is_empty() {
+            let comments = std::mem::take(&mut self.comments);
+            self.leading_comments.push((self.depth, comments));
+        }
+    }
+
+    fn take_leading_comments(&mut self) -> Vec<CommentNode> {
+        match self.leading_comments.last() {
+            Some(&(depth, _)) if depth == self.depth => {
+                self.leading_comments.pop().unwrap_or_default().1
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Moves the pending comments that `which` picks to the end of `expr`
+    fn attach_trailing(&mut self, expr: &mut Expr, which: impl Fn(&CommentNode) -> bool) {
+        let count = self.comments.iter().take_while(|c| which(c)).count();
+        if count > 0 {
+            let comments = self.comments.drain(..count);
+            expr.comments_mut().trailing.extend(comments);
+        }
+    }
+
+    fn parse_prefix(&mut self) -> ParseResult<Expr> {
+        let span = self.peek().span();
+        self.set_leading_comments();
+        let mut expr = match prefix_operator(self.peek().token_type) {
+            Some(operator) => {
+                self.advance();
+                let operand = self.parse_precedence(PREFIX_PRECEDENCE)?;
+                Expr::Unary(UnaryOperationNode::new(operator, operand))
+            }
+            None => self.parse_primary()?,
+        };
+        expr.set_span(span);
+        let leading = self.take_leading_comments();
+        if !leading.is_empty() {
+            expr.comments_mut().leading.splice(0..0, leading);
+        }
+        Ok(expr)
+    }
+
+    // Called with the postfix operator token already consumed
+    fn parse_postfix(&mut self, expr: Expr) -> ParseResult<Expr> {
+        match self.previous().token_type {
+            TokenType::LeftParen => {
+                let arguments = self.parse_arguments()?;
+                Ok(Expr::Call(CallNode::new(expr, arguments)))
+            }
+            TokenType::Dot => {
+                let member = self
+                    .consume(TokenType::Identifier, "Expect member name after '.'.")?
+                    .lexeme
+                    .clone();
+                self.parse_member(expr, member, false)
+            }
+            TokenType::LeftBracket => self.parse_index(expr, false),
+            TokenType::QuestionDot => {
+                if self.match_token(TokenType::LeftBracket) {
+                    return self.parse_index(expr, true);
+                }
+                let member = self
+                    .consume(TokenType::Identifier, "Expect member name after '?.'.")?
+                    .lexeme
+                    .clone();
+                self.parse_member(expr, member, true)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // `object.member`, or a method call when an argument list follows
+    fn parse_member(&mut self, object: Expr, member: String, optional: bool) -> ParseResult<Expr> {
+        if self.match_token(TokenType::LeftParen) {
+            let arguments = self.parse_arguments()?;
+            return Ok(Expr::MethodCall(MethodCallNode::new(
+                object, member, arguments, optional,
+            )));
+        }
+        Ok(Expr::MemberAccess(MemberAccessNode::new(
+            object, member, optional,
+        )))
+    }
+
+    // Parses an index after its '[' has been consumed
+    fn parse_index(&mut self, object: Expr, optional: bool) -> ParseResult<Expr> {
+        let index = self.nested(Self::parse_expression)?;
+        self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+        Ok(Expr::Index(IndexNode::new(object, index, optional)))
+    }
+
+    // `a ? b : c ? d : e` groups as `a ? b : (c ? d : e)`. Both branches
+    // may be assignments, so `ok ? x = 1 : y = 2` assigns in either branch,
+    // while the condition itself stops at the logical operators.
+    fn parse_conditional(&mut self, condition: Expr) -> ParseResult<Expr> {
+        let then_expression = self.parse_precedence(ASSIGNMENT_PRECEDENCE)?;
+        self.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.",
+        )?;
+        let else_expression = self.parse_precedence(ASSIGNMENT_PRECEDENCE)?;
+        Ok(Expr::Conditional(ConditionalExpressionNode::new(
+            condition,
+            then_expression,
+            else_expression,
+        )))
+    }
+
+    // Parses a call's argument list after its '(' has been consumed
+    fn parse_arguments(&mut self) -> ParseResult<Vec<Expr>> {
+        self.nested(|parser| {
+            let mut arguments = Vec::new();
+            if !parser.check(TokenType::RightParen) {
+                loop {
+                    let mut argument = parser.parse_expression()?;
+                    let comma = parser.match_token(TokenType::Comma);
+                    // A comment after the comma still ends the argument's line
+                    parser.attach_trailing(&mut argument, |c| {
+                        comma && c.same_line && c.is_line_comment()
+                    });
+                    arguments.push(argument);
+                    if !comma {
+                        break;
+                    }
+                }
+            }
+            parser.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+            Ok(arguments)
+        })
+    }
+
+    // Parses a (possibly nested) tensor literal after its '[' has been
+    // consumed, returning the flattened elements and the inferred shape
+    fn parse_tensor_elements(&mut self) -> ParseResult<(Vec<Expr>, Vec<usize>)> {
+        let mut elements = Vec::new();
+        let mut rows = 0;
+        let mut row_shape: Option<Vec<usize>> = None;
+
+        while !self.check(TokenType::RightBracket) && !self.is_at_end() {
+            let row_start = self.peek().clone();
+            let (row, shape) = if self.match_token(TokenType::LeftBracket) {
+                self.parse_tensor_elements()?
+            } else {
+                (vec![self.parse_expression()?], Vec::new())
+            };
+
+            match &row_shape {
+                Some(expected) if *expected != shape => {
+                    let message = format!(
+                        "Tensor rows must all have the same shape: expected {:?}, found {:?}",
+                        expected, shape
+                    );
+                    return Err(ParseError::new(message, None, &row_start));
+                }
+                Some(_) => {}
+                None => row_shape = Some(shape),
+            }
+            elements.extend(row);
+            rows += 1;
+
+            let comma = self.match_token(TokenType::Comma);
+            if let Some(last) = elements.last_mut() {
+                self.attach_trailing(last, |c| comma && c.same_line && c.is_line_comment());
+            }
+            if !comma {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after tensor elements.")?;
+
+        let mut shape = vec![rows];
+        shape.extend(row_shape.unwrap_or_default());
+        Ok((elements, shape))
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        if let Some(literal) = self.parse_literal()? {
+            Ok(literal)
+        } else if self.match_token(TokenType::Identifier) {
+            self.parse_identifier()
+        } else if self.match_any(&[TokenType::Pipe, TokenType::Or]) {
+            self.parse_closure()
+        } else if self.match_token(TokenType::Fun) {
+            self.parse_function_expression()
+        } else if self.match_token(TokenType::Spawn) {
+            self.parse_spawn()
+        } else if self.match_token(TokenType::Match) {
+            self.parse_match_expression()
+        } else if self.match_token(TokenType::At) {
+            self.parse_directive()
+        } else if self.match_token(TokenType::LeftBracket) {
+            self.parse_tensor_literal()
+        } else if self.match_token(TokenType::LeftParen) {
+            self.parse_parenthesized()
+        } else {
+            Err(ParseError::new("Expect expression.", None, self.peek()))
+        }
+    }
+
+    // A variable, or an enum variant `Name::Variant`
+    fn parse_identifier(&mut self) -> ParseResult<Expr> {
+        if self.check(TokenType::ColonColon) {
+            let enum_name = self.previous().lexeme.clone();
+            self.advance();
+            let variant = self
+                .consume(TokenType::Identifier, "Expect variant name after '::'.")?
+                .lexeme
+                .clone();
+            return Ok(Expr::EnumVariant(EnumVariantNode::new(enum_name, variant)));
+        }
+        // Its type is for the checker to work out
+        Ok(Expr::Variable(VariableNode::new(
+            self.previous().lexeme.clone(),
+        )))
+    }
+
+    fn parse_spawn(&mut self) -> ParseResult<Expr> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'spawn'.")?;
+        Ok(Expr::Spawn(SpawnNode::new(self.parse_function_body()?)))
+    }
+
+    fn parse_tensor_literal(&mut self) -> ParseResult<Expr> {
+        let (elements, shape) = self.nested(Self::parse_tensor_elements)?;
+        Ok(Expr::TensorLiteral(TensorLiteralNode::new(elements, shape)))
+    }
+
+    fn parse_parenthesized(&mut self) -> ParseResult<Expr> {
+        let expr = self.nested(Self::parse_expression)?;
+        self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
+        Ok(Expr::Parenthesis(ParenthesisNode::new(expr)))
+    }
+
+    // Number, string, boolean, null and character literals
+    fn parse_literal(&mut self) -> ParseResult<Option<Expr>> {
+        let literal = if self.match_token(TokenType::Number) {
+            let token = self.previous();
+            // Determine if the number is an integer or float for correct data type
+            let data_type = if token.lexeme.contains('.') {
+                DataType::Float
+            } else {
+                DataType::Integer
+            };
+            Ok(Expr::Number(NumberNode::new(
+                token.lexeme.clone(),
+                data_type,
+            )))
+        } else if self.match_token(TokenType::String) {
+            let token = self.previous();
+            match string_value(&token.lexeme) {
+                Ok(value) => Ok(Expr::String(StringNode::new(value))),
+                Err(message) => Err(ParseError::new(message, None, token)),
+            }
+        } else if self.match_any(&[TokenType::True, TokenType::False]) {
+            let value = self.previous().token_type == TokenType::True;
+            Ok(Expr::Boolean(BooleanNode::new(value)))
+        } else if self.match_token(TokenType::Nil) {
+            Ok(Expr::Null(NullNode::default()))
+        } else if self.match_token(TokenType::Char) {
+            let token = self.previous();
+            match char_value(&token.lexeme) {
+                Ok(value) => Ok(Expr::Char(CharNode::new(value))),
+                Err(message) => Err(ParseError::new(message, None, token)),
+            }
+        } else {
+            return Ok(None);
+        };
+        literal.map(Some)
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> bool {
+        if self.check(token_type) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn match_any(&mut self, token_types: &[TokenType]) -> bool {
+        for &token_type in token_types {
+            if self.check(token_type) {
+                self.advance();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        if self.is_at_end() {
+            false
+        } else {
+            self.peek().token_type == token_type
+        }
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            let next = self.next_significant_token();
+            self.previous = std::mem::replace(&mut self.current, next);
+        }
+        self.previous()
+    }
+
+    /// Pulls the next non-comment token. Comments are set aside until the
+    /// node they belong to is parsed: the expression or statement after
+    /// them, or the one they end the line of.
+    /// Error tokens are recorded and skipped wherever they appear.
+    fn next_significant_token(&mut self) -> Token {
+        loop {
+            let token = Self::next_token(&mut self.tokens, self.current.line);
+            match token.token_type {
+                TokenType::Comment => {
+                    let same_line = token.line == self.current.end_line();
+                    self.comments
+                        .push(CommentNode::new(token.lexeme, same_line));
+                }
+                TokenType::Error => {
+                    let message = error_message(&token.lexeme);
+                    self.lexical_errors
+                        .push(ParseError::new(message, None, &token));
+                }
+                _ => return token,
+            }
+        }
+    }
+
+    /// Pulls the next token, synthesizing `EOF` if the source runs dry
+    /// without producing one.
+    fn next_token(tokens: &mut Box<dyn Iterator<Item = Token>>, line: usize) -> Token {
+        tokens
+            .next()
+            .unwrap_or_else(|| Token::new(TokenType::EOF, String::new(), line, 0))
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::EOF
+    }
+
+    fn peek(&self) -> &Token {
+        &self.current
+    }
+
+    fn previous(&self) -> &Token {
+        &self.previous
+    }
+
+    // Runs `parse` inside a bracketing construct, where line breaks are
+    // insignificant even with newline-terminated statements
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    fn descend<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(ParseError::new("Nesting too deep.", None, self.peek()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn at_line_break(&self) -> bool {
+        self.newline_terminated
+            && self.nesting == 0
+            && self.peek().line > self.previous().end_line()
+    }
+
+    // Whether the current statement ends here: at a ';', or with
+    // newline-terminated statements also at a line break, '}' or the end
+    fn at_terminator(&self) -> bool {
+        self.check(TokenType::Semicolon)
+            || (self.newline_terminated
+                && (self.at_line_break() || self.check(TokenType::RightBrace) || self.is_at_end()))
+    }
+
+    fn consume_terminator(&mut self, message: &str) -> ParseResult<()> {
+        if !self.match_token(TokenType::Semicolon) && !self.at_terminator() {
+            return Err(ParseError::new(
+                message,
+                Some(TokenType::Semicolon),
+                self.peek(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> ParseResult<&Token> {
+        if self.check(token_type) {
+            Ok(self.advance())
+        } else {
+            Err(ParseError::new(message, Some(token_type), self.peek()))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Substitution of compile-time settings. Each `@cfg("key")` is replaced by
+//! the value of `key` under project.zom's `## Custom Settings`, so later
+//! passes (constant folding in particular) see a plain literal.
+
+use crate::ast::*;
+use crate::visitor::{walk_expr_mut, MutVisitor};
+use std::collections::HashMap;
+
+/// Replaces every `@cfg` in `program` with its setting's value, typed as a
+/// boolean or number when the text reads as one and as a string otherwise.
+/// Fails on the first setting that isn't defined.
+pub fn substitute_cfg(
+    program: &mut ProgramNode,
+    settings: &HashMap<String, String>,
+) -> Result<(), String> {
+    let mut substituter = CfgSubstituter {
+        settings,
+        error: None,
+    };
+    substituter.visit_program_mut(program);
+    match substituter.error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn setting_literal(value: &str) -> Expr {
+    if value == "true" || value == "false" {
+        return Expr::Boolean(BooleanNode::new(value == "true"));
+    }
+    let is_digits = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let number = if digits.parse::<i64>().is_ok() {
+        Some(NumberNode::new(digits.to_string(), DataType::Integer))
+    } else if digits
+        .split_once('.')
+        .is_some_and(|(whole, fraction)| is_digits(whole) && is_digits(fraction))
+    {
+        Some(NumberNode::new(digits.to_string(), DataType::Float))
+    } else {
+        None
+    };
+    match number {
+        Some(number) if negative => Expr::Unary(UnaryOperationNode::new(
+            UnaryOperator::Negate,
+            Expr::Number(number),
+        )),
+        Some(number) => Expr::Number(number),
+        None => Expr::String(StringNode::new(value.to_string())),
+    }
+}
+
+struct CfgSubstituter<'a> {
+    settings: &'a HashMap<String, String>,
+    error: Option<String>,
+}
+
+impl MutVisitor for CfgSubstituter<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Cfg(cfg) = expr {
+            match self.settings.get(&cfg.key) {
+                Some(value) => *expr = setting_literal(value),
+                None => {
+                    self.error.get_or_insert_with(|| {
+                        format!("Undefined configuration setting '{}'", cfg.key)
+                    });
+                }
+            }
+            return;
+        }
+        walk_expr_mut(self, expr);
+    }
+}
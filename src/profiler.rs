@@ -0,0 +1,108 @@
+//! Where a script spends its time. An interpreter with profiling enabled
+//! counts how often each line and each PUT function runs and how long it
+//! takes, including everything it calls.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// How many times something ran and how long it took in total.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counter {
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl Counter {
+    fn add(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    lines: HashMap<usize, Counter>,
+    functions: HashMap<String, Counter>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one run of the statements on `line`. Time spent while the
+    /// line was already running, as in a recursive call, should be passed
+    /// as zero so it is not counted twice.
+    pub fn record_line(&mut self, line: usize, time: Duration) {
+        self.lines.entry(line).or_default().add(time);
+    }
+
+    /// Counts one call to `function`, with the same rule for time as
+    /// `record_line`.
+    pub fn record_call(&mut self, function: &str, time: Duration) {
+        self.functions
+            .entry(function.to_string())
+            .or_default()
+            .add(time);
+    }
+
+    /// Each line that ran, slowest first.
+    pub fn lines(&self) -> Vec<(usize, Counter)> {
+        let mut lines: Vec<_> = self.lines.iter().map(|(&line, &c)| (line, c)).collect();
+        lines.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(&b.0)));
+        lines
+    }
+
+    /// Each function that was called, slowest first.
+    pub fn functions(&self) -> Vec<(&str, Counter)> {
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(name, &c)| (name.as_str(), c))
+            .collect();
+        functions.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        functions
+    }
+
+    /// A table of the functions and then the lines, slowest first, with
+    /// each line's text from `source`.
+    pub fn report(&self, source: &str) -> String {
+        let source: Vec<&str> = source.lines().collect();
+        let mut report = String::new();
+        if !self.functions.is_empty() {
+            let _ = writeln!(report, "{:<20} {:>10} {:>12}", "function", "calls", "time");
+            for (name, counter) in self.functions() {
+                let _ = writeln!(
+                    report,
+                    "{:<20} {:>10} {:>12}",
+                    name,
+                    counter.count,
+                    milliseconds(counter.time)
+                );
+            }
+            report.push('\n');
+        }
+        let _ = writeln!(
+            report,
+            "{:>6} {:>10} {:>12}  source",
+            "line", "runs", "time"
+        );
+        for (line, counter) in self.lines() {
+            let text = source.get(line - 1).map_or("", |text| text.trim());
+            let _ = writeln!(
+                report,
+                "{:>6} {:>10} {:>12}  {}",
+                line,
+                counter.count,
+                milliseconds(counter.time),
+                text
+            );
+        }
+        report
+    }
+}
+
+fn milliseconds(time: Duration) -> String {
+    format!("{:.3} ms", time.as_secs_f64() * 1000.0)
+}
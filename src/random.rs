@@ -0,0 +1,81 @@
+//! The pseudo-random generator behind the `random` builtins. It is
+//! SplitMix64: tiny, fast, and fully determined by its seed, so a script
+//! that calls `seed(n)` produces the same numbers on every run and
+//! platform. It is not suitable for anything security related.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// A generator seeded from the clock, for scripts that never call
+    /// `seed`. `wasm32-unknown-unknown` has no clock, so there the seed is
+    /// fixed.
+    pub fn from_time() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return Rng::new(0);
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill a double's mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `lo..=hi`, which must not be empty.
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        debug_assert!(lo <= hi);
+        let size = (hi as i128 - lo as i128 + 1) as u128;
+        let offset = (self.next_u64() as u128 * size) >> 64;
+        (lo as i128 + offset as i128) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+            let n = rng.range(-3, 3);
+            assert!((-3..=3).contains(&n));
+        }
+        assert_eq!(rng.range(5, 5), 5);
+        // The full range must not overflow
+        rng.range(i64::MIN, i64::MAX);
+    }
+}
@@ -0,0 +1,424 @@
+//! The interactive prompt behind `put repl`. A `Session` evaluates one
+//! input after another in the same engine, and can be saved to a file and
+//! loaded back later, so a long tensor experiment survives closing the
+//! terminal. A saved session holds the inputs typed so far and the value
+//! of every global; functions are kept as their syntax tree and come back
+//! as if declared at the top level.
+
+use crate::ast::{ProgramNode, Stmt};
+use crate::debugger::describe;
+use crate::engine::{Engine, EngineError};
+use crate::interpreter::{EnumValue, StructValue, Value};
+use crate::json::JsonValue;
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+use crate::tensor::Tensor;
+use crate::token::TokenType;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+const HELP: &str =
+    "Type a statement or expression to run it; the ; after the last one may be left off.
+  :save <file>  save the history and globals to a file
+  :load <file>  define the globals saved in a file
+  :history      list the inputs run so far
+  :help         show this list
+  :quit         leave the session";
+
+pub struct Session {
+    engine: Engine,
+    history: Vec<String>,
+}
+
+impl Session {
+    pub fn new(engine: Engine) -> Self {
+        Session {
+            engine,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn engine(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Every input that has run, including those loaded from a file, oldest
+    /// first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Runs `input` and returns the value of its last expression statement,
+    /// as `Engine::eval` does, supplying the final `;` if it is missing.
+    /// Inputs that parse are added to the history even if they fail.
+    pub fn eval(&mut self, input: &str) -> Result<Value, EngineError> {
+        let input = input.trim();
+        let terminated = format!("{};", input);
+        let source = if ends_early(input) && parses(&terminated) {
+            &terminated
+        } else {
+            input
+        };
+        let result = self.engine.eval(source);
+        if !matches!(result, Err(EngineError::Parse(_))) {
+            self.history.push(source.to_string());
+        }
+        result
+    }
+
+    /// Writes the history and globals to `path`. Globals holding threads,
+    /// channels or modules cannot be saved and are left out.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let globals = self
+            .engine
+            .globals()
+            .filter_map(|(name, value)| {
+                let constant = self.engine.is_constant(&name);
+                Some(JsonValue::Object(vec![
+                    ("name".to_string(), JsonValue::String(name)),
+                    ("constant".to_string(), JsonValue::Bool(constant)),
+                    ("value".to_string(), value_to_json(&value)?),
+                ]))
+            })
+            .collect();
+        let history = self
+            .history
+            .iter()
+            .map(|input| JsonValue::String(input.clone()))
+            .collect();
+        let session = JsonValue::Object(vec![
+            ("history".to_string(), JsonValue::Array(history)),
+            ("globals".to_string(), JsonValue::Array(globals)),
+        ]);
+        fs::write(path, session.to_string())
+            .map_err(|error| format!("Cannot save session to {}: {}", path, error))
+    }
+
+    /// Defines the globals saved in `path`, replacing those of the same
+    /// name, and adds its history to this session's.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let fail = |message: String| format!("Cannot load session from {}: {}", path, message);
+        let text = fs::read_to_string(path).map_err(|error| fail(error.to_string()))?;
+        let session = JsonValue::parse(&text).map_err(fail)?;
+        let (Some(history), Some(globals)) = (
+            session.get("history").and_then(JsonValue::as_array),
+            session.get("globals").and_then(JsonValue::as_array),
+        ) else {
+            return Err(fail("not a saved session".to_string()));
+        };
+        for global in globals {
+            let name = global
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| fail("a global has no name".to_string()))?;
+            let constant = global
+                .get("constant")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false);
+            let value = global
+                .get("value")
+                .ok_or_else(|| format!("no value for '{}'", name))
+                .and_then(|value| self.value_from_json(value))
+                .map_err(fail)?;
+            self.engine
+                .interpreter()
+                .define_global(name, value, constant)
+                .map_err(|error| fail(error.message))?;
+        }
+        self.history.extend(
+            history
+                .iter()
+                .filter_map(JsonValue::as_str)
+                .map(str::to_string),
+        );
+        Ok(())
+    }
+
+    /// Reads inputs from `input` until it runs out or `:quit` is typed,
+    /// writing prompts, results and errors to `output`. An input that stops
+    /// partway, like a function whose body is still open, carries on onto
+    /// the next line; an empty line gives up on it. The console is best
+    /// effort, so write errors are ignored.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) {
+        let mut pending = String::new();
+        loop {
+            let prompt = if pending.is_empty() { "put> " } else { "...> " };
+            let _ = write!(output, "{}", prompt);
+            let _ = output.flush();
+            let mut line = String::new();
+            if !matches!(input.read_line(&mut line), Ok(read) if read > 0) {
+                return;
+            }
+            if pending.is_empty() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Some(command) = line.trim().strip_prefix(':') {
+                    if !self.command(command, &mut output) {
+                        return;
+                    }
+                    continue;
+                }
+            }
+            let giving_up = !pending.is_empty() && line.trim().is_empty();
+            pending.push_str(&line);
+            match self.eval(&pending) {
+                Err(EngineError::Parse(error)) if !giving_up && ends_early_with(&error) => {
+                    continue;
+                }
+                Ok(Value::Null) => {}
+                Ok(value) => {
+                    let _ = writeln!(output, "{}", describe(&value));
+                }
+                Err(error) => {
+                    let _ = writeln!(output, "{}", error.render(&pending));
+                }
+            }
+            pending.clear();
+        }
+    }
+
+    // Answers one `:` command, returning whether the session goes on
+    fn command(&mut self, command: &str, output: &mut impl Write) -> bool {
+        let (word, argument) = match command.split_once(' ') {
+            Some((word, argument)) => (word, argument.trim()),
+            None => (command, ""),
+        };
+        let result = match word {
+            "save" | "load" if argument.is_empty() => Err(format!("Usage: :{} <file>", word)),
+            "save" => self.save(argument),
+            "load" => self.load(argument),
+            "history" => {
+                for (i, input) in self.history.iter().enumerate() {
+                    let input = input.replace('\n', "\n      ");
+                    let _ = writeln!(output, "{:>4}  {}", i + 1, input);
+                }
+                Ok(())
+            }
+            "help" => {
+                let _ = writeln!(output, "{}", HELP);
+                Ok(())
+            }
+            "quit" => return false,
+            other => Err(format!(
+                "Unknown command ':{}'; type :help for the list",
+                other
+            )),
+        };
+        if let Err(message) = result {
+            let _ = writeln!(output, "{}", message);
+        }
+        true
+    }
+
+    fn value_from_json(&mut self, value: &JsonValue) -> Result<Value, String> {
+        let field = |key: &str| {
+            value
+                .get(key)
+                .ok_or_else(|| format!("Missing field '{}' in a saved value", key))
+        };
+        let text = |key: &str| {
+            field(key)?
+                .as_str()
+                .ok_or_else(|| format!("Field '{}' of a saved value must be a string", key))
+        };
+        let kind = value
+            .get("type")
+            .and_then(JsonValue::as_str)
+            .ok_or("Expected a saved value with a string 'type' field")?;
+        Ok(match kind {
+            "null" => Value::Null,
+            "int" => Value::Integer(
+                text("value")?
+                    .parse()
+                    .map_err(|_| "Saved int is out of range")?,
+            ),
+            "float" => Value::Float(float_from_json(field("value")?)?),
+            "bool" => Value::Boolean(
+                field("value")?
+                    .as_bool()
+                    .ok_or("Saved bool must be true or false")?,
+            ),
+            "char" => {
+                let mut chars = text("value")?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Value::Char(c),
+                    _ => return Err("Saved char must be one character".to_string()),
+                }
+            }
+            "string" => Value::String(text("value")?.into()),
+            "enum" => Value::Enum(Arc::new(EnumValue {
+                enum_name: text("enum")?.to_string(),
+                variant: text("variant")?.to_string(),
+            })),
+            "struct" => Value::Struct(Arc::new(StructValue {
+                name: text("name")?.to_string(),
+                fields: match field("fields")? {
+                    JsonValue::Object(fields) => fields
+                        .iter()
+                        .map(|(name, field)| Ok((name.clone(), self.value_from_json(field)?)))
+                        .collect::<Result<Vec<(String, Value)>, String>>()?,
+                    _ => return Err("Saved struct fields must be an object".to_string()),
+                },
+            })),
+            "tensor" => {
+                let shape = field("shape")?
+                    .as_array()
+                    .ok_or("Saved tensor shape must be an array")?
+                    .iter()
+                    .map(|size| match size.as_f64() {
+                        Some(size) if size >= 0.0 && size.fract() == 0.0 => Ok(size as usize),
+                        _ => Err("Saved tensor shape must hold sizes".to_string()),
+                    })
+                    .collect::<Result<Vec<usize>, String>>()?;
+                let data = field("data")?
+                    .as_array()
+                    .ok_or("Saved tensor data must be an array")?
+                    .iter()
+                    .map(float_from_json)
+                    .collect::<Result<Vec<f64>, String>>()?;
+                if data.len() != shape.iter().product::<usize>() {
+                    return Err("Saved tensor data does not match its shape".to_string());
+                }
+                Value::Tensor(Arc::new(Tensor::new(data, shape)))
+            }
+            "array" => Value::Array(Arc::new(
+                field("elements")?
+                    .as_array()
+                    .ok_or("Saved array elements must be an array")?
+                    .iter()
+                    .map(|element| self.value_from_json(element))
+                    .collect::<Result<Vec<Value>, String>>()?,
+            )),
+            "function" => {
+                let program = ProgramNode::from_json_value(field("declaration")?)?;
+                match <[Stmt; 1]>::try_from(program.statements) {
+                    Ok([Stmt::FunctionDeclaration(declaration)]) => {
+                        self.engine.interpreter().global_function(declaration)
+                    }
+                    _ => return Err("Saved function must be one declaration".to_string()),
+                }
+            }
+            other => return Err(format!("Unknown saved value type '{}'", other)),
+        })
+    }
+}
+
+fn parses(source: &str) -> bool {
+    Parser::new(Lexer::new(source)).parse().is_ok()
+}
+
+// Whether parsing `source` runs out of input before it is complete
+fn ends_early(source: &str) -> bool {
+    match Parser::new(Lexer::new(source)).parse() {
+        Ok(_) => false,
+        Err(error) => ends_early_with(&error),
+    }
+}
+
+fn ends_early_with(error: &ParseError) -> bool {
+    error.found.token_type == TokenType::EOF
+}
+
+fn typed(kind: &str, fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    let mut entries = vec![("type".to_string(), JsonValue::String(kind.to_string()))];
+    entries.extend(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value)),
+    );
+    JsonValue::Object(entries)
+}
+
+// JSON has no NaN or infinities, so those are written as strings
+fn float_to_json(value: f64) -> JsonValue {
+    if value.is_finite() {
+        JsonValue::Number(value)
+    } else {
+        JsonValue::String(value.to_string())
+    }
+}
+
+fn float_from_json(value: &JsonValue) -> Result<f64, String> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str()?.parse().ok())
+        .ok_or_else(|| "Saved float must be a number".to_string())
+}
+
+// `None` for values that only mean something while the session runs
+fn value_to_json(value: &Value) -> Option<JsonValue> {
+    Some(match value {
+        Value::Null => typed("null", vec![]),
+        // As a string, since JSON numbers lose precision past 2^53
+        Value::Integer(value) => {
+            typed("int", vec![("value", JsonValue::String(value.to_string()))])
+        }
+        Value::Float(value) => typed("float", vec![("value", float_to_json(*value))]),
+        Value::Boolean(value) => typed("bool", vec![("value", JsonValue::Bool(*value))]),
+        Value::Char(c) => typed("char", vec![("value", JsonValue::String(c.to_string()))]),
+        Value::String(text) => typed(
+            "string",
+            vec![("value", JsonValue::String(text.to_string()))],
+        ),
+        Value::Enum(value) => typed(
+            "enum",
+            vec![
+                ("enum", JsonValue::String(value.enum_name.clone())),
+                ("variant", JsonValue::String(value.variant.clone())),
+            ],
+        ),
+        Value::Struct(value) => typed(
+            "struct",
+            vec![
+                ("name", JsonValue::String(value.name.clone())),
+                (
+                    "fields",
+                    JsonValue::Object(
+                        value
+                            .fields
+                            .iter()
+                            .map(|(name, field)| Some((name.clone(), value_to_json(field)?)))
+                            .collect::<Option<_>>()?,
+                    ),
+                ),
+            ],
+        ),
+        Value::Tensor(tensor) => typed(
+            "tensor",
+            vec![
+                (
+                    "shape",
+                    JsonValue::Array(
+                        tensor
+                            .shape()
+                            .iter()
+                            .map(|&size| JsonValue::Number(size as f64))
+                            .collect(),
+                    ),
+                ),
+                (
+                    "data",
+                    JsonValue::Array(tensor.data().iter().copied().map(float_to_json).collect()),
+                ),
+            ],
+        ),
+        Value::Array(elements) => typed(
+            "array",
+            vec![(
+                "elements",
+                JsonValue::Array(elements.iter().map(value_to_json).collect::<Option<_>>()?),
+            )],
+        ),
+        Value::Function(function) => {
+            let program = ProgramNode {
+                statements: vec![Stmt::FunctionDeclaration(function.declaration.clone())],
+            };
+            typed("function", vec![("declaration", program.to_json_value())])
+        }
+        Value::NativeFunction(_) | Value::Thread(_) | Value::Channel(_) | Value::Module(_) => {
+            return None
+        }
+    })
+}
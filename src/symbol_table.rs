@@ -0,0 +1,248 @@
+//! The names the type checker knows about at each point in a program. It
+//! mirrors the interpreter's environments: every block, loop and function
+//! body opens a scope, and a name resolves to its innermost declaration.
+
+use crate::ast::{DataType, FunctionDeclarationNode, ParameterNode, StructDeclarationNode};
+use crate::constants::Constant;
+use crate::token::Span;
+use std::collections::{HashMap, HashSet};
+
+/// What the checker knows about a declared name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    /// The declared type; `None` when the declaration doesn't say
+    pub data_type: Option<DataType>,
+    /// Bound by `const`, so never rebound
+    pub constant: bool,
+    /// What calls must pass, for names bound by `fn`
+    pub signature: Option<Signature>,
+    /// Whether anything reads the value, as opposed to only assigning it
+    pub read: bool,
+    /// The value of a constant whose initializer the checker can compute
+    pub value: Option<Constant>,
+    /// The shape of a constant tensor, where the checker can tell it
+    pub shape: Option<Vec<usize>>,
+    /// Where the name is declared, if that is known
+    pub span: Option<Span>,
+}
+
+impl Symbol {
+    pub fn variable(data_type: Option<DataType>) -> Self {
+        Symbol {
+            data_type,
+            constant: false,
+            signature: None,
+            read: false,
+            value: None,
+            shape: None,
+            span: None,
+        }
+    }
+
+    pub fn constant(data_type: Option<DataType>) -> Self {
+        Symbol {
+            data_type,
+            constant: true,
+            signature: None,
+            read: false,
+            value: None,
+            shape: None,
+            span: None,
+        }
+    }
+
+    pub fn function(signature: Signature) -> Self {
+        Symbol {
+            data_type: None,
+            constant: false,
+            signature: Some(signature),
+            read: false,
+            value: None,
+            shape: None,
+            span: None,
+        }
+    }
+}
+
+/// The parameters and return type a function was declared with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub parameters: Vec<ParameterNode>,
+    /// `None` when the declaration doesn't say
+    pub return_type: Option<DataType>,
+}
+
+impl Signature {
+    pub fn of(function: &FunctionDeclarationNode) -> Self {
+        Signature {
+            parameters: function.parameters.clone(),
+            return_type: function.return_type.clone(),
+        }
+    }
+
+    /// The constructor a struct declaration defines, which takes the
+    /// struct's fields in order.
+    pub fn constructor(declaration: &StructDeclarationNode) -> Self {
+        Signature {
+            parameters: declaration
+                .fields
+                .iter()
+                .map(|field| ParameterNode::new(field.name.clone(), Some(field.data_type.clone())))
+                .collect(),
+            return_type: Some(DataType::Struct(declaration.name.clone())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    symbols: HashMap<String, Symbol>,
+    // Names declared anywhere in the scope, including further on. A
+    // function body can use them before their declaration, since it only
+    // runs once it is called
+    declared: HashSet<String>,
+    // The signatures of the functions declared further on
+    signatures_ahead: HashMap<String, Signature>,
+    // Names read, from a function body, before their declaration
+    read_ahead: HashSet<String>,
+    // Every name declared so far, in order
+    order: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    // The global scope first, innermost last; never empty
+    scopes: Vec<Scope>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    /// A table holding only an empty global scope.
+    pub fn new() -> Self {
+        SymbolTable {
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Forgets the names declared in the innermost scope. The global scope
+    /// is never left.
+    pub fn exit_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Declares `name` in the innermost scope, replacing any declaration
+    /// of it there.
+    pub fn declare(&mut self, name: &str, mut symbol: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declared.insert(name.to_string());
+            symbol.read |= scope.read_ahead.contains(name);
+            if scope.symbols.insert(name.to_string(), symbol).is_none() {
+                scope.order.push(name.to_string());
+            }
+        }
+    }
+
+    /// Notes that the innermost scope declares `name` somewhere, before
+    /// the declaration itself is reached, with `signature` if it is a
+    /// function.
+    pub fn declare_ahead(&mut self, name: &str, signature: Option<Signature>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declared.insert(name.to_string());
+            if let Some(signature) = signature {
+                scope.signatures_ahead.insert(name.to_string(), signature);
+            }
+        }
+    }
+
+    /// Notes that the value of `name` is read where it is used, counting
+    /// declarations not yet reached.
+    pub fn mark_read(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(symbol) = scope.symbols.get_mut(name) {
+                symbol.read = true;
+                return;
+            }
+            if scope.declared.contains(name) {
+                scope.read_ahead.insert(name.to_string());
+                return;
+            }
+        }
+    }
+
+    /// The variables the innermost scope declares that nothing has read,
+    /// with where each is declared, in the order they were declared.
+    /// Functions are left out.
+    pub fn unread(&self) -> Vec<(&str, Option<Span>)> {
+        let Some(scope) = self.scopes.last() else {
+            return Vec::new();
+        };
+        scope
+            .order
+            .iter()
+            .filter(|name| {
+                let symbol = &scope.symbols[name.as_str()];
+                !symbol.read && symbol.signature.is_none()
+            })
+            .map(|name| (name.as_str(), scope.symbols[name.as_str()].span))
+            .collect()
+    }
+
+    /// How many scopes enclose the innermost one; the global scope is at
+    /// depth 0.
+    pub fn depth(&self) -> usize {
+        self.scopes.len() - 1
+    }
+
+    /// The depth of the scope holding the innermost declaration of `name`
+    /// reached so far.
+    pub fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.symbols.contains_key(name))
+    }
+
+    /// The innermost declaration of `name` reached so far.
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.symbols.get(name))
+    }
+
+    /// The declaration of `name` in the innermost scope itself.
+    pub fn lookup_local(&self, name: &str) -> Option<&Symbol> {
+        self.scopes.last()?.symbols.get(name)
+    }
+
+    /// The signature of the function that `name` names where it is used,
+    /// counting declarations not yet reached.
+    pub fn signature(&self, name: &str) -> Option<&Signature> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| match scope.symbols.get(name) {
+                Some(symbol) => Some(symbol.signature.as_ref()),
+                None if scope.declared.contains(name) => Some(scope.signatures_ahead.get(name)),
+                None => None,
+            })?
+    }
+
+    /// Whether any enclosing scope declares `name`, wherever in the scope
+    /// the declaration is.
+    pub fn is_declared(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.declared.contains(name))
+    }
+}
@@ -1,288 +1,949 @@
-use std::fmt;
-use std::ops::{Add, Mul, Sub};
-
-#[derive(Clone, Debug)]
-pub struct Tensor {
-    data: Vec<f64>,
-    shape: Vec<usize>,
-}
-
-impl Tensor {
-    pub fn new(data: Vec<f64>, shape: Vec<usize>) -> Self {
-        assert_eq!(data.len(), shape.iter().product());
-        Tensor { data, shape }
-    }
-
-    pub fn zeros(shape: Vec<usize>) -> Self {
-        let size = shape.iter().product();
-        Tensor {
-            data: vec![0.0; size],
-            shape,
-        }
-    }
-
-    pub fn get(&self, indices: &[usize]) -> Option<f64> {
-        let index = self.compute_index(indices)?;
-        self.data.get(index).cloned()
-    }
-
-    pub fn set(&mut self, indices: &[usize], value: f64) -> Result<(), String> {
-        let index = self.compute_index(indices).ok_or("Invalid indices")?;
-        if let Some(elem) = self.data.get_mut(index) {
-            *elem = value;
-            Ok(())
-        } else {
-            Err("Index out of bounds".to_string())
-        }
-    }
-
-    fn compute_index(&self, indices: &[usize]) -> Option<usize> {
-        if indices.len() != self.shape.len() {
-            return None;
-        }
-        let mut index = 0;
-        let mut multiplier = 1;
-        for (&dim, &idx) in self.shape.iter().zip(indices).rev() {
-            if idx >= dim {
-                return None;
-            }
-            index += idx * multiplier;
-            multiplier *= dim;
-        }
-        Some(index)
-    }
-
-    pub fn matmul(&self, other: &Tensor) -> Result<Tensor, String> {
-        if self.shape.len() != 2 || other.shape.len() != 2 {
-            return Err("Both tensors must be 2-dimensional for matrix multiplication".to_string());
-        }
-        if self.shape[1] != other.shape[0] {
-            return Err("Inner dimensions must match for matrix multiplication".to_string());
-        }
-
-        let m = self.shape[0];
-        let n = other.shape[1];
-        let p = self.shape[1];
-
-        let mut result_data = vec![0.0; m * n];
-
-        for i in 0..m {
-            for j in 0..n {
-                let mut sum = 0.0;
-                for k in 0..p {
-                    sum += self.data[i * p + k] * other.data[k * n + j];
-                }
-                result_data[i * n + j] = sum;
-            }
-        }
-
-        Ok(Tensor::new(result_data, vec![m, n]))
-    }
-
-    pub fn transpose(&self) -> Tensor {
-        if self.shape.len() != 2 {
-            panic!("Transpose is currently only supported for 2D tensors");
-        }
-        let (rows, cols) = (self.shape[0], self.shape[1]);
-        let mut new_data = vec![0.0; self.data.len()];
-        for i in 0..rows {
-            for j in 0..cols {
-                new_data[j * rows + i] = self.data[i * cols + j];
-            }
-        }
-        Tensor::new(new_data, vec![cols, rows])
-    }
-
-    pub fn apply<F>(&self, f: F) -> Tensor
-    where
-        F: Fn(f64) -> f64,
-    {
-        let new_data = self.data.iter().map(|&x| f(x)).collect();
-        Tensor::new(new_data, self.shape.clone())
-    }
-
-    pub fn exp(&self) -> Tensor {
-        self.apply(|x| x.exp())
-    }
-
-    pub fn log(&self) -> Tensor {
-        self.apply(|x| x.ln())
-    }
-
-    pub fn mean(&self) -> f64 {
-        self.data.iter().sum::<f64>() / self.data.len() as f64
-    }
-
-    pub fn variance(&self) -> f64 {
-        let mean = self.mean();
-        self.data.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / self.data.len() as f64
-    }
-
-    pub fn std_dev(&self) -> f64 {
-        self.variance().sqrt()
-    }
-}
-
-impl Add for &Tensor {
-    type Output = Tensor;
-
-    fn add(self, other: &Tensor) -> Tensor {
-        assert_eq!(self.shape, other.shape, "Shapes must match for addition");
-        let data = self
-            .data
-            .iter()
-            .zip(&other.data)
-            .map(|(&a, &b)| a + b)
-            .collect();
-        Tensor::new(data, self.shape.clone())
-    }
-}
-
-impl Sub for &Tensor {
-    type Output = Tensor;
-
-    fn sub(self, other: &Tensor) -> Tensor {
-        assert_eq!(self.shape, other.shape, "Shapes must match for subtraction");
-        let data = self
-            .data
-            .iter()
-            .zip(&other.data)
-            .map(|(&a, &b)| a - b)
-            .collect();
-        Tensor::new(data, self.shape.clone())
-    }
-}
-
-impl Mul for &Tensor {
-    type Output = Tensor;
-
-    fn mul(self, other: &Tensor) -> Tensor {
-        assert_eq!(
-            self.shape, other.shape,
-            "Shapes must match for element-wise multiplication"
-        );
-        let data = self
-            .data
-            .iter()
-            .zip(&other.data)
-            .map(|(&a, &b)| a * b)
-            .collect();
-        Tensor::new(data, self.shape.clone())
-    }
-}
-
-impl fmt::Display for Tensor {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Tensor(shape={:?}, data={:?})", self.shape, self.data)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_tensor_creation() {
-        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        assert_eq!(t.shape, vec![2, 2]);
-        assert_eq!(t.data, vec![1.0, 2.0, 3.0, 4.0]);
-    }
-
-    #[test]
-    fn test_tensor_zeros() {
-        let t = Tensor::zeros(vec![2, 3]);
-        assert_eq!(t.shape, vec![2, 3]);
-        assert_eq!(t.data, vec![0.0; 6]);
-    }
-
-    #[test]
-    fn test_tensor_get() {
-        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        assert_eq!(t.get(&[0, 0]), Some(1.0));
-        assert_eq!(t.get(&[1, 1]), Some(4.0));
-        assert_eq!(t.get(&[2, 0]), None);
-    }
-
-    #[test]
-    fn test_tensor_set() {
-        let mut t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        assert!(t.set(&[0, 1], 5.0).is_ok());
-        assert_eq!(t.get(&[0, 1]), Some(5.0));
-        assert!(t.set(&[2, 0], 6.0).is_err());
-    }
-
-    #[test]
-    fn test_tensor_add() {
-        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
-        let result = &t1 + &t2;
-        assert_eq!(result.data, vec![6.0, 8.0, 10.0, 12.0]);
-    }
-
-    #[test]
-    fn test_tensor_subtraction() {
-        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
-        let result = &t1 - &t2;
-        assert_eq!(result.data, vec![-4.0, -4.0, -4.0, -4.0]);
-    }
-
-    #[test]
-    fn test_tensor_element_wise_multiplication() {
-        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
-        let result = &t1 * &t2;
-        assert_eq!(result.data, vec![5.0, 12.0, 21.0, 32.0]);
-    }
-
-    #[test]
-    fn test_tensor_matrix_multiplication() {
-        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
-        let result = t1.matmul(&t2).unwrap();
-        assert_eq!(result.data, vec![19.0, 22.0, 43.0, 50.0]);
-        assert_eq!(result.shape, vec![2, 2]);
-    }
-
-    #[test]
-    fn test_tensor_transpose() {
-        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
-        let transposed = t.transpose();
-        assert_eq!(transposed.shape, vec![3, 2]);
-        assert_eq!(transposed.data, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
-    }
-
-    #[test]
-    fn test_tensor_exp() {
-        let t = Tensor::new(vec![0.0, 1.0, 2.0], vec![3]);
-        let exp_t = t.exp();
-        assert!((exp_t.data[0] - 1.0).abs() < 1e-6);
-        assert!((exp_t.data[1] - std::f64::consts::E).abs() < 1e-6);
-        assert!((exp_t.data[2] - std::f64::consts::E.powi(2)).abs() < 1e-6);
-    }
-
-    #[test]
-    fn test_tensor_log() {
-        let t = Tensor::new(
-            vec![1.0, std::f64::consts::E, std::f64::consts::E.powi(2)],
-            vec![3],
-        );
-        let log_t = t.log();
-        assert!((log_t.data[0]).abs() < 1e-6);
-        assert!((log_t.data[1] - 1.0).abs() < 1e-6);
-        assert!((log_t.data[2] - 2.0).abs() < 1e-6);
-    }
-
-    #[test]
-    fn test_tensor_mean() {
-        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        assert_eq!(t.mean(), 2.5);
-    }
-
-    #[test]
-    fn test_tensor_variance_and_std_dev() {
-        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        assert!((t.variance() - 1.25).abs() < 1e-6);
-        assert!((t.std_dev() - 1.118033988749895).abs() < 1e-6);
-    }
-}
+use std::fmt;
+use std::ops::{Add, Mul, Range, Sub};
+
+/// The shape of the result of an element-wise operation on tensors of
+/// shapes `a` and `b`, by NumPy's broadcasting rules. The shapes are lined
+/// up from their last dimension, the shorter one counting as having 1s in
+/// front, and each pair of sizes must be equal, or one of them 1, which
+/// stretches to the other. `None` when they can't be lined up.
+pub fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let rank = a.len().max(b.len());
+    let size = |shape: &[usize], dim: usize| {
+        let padding = rank - shape.len();
+        if dim < padding {
+            1
+        } else {
+            shape[dim - padding]
+        }
+    };
+    (0..rank)
+        .map(|dim| match (size(a, dim), size(b, dim)) {
+            (a, b) if a == b || b == 1 => Some(a),
+            (1, b) => Some(b),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor {
+    data: Vec<f64>,
+    shape: Vec<usize>,
+}
+
+impl Tensor {
+    pub fn new(data: Vec<f64>, shape: Vec<usize>) -> Self {
+        assert_eq!(data.len(), shape.iter().product());
+        Tensor { data, shape }
+    }
+
+    pub fn zeros(shape: Vec<usize>) -> Self {
+        let size = shape.iter().product();
+        Tensor {
+            data: vec![0.0; size],
+            shape,
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// The elements in row-major order.
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn get(&self, indices: &[usize]) -> Option<f64> {
+        let index = self.compute_index(indices)?;
+        self.data.get(index).cloned()
+    }
+
+    pub fn set(&mut self, indices: &[usize], value: f64) -> Result<(), String> {
+        let index = self.compute_index(indices).ok_or("Invalid indices")?;
+        if let Some(elem) = self.data.get_mut(index) {
+            *elem = value;
+            Ok(())
+        } else {
+            Err("Index out of bounds".to_string())
+        }
+    }
+
+    fn compute_index(&self, indices: &[usize]) -> Option<usize> {
+        if indices.len() != self.shape.len() {
+            return None;
+        }
+        let mut index = 0;
+        let mut multiplier = 1;
+        for (&dim, &idx) in self.shape.iter().zip(indices).rev() {
+            if idx >= dim {
+                return None;
+            }
+            index += idx * multiplier;
+            multiplier *= dim;
+        }
+        Some(index)
+    }
+
+    /// Combines the elements of `self` and `other` pairwise with `f`, after
+    /// broadcasting both to a common shape; see `broadcast_shape`.
+    pub fn zip_with<F>(&self, other: &Tensor, f: F) -> Result<Tensor, String>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        if self.shape == other.shape {
+            let data = self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| f(a, b))
+                .collect();
+            return Ok(Tensor::new(data, self.shape.clone()));
+        }
+        let shape = broadcast_shape(&self.shape, &other.shape).ok_or_else(|| {
+            format!(
+                "Shapes {:?} and {:?} cannot be broadcast together",
+                self.shape, other.shape
+            )
+        })?;
+        let (left, right) = (self.strides_for(&shape), other.strides_for(&shape));
+        let size = shape.iter().product();
+        let mut data = Vec::with_capacity(size);
+        for mut flat in 0..size {
+            // Splits the result's index into one per dimension, last first,
+            // and finds the element each operand has there
+            let (mut a, mut b) = (0, 0);
+            for dim in (0..shape.len()).rev() {
+                let index = flat % shape[dim];
+                flat /= shape[dim];
+                a += index * left[dim];
+                b += index * right[dim];
+            }
+            data.push(f(self.data[a], other.data[b]));
+        }
+        Ok(Tensor::new(data, shape))
+    }
+
+    // How far apart in `data` consecutive indices along each dimension of
+    // `shape` are, once this tensor is broadcast to it: 0 along the
+    // dimensions it stretches
+    fn strides_for(&self, shape: &[usize]) -> Vec<usize> {
+        let padding = shape.len() - self.shape.len();
+        let mut strides = vec![0; shape.len()];
+        let mut stride = 1;
+        for (dim, &size) in self.shape.iter().enumerate().rev() {
+            if size != 1 {
+                strides[padding + dim] = stride;
+            }
+            stride *= size;
+        }
+        strides
+    }
+
+    pub fn matmul(&self, other: &Tensor) -> Result<Tensor, String> {
+        if self.shape.len() != 2 || other.shape.len() != 2 {
+            return Err("Both tensors must be 2-dimensional for matrix multiplication".to_string());
+        }
+        if self.shape[1] != other.shape[0] {
+            return Err("Inner dimensions must match for matrix multiplication".to_string());
+        }
+
+        let m = self.shape[0];
+        let n = other.shape[1];
+        let p = self.shape[1];
+
+        let mut result_data = vec![0.0; m * n];
+
+        for i in 0..m {
+            for j in 0..n {
+                let mut sum = 0.0;
+                for k in 0..p {
+                    sum += self.data[i * p + k] * other.data[k * n + j];
+                }
+                result_data[i * n + j] = sum;
+            }
+        }
+
+        Ok(Tensor::new(result_data, vec![m, n]))
+    }
+
+    /// The same elements, in the same order, arranged in `shape`. One size
+    /// may be -1, standing for whatever size makes the element count come
+    /// out the same.
+    pub fn reshape(&self, shape: &[isize]) -> Result<Tensor, String> {
+        let mismatch = || {
+            format!(
+                "Cannot reshape a tensor of {} elements into shape {:?}",
+                self.data.len(),
+                shape
+            )
+        };
+        let mut inferred = None;
+        let mut sizes = Vec::with_capacity(shape.len());
+        for (axis, &size) in shape.iter().enumerate() {
+            match size {
+                -1 if inferred.is_some() => {
+                    return Err("Only one size in a reshape can be -1".to_string())
+                }
+                -1 => {
+                    inferred = Some(axis);
+                    sizes.push(1);
+                }
+                size => sizes.push(
+                    usize::try_from(size)
+                        .map_err(|_| format!("Size {} in a reshape must not be negative", size))?,
+                ),
+            }
+        }
+        let known: usize = sizes.iter().product();
+        if let Some(axis) = inferred {
+            if known == 0 || !self.data.len().is_multiple_of(known) {
+                return Err(mismatch());
+            }
+            sizes[axis] = self.data.len() / known;
+        } else if known != self.data.len() {
+            return Err(mismatch());
+        }
+        Ok(Tensor::new(self.data.clone(), sizes))
+    }
+
+    /// The elements as a one-dimensional tensor, in row-major order.
+    pub fn flatten(&self) -> Tensor {
+        Tensor::new(self.data.clone(), vec![self.data.len()])
+    }
+
+    /// The tensor with its axes reversed: rows and columns swap places
+    /// for a matrix, and tensors of fewer than two dimensions are
+    /// unchanged.
+    pub fn transpose(&self) -> Tensor {
+        let axes: Vec<usize> = (0..self.shape.len()).rev().collect();
+        self.reorder(&axes)
+    }
+
+    /// The tensor with its axes reordered: axis `i` of the result is axis
+    /// `axes[i]` of this one, so `permute(&[1, 0])` transposes a matrix.
+    /// `axes` must name each axis exactly once.
+    pub fn permute(&self, axes: &[usize]) -> Result<Tensor, String> {
+        let rank = self.shape.len();
+        if axes.len() != rank {
+            return Err(format!(
+                "Permute expects {} axes for a {}-dimensional tensor, found {}",
+                rank,
+                rank,
+                axes.len()
+            ));
+        }
+        let mut seen = vec![false; rank];
+        for &axis in axes {
+            if axis >= rank {
+                return Err(format!(
+                    "Axis {} is out of range for a {}-dimensional tensor",
+                    axis, rank
+                ));
+            }
+            if std::mem::replace(&mut seen[axis], true) {
+                return Err(format!("Axis {} appears more than once", axis));
+            }
+        }
+        Ok(self.reorder(axes))
+    }
+
+    // `permute`, for axes known to name each axis once
+    fn reorder(&self, axes: &[usize]) -> Tensor {
+        let strides = self.strides();
+        TensorView {
+            data: &self.data,
+            offset: 0,
+            shape: axes.iter().map(|&axis| self.shape[axis]).collect(),
+            strides: axes.iter().map(|&axis| strides[axis]).collect(),
+        }
+        .to_tensor()
+    }
+
+    /// The whole tensor, as a view to slice further.
+    pub fn view(&self) -> TensorView<'_> {
+        TensorView {
+            data: &self.data,
+            offset: 0,
+            shape: self.shape.clone(),
+            strides: self.strides(),
+        }
+    }
+
+    /// The part of the tensor that `ranges` pick out along its leading
+    /// axes, so `t.slice(&[0..2, 1..3])` is what `t[0..2, 1..3]` is in
+    /// NumPy. Axes without a range are taken whole.
+    pub fn slice(&self, ranges: &[Range<usize>]) -> Result<TensorView<'_>, String> {
+        self.view().slice(ranges)
+    }
+
+    /// Row `index` of a matrix.
+    pub fn row(&self, index: usize) -> Result<TensorView<'_>, String> {
+        self.view().select(0, index, "row")
+    }
+
+    /// Column `index` of a matrix.
+    pub fn column(&self, index: usize) -> Result<TensorView<'_>, String> {
+        self.view().select(1, index, "column")
+    }
+
+    // How far apart in `data` consecutive indices along each axis are
+    fn strides(&self) -> Vec<usize> {
+        let mut strides = vec![1; self.shape.len()];
+        for axis in (0..self.shape.len().saturating_sub(1)).rev() {
+            strides[axis] = strides[axis + 1] * self.shape[axis + 1];
+        }
+        strides
+    }
+
+    pub fn apply<F>(&self, f: F) -> Tensor
+    where
+        F: Fn(f64) -> f64,
+    {
+        let new_data = self.data.iter().map(|&x| f(x)).collect();
+        Tensor::new(new_data, self.shape.clone())
+    }
+
+    pub fn exp(&self) -> Tensor {
+        self.apply(|x| x.exp())
+    }
+
+    pub fn log(&self) -> Tensor {
+        self.apply(|x| x.ln())
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.data.iter().sum::<f64>() / self.data.len() as f64
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        self.data.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / self.data.len() as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The sums along `axis`, which the result drops, or keeps with size 1
+    /// if `keep_dims` is set. So are the other `_axis` reductions.
+    pub fn sum_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.reduce_axis(axis, keep_dims, |lane| lane.iter().sum())
+    }
+
+    pub fn mean_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.reduce_axis(axis, keep_dims, |lane| {
+            lane.iter().sum::<f64>() / lane.len() as f64
+        })
+    }
+
+    /// The largest elements along `axis`, or NaN where a lane holds one,
+    /// as for `max`.
+    pub fn max_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.check_nonempty_axis("max", axis)?;
+        self.reduce_axis(axis, keep_dims, |lane| {
+            lane[position_of_best(lane, |a, b| a > b).unwrap_or_default()]
+        })
+    }
+
+    pub fn min_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.check_nonempty_axis("min", axis)?;
+        self.reduce_axis(axis, keep_dims, |lane| {
+            lane[position_of_best(lane, |a, b| a < b).unwrap_or_default()]
+        })
+    }
+
+    /// The largest element, or NaN if any element is NaN, as in NumPy;
+    /// `None` for an empty tensor.
+    pub fn max(&self) -> Option<f64> {
+        Some(self.data[self.argmax()?])
+    }
+
+    /// The smallest element, or NaN if any is; `None` for an empty tensor.
+    pub fn min(&self) -> Option<f64> {
+        Some(self.data[self.argmin()?])
+    }
+
+    /// Where in row-major order the largest element is, the first of them
+    /// on a tie and the first NaN if there is one; `unravel_index` turns
+    /// it into an index per axis. `None` for an empty tensor.
+    pub fn argmax(&self) -> Option<usize> {
+        position_of_best(&self.data, |a, b| a > b)
+    }
+
+    /// Where in row-major order the smallest element is; see `argmax`.
+    pub fn argmin(&self) -> Option<usize> {
+        position_of_best(&self.data, |a, b| a < b)
+    }
+
+    /// The positions of the largest elements along `axis`, as a tensor of
+    /// indices into that axis; `keep_dims` is as for `sum_axis`.
+    pub fn argmax_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.check_nonempty_axis("argmax", axis)?;
+        self.reduce_axis(axis, keep_dims, |lane| {
+            position_of_best(lane, |a, b| a > b).unwrap_or_default() as f64
+        })
+    }
+
+    pub fn argmin_axis(&self, axis: usize, keep_dims: bool) -> Result<Tensor, String> {
+        self.check_nonempty_axis("argmin", axis)?;
+        self.reduce_axis(axis, keep_dims, |lane| {
+            position_of_best(lane, |a, b| a < b).unwrap_or_default() as f64
+        })
+    }
+
+    /// The index per axis of the element at `flat` in row-major order,
+    /// as `get` takes it; `None` past the last element.
+    pub fn unravel_index(&self, mut flat: usize) -> Option<Vec<usize>> {
+        if flat >= self.data.len() {
+            return None;
+        }
+        let mut indices = vec![0; self.shape.len()];
+        for axis in (0..self.shape.len()).rev() {
+            indices[axis] = flat % self.shape[axis];
+            flat /= self.shape[axis];
+        }
+        Some(indices)
+    }
+
+    // Reduces each lane of elements along `axis` to one with `reduce`
+    fn reduce_axis<F>(&self, axis: usize, keep_dims: bool, reduce: F) -> Result<Tensor, String>
+    where
+        F: Fn(&[f64]) -> f64,
+    {
+        if axis >= self.shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for a {}-dimensional tensor",
+                axis,
+                self.shape.len()
+            ));
+        }
+        // The elements split into `outer` blocks, each holding `size`
+        // lanes' worth of `inner` elements, interleaved
+        let outer: usize = self.shape[..axis].iter().product();
+        let size = self.shape[axis];
+        let inner: usize = self.shape[axis + 1..].iter().product();
+        let mut data = Vec::with_capacity(outer * inner);
+        let mut lane = Vec::with_capacity(size);
+        for block in 0..outer {
+            for start in 0..inner {
+                lane.clear();
+                lane.extend(
+                    (0..size).map(|index| self.data[(block * size + index) * inner + start]),
+                );
+                data.push(reduce(&lane));
+            }
+        }
+        let mut shape = self.shape.clone();
+        if keep_dims {
+            shape[axis] = 1;
+        } else {
+            shape.remove(axis);
+        }
+        Ok(Tensor::new(data, shape))
+    }
+
+    // The largest or smallest of no elements is undefined
+    fn check_nonempty_axis(&self, reduction: &str, axis: usize) -> Result<(), String> {
+        match self.shape.get(axis) {
+            Some(0) => Err(format!(
+                "Cannot take the {} along axis {}, which is empty",
+                reduction, axis
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// The position of the first of `values` that no later one is `better`
+// than. A NaN beats everything, so the first NaN wins wherever it is, as
+// NumPy's `argmax` and `max` have it
+fn position_of_best(values: &[f64], better: impl Fn(f64, f64) -> bool) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for (index, &value) in values.iter().enumerate() {
+        if value.is_nan() {
+            return Some(index);
+        }
+        if best.is_none_or(|best| better(value, values[best])) {
+            best = Some(index);
+        }
+    }
+    best
+}
+
+/// Part of a tensor, as `Tensor::slice`, `row` and `column` take them.
+/// Reading through a view finds the tensor's own elements, so taking one
+/// copies nothing; `to_tensor` copies them out.
+#[derive(Clone, Debug)]
+pub struct TensorView<'a> {
+    data: &'a [f64],
+    // Where in `data` the view's first element is
+    offset: usize,
+    shape: Vec<usize>,
+    // How far apart in `data` consecutive indices along each axis are
+    strides: Vec<usize>,
+}
+
+impl<'a> TensorView<'a> {
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn get(&self, indices: &[usize]) -> Option<f64> {
+        if indices.len() != self.shape.len() {
+            return None;
+        }
+        let mut offset = self.offset;
+        for ((&index, &size), &stride) in indices.iter().zip(&self.shape).zip(&self.strides) {
+            if index >= size {
+                return None;
+            }
+            offset += index * stride;
+        }
+        self.data.get(offset).copied()
+    }
+
+    /// The part of the view that `ranges` pick out; see `Tensor::slice`.
+    pub fn slice(&self, ranges: &[Range<usize>]) -> Result<TensorView<'a>, String> {
+        if ranges.len() > self.shape.len() {
+            return Err(format!(
+                "Cannot slice {} axes of a {}-dimensional tensor",
+                ranges.len(),
+                self.shape.len()
+            ));
+        }
+        let mut view = self.clone();
+        for (axis, range) in ranges.iter().enumerate() {
+            if range.start > range.end || range.end > self.shape[axis] {
+                return Err(format!(
+                    "Range {:?} is out of bounds for axis {} of size {}",
+                    range, axis, self.shape[axis]
+                ));
+            }
+            view.offset += range.start * self.strides[axis];
+            view.shape[axis] = range.len();
+        }
+        Ok(view)
+    }
+
+    // The view at `index` along `axis` of a matrix, which drops that axis.
+    // `what` is what errors call the result
+    fn select(&self, axis: usize, index: usize, what: &str) -> Result<TensorView<'a>, String> {
+        if self.shape.len() != 2 {
+            return Err(format!(
+                "Cannot take a {} of a {}-dimensional tensor",
+                what,
+                self.shape.len()
+            ));
+        }
+        if index >= self.shape[axis] {
+            return Err(format!(
+                "Cannot take {} {} of a tensor with {} {}s",
+                what, index, self.shape[axis], what
+            ));
+        }
+        let mut view = self.clone();
+        view.offset += index * self.strides[axis];
+        view.shape.remove(axis);
+        view.strides.remove(axis);
+        Ok(view)
+    }
+
+    /// The elements of the view, copied into a tensor of its own.
+    pub fn to_tensor(&self) -> Tensor {
+        let size = self.shape.iter().product();
+        let mut data = Vec::with_capacity(size);
+        for mut flat in 0..size {
+            let mut offset = self.offset;
+            for axis in (0..self.shape.len()).rev() {
+                offset += flat % self.shape[axis] * self.strides[axis];
+                flat /= self.shape[axis];
+            }
+            data.push(self.data[offset]);
+        }
+        Tensor::new(data, self.shape.clone())
+    }
+}
+
+// The operators broadcast, and panic on shapes that can't be broadcast
+// together
+impl Add for &Tensor {
+    type Output = Tensor;
+
+    fn add(self, other: &Tensor) -> Tensor {
+        self.zip_with(other, |a, b| a + b)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Sub for &Tensor {
+    type Output = Tensor;
+
+    fn sub(self, other: &Tensor) -> Tensor {
+        self.zip_with(other, |a, b| a - b)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl Mul for &Tensor {
+    type Output = Tensor;
+
+    fn mul(self, other: &Tensor) -> Tensor {
+        self.zip_with(other, |a, b| a * b)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+}
+
+impl fmt::Display for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tensor(shape={:?}, data={:?})", self.shape, self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_creation() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        assert_eq!(t.shape, vec![2, 2]);
+        assert_eq!(t.data, vec![1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn test_tensor_zeros() {
+        let t = Tensor::zeros(vec![2, 3]);
+        assert_eq!(t.shape, vec![2, 3]);
+        assert_eq!(t.data, vec![0.0; 6]);
+    }
+
+    #[test]
+    fn test_tensor_get() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        assert_eq!(t.get(&[0, 0]), Some(1.0));
+        assert_eq!(t.get(&[1, 1]), Some(4.0));
+        assert_eq!(t.get(&[2, 0]), None);
+    }
+
+    #[test]
+    fn test_tensor_set() {
+        let mut t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        assert!(t.set(&[0, 1], 5.0).is_ok());
+        assert_eq!(t.get(&[0, 1]), Some(5.0));
+        assert!(t.set(&[2, 0], 6.0).is_err());
+    }
+
+    #[test]
+    fn test_tensor_add() {
+        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
+        let result = &t1 + &t2;
+        assert_eq!(result.data, vec![6.0, 8.0, 10.0, 12.0]);
+    }
+
+    #[test]
+    fn test_tensor_subtraction() {
+        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
+        let result = &t1 - &t2;
+        assert_eq!(result.data, vec![-4.0, -4.0, -4.0, -4.0]);
+    }
+
+    #[test]
+    fn test_tensor_element_wise_multiplication() {
+        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
+        let result = &t1 * &t2;
+        assert_eq!(result.data, vec![5.0, 12.0, 21.0, 32.0]);
+    }
+
+    #[test]
+    fn test_tensor_broadcasting() {
+        assert_eq!(broadcast_shape(&[2, 3], &[3]), Some(vec![2, 3]));
+        assert_eq!(broadcast_shape(&[2, 1], &[1, 4]), Some(vec![2, 4]));
+        assert_eq!(broadcast_shape(&[], &[2]), Some(vec![2]));
+        assert_eq!(broadcast_shape(&[2, 3], &[2]), None);
+
+        let rows = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let row = Tensor::new(vec![10.0, 20.0, 30.0], vec![3]);
+        let sum = &rows + &row;
+        assert_eq!(sum.shape, vec![2, 3]);
+        assert_eq!(sum.data, vec![11.0, 22.0, 33.0, 14.0, 25.0, 36.0]);
+
+        let column = Tensor::new(vec![1.0, 2.0], vec![2, 1]);
+        let line = Tensor::new(vec![1.0, 10.0, 100.0, 1000.0], vec![1, 4]);
+        let product = &column * &line;
+        assert_eq!(product.shape, vec![2, 4]);
+        assert_eq!(
+            product.data,
+            vec![1.0, 10.0, 100.0, 1000.0, 2.0, 20.0, 200.0, 2000.0]
+        );
+        assert_eq!(
+            (&line - &column).data,
+            vec![0.0, 9.0, 99.0, 999.0, -1.0, 8.0, 98.0, 998.0]
+        );
+
+        assert_eq!(
+            rows.zip_with(&column.transpose(), |a, b| a + b),
+            Err("Shapes [2, 3] and [1, 2] cannot be broadcast together".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tensor_matrix_multiplication() {
+        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
+        let result = t1.matmul(&t2).unwrap();
+        assert_eq!(result.data, vec![19.0, 22.0, 43.0, 50.0]);
+        assert_eq!(result.shape, vec![2, 2]);
+    }
+
+    #[test]
+    fn test_tensor_transpose() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let transposed = t.transpose();
+        assert_eq!(transposed.shape, vec![3, 2]);
+        assert_eq!(transposed.data, vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn test_tensor_permute() {
+        let t = Tensor::new((0..24).map(f64::from).collect(), vec![2, 3, 4]);
+        let permuted = t.permute(&[2, 0, 1]).unwrap();
+        assert_eq!(permuted.shape, vec![4, 2, 3]);
+        for (i, j, k) in [(0, 0, 0), (1, 2, 3), (0, 1, 2), (1, 0, 3)] {
+            assert_eq!(permuted.get(&[k, i, j]), t.get(&[i, j, k]));
+        }
+        assert_eq!(t.permute(&[0, 1, 2]).unwrap(), t);
+
+        let transposed = t.transpose();
+        assert_eq!(transposed.shape, vec![4, 3, 2]);
+        assert_eq!(transposed.get(&[3, 1, 0]), t.get(&[0, 1, 3]));
+        let row = Tensor::new(vec![1.0, 2.0], vec![2]);
+        assert_eq!(row.transpose(), row);
+
+        assert_eq!(
+            t.permute(&[0, 1]),
+            Err("Permute expects 3 axes for a 3-dimensional tensor, found 2".to_string())
+        );
+        assert_eq!(
+            t.permute(&[0, 3, 1]),
+            Err("Axis 3 is out of range for a 3-dimensional tensor".to_string())
+        );
+        assert_eq!(
+            t.permute(&[1, 1, 0]),
+            Err("Axis 1 appears more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tensor_reshape_and_flatten() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let reshaped = t.reshape(&[3, 2]).unwrap();
+        assert_eq!(reshaped.shape, vec![3, 2]);
+        assert_eq!(reshaped.data, t.data);
+        assert_eq!(reshaped.get(&[1, 0]), Some(3.0));
+        assert_eq!(t.reshape(&[-1, 3, 1]).unwrap().shape, vec![2, 3, 1]);
+        assert_eq!(t.reshape(&[-1]).unwrap(), t.flatten());
+        assert_eq!(t.flatten().shape, vec![6]);
+
+        assert_eq!(
+            t.reshape(&[4, 2]),
+            Err("Cannot reshape a tensor of 6 elements into shape [4, 2]".to_string())
+        );
+        assert_eq!(
+            t.reshape(&[4, -1]),
+            Err("Cannot reshape a tensor of 6 elements into shape [4, -1]".to_string())
+        );
+        assert_eq!(
+            t.reshape(&[-1, -1]),
+            Err("Only one size in a reshape can be -1".to_string())
+        );
+        assert_eq!(
+            t.reshape(&[-2, 3]),
+            Err("Size -2 in a reshape must not be negative".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tensor_slicing() {
+        let t = Tensor::new((0..12).map(f64::from).collect(), vec![3, 4]);
+        let block = t.slice(&[0..2, 1..3]).unwrap();
+        assert_eq!(block.shape(), [2, 2]);
+        assert_eq!(block.get(&[1, 0]), Some(5.0));
+        assert_eq!(block.get(&[2, 0]), None);
+        assert_eq!(
+            block.to_tensor(),
+            Tensor::new(vec![1.0, 2.0, 5.0, 6.0], vec![2, 2])
+        );
+        // Axes without a range are taken whole, and views slice further
+        let cube = Tensor::zeros(vec![2, 3, 4]);
+        assert_eq!(cube.slice(&[0..1, 1..3]).unwrap().shape(), [1, 2, 4]);
+        let rows = t.slice(&[1..3, 0..4]).unwrap();
+        assert_eq!(rows.shape(), [2, 4]);
+        assert_eq!(
+            rows.slice(&[1..2, 2..4]).unwrap().to_tensor().data,
+            vec![10.0, 11.0]
+        );
+        assert_eq!(
+            t.slice(&[1..1, 0..4]).unwrap().to_tensor().data,
+            Vec::<f64>::new()
+        );
+
+        assert_eq!(t.row(1).unwrap().to_tensor().data, vec![4.0, 5.0, 6.0, 7.0]);
+        let column = t.column(2).unwrap();
+        assert_eq!(column.shape(), [3]);
+        assert_eq!(column.to_tensor().data, vec![2.0, 6.0, 10.0]);
+        assert_eq!(
+            rows.slice(&[0..2, 1..2]).unwrap().to_tensor().data,
+            vec![5.0, 9.0]
+        );
+
+        assert_eq!(
+            t.slice(&[0..2, 3..5]).unwrap_err(),
+            "Range 3..5 is out of bounds for axis 1 of size 4"
+        );
+        assert_eq!(
+            t.slice(&[0..1, 0..1, 0..1]).unwrap_err(),
+            "Cannot slice 3 axes of a 2-dimensional tensor"
+        );
+        assert_eq!(
+            t.column(4).unwrap_err(),
+            "Cannot take column 4 of a tensor with 4 columns"
+        );
+        assert_eq!(
+            Tensor::zeros(vec![2]).row(0).unwrap_err(),
+            "Cannot take a row of a 1-dimensional tensor"
+        );
+    }
+
+    #[test]
+    fn test_tensor_exp() {
+        let t = Tensor::new(vec![0.0, 1.0, 2.0], vec![3]);
+        let exp_t = t.exp();
+        assert!((exp_t.data[0] - 1.0).abs() < 1e-6);
+        assert!((exp_t.data[1] - std::f64::consts::E).abs() < 1e-6);
+        assert!((exp_t.data[2] - std::f64::consts::E.powi(2)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tensor_log() {
+        let t = Tensor::new(
+            vec![1.0, std::f64::consts::E, std::f64::consts::E.powi(2)],
+            vec![3],
+        );
+        let log_t = t.log();
+        assert!((log_t.data[0]).abs() < 1e-6);
+        assert!((log_t.data[1] - 1.0).abs() < 1e-6);
+        assert!((log_t.data[2] - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tensor_mean() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        assert_eq!(t.mean(), 2.5);
+    }
+
+    #[test]
+    fn test_tensor_axis_reductions() {
+        let t = Tensor::new(vec![1.0, 5.0, 3.0, 4.0, 2.0, 6.0], vec![2, 3]);
+        assert_eq!(
+            t.sum_axis(0, false).unwrap(),
+            Tensor::new(vec![5.0, 7.0, 9.0], vec![3])
+        );
+        assert_eq!(
+            t.sum_axis(1, true).unwrap(),
+            Tensor::new(vec![9.0, 12.0], vec![2, 1])
+        );
+        assert_eq!(t.mean_axis(1, false).unwrap().data, vec![3.0, 4.0]);
+        assert_eq!(t.max_axis(0, false).unwrap().data, vec![4.0, 5.0, 6.0]);
+        assert_eq!(t.min_axis(1, false).unwrap().data, vec![1.0, 2.0]);
+
+        // A middle axis, and reducing a vector to a scalar tensor
+        let cube = Tensor::new((0..24).map(f64::from).collect(), vec![2, 3, 4]);
+        let sums = cube.sum_axis(1, false).unwrap();
+        assert_eq!(sums.shape, vec![2, 4]);
+        assert_eq!(sums.get(&[1, 2]), Some(14.0 + 18.0 + 22.0));
+        let row = Tensor::new(vec![1.0, 2.0, 3.0], vec![3]);
+        assert_eq!(
+            row.sum_axis(0, false).unwrap(),
+            Tensor::new(vec![6.0], vec![])
+        );
+
+        assert_eq!(
+            t.sum_axis(2, false),
+            Err("Axis 2 is out of range for a 2-dimensional tensor".to_string())
+        );
+        assert_eq!(
+            Tensor::zeros(vec![2, 0]).max_axis(1, false),
+            Err("Cannot take the max along axis 1, which is empty".to_string())
+        );
+        assert_eq!(
+            Tensor::zeros(vec![2, 0]).sum_axis(1, false).unwrap().data,
+            vec![0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_tensor_min_max() {
+        let t = Tensor::new(vec![3.0, 9.0, -2.0, 9.0, 0.0, -2.0], vec![2, 3]);
+        assert_eq!(t.max(), Some(9.0));
+        assert_eq!(t.min(), Some(-2.0));
+        // Ties go to the first
+        assert_eq!(t.argmax(), Some(1));
+        assert_eq!(t.argmin(), Some(2));
+        assert_eq!(t.unravel_index(4), Some(vec![1, 1]));
+        assert_eq!(t.unravel_index(6), None);
+        let flat = t.argmin().unwrap();
+        assert_eq!(t.get(&t.unravel_index(flat).unwrap()), t.min());
+
+        assert_eq!(
+            t.argmax_axis(0, false).unwrap(),
+            Tensor::new(vec![1.0, 0.0, 0.0], vec![3])
+        );
+        assert_eq!(
+            t.argmin_axis(1, true).unwrap(),
+            Tensor::new(vec![2.0, 2.0], vec![2, 1])
+        );
+
+        let empty = Tensor::zeros(vec![0, 2]);
+        assert_eq!(empty.max(), None);
+        assert_eq!(empty.argmin(), None);
+        assert_eq!(
+            empty.argmax_axis(0, false),
+            Err("Cannot take the argmax along axis 0, which is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tensor_min_max_nan() {
+        // The first NaN wins, wherever it is, overall and along an axis
+        let t = Tensor::new(vec![1.0, f64::NAN, 3.0, 4.0, 0.0, f64::NAN], vec![2, 3]);
+        assert!(t.max().unwrap().is_nan());
+        assert!(t.min().unwrap().is_nan());
+        assert_eq!(t.argmax(), Some(1));
+        assert_eq!(t.argmin(), Some(1));
+
+        let max = t.max_axis(0, false).unwrap();
+        assert_eq!(max.data[0], 4.0);
+        assert!(max.data[1].is_nan() && max.data[2].is_nan());
+        let min = t.min_axis(1, false).unwrap();
+        assert!(min.data.iter().all(|x| x.is_nan()));
+        assert_eq!(
+            t.argmax_axis(0, false).unwrap(),
+            Tensor::new(vec![1.0, 0.0, 1.0], vec![3])
+        );
+        assert_eq!(
+            t.argmin_axis(1, false).unwrap(),
+            Tensor::new(vec![1.0, 2.0], vec![2])
+        );
+    }
+
+    #[test]
+    fn test_tensor_variance_and_std_dev() {
+        let t = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        assert!((t.variance() - 1.25).abs() < 1e-6);
+        assert!((t.std_dev() - 1.118033988749895).abs() < 1e-6);
+    }
+}
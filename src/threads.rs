@@ -0,0 +1,93 @@
+//! The values behind script-level concurrency. `spawn { ... }` runs a
+//! block on a new OS thread and evaluates to a `ThreadHandle`, and threads
+//! pass values to each other through `Channel`s. Threads share the
+//! variables in scope where they were spawned, so a script can split a
+//! tensor workload across cores and collect the results with `join`.
+
+use crate::interpreter::{RuntimeError, RuntimeResult, Value};
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// A spawned thread, until `join` collects its result.
+pub struct ThreadHandle {
+    // Taken by the first `join`
+    thread: Mutex<Option<JoinHandle<RuntimeResult<Value>>>>,
+}
+
+impl ThreadHandle {
+    pub fn new(thread: JoinHandle<RuntimeResult<Value>>) -> Self {
+        ThreadHandle {
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    /// Waits for the thread to finish and returns the value of its block,
+    /// or the runtime error that stopped it.
+    pub fn join(&self) -> RuntimeResult<Value> {
+        let thread = self
+            .thread
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .ok_or_else(|| RuntimeError::new("Thread already joined"))?;
+        thread
+            .join()
+            .map_err(|_| RuntimeError::new("Spawned thread panicked"))?
+    }
+}
+
+/// An unbounded queue of values, in the order they were sent.
+#[derive(Default)]
+pub struct Channel {
+    values: Mutex<VecDeque<Value>>,
+    // Signalled on every send
+    sent: Condvar,
+}
+
+impl Channel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&self, value: Value) {
+        self.values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(value);
+        self.sent.notify_one();
+    }
+
+    /// The oldest value, waiting for one to be sent if need be, but not
+    /// past `deadline`. `None` if the deadline passed first.
+    pub fn recv(&self, deadline: Option<Instant>) -> Option<Value> {
+        let mut values = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(value) = values.pop_front() {
+                return Some(value);
+            }
+            values = match deadline {
+                Some(deadline) => {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    self.sent
+                        .wait_timeout(values, left)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .sent
+                    .wait(values)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// The oldest value, if one is waiting.
+    pub fn try_recv(&self) -> Option<Value> {
+        self.values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop_front()
+    }
+}
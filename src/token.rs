@@ -1,69 +1,135 @@
-#[allow(dead_code)]
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum TokenType {
-    // Single-character tokens
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-    Comma,
-    Dot,
-    Minus,
-    Plus,
-    Semicolon,
-    Slash,
-    Star,
-
-    // One or two character tokens
-    Bang,
-    BangEqual,
-    Equal,
-    EqualEqual,
-    Greater,
-    GreaterEqual,
-    Less,
-    LessEqual,
-    Assign,
-
-    // Literals
-    Identifier,
-    String,
-    Number,
-
-    // Keywords
-    And,
-    Class,
-    Else,
-    False,
-    Fun,
-    For,
-    If,
-    Nil,
-    Or,
-    Print,
-    Return,
-    Super,
-    This,
-    True,
-    Var,
-    While,
-
-    EOF,
-}
-
-#[derive(Debug, Clone)]
-pub struct Token {
-    pub token_type: TokenType,
-    pub lexeme: String,
-    pub line: usize,
-}
-
-impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: usize) -> Self {
-        Token {
-            token_type,
-            lexeme,
-            line,
-        }
-    }
-}
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenType {
+    // Single-character tokens
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Dot,
+    Minus,
+    Plus,
+    Semicolon,
+    Slash,
+    Star,
+    Ampersand,
+    Pipe,
+    Caret,
+    At,
+    Colon,
+    Question,
+
+    // One or two character tokens
+    Bang,
+    BangEqual,
+    Equal,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Assign,
+    ShiftLeft,
+    ShiftRight,
+    Arrow,
+    ColonColon,
+    FatArrow,
+    DotDot,
+    DotDotEqual,
+    QuestionDot,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+
+    // Literals
+    Identifier,
+    String,
+    Number,
+    Char,
+
+    // Keywords
+    And,
+    As,
+    Breakpoint,
+    Catch,
+    Class,
+    Const,
+    Do,
+    Else,
+    Enum,
+    False,
+    Fun,
+    For,
+    If,
+    Import,
+    In,
+    Match,
+    Nil,
+    Or,
+    Print,
+    Return,
+    Spawn,
+    Struct,
+    Super,
+    This,
+    Throw,
+    True,
+    Try,
+    Use,
+    Var,
+    While,
+
+    // `// ...` or `/* ... */`; the parser keeps these as comment statements
+    Comment,
+
+    // Lexical error: the lexeme holds the offending source text
+    Error,
+
+    EOF,
+}
+
+/// A source location: where a token starts and how many characters it
+/// covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Token {
+    pub fn new(token_type: TokenType, lexeme: String, line: usize, column: usize) -> Self {
+        Token {
+            token_type,
+            lexeme,
+            line,
+            column,
+        }
+    }
+
+    /// The line holding the token's last character, which is later than
+    /// `line` only for multi-line strings and block comments.
+    pub fn end_line(&self) -> usize {
+        self.line + self.lexeme.matches('\n').count()
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            length: self.lexeme.chars().count(),
+        }
+    }
+}
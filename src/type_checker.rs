@@ -1,38 +1,1650 @@
-use crate::ast::*;
-
-pub struct TypeChecker;
-
-impl TypeChecker {
-    pub fn check_program(program: &ProgramNode) -> Result<(), String> {
-        for statement in &program.statements {
-            Self::check_statement(statement.as_ref())?;
-        }
-        Ok(())
-    }
-
-    fn check_statement(statement: &dyn StatementNode) -> Result<(), String> {
-        match statement.as_any().downcast_ref::<VariableNode>() {
-            Some(var) => {
-                // Check variable data types or more logic can be added
-                Ok(())
-            }
-            None => match statement.as_any().downcast_ref::<AssignmentNode>() {
-                Some(assign) => {
-                    // Ensure the types of the left and right side of assignment match
-                    Self::check_statement(assign.left.as_ref())?;
-                    Self::check_statement(assign.right.as_ref())?;
-                    Ok(())
-                }
-                None => match statement.as_any().downcast_ref::<BinaryOperationNode>() {
-                    Some(bin_op) => {
-                        // Check types of operands and ensure they are compatible with the operation
-                        Self::check_statement(bin_op.left.as_ref())?;
-                        Self::check_statement(bin_op.right.as_ref())?;
-                        Ok(())
-                    }
-                    None => Err("Unknown statement type encountered".to_string()),
-                },
-            },
-        }
-    }
-}
+//! Static checks on a program: that every name it uses is declared, and
+//! that values have the types their uses call for. Expressions computed
+//! from literals and constants are worked out as well, so that arithmetic
+//! and casts bound to fail at run time, like `n / 0` or `"x" as int`, are
+//! reported before the program runs.
+//!
+//! Values convert implicitly only from int to float, so an int can be used
+//! wherever a float is expected, and a value of any type where an optional
+//! of that type is. Every other conversion needs an `as` cast:
+//!
+//! - to `int`: floats, dropping the fraction, bools as 0 or 1, and chars
+//!   as their code point
+//! - to `float`: ints
+//! - to `char`: ints that are a code point
+//! - to `string`: ints, floats, bools and chars, written as they print
+//! - from `string` to `int` or `float`, when the text is a number
+//!
+//! Casts the checker cannot rule out, like a string that is not a number,
+//! fail at run time.
+//!
+//! A generic function, like `fn first<T>(items: [T]) -> T`, is checked
+//! once, with its type parameters erased: in the body `T` is a type of its
+//! own that only values of type `T` match, and each call binds `T` to the
+//! type of the argument passed for it, which every other argument for `T`
+//! must then match. The call has the return type under those bindings.
+//!
+//! The conditions of `if`, `while`, `for` and `?:` must be bools, and a
+//! variable declared without a value, like `var x;`, must be assigned on
+//! every path to where it is read. Paths are followed through branches and
+//! loops, taking that a loop body may not run at all, and that a `try`
+//! body may stop at any point; a `return` or `throw` ends its path. A
+//! function body may read any variable in scope, as it only runs once
+//! called. A variable of an optional type, like `var x: int?;`, starts out
+//! null, so counts as assigned.
+//!
+//! Tensor shapes are followed where constants fix them: tensor literals,
+//! `zeros` with constant dimensions, and the results of operators on those.
+//! Dimensions can't be negative, element-wise operators need shapes that
+//! broadcast together and `@` matrices whose inner dimensions agree.
+//!
+//! A range, like `0..10 step 2`, has no value of its own: it may only be
+//! what a `for` loop counts over, with int bounds and a positive step.
+
+use crate::ast::*;
+use crate::builtins::{standard_basket, standard_builtins};
+use crate::constants::{self, fold_binary, fold_unary, Constant};
+use crate::formatter::{binary_symbol, format_expr};
+use crate::symbol_table::{Signature, Symbol, SymbolTable};
+use crate::tensor::broadcast_shape;
+use crate::token::Span;
+use crate::visitor::{walk_expr_mut, walk_stmt_mut, MutVisitor};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// What kind of problem a `TypeError` reports. Each has a code, like
+/// `E004`, that stays the same however the message is worded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UndeclaredVariable,
+    DuplicateDeclaration,
+    /// Assigning to a constant, or declaring its name again
+    ConstantRebound,
+    /// A value of another type than the declaration, parameter or return
+    /// type it goes to
+    TypeMismatch,
+    /// An operator applied to values of types it doesn't take
+    InvalidOperands,
+    ArgumentCount,
+    MissingReturn,
+    InvalidCast,
+    /// Arithmetic on constants that would fail at run time, like a
+    /// division by zero
+    ConstantEvaluation,
+    /// A read of a variable that may not have been assigned yet
+    UnassignedVariable,
+    /// A range anywhere but the header of a `for` loop, the only place
+    /// one has a value
+    MisplacedRange,
+    /// Access to a field the struct doesn't declare
+    UnknownField,
+    /// A number literal too large for its type
+    NumberOutOfRange,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::UndeclaredVariable => "E001",
+            ErrorCode::DuplicateDeclaration => "E002",
+            ErrorCode::ConstantRebound => "E003",
+            ErrorCode::TypeMismatch => "E004",
+            ErrorCode::InvalidOperands => "E005",
+            ErrorCode::ArgumentCount => "E006",
+            ErrorCode::MissingReturn => "E007",
+            ErrorCode::InvalidCast => "E008",
+            ErrorCode::ConstantEvaluation => "E009",
+            ErrorCode::UnassignedVariable => "E010",
+            ErrorCode::MisplacedRange => "E011",
+            ErrorCode::UnknownField => "E012",
+            ErrorCode::NumberOutOfRange => "E013",
+        }
+    }
+}
+
+/// A problem the checker found, and where: the offending identifier or
+/// operator, the name a declaration binds, or the value that has the wrong
+/// type. A few bindings, like a `for` loop's variable, have no span, so
+/// errors about those have none either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Type error [{}]: {}", self.code.code(), self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at line {}, column {}", span.line, span.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// Something the checker found that is allowed but likely a mistake, like
+/// a variable that is never read, and where: the declaration, or the
+/// value that goes unused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Warning: {}", self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at line {}, column {}", span.line, span.column)?;
+        }
+        Ok(())
+    }
+}
+
+/// `errors` one per line, for reporting them as a single message.
+pub fn report(errors: &[TypeError]) -> String {
+    errors
+        .iter()
+        .map(TypeError::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub struct TypeChecker {
+    errors: Vec<TypeError>,
+    warnings: Vec<Warning>,
+    symbols: SymbolTable,
+    // The interpreter's own functions, which are in scope everywhere, with
+    // the number of arguments each takes if fixed
+    builtins: HashMap<String, Option<usize>>,
+    // The functions and lambdas the checker is inside, innermost last: how
+    // errors name each, and its declared return type. Their bodies run only
+    // when called, so they may use names declared after them
+    functions: Vec<(String, Option<DataType>)>,
+    warn_on_shadowing: bool,
+    // The lints that `#allow` comments switch off for the statement being
+    // checked
+    allowed: Vec<String>,
+    // Where the program's last statement is, if it is an expression: its
+    // value is what `Engine::eval` and the REPL give back, so not unused
+    program_value: Option<Span>,
+    // The variables declared without a value that some path to the point
+    // being checked leaves unassigned, with the depth of their scope
+    unassigned: Unassigned,
+    // The variants of each enum the program declares, wherever it does
+    enums: HashMap<String, Vec<String>>,
+    // The fields of each struct the program declares, wherever it does
+    structs: HashMap<String, Vec<FieldNode>>,
+    // The names defined before the program runs, in a scope around its
+    // globals
+    predefined: Vec<(String, Symbol)>,
+}
+
+type Unassigned = HashSet<(usize, String)>;
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            symbols: SymbolTable::new(),
+            builtins: standard_builtins()
+                .into_iter()
+                .map(|builtin| (builtin.name, builtin.arity))
+                .collect(),
+            functions: Vec::new(),
+            warn_on_shadowing: false,
+            allowed: Vec::new(),
+            program_value: None,
+            unassigned: HashSet::new(),
+            enums: HashMap::new(),
+            structs: HashMap::new(),
+            predefined: Vec::new(),
+        }
+    }
+
+    /// Warns about declarations that hide a variable of an enclosing
+    /// scope. Off by default.
+    pub fn warn_on_shadowing(mut self, warn: bool) -> Self {
+        self.warn_on_shadowing = warn;
+        self
+    }
+
+    /// Takes `name` as one of the interpreter's own functions, like those
+    /// a host registers, taking `arity` arguments if that is fixed.
+    pub fn add_builtin(&mut self, name: &str, arity: Option<usize>) {
+        self.builtins.insert(name.to_string(), arity);
+    }
+
+    /// Takes `name` as defined before the program runs, as the globals an
+    /// `Engine`'s earlier scripts left are. The program may declare it
+    /// again, unless it is a constant.
+    pub fn predefine(&mut self, name: &str, symbol: Symbol) {
+        self.predefined.push((name.to_string(), symbol));
+    }
+
+    /// Checks `program`, leaving it as it was, and returns every error
+    /// found, in the order they appear.
+    pub fn check_program(program: &ProgramNode) -> Result<(), Vec<TypeError>> {
+        Self::infer_types(&mut program.clone())
+    }
+
+    /// Checks `program`, recording on every expression what it resolves
+    /// to (see `Expr::resolved`), so later passes needn't work it out
+    /// again, and on every variable reference the type of the variable it
+    /// names: the declared type, or else the type of the variable's
+    /// initializer where that is plain from the expression.
+    pub fn infer_types(program: &mut ProgramNode) -> Result<(), Vec<TypeError>> {
+        TypeChecker::new().check(program).map(|_| ())
+    }
+
+    /// Checks `program` as `infer_types` does, returning the warnings
+    /// about it if it has no errors, or else the errors. Local variables
+    /// that are never read and expression statements whose value goes
+    /// unused get a warning, unless the name starts with `_` or a comment
+    /// on the statement, or leading into it, says
+    /// `#allow(unused_variables)`, `#allow(unused_results)` or
+    /// `#allow(unused)` for both. Messages quote the program as given, so
+    /// check it before `fold_constants` rewrites it.
+    pub fn check(&mut self, program: &mut ProgramNode) -> Result<Vec<Warning>, Vec<TypeError>> {
+        self.errors.clear();
+        self.warnings.clear();
+        self.symbols = SymbolTable::new();
+        if !self.predefined.is_empty() {
+            for (name, symbol) in &self.predefined {
+                self.symbols.declare(name, symbol.clone());
+            }
+            self.symbols.enter_scope();
+        }
+        self.program_value = match program
+            .statements
+            .iter()
+            .rfind(|statement| !matches!(statement, Stmt::Comment(_)))
+        {
+            Some(Stmt::Expression(expr)) => expr.span(),
+            _ => None,
+        };
+        self.check_statements(&mut program.statements);
+        if self.errors.is_empty() {
+            Ok(std::mem::take(&mut self.warnings))
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn warn(&mut self, span: Option<Span>, message: String) {
+        self.warnings.push(Warning { message, span });
+    }
+
+    // Checking carries on after an error, so one run finds them all
+    fn error(&mut self, code: ErrorCode, span: Option<Span>, message: String) {
+        self.errors.push(TypeError {
+            code,
+            message,
+            span,
+        });
+    }
+
+    // Checks `check` in a scope of its own. Globals aren't reported when
+    // unread, as importers and hosts can still read them
+    fn scoped(&mut self, check: impl FnOnce(&mut Self)) {
+        self.symbols.enter_scope();
+        check(self);
+        let depth = self.symbols.depth();
+        self.unassigned.retain(|(declared, _)| *declared < depth);
+        let unread: Vec<(String, Option<Span>)> = self
+            .symbols
+            .unread()
+            .into_iter()
+            .map(|(name, span)| (name.to_string(), span))
+            .collect();
+        for (name, span) in unread {
+            self.warn(span, format!("Variable '{}' is never read", name));
+        }
+        self.symbols.exit_scope();
+    }
+
+    fn allows(&self, lint: &str) -> bool {
+        self.allowed
+            .iter()
+            .any(|allowed| allowed == lint || allowed == "unused")
+    }
+
+    // Each scope declares a name once. An inner scope may declare it again,
+    // hiding the outer variable, unless that is a constant
+    fn declare(&mut self, name: &str, symbol: Symbol, span: Option<Span>) {
+        if self
+            .symbols
+            .lookup(name)
+            .is_some_and(|symbol| symbol.constant)
+        {
+            self.error(
+                ErrorCode::ConstantRebound,
+                span,
+                format!("Cannot redeclare constant '{}'", name),
+            );
+        } else if self.symbols.lookup_local(name).is_some() {
+            self.error(
+                ErrorCode::DuplicateDeclaration,
+                span,
+                format!("'{}' is already declared in this scope", name),
+            );
+        } else if self.warn_on_shadowing && self.symbols.lookup(name).is_some() {
+            self.warn(
+                span,
+                format!("'{}' shadows a variable of an enclosing scope", name),
+            );
+        }
+        self.symbols.declare(name, Symbol { span, ..symbol });
+        if name.starts_with('_') || self.allows("unused_variables") {
+            self.symbols.mark_read(name);
+        }
+    }
+
+    fn declare_parameters(&mut self, parameters: &[ParameterNode]) {
+        for parameter in parameters {
+            self.declare(
+                &parameter.name,
+                Symbol::variable(parameter.data_type.clone()),
+                parameter.span.0,
+            );
+        }
+    }
+
+    // The statements of a program or block, in the current scope, which
+    // first learns every name they declare
+    fn check_statements(&mut self, statements: &mut [Stmt]) {
+        for stmt in statements.iter() {
+            let signature = match stmt {
+                Stmt::FunctionDeclaration(function) => Some(Signature::of(function)),
+                Stmt::StructDeclaration(declaration) => {
+                    self.structs
+                        .insert(declaration.name.clone(), declaration.fields.clone());
+                    Some(Signature::constructor(declaration))
+                }
+                Stmt::EnumDeclaration(declaration) => {
+                    self.enums
+                        .insert(declaration.name.clone(), declaration.variants.clone());
+                    None
+                }
+                _ => None,
+            };
+            for name in declared_names(stmt) {
+                self.symbols.declare_ahead(&name, signature.clone());
+            }
+        }
+        for statement in statements.iter_mut() {
+            let outer = self.allowed.len();
+            self.allowed.extend(allowed_lints(statement));
+            self.visit_stmt_mut(statement);
+            self.allowed.truncate(outer);
+        }
+    }
+
+    // A function or lambda body, in a scope holding its parameters. `name`
+    // is how errors refer to the function, and `span` where it starts
+    fn check_function(
+        &mut self,
+        name: String,
+        span: Option<Span>,
+        parameters: &[ParameterNode],
+        return_type: Option<&DataType>,
+        body: &mut [Stmt],
+    ) {
+        self.functions.push((name, return_type.cloned()));
+        let outer = std::mem::take(&mut self.unassigned);
+        self.scoped(|checker| {
+            checker.declare_parameters(parameters);
+            checker.check_statements(body);
+        });
+        self.unassigned = outer;
+        let (name, _) = self.functions.pop().unwrap_or_default();
+        if let Some(return_type) = return_type.filter(|data_type| **data_type != DataType::Void) {
+            if !body.iter().any(always_returns) {
+                self.error(
+                    ErrorCode::MissingReturn,
+                    span,
+                    format!(
+                        "Missing return at the end of {}, which returns {:?}",
+                        name, return_type
+                    ),
+                );
+            }
+        }
+    }
+
+    // What a `return` hands back must suit the return type of the function
+    // it is in
+    fn check_return(&mut self, value: Option<&Expr>, found: Option<DataType>) {
+        let Some((name, Some(declared))) = self.functions.last().cloned() else {
+            return;
+        };
+        let (code, message) = match (value, &declared) {
+            (None, DataType::Void) => return,
+            (None, _) => (
+                ErrorCode::MissingReturn,
+                format!(
+                    "Missing return value in {}, which returns {:?}",
+                    name, declared
+                ),
+            ),
+            (Some(_), DataType::Void) => (
+                ErrorCode::TypeMismatch,
+                format!("Cannot return a value from {}, which returns Void", name),
+            ),
+            (Some(Expr::Null(_)), declared) if !matches!(declared, DataType::Optional(_)) => (
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Cannot return null from {}, which returns {:?}",
+                    name, declared
+                ),
+            ),
+            (Some(_), declared) => match found {
+                Some(found) if !accepts(declared, &found) => (
+                    ErrorCode::TypeMismatch,
+                    format!(
+                        "Cannot return {:?} from {}, which returns {:?}",
+                        found, name, declared
+                    ),
+                ),
+                _ => return,
+            },
+        };
+        self.error(code, value.and_then(Expr::span), message);
+    }
+
+    // Records on `variable` the type of the variable it names, and returns
+    // it
+    fn check_variable(&mut self, variable: &mut VariableNode) -> Option<DataType> {
+        self.check_declared(variable);
+        variable.data_type = self
+            .symbols
+            .lookup(&variable.name)
+            .and_then(|symbol| symbol.data_type.clone());
+        variable.data_type.clone()
+    }
+
+    // References must name a variable in scope, or, from inside a function,
+    // one that an enclosing scope declares further on
+    fn check_declared(&mut self, variable: &VariableNode) {
+        let declared = if !self.functions.is_empty() {
+            self.symbols.is_declared(&variable.name)
+        } else {
+            self.symbols.lookup(&variable.name).is_some()
+        };
+        if declared || self.builtins.contains_key(&variable.name) {
+            return;
+        }
+        self.error(
+            ErrorCode::UndeclaredVariable,
+            variable.span.0,
+            format!("Undeclared variable '{}'", variable.name),
+        );
+    }
+
+    // Reads must come after an assignment on every path to them. A
+    // variable is reported once on each path
+    fn check_assigned(&mut self, variable: &VariableNode) {
+        let Some(depth) = self.symbols.depth_of(&variable.name) else {
+            return;
+        };
+        if self.unassigned.remove(&(depth, variable.name.clone())) {
+            self.error(
+                ErrorCode::UnassignedVariable,
+                variable.span.0,
+                format!(
+                    "Variable '{}' may be read before it is assigned",
+                    variable.name
+                ),
+            );
+        }
+    }
+
+    // A `for` loop's range counts over ints, by a step that must be
+    // positive
+    fn check_range(&mut self, range: &mut RangeNode) {
+        let step = range.step.as_deref_mut();
+        for bound in [Some(&mut *range.start), Some(&mut *range.end), step]
+            .into_iter()
+            .flatten()
+        {
+            let found = self.check_expr(bound);
+            if let Some(found) =
+                found.filter(|found| !matches!(found, DataType::Integer | DataType::Parameter(_)))
+            {
+                self.error(
+                    ErrorCode::TypeMismatch,
+                    bound.span(),
+                    format!("Range bounds and steps must be int, found {:?}", found),
+                );
+            }
+        }
+        let Some(step) = &range.step else {
+            return;
+        };
+        if let Some(Constant::Integer(value)) = self.constant_value(step) {
+            if value <= 0 {
+                self.error(
+                    ErrorCode::ConstantEvaluation,
+                    step.span(),
+                    format!("Range step must be positive, found {}", value),
+                );
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &mut Expr, statement: &str) {
+        let found = self.check_expr(condition);
+        if let Some(found) =
+            found.filter(|found| !matches!(found, DataType::Boolean | DataType::Parameter(_)))
+        {
+            self.error(
+                ErrorCode::TypeMismatch,
+                condition.span(),
+                format!(
+                    "Condition of '{}' must be a bool, found {:?}",
+                    statement, found
+                ),
+            );
+        }
+    }
+
+    // Checks code that may not run at all, like a loop body, so that
+    // afterwards what was unassigned before it still is
+    fn check_skippable(&mut self, check: impl FnOnce(&mut Self)) {
+        let before = self.unassigned.clone();
+        check(self);
+        self.unassigned = before;
+    }
+
+    // Joins the path being checked with `other`, another path to the same
+    // point. A path that ends in a `return` or `throw` never gets there
+    fn join(&mut self, other: Unassigned, other_returns: bool, returns: bool) {
+        if returns {
+            self.unassigned = other;
+        } else if !other_returns {
+            self.unassigned.extend(other);
+        }
+    }
+
+    // Only `T?` annotations admit a null initializer
+    fn check_null_initializer(&mut self, name: &str, data_type: Option<&DataType>, value: &Expr) {
+        if let (Expr::Null(_), Some(data_type)) = (value, data_type) {
+            if !matches!(data_type, DataType::Optional(_)) {
+                self.error(
+                    ErrorCode::TypeMismatch,
+                    value.span(),
+                    format!(
+                        "Cannot initialize '{}' of non-optional type {:?} with null",
+                        name, data_type
+                    ),
+                );
+            }
+        }
+    }
+
+    // Checks a declaration's initializer against its annotation, and
+    // returns the type the declaration gives `name`: the annotation if it
+    // has one, or else the initializer's type
+    fn check_initializer(
+        &mut self,
+        name: &str,
+        annotation: Option<&DataType>,
+        value: Option<&mut Expr>,
+    ) -> Option<DataType> {
+        let value = value?;
+        let found = self.check_expr(value);
+        self.check_null_initializer(name, annotation, value);
+        let Some(declared) = annotation else {
+            return found;
+        };
+        if let Some(found) = found.filter(|found| !accepts(declared, found)) {
+            self.error(
+                ErrorCode::TypeMismatch,
+                value.span(),
+                format!(
+                    "Cannot initialize '{}' of type {:?} with {:?}",
+                    name, declared, found
+                ),
+            );
+        }
+        Some(declared.clone())
+    }
+
+    // Assignments must respect the variable's declaration: no rebinding
+    // constants, and no values of another type than the one declared
+    fn check_assignment(&mut self, target: &Expr, value: &Expr, found: Option<DataType>) {
+        if let Expr::MemberAccess(member) = target {
+            self.check_field_assignment(member, target.resolved().data_type(), value, found);
+            return;
+        }
+        let Expr::Variable(variable) = target else {
+            return;
+        };
+        let Some(symbol) = self.symbols.lookup(&variable.name) else {
+            return;
+        };
+        if symbol.constant {
+            self.error(
+                ErrorCode::ConstantRebound,
+                variable.span.0,
+                format!("Cannot assign to constant '{}'", variable.name),
+            );
+            return;
+        }
+        let Some(declared) = symbol.data_type.clone() else {
+            return;
+        };
+        if matches!(value, Expr::Null(_)) && !matches!(declared, DataType::Optional(_)) {
+            self.error(
+                ErrorCode::TypeMismatch,
+                value.span(),
+                format!(
+                    "Cannot assign null to '{}' of non-optional type {:?}",
+                    variable.name, declared
+                ),
+            );
+        } else if let Some(found) = found.filter(|found| !accepts(&declared, found)) {
+            self.error(
+                ErrorCode::TypeMismatch,
+                value.span(),
+                format!(
+                    "Cannot assign {:?} to '{}' of type {:?}",
+                    found, variable.name, declared
+                ),
+            );
+        }
+    }
+
+    // A struct's field takes values of the type the struct declares for it
+    fn check_field_assignment(
+        &mut self,
+        member: &MemberAccessNode,
+        declared: Option<&DataType>,
+        value: &Expr,
+        found: Option<DataType>,
+    ) {
+        let Some(declared) = declared.cloned() else {
+            return;
+        };
+        let found = match (value, found) {
+            (Expr::Null(_), _) if !matches!(declared, DataType::Optional(_)) => "null".to_string(),
+            (_, Some(found)) if !accepts(&declared, &found) => format!("{:?}", found),
+            _ => return,
+        };
+        self.error(
+            ErrorCode::TypeMismatch,
+            value.span(),
+            format!(
+                "Cannot assign {} to field '{}' of type {:?}",
+                found, member.member, declared
+            ),
+        );
+    }
+
+    // Calls must pass as many arguments as the callee takes, of the types
+    // its parameters declare. Returns the type of the call's result
+    fn check_call(
+        &mut self,
+        name: &str,
+        arguments: &[Expr],
+        found: &[Option<DataType>],
+        span: Option<Span>,
+    ) -> Option<DataType> {
+        let Some(signature) = self.symbols.signature(name).cloned() else {
+            if self.symbols.is_declared(name) {
+                return None;
+            }
+            if name == "zeros" && self.builtins.contains_key(name) {
+                self.check_dimensions(arguments, found, span);
+                return Some(DataType::Tensor);
+            }
+            if let Some(Some(arity)) = self.builtins.get(name) {
+                self.check_arity(name, *arity, arguments.len(), span);
+            }
+            return None;
+        };
+        self.check_arity(name, signature.parameters.len(), arguments.len(), span);
+        // The types this call gives the callee's type parameters
+        let mut bindings = HashMap::new();
+        for ((parameter, argument), found) in signature.parameters.iter().zip(arguments).zip(found)
+        {
+            let Some(declared) = &parameter.data_type else {
+                continue;
+            };
+            let found = match (argument, found) {
+                (Expr::Null(_), _)
+                    if !matches!(declared, DataType::Optional(_) | DataType::Parameter(_)) =>
+                {
+                    "null".to_string()
+                }
+                (_, Some(found)) => {
+                    let found = match (declared, found) {
+                        (DataType::Array(_), DataType::Tensor) => {
+                            match tensor_rows(argument.resolved().shape()) {
+                                Some(row) => DataType::Array(Box::new(row)),
+                                None => continue,
+                            }
+                        }
+                        _ => found.clone(),
+                    };
+                    if bind(declared, &found, &mut bindings) {
+                        continue;
+                    }
+                    format!("{:?}", found)
+                }
+                _ => continue,
+            };
+            let expected = substitute(declared, &bindings).unwrap_or_else(|| declared.clone());
+            self.error(
+                ErrorCode::TypeMismatch,
+                argument.span(),
+                format!(
+                    "'{}' expects {:?} for parameter '{}' but got {}",
+                    name, expected, parameter.name, found
+                ),
+            );
+        }
+        signature
+            .return_type
+            .and_then(|return_type| substitute(&return_type, &bindings))
+    }
+
+    // The dimensions given to `zeros` must be ints, and those worked out
+    // from constants can't be negative
+    fn check_dimensions(
+        &mut self,
+        arguments: &[Expr],
+        found: &[Option<DataType>],
+        span: Option<Span>,
+    ) {
+        if arguments.is_empty() {
+            self.error(
+                ErrorCode::ArgumentCount,
+                span,
+                "'zeros' expects at least 1 argument".to_string(),
+            );
+        }
+        for (argument, found) in arguments.iter().zip(found) {
+            if let Some(found) = found.as_ref().filter(|found| **found != DataType::Integer) {
+                self.error(
+                    ErrorCode::TypeMismatch,
+                    argument.span().or(span),
+                    format!("'zeros' expects int dimensions but got {:?}", found),
+                );
+            } else if let Some(Constant::Integer(size @ ..0)) = self.constant_value(argument) {
+                self.error(
+                    ErrorCode::ConstantEvaluation,
+                    argument.span().or(span),
+                    format!("Tensor dimensions can't be negative, found {}", size),
+                );
+            }
+        }
+    }
+
+    fn check_arity(&mut self, name: &str, expected: usize, found: usize, span: Option<Span>) {
+        if expected != found {
+            self.error(
+                ErrorCode::ArgumentCount,
+                span,
+                format!(
+                    "'{}' expects {} argument{} but got {}",
+                    name,
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    found
+                ),
+            );
+        }
+    }
+
+    // Checks `expr` and everything in it, records on it what the checker
+    // worked out, and returns its type where the checker can tell it
+    fn check_expr(&mut self, expr: &mut Expr) -> Option<DataType> {
+        let data_type = self.check_expr_kind(expr);
+        let shape = match data_type {
+            Some(DataType::Tensor) => self.tensor_shape(expr),
+            _ => None,
+        };
+        expr.set_resolved(ResolvedType::new(data_type.clone(), shape));
+        data_type
+    }
+
+    fn check_expr_kind(&mut self, expr: &mut Expr) -> Option<DataType> {
+        let span = expr.span();
+        match expr {
+            Expr::Number(number) => {
+                let in_range = match number.data_type {
+                    DataType::Integer => number.value.parse::<i64>().is_ok(),
+                    _ => number.value.parse::<f64>().is_ok_and(f64::is_finite),
+                };
+                if !in_range {
+                    let message = format!(
+                        "{:?} literal '{}' is out of range",
+                        number.data_type, number.value
+                    );
+                    self.error(ErrorCode::NumberOutOfRange, span, message);
+                }
+                Some(number.data_type.clone())
+            }
+            Expr::String(_) => Some(DataType::String),
+            Expr::Char(_) => Some(DataType::Char),
+            Expr::Boolean(_) => Some(DataType::Boolean),
+            Expr::EnumVariant(variant) => {
+                let message = match self.enums.get(&variant.enum_name) {
+                    None => format!("Undeclared enum '{}'", variant.enum_name),
+                    Some(variants) if !variants.contains(&variant.variant) => format!(
+                        "Enum '{}' has no variant '{}'",
+                        variant.enum_name, variant.variant
+                    ),
+                    Some(_) => return Some(variant.data_type.clone()),
+                };
+                self.error(ErrorCode::UndeclaredVariable, span, message);
+                None
+            }
+            Expr::TensorLiteral(_) => {
+                walk_expr_mut(self, expr);
+                Some(DataType::Tensor)
+            }
+            Expr::Parenthesis(paren) => self.check_expr(&mut paren.expression),
+            Expr::Cast(cast) => {
+                let found = self.check_expr(&mut cast.expression);
+                if let Some(found) = found.filter(|found| !can_cast(found, &cast.data_type)) {
+                    self.error(
+                        ErrorCode::InvalidCast,
+                        span,
+                        format!("Cannot cast {:?} to {:?}", found, cast.data_type),
+                    );
+                } else if let Some(value) = self.constant_value(&cast.expression) {
+                    // A constant is cast as the interpreter would cast it
+                    if let Err(message) = constants::cast(value, &cast.data_type) {
+                        self.error(ErrorCode::InvalidCast, span, message);
+                    }
+                }
+                Some(cast.data_type.clone())
+            }
+            Expr::Variable(variable) => {
+                self.symbols.mark_read(&variable.name);
+                self.check_assigned(variable);
+                self.check_variable(variable)
+            }
+            Expr::Assignment(assignment) => {
+                // Assigning a variable doesn't read it
+                if let Expr::Variable(variable) = &mut *assignment.target {
+                    let data_type = self.check_variable(variable);
+                    assignment
+                        .target
+                        .set_resolved(ResolvedType::new(data_type, None));
+                } else {
+                    self.check_expr(&mut assignment.target);
+                }
+                let found = self.check_expr(&mut assignment.value);
+                self.check_assignment(&assignment.target, &assignment.value, found.clone());
+                if let Expr::Variable(variable) = &*assignment.target {
+                    if let Some(depth) = self.symbols.depth_of(&variable.name) {
+                        self.unassigned.remove(&(depth, variable.name.clone()));
+                    }
+                }
+                found
+            }
+            Expr::CompoundAssignment(assignment) => {
+                let target = self.check_expr(&mut assignment.target);
+                let value = self.check_expr(&mut assignment.value);
+                let found = self.binary_type(target, assignment.operator, value, span);
+                self.check_assignment(&assignment.target, &assignment.value, found.clone());
+                found
+            }
+            Expr::Binary(binary) => {
+                let left = self.check_expr(&mut binary.left);
+                let right = match binary.operator {
+                    // The right operand isn't evaluated when the left
+                    // settles the result
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        let mut right = None;
+                        self.check_skippable(|checker| {
+                            right = checker.check_expr(&mut binary.right)
+                        });
+                        right
+                    }
+                    _ => self.check_expr(&mut binary.right),
+                };
+                let result = self.binary_type(left, binary.operator, right, span);
+                match result {
+                    Some(DataType::Integer | DataType::Float) => {
+                        self.check_constant_operands(binary, span)
+                    }
+                    Some(DataType::Tensor) => self.check_shapes(binary, span),
+                    _ => {}
+                }
+                result
+            }
+            Expr::Unary(unary) => {
+                let operand = self.check_expr(&mut unary.operand)?;
+                match (unary.operator, &operand) {
+                    (UnaryOperator::Negate, DataType::Integer | DataType::Float)
+                    | (UnaryOperator::Not, DataType::Boolean) => Some(operand),
+                    (_, DataType::Optional(_) | DataType::Parameter(_)) => None,
+                    (operator, _) => {
+                        let symbol = match operator {
+                            UnaryOperator::Negate => "-",
+                            UnaryOperator::Not => "!",
+                        };
+                        self.error(
+                            ErrorCode::InvalidOperands,
+                            span,
+                            format!("Cannot apply '{}' to {:?}", symbol, operand),
+                        );
+                        None
+                    }
+                }
+            }
+            Expr::Conditional(conditional) => {
+                self.check_condition(&mut conditional.condition, "?:");
+                let before = self.unassigned.clone();
+                let then_type = self.check_expr(&mut conditional.then_expression);
+                let then_branch = std::mem::replace(&mut self.unassigned, before);
+                let else_type = self.check_expr(&mut conditional.else_expression);
+                self.join(then_branch, false, false);
+                match (then_type?, else_type?) {
+                    (a, b) if a == b => Some(a),
+                    (DataType::Integer | DataType::Float, DataType::Integer | DataType::Float) => {
+                        Some(DataType::Float)
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Lambda(lambda) => {
+                match &mut lambda.body {
+                    LambdaBody::Expression(body) => {
+                        self.functions
+                            .push(("a lambda".to_string(), lambda.return_type.clone()));
+                        let outer = std::mem::take(&mut self.unassigned);
+                        self.scoped(|checker| {
+                            checker.declare_parameters(&lambda.parameters);
+                            let found = checker.check_expr(body);
+                            checker.check_return(Some(body), found);
+                        });
+                        self.unassigned = outer;
+                        self.functions.pop();
+                    }
+                    LambdaBody::Block(body) => self.check_function(
+                        "a lambda".to_string(),
+                        span,
+                        &lambda.parameters,
+                        lambda.return_type.as_ref(),
+                        body,
+                    ),
+                }
+                None
+            }
+            // A spawned body runs alongside the code after it, so like a
+            // function body, may read any variable in scope
+            Expr::Spawn(_) => {
+                let outer = std::mem::take(&mut self.unassigned);
+                self.scoped(|checker| walk_expr_mut(checker, expr));
+                self.unassigned = outer;
+                None
+            }
+            Expr::Match(match_node) => {
+                self.check_expr(&mut match_node.subject);
+                let before = self.unassigned.clone();
+                let mut after = Unassigned::new();
+                for arm in &mut match_node.arms {
+                    self.unassigned = before.clone();
+                    self.visit_pattern_mut(&mut arm.pattern);
+                    self.check_expr(&mut arm.value);
+                    after.extend(std::mem::take(&mut self.unassigned));
+                }
+                if !match_node
+                    .arms
+                    .iter()
+                    .any(|arm| matches!(arm.pattern, Pattern::Wildcard))
+                {
+                    after.extend(before);
+                }
+                self.unassigned = after;
+                None
+            }
+            Expr::Index(index) => {
+                let object = self.check_expr(&mut index.object);
+                self.check_expr(&mut index.index);
+                let element = match object? {
+                    DataType::Array(element) => *element,
+                    DataType::Tensor => tensor_rows(index.object.resolved().shape())?,
+                    _ => return None,
+                };
+                match index.optional {
+                    true => Some(DataType::Optional(Box::new(element))),
+                    false => Some(element),
+                }
+            }
+            Expr::MemberAccess(member) => {
+                let object = self.check_expr(&mut member.object);
+                let name = match object? {
+                    DataType::Struct(name) => name,
+                    DataType::Optional(inner) => match *inner {
+                        DataType::Struct(name) => name,
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                let field = self
+                    .structs
+                    .get(&name)?
+                    .iter()
+                    .find(|field| field.name == member.member)
+                    .map(|field| field.data_type.clone());
+                let Some(field) = field else {
+                    self.error(
+                        ErrorCode::UnknownField,
+                        span,
+                        format!("Struct '{}' has no field '{}'", name, member.member),
+                    );
+                    return None;
+                };
+                match member.optional {
+                    true => Some(DataType::Optional(Box::new(field))),
+                    false => Some(field),
+                }
+            }
+            Expr::Range(_) => {
+                walk_expr_mut(self, expr);
+                self.error(
+                    ErrorCode::MisplacedRange,
+                    span,
+                    "A range can only be used as what a 'for' loop counts over".to_string(),
+                );
+                None
+            }
+            Expr::Call(call) => {
+                self.check_expr(&mut call.callee);
+                let found: Vec<Option<DataType>> = call
+                    .arguments
+                    .iter_mut()
+                    .map(|argument| self.check_expr(argument))
+                    .collect();
+                let Expr::Variable(callee) = &*call.callee else {
+                    return None;
+                };
+                self.check_call(&callee.name, &call.arguments, &found, span)
+            }
+            _ => {
+                walk_expr_mut(self, expr);
+                None
+            }
+        }
+    }
+
+    // The shape of the tensor `expr` evaluates to, going by the shapes
+    // resolved for its parts, where the checker can tell it
+    fn tensor_shape(&self, expr: &Expr) -> Option<Vec<usize>> {
+        match expr {
+            Expr::TensorLiteral(literal) => Some(literal.shape.clone()),
+            Expr::Call(call) if matches!(&*call.callee, Expr::Variable(callee) if callee.name == "zeros") => {
+                call.arguments
+                    .iter()
+                    .map(|argument| match self.constant_value(argument)? {
+                        Constant::Integer(size) => usize::try_from(size).ok(),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            Expr::Variable(variable) => self.symbols.lookup(&variable.name)?.shape.clone(),
+            Expr::Parenthesis(paren) => paren.expression.resolved().shape().map(<[usize]>::to_vec),
+            Expr::Unary(unary) => unary.operand.resolved().shape().map(<[usize]>::to_vec),
+            Expr::Binary(binary) => {
+                let left = binary.left.resolved().shape()?;
+                let right = binary.right.resolved().shape()?;
+                match (binary.operator, left, right) {
+                    (BinaryOperator::MatMul, [rows, inner], [right_rows, columns])
+                        if inner == right_rows =>
+                    {
+                        Some(vec![*rows, *columns])
+                    }
+                    (
+                        BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply,
+                        _,
+                        _,
+                    ) => broadcast_shape(left, right),
+                    _ => None,
+                }
+            }
+            Expr::Conditional(conditional) => {
+                let shape = conditional.then_expression.resolved().shape()?;
+                let other = conditional.else_expression.resolved().shape();
+                (other == Some(shape)).then(|| shape.to_vec())
+            }
+            _ => None,
+        }
+    }
+
+    // Element-wise operations on tensors of known shapes need shapes that
+    // broadcast together, and `@` matrices whose inner dimensions agree
+    fn check_shapes(&mut self, binary: &BinaryOperationNode, span: Option<Span>) {
+        let (Some(left), Some(right)) = (
+            binary.left.resolved().shape(),
+            binary.right.resolved().shape(),
+        ) else {
+            return;
+        };
+        let fits = match binary.operator {
+            BinaryOperator::Add | BinaryOperator::Subtract | BinaryOperator::Multiply => {
+                broadcast_shape(left, right).is_some()
+            }
+            BinaryOperator::MatMul => {
+                matches!((left, right), ([_, inner], [rows, _]) if inner == rows)
+            }
+            _ => true,
+        };
+        if !fits {
+            self.error(
+                ErrorCode::InvalidOperands,
+                span,
+                format!(
+                    "Cannot apply '{}' to tensors of shapes {:?} and {:?}",
+                    binary_symbol(binary.operator),
+                    left,
+                    right
+                ),
+            );
+        }
+    }
+
+    // The value of `expr` if it is computed from literals and constants
+    // alone, as far as it can be without failing
+    fn constant_value(&self, expr: &Expr) -> Option<Constant> {
+        match expr {
+            Expr::Number(_) | Expr::Boolean(_) | Expr::String(_) => Constant::from_expr(expr),
+            Expr::Parenthesis(paren) => self.constant_value(&paren.expression),
+            Expr::Variable(variable) => self.symbols.lookup(&variable.name)?.value.clone(),
+            Expr::Unary(unary) => fold_unary(unary.operator, self.constant_value(&unary.operand)?),
+            Expr::Binary(binary) => fold_binary(
+                &self.constant_value(&binary.left)?,
+                binary.operator,
+                &self.constant_value(&binary.right)?,
+            ),
+            Expr::Cast(cast) => {
+                constants::cast(self.constant_value(&cast.expression)?, &cast.data_type).ok()?
+            }
+            _ => None,
+        }
+    }
+
+    // Numeric operations the interpreter would fail on however the program
+    // gets to them: dividing by a constant zero, shifting by a constant
+    // out of range, or overflowing on constant operands
+    fn check_constant_operands(&mut self, binary: &BinaryOperationNode, span: Option<Span>) {
+        use BinaryOperator::*;
+
+        let Some(right) = self.constant_value(&binary.right) else {
+            return;
+        };
+        let message = match (binary.operator, &right) {
+            (Divide, Constant::Integer(0)) => "Division by zero".to_string(),
+            (Divide, Constant::Float(divisor)) if *divisor == 0.0 => "Division by zero".to_string(),
+            (ShiftLeft | ShiftRight, Constant::Integer(shift)) if !(0..64).contains(shift) => {
+                format!("Shift amount {} is out of range", shift)
+            }
+            (Add | Subtract | Multiply | Divide, Constant::Integer(_)) => {
+                match self.constant_value(&binary.left) {
+                    Some(left @ Constant::Integer(_))
+                        if fold_binary(&left, binary.operator, &right).is_none() =>
+                    {
+                        "Integer overflow".to_string()
+                    }
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        self.error(ErrorCode::ConstantEvaluation, span, message);
+    }
+
+    // The type of `left operator right`, or an error if no values of the
+    // operand types can be combined that way. Unknown operands give an
+    // unknown result, except where the operator fixes it
+    fn binary_type(
+        &mut self,
+        left: Option<DataType>,
+        operator: BinaryOperator,
+        right: Option<DataType>,
+        span: Option<Span>,
+    ) -> Option<DataType> {
+        use BinaryOperator::*;
+        use DataType::*;
+
+        let comparison = matches!(operator, Less | LessEqual | Greater | GreaterEqual);
+        if matches!(operator, Equal | NotEqual) {
+            return Some(Boolean);
+        }
+        let (left, right) = match (left, right) {
+            (Some(left), Some(right)) => (left, right),
+            _ if comparison || matches!(operator, And | Or) => return Some(Boolean),
+            _ => return None,
+        };
+        let result = match (&left, &right) {
+            // Null may turn up at run time, so optionals are left to it
+            (Optional(_), _) | (_, Optional(_)) => return None,
+            // So is what a type parameter's values can do, as any type may
+            // stand in for it
+            (Parameter(_), _) | (_, Parameter(_)) => return None,
+            (Boolean, Boolean) if matches!(operator, And | Or) => Some(Boolean),
+            _ if matches!(operator, And | Or) => None,
+            (Integer, Integer) => match operator {
+                MatMul => None,
+                _ if comparison => Some(Boolean),
+                _ => Some(Integer),
+            },
+            (Integer | Float, Integer | Float) => match operator {
+                Add | Subtract | Multiply | Divide => Some(Float),
+                _ if comparison => Some(Boolean),
+                _ => None,
+            },
+            (String, String) => match operator {
+                Add => Some(String),
+                _ if comparison => Some(Boolean),
+                _ => None,
+            },
+            (Char, Char) if comparison => Some(Boolean),
+            (Tensor, Tensor) => match operator {
+                Add | Subtract | Multiply | MatMul => Some(Tensor),
+                _ => None,
+            },
+            _ => None,
+        };
+        if result.is_none() {
+            self.error(
+                ErrorCode::InvalidOperands,
+                span,
+                format!(
+                    "Cannot apply '{}' to {:?} and {:?}",
+                    binary_symbol(operator),
+                    left,
+                    right
+                ),
+            );
+        }
+        result
+    }
+}
+
+// The lints the `#allow(...)` comments on `stmt` switch off: those
+// leading into it and the ones on the line it ends on
+fn allowed_lints(stmt: &Stmt) -> Vec<String> {
+    let Some(comments) = stmt.comments() else {
+        return Vec::new();
+    };
+    let trailing = comments.trailing.iter().filter(|comment| comment.same_line);
+    comments
+        .leading
+        .iter()
+        .chain(trailing)
+        .filter_map(|comment| {
+            let (_, rest) = comment.text.split_once("#allow(")?;
+            let (lints, _) = rest.split_once(')')?;
+            Some(lints.split(',').map(|lint| lint.trim().to_string()))
+        })
+        .flatten()
+        .collect()
+}
+
+// Whether evaluating `expr` does anything besides produce its value
+fn has_effect(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(_)
+        | Expr::MethodCall(_)
+        | Expr::Assignment(_)
+        | Expr::CompoundAssignment(_)
+        | Expr::Spawn(_)
+        | Expr::Match(_) => true,
+        Expr::Parenthesis(paren) => has_effect(&paren.expression),
+        Expr::Conditional(conditional) => {
+            has_effect(&conditional.then_expression) || has_effect(&conditional.else_expression)
+        }
+        _ => false,
+    }
+}
+
+// The names `stmt` declares in the scope it is in
+fn declared_names(stmt: &Stmt) -> Vec<String> {
+    match stmt {
+        Stmt::VarDeclaration(declaration) => vec![declaration.name.clone()],
+        Stmt::ConstDeclaration(declaration) => vec![declaration.name.clone()],
+        Stmt::FunctionDeclaration(function) => vec![function.name.clone()],
+        Stmt::StructDeclaration(declaration) => vec![declaration.name.clone()],
+        Stmt::Import(import) => imported_names(&import.source),
+        _ => Vec::new(),
+    }
+}
+
+// The names an import binds: everything in a standard basket, or else the
+// module, named after the last part of its path
+fn imported_names(source: &ImportSource) -> Vec<String> {
+    match source {
+        ImportSource::Basket(path) => match standard_basket(path) {
+            Some(basket) => basket
+                .functions
+                .into_iter()
+                .map(|function| function.name)
+                .chain(
+                    basket
+                        .constants
+                        .into_iter()
+                        .map(|(name, _)| name.to_string()),
+                )
+                .collect(),
+            None => path.last().cloned().into_iter().collect(),
+        },
+        ImportSource::File(path) => std::path::Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .into_iter()
+            .collect(),
+    }
+}
+
+// Whether running `stmt` always ends in a `return` or `throw`, so never
+// carries on to the statement after it
+fn always_returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(_) | Stmt::Throw(_) => true,
+        Stmt::Block(block) => block.statements.iter().any(always_returns),
+        Stmt::If(if_node) => {
+            always_returns(&if_node.then_branch)
+                && if_node
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| always_returns(else_branch))
+        }
+        Stmt::Try(try_node) => {
+            try_node.body.statements.iter().any(always_returns)
+                && try_node.handler.statements.iter().any(always_returns)
+        }
+        Stmt::Match(match_node) => {
+            match_node
+                .arms
+                .iter()
+                .any(|arm| matches!(arm.pattern, Pattern::Wildcard))
+                && match_node.arms.iter().all(|arm| always_returns(&arm.body))
+        }
+        _ => false,
+    }
+}
+
+// Whether `as` converts a `from` to a `to`; see the module comment
+fn can_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+
+    match (from, to) {
+        (Optional(from), to) => can_cast(from, to),
+        (from, Optional(to)) => can_cast(from, to),
+        (from, to) if from == to => true,
+        (Parameter(_), _) | (_, Parameter(_)) => true,
+        (Integer | Float | Boolean | Char | String, Integer) => true,
+        (Integer | String, Float) => true,
+        (Integer, Char) => true,
+        (Integer | Float | Boolean | Char, String) => true,
+        _ => false,
+    }
+}
+
+// What indexing a tensor of `shape` gives: a float if it is 1-D, or else
+// a tensor one dimension down. Unknown without the shape
+fn tensor_rows(shape: Option<&[usize]>) -> Option<DataType> {
+    match shape?.len() {
+        1 => Some(DataType::Float),
+        _ => Some(DataType::Tensor),
+    }
+}
+
+// Whether a variable declared `declared` can hold a `found`. Ints widen to
+// floats, an optional holds its inner type too, and an array of floats or
+// of tensors holds a tensor
+fn accepts(declared: &DataType, found: &DataType) -> bool {
+    match (declared, found) {
+        (DataType::Float, DataType::Integer) => true,
+        (DataType::Array(element), DataType::Tensor) => {
+            matches!(**element, DataType::Float | DataType::Tensor)
+        }
+        (DataType::Optional(declared), DataType::Optional(found)) => accepts(declared, found),
+        (DataType::Optional(declared), found) => accepts(declared, found),
+        (DataType::Array(declared), DataType::Array(found)) => accepts(declared, found),
+        (declared, found) => declared == found,
+    }
+}
+
+// Whether a `found` can be passed where `declared` is expected, binding
+// each type parameter in `declared` to the type it stands for in this
+// call. A parameter already bound takes only that type again
+fn bind(declared: &DataType, found: &DataType, bindings: &mut HashMap<String, DataType>) -> bool {
+    match (declared, found) {
+        (DataType::Parameter(name), found) => match bindings.get(name) {
+            Some(bound) => accepts(bound, found),
+            None => {
+                bindings.insert(name.clone(), found.clone());
+                true
+            }
+        },
+        (DataType::Array(declared), DataType::Array(found))
+        | (DataType::Optional(declared), DataType::Optional(found)) => {
+            bind(declared, found, bindings)
+        }
+        (DataType::Optional(declared), found) => bind(declared, found, bindings),
+        (declared, found) => accepts(declared, found),
+    }
+}
+
+// `data_type` with its type parameters replaced by their bindings, or
+// `None` if any is unbound
+fn substitute(data_type: &DataType, bindings: &HashMap<String, DataType>) -> Option<DataType> {
+    Some(match data_type {
+        DataType::Parameter(name) => bindings.get(name)?.clone(),
+        DataType::Array(element) => DataType::Array(Box::new(substitute(element, bindings)?)),
+        DataType::Optional(inner) => match substitute(inner, bindings)? {
+            inner @ DataType::Optional(_) => inner,
+            inner => DataType::Optional(Box::new(inner)),
+        },
+        other => other.clone(),
+    })
+}
+
+impl MutVisitor for TypeChecker {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::ConstDeclaration(declaration) => {
+                let data_type = self.check_initializer(
+                    &declaration.name,
+                    declaration.data_type.as_ref(),
+                    Some(&mut declaration.value),
+                );
+                let symbol = Symbol {
+                    value: self.constant_value(&declaration.value),
+                    shape: declaration.value.resolved().shape().map(<[usize]>::to_vec),
+                    ..Symbol::constant(data_type)
+                };
+                self.declare(&declaration.name, symbol, declaration.span.0);
+            }
+            Stmt::VarDeclaration(declaration) => {
+                let data_type = self
+                    .check_initializer(
+                        &declaration.name,
+                        declaration.data_type.as_ref(),
+                        declaration.initializer.as_mut(),
+                    )
+                    .or_else(|| declaration.data_type.clone());
+                let unassigned = declaration.initializer.is_none()
+                    && !matches!(data_type, Some(DataType::Optional(_)));
+                self.declare(
+                    &declaration.name,
+                    Symbol::variable(data_type),
+                    declaration.span.0,
+                );
+                if unassigned {
+                    self.unassigned
+                        .insert((self.symbols.depth(), declaration.name.clone()));
+                }
+            }
+            Stmt::FunctionDeclaration(function) => {
+                // Declared first so the body may call itself
+                self.declare(
+                    &function.name,
+                    Symbol::function(Signature::of(function)),
+                    function.span.0,
+                );
+                self.check_function(
+                    format!("'{}'", function.name),
+                    function.span.0,
+                    &function.parameters,
+                    function.return_type.as_ref(),
+                    &mut function.body,
+                );
+            }
+            Stmt::StructDeclaration(declaration) => self.declare(
+                &declaration.name,
+                Symbol::function(Signature::constructor(declaration)),
+                None,
+            ),
+            Stmt::Block(block) => {
+                self.scoped(|checker| checker.check_statements(&mut block.statements))
+            }
+            Stmt::If(if_node) => {
+                self.check_condition(&mut if_node.condition, "if");
+                let before = self.unassigned.clone();
+                self.visit_stmt_mut(&mut if_node.then_branch);
+                let then_branch = std::mem::replace(&mut self.unassigned, before);
+                if let Some(else_branch) = &mut if_node.else_branch {
+                    self.visit_stmt_mut(else_branch);
+                }
+                let else_returns = if_node
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| always_returns(else_branch));
+                self.join(
+                    then_branch,
+                    always_returns(&if_node.then_branch),
+                    else_returns,
+                );
+            }
+            Stmt::While(while_node) => {
+                self.check_condition(&mut while_node.condition, "while");
+                self.check_skippable(|checker| checker.visit_stmt_mut(&mut while_node.body));
+            }
+            Stmt::DoWhile(do_while) => {
+                self.visit_stmt_mut(&mut do_while.body);
+                self.check_condition(&mut do_while.condition, "while");
+            }
+            Stmt::For(for_node) => self.scoped(|checker| {
+                if let Some(initializer) = &mut for_node.initializer {
+                    checker.visit_stmt_mut(initializer);
+                }
+                if let Some(condition) = &mut for_node.condition {
+                    checker.check_condition(condition, "for");
+                }
+                checker.check_skippable(|checker| {
+                    checker.visit_stmt_mut(&mut for_node.body);
+                    if let Some(increment) = &mut for_node.increment {
+                        checker.visit_expr_mut(increment);
+                    }
+                });
+            }),
+            Stmt::ForIn(for_in) => {
+                let data_type = match &mut for_in.iterable {
+                    Expr::Range(range) => {
+                        self.check_range(range);
+                        Some(DataType::Integer)
+                    }
+                    iterable => {
+                        self.check_expr(iterable);
+                        None
+                    }
+                };
+                self.scoped(|checker| {
+                    checker.declare(&for_in.variable, Symbol::variable(data_type), None);
+                    checker.check_skippable(|checker| checker.visit_stmt_mut(&mut for_in.body));
+                });
+            }
+            // Only one arm runs, if any does
+            Stmt::Match(match_node) => {
+                self.check_expr(&mut match_node.subject);
+                let before = self.unassigned.clone();
+                let mut after: Option<Unassigned> = None;
+                for arm in &mut match_node.arms {
+                    self.unassigned = before.clone();
+                    self.visit_pattern_mut(&mut arm.pattern);
+                    self.visit_stmt_mut(&mut arm.body);
+                    if !always_returns(&arm.body) {
+                        let arm = std::mem::take(&mut self.unassigned);
+                        after.get_or_insert_with(Unassigned::new).extend(arm);
+                    }
+                }
+                let exhaustive = match_node
+                    .arms
+                    .iter()
+                    .any(|arm| matches!(arm.pattern, Pattern::Wildcard));
+                self.unassigned = match after {
+                    Some(mut after) if !exhaustive => {
+                        after.extend(before);
+                        after
+                    }
+                    Some(after) => after,
+                    None => before,
+                };
+            }
+            // The body may stop at any point, so the handler starts from
+            // what was unassigned before it
+            Stmt::Try(try_node) => {
+                let before = self.unassigned.clone();
+                self.scoped(|checker| checker.check_statements(&mut try_node.body.statements));
+                let body = std::mem::replace(&mut self.unassigned, before);
+                self.scoped(|checker| {
+                    if let Some(name) = &try_node.error_name {
+                        checker.declare(name, Symbol::variable(None), None);
+                    }
+                    checker.check_statements(&mut try_node.handler.statements);
+                });
+                self.join(
+                    body,
+                    try_node.body.statements.iter().any(always_returns),
+                    try_node.handler.statements.iter().any(always_returns),
+                );
+            }
+            Stmt::Return(return_node) => {
+                let found = return_node
+                    .value
+                    .as_mut()
+                    .and_then(|value| self.check_expr(value));
+                self.check_return(return_node.value.as_ref(), found);
+            }
+            Stmt::Expression(expr) => {
+                self.check_expr(expr);
+                let program_value = expr.span().is_some() && expr.span() == self.program_value;
+                if !has_effect(expr) && !program_value && !self.allows("unused_results") {
+                    let message = format!("Result of '{}' is never used", format_expr(expr));
+                    self.warn(expr.span(), message);
+                }
+            }
+            // Importing twice binds the same names again. Imported names
+            // that go unused aren't reported
+            Stmt::Import(import) => {
+                for name in imported_names(&import.source) {
+                    self.symbols.declare(&name, Symbol::variable(None));
+                    self.symbols.mark_read(&name);
+                }
+            }
+            _ => walk_stmt_mut(self, stmt),
+        }
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        self.check_expr(expr);
+    }
+}
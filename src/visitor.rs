@@ -0,0 +1,390 @@
+//! Generic AST traversal. Implement `Visitor` (read-only) or `MutVisitor`
+//! (in-place rewriting) and override only the `visit_*` methods you care
+//! about; call the matching `walk_*` function from an override to keep
+//! descending into the node's children.
+
+use crate::ast::*;
+
+pub trait Visitor {
+    fn visit_program(&mut self, program: &ProgramNode) {
+        walk_program(self, program);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &ProgramNode) {
+    for stmt in &program.statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expression(expr) => visitor.visit_expr(expr),
+        Stmt::VarDeclaration(declaration) => {
+            if let Some(initializer) = &declaration.initializer {
+                visitor.visit_expr(initializer);
+            }
+        }
+        Stmt::ConstDeclaration(declaration) => visitor.visit_expr(&declaration.value),
+        Stmt::FunctionDeclaration(function) => {
+            for stmt in &function.body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::StructDeclaration(_)
+        | Stmt::EnumDeclaration(_)
+        | Stmt::Breakpoint(_)
+        | Stmt::Import(_)
+        | Stmt::Comment(_) => {}
+        Stmt::Return(return_node) => {
+            if let Some(value) = &return_node.value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::Throw(throw) => visitor.visit_expr(&throw.value),
+        Stmt::If(if_node) => {
+            visitor.visit_expr(&if_node.condition);
+            visitor.visit_stmt(&if_node.then_branch);
+            if let Some(else_branch) = &if_node.else_branch {
+                visitor.visit_stmt(else_branch);
+            }
+        }
+        Stmt::While(while_node) => {
+            visitor.visit_expr(&while_node.condition);
+            visitor.visit_stmt(&while_node.body);
+        }
+        Stmt::DoWhile(do_while) => {
+            visitor.visit_stmt(&do_while.body);
+            visitor.visit_expr(&do_while.condition);
+        }
+        Stmt::For(for_node) => {
+            if let Some(initializer) = &for_node.initializer {
+                visitor.visit_stmt(initializer);
+            }
+            if let Some(condition) = &for_node.condition {
+                visitor.visit_expr(condition);
+            }
+            if let Some(increment) = &for_node.increment {
+                visitor.visit_expr(increment);
+            }
+            visitor.visit_stmt(&for_node.body);
+        }
+        Stmt::ForIn(for_in) => {
+            visitor.visit_expr(&for_in.iterable);
+            visitor.visit_stmt(&for_in.body);
+        }
+        Stmt::Block(block) => {
+            for stmt in &block.statements {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::Try(try_node) => {
+            for stmt in &try_node.body.statements {
+                visitor.visit_stmt(stmt);
+            }
+            for stmt in &try_node.handler.statements {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::Match(match_node) => {
+            visitor.visit_expr(&match_node.subject);
+            for arm in &match_node.arms {
+                visitor.visit_pattern(&arm.pattern);
+                visitor.visit_stmt(&arm.body);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Number(_)
+        | Expr::String(_)
+        | Expr::Char(_)
+        | Expr::Boolean(_)
+        | Expr::Null(_)
+        | Expr::Variable(_)
+        | Expr::EnumVariant(_)
+        | Expr::Cfg(_) => {}
+        Expr::Assignment(assignment) => {
+            visitor.visit_expr(&assignment.target);
+            visitor.visit_expr(&assignment.value);
+        }
+        Expr::CompoundAssignment(assignment) => {
+            visitor.visit_expr(&assignment.target);
+            visitor.visit_expr(&assignment.value);
+        }
+        Expr::Binary(binary) => {
+            visitor.visit_expr(&binary.left);
+            visitor.visit_expr(&binary.right);
+        }
+        Expr::Unary(unary) => visitor.visit_expr(&unary.operand),
+        Expr::Cast(cast) => visitor.visit_expr(&cast.expression),
+        Expr::Parenthesis(paren) => visitor.visit_expr(&paren.expression),
+        Expr::Conditional(conditional) => {
+            visitor.visit_expr(&conditional.condition);
+            visitor.visit_expr(&conditional.then_expression);
+            visitor.visit_expr(&conditional.else_expression);
+        }
+        Expr::Range(range) => {
+            visitor.visit_expr(&range.start);
+            visitor.visit_expr(&range.end);
+            if let Some(step) = &range.step {
+                visitor.visit_expr(step);
+            }
+        }
+        Expr::Call(call) => {
+            visitor.visit_expr(&call.callee);
+            for argument in &call.arguments {
+                visitor.visit_expr(argument);
+            }
+        }
+        Expr::MethodCall(method_call) => {
+            visitor.visit_expr(&method_call.object);
+            for argument in &method_call.arguments {
+                visitor.visit_expr(argument);
+            }
+        }
+        Expr::MemberAccess(member) => visitor.visit_expr(&member.object),
+        Expr::Index(index) => {
+            visitor.visit_expr(&index.object);
+            visitor.visit_expr(&index.index);
+        }
+        Expr::TensorLiteral(tensor) => {
+            for element in &tensor.elements {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::Match(match_node) => {
+            visitor.visit_expr(&match_node.subject);
+            for arm in &match_node.arms {
+                visitor.visit_pattern(&arm.pattern);
+                visitor.visit_expr(&arm.value);
+            }
+        }
+        Expr::Spawn(spawn) => {
+            for stmt in &spawn.body {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Expr::Lambda(lambda) => match &lambda.body {
+            LambdaBody::Expression(body) => visitor.visit_expr(body),
+            LambdaBody::Block(body) => {
+                for stmt in body {
+                    visitor.visit_stmt(stmt);
+                }
+            }
+        },
+    }
+}
+
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Literal(literal) => visitor.visit_expr(literal),
+        Pattern::Wildcard => {}
+    }
+}
+
+pub trait MutVisitor {
+    fn visit_program_mut(&mut self, program: &mut ProgramNode) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        walk_pattern_mut(self, pattern);
+    }
+}
+
+pub fn walk_program_mut<V: MutVisitor + ?Sized>(visitor: &mut V, program: &mut ProgramNode) {
+    for stmt in &mut program.statements {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: MutVisitor + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expression(expr) => visitor.visit_expr_mut(expr),
+        Stmt::VarDeclaration(declaration) => {
+            if let Some(initializer) = &mut declaration.initializer {
+                visitor.visit_expr_mut(initializer);
+            }
+        }
+        Stmt::ConstDeclaration(declaration) => visitor.visit_expr_mut(&mut declaration.value),
+        Stmt::FunctionDeclaration(function) => {
+            for stmt in &mut function.body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Stmt::StructDeclaration(_)
+        | Stmt::EnumDeclaration(_)
+        | Stmt::Breakpoint(_)
+        | Stmt::Import(_)
+        | Stmt::Comment(_) => {}
+        Stmt::Return(return_node) => {
+            if let Some(value) = &mut return_node.value {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        Stmt::Throw(throw) => visitor.visit_expr_mut(&mut throw.value),
+        Stmt::If(if_node) => {
+            visitor.visit_expr_mut(&mut if_node.condition);
+            visitor.visit_stmt_mut(&mut if_node.then_branch);
+            if let Some(else_branch) = &mut if_node.else_branch {
+                visitor.visit_stmt_mut(else_branch);
+            }
+        }
+        Stmt::While(while_node) => {
+            visitor.visit_expr_mut(&mut while_node.condition);
+            visitor.visit_stmt_mut(&mut while_node.body);
+        }
+        Stmt::DoWhile(do_while) => {
+            visitor.visit_stmt_mut(&mut do_while.body);
+            visitor.visit_expr_mut(&mut do_while.condition);
+        }
+        Stmt::For(for_node) => {
+            if let Some(initializer) = &mut for_node.initializer {
+                visitor.visit_stmt_mut(initializer);
+            }
+            if let Some(condition) = &mut for_node.condition {
+                visitor.visit_expr_mut(condition);
+            }
+            if let Some(increment) = &mut for_node.increment {
+                visitor.visit_expr_mut(increment);
+            }
+            visitor.visit_stmt_mut(&mut for_node.body);
+        }
+        Stmt::ForIn(for_in) => {
+            visitor.visit_expr_mut(&mut for_in.iterable);
+            visitor.visit_stmt_mut(&mut for_in.body);
+        }
+        Stmt::Block(block) => {
+            for stmt in &mut block.statements {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Stmt::Try(try_node) => {
+            for stmt in &mut try_node.body.statements {
+                visitor.visit_stmt_mut(stmt);
+            }
+            for stmt in &mut try_node.handler.statements {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Stmt::Match(match_node) => {
+            visitor.visit_expr_mut(&mut match_node.subject);
+            for arm in &mut match_node.arms {
+                visitor.visit_pattern_mut(&mut arm.pattern);
+                visitor.visit_stmt_mut(&mut arm.body);
+            }
+        }
+    }
+}
+
+pub fn walk_expr_mut<V: MutVisitor + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Number(_)
+        | Expr::String(_)
+        | Expr::Char(_)
+        | Expr::Boolean(_)
+        | Expr::Null(_)
+        | Expr::Variable(_)
+        | Expr::EnumVariant(_)
+        | Expr::Cfg(_) => {}
+        Expr::Assignment(assignment) => {
+            visitor.visit_expr_mut(&mut assignment.target);
+            visitor.visit_expr_mut(&mut assignment.value);
+        }
+        Expr::CompoundAssignment(assignment) => {
+            visitor.visit_expr_mut(&mut assignment.target);
+            visitor.visit_expr_mut(&mut assignment.value);
+        }
+        Expr::Binary(binary) => {
+            visitor.visit_expr_mut(&mut binary.left);
+            visitor.visit_expr_mut(&mut binary.right);
+        }
+        Expr::Unary(unary) => visitor.visit_expr_mut(&mut unary.operand),
+        Expr::Cast(cast) => visitor.visit_expr_mut(&mut cast.expression),
+        Expr::Parenthesis(paren) => visitor.visit_expr_mut(&mut paren.expression),
+        Expr::Conditional(conditional) => {
+            visitor.visit_expr_mut(&mut conditional.condition);
+            visitor.visit_expr_mut(&mut conditional.then_expression);
+            visitor.visit_expr_mut(&mut conditional.else_expression);
+        }
+        Expr::Range(range) => {
+            visitor.visit_expr_mut(&mut range.start);
+            visitor.visit_expr_mut(&mut range.end);
+            if let Some(step) = &mut range.step {
+                visitor.visit_expr_mut(step);
+            }
+        }
+        Expr::Call(call) => {
+            visitor.visit_expr_mut(&mut call.callee);
+            for argument in &mut call.arguments {
+                visitor.visit_expr_mut(argument);
+            }
+        }
+        Expr::MethodCall(method_call) => {
+            visitor.visit_expr_mut(&mut method_call.object);
+            for argument in &mut method_call.arguments {
+                visitor.visit_expr_mut(argument);
+            }
+        }
+        Expr::MemberAccess(member) => visitor.visit_expr_mut(&mut member.object),
+        Expr::Index(index) => {
+            visitor.visit_expr_mut(&mut index.object);
+            visitor.visit_expr_mut(&mut index.index);
+        }
+        Expr::TensorLiteral(tensor) => {
+            for element in &mut tensor.elements {
+                visitor.visit_expr_mut(element);
+            }
+        }
+        Expr::Match(match_node) => {
+            visitor.visit_expr_mut(&mut match_node.subject);
+            for arm in &mut match_node.arms {
+                visitor.visit_pattern_mut(&mut arm.pattern);
+                visitor.visit_expr_mut(&mut arm.value);
+            }
+        }
+        Expr::Spawn(spawn) => {
+            for stmt in &mut spawn.body {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        Expr::Lambda(lambda) => match &mut lambda.body {
+            LambdaBody::Expression(body) => visitor.visit_expr_mut(body),
+            LambdaBody::Block(body) => {
+                for stmt in body {
+                    visitor.visit_stmt_mut(stmt);
+                }
+            }
+        },
+    }
+}
+
+pub fn walk_pattern_mut<V: MutVisitor + ?Sized>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Literal(literal) => visitor.visit_expr_mut(literal),
+        Pattern::Wildcard => {}
+    }
+}
@@ -0,0 +1,146 @@
+//! The entry points of the browser playground. `parse`, `check` and
+//! `eval` each take a whole script and return text for the page to show,
+//! so the same functions serve native callers and the WebAssembly build.
+//!
+//! Built for `wasm32-unknown-unknown` (`cargo build --lib --release
+//! --target wasm32-unknown-unknown`), the crate also exports a small C ABI
+//! over them, described on the `exports` module.
+
+use crate::engine::{Engine, EngineError};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::type_checker::{self, TypeChecker};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// The script's syntax tree as JSON.
+pub fn parse(source: &str) -> Result<String, String> {
+    let program = Parser::new(Lexer::new(source))
+        .parse()
+        .map_err(|error| error.to_string())?;
+    Ok(program.to_json())
+}
+
+/// Parses and type-checks the script without running it.
+pub fn check(source: &str) -> Result<(), String> {
+    let program = Parser::new(Lexer::new(source))
+        .parse()
+        .map_err(|error| error.to_string())?;
+    TypeChecker::check_program(&program).map_err(|errors| type_checker::report(&errors))
+}
+
+/// Runs the script and returns what it printed. On a runtime error the
+/// `Err` holds the output up to that point followed by the rendered error.
+/// There is no project.zom in the playground, so every `@cfg` is unset,
+/// and scripts cannot touch files.
+pub fn eval(source: &str) -> Result<String, String> {
+    let output = SharedOutput::default();
+    let result = Engine::with_output(output.clone()).eval(source);
+    let printed = String::from_utf8_lossy(&output.0.lock().unwrap()).into_owned();
+    match result {
+        Ok(_) => Ok(printed),
+        Err(error @ EngineError::Runtime(_)) => Err(printed + &error.render(source)),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+// Collects a script's output where `eval` can still read it once the
+// interpreter is done
+#[derive(Clone, Default)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The functions a page calls on the `.wasm` module. To run an entry
+/// point, the page copies the script as UTF-8 into memory from
+/// `put_alloc`, calls `put_parse`, `put_check` or `put_eval` with its
+/// address and length, and frees it with `put_free`. The call returns 0 on
+/// success and 1 on error; either way the text to show is then at
+/// `put_result_ptr()`, `put_result_len()` bytes long, until the next call.
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static RESULT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[no_mangle]
+    pub extern "C" fn put_alloc(len: usize) -> *mut u8 {
+        let mut buffer = Vec::<u8>::with_capacity(len);
+        let pointer = buffer.as_mut_ptr();
+        std::mem::forget(buffer);
+        pointer
+    }
+
+    /// # Safety
+    ///
+    /// `pointer` and `len` must come from one `put_alloc` call, and the
+    /// buffer must not be used afterwards.
+    #[no_mangle]
+    pub unsafe extern "C" fn put_free(pointer: *mut u8, len: usize) {
+        drop(Vec::from_raw_parts(pointer, 0, len));
+    }
+
+    #[no_mangle]
+    pub extern "C" fn put_result_ptr() -> *const u8 {
+        RESULT.with(|result| result.borrow().as_ptr())
+    }
+
+    #[no_mangle]
+    pub extern "C" fn put_result_len() -> usize {
+        RESULT.with(|result| result.borrow().len())
+    }
+
+    /// # Safety
+    ///
+    /// `pointer` must point to `len` initialized bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn put_parse(pointer: *const u8, len: usize) -> i32 {
+        respond(pointer, len, super::parse)
+    }
+
+    /// # Safety
+    ///
+    /// `pointer` must point to `len` initialized bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn put_check(pointer: *const u8, len: usize) -> i32 {
+        respond(pointer, len, |source| {
+            super::check(source).map(|()| String::new())
+        })
+    }
+
+    /// # Safety
+    ///
+    /// `pointer` must point to `len` initialized bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn put_eval(pointer: *const u8, len: usize) -> i32 {
+        respond(pointer, len, super::eval)
+    }
+
+    unsafe fn respond(
+        pointer: *const u8,
+        len: usize,
+        entry: impl Fn(&str) -> Result<String, String>,
+    ) -> i32 {
+        let bytes = std::slice::from_raw_parts(pointer, len);
+        let (status, text) = match std::str::from_utf8(bytes) {
+            Ok(source) => match entry(source) {
+                Ok(text) => (0, text),
+                Err(text) => (1, text),
+            },
+            Err(_) => (1, "Source is not valid UTF-8".to_string()),
+        };
+        RESULT.with(|result| *result.borrow_mut() = text.into_bytes());
+        status
+    }
+}
@@ -1,77 +1,4267 @@
-use put_lang::ast::{AssignmentNode, BinaryOperationNode, VariableNode};
-use put_lang::lexer::Lexer;
-use put_lang::parser::Parser;
-use put_lang::tensor::Tensor;
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_basic_parsing() {
-        let source = "var x = 42 + 5;";
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.tokenize();
-
-        let mut parser = Parser::new(tokens);
-        let program = parser.parse();
-
-        assert_eq!(program.statements.len(), 1);
-
-        if let Some(assignment) = program.statements[0]
-            .as_any()
-            .downcast_ref::<AssignmentNode>()
-        {
-            assert!(assignment
-                .left
-                .as_any()
-                .downcast_ref::<VariableNode>()
-                .is_some());
-            assert!(assignment
-                .right
-                .as_any()
-                .downcast_ref::<BinaryOperationNode>()
-                .is_some());
-        } else {
-            panic!("Expected AssignmentNode");
-        }
-    }
-
-    #[test]
-    fn test_complex_expression() {
-        let source = "var x = (42 + 5) * 2 - 3 / 1.5;";
-        let mut lexer = Lexer::new(source);
-        let tokens = lexer.tokenize();
-
-        let mut parser = Parser::new(tokens);
-        let program = parser.parse();
-
-        assert_eq!(program.statements.len(), 1);
-        // Add more specific assertions to check the structure of the AST
-    }
-
-    #[test]
-    fn test_tensor_operations() {
-        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
-        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
-
-        let t_add = &t1 + &t2;
-        assert_eq!(t_add.get(&[0, 0]), Some(6.0));
-
-        let t_sub = &t1 - &t2;
-        assert_eq!(t_sub.get(&[0, 0]), Some(-4.0));
-
-        let t_mul = &t1 * &t2;
-        assert_eq!(t_mul.get(&[0, 0]), Some(5.0));
-
-        let t_matmul = t1.matmul(&t2).unwrap();
-        assert_eq!(t_matmul.get(&[0, 0]), Some(19.0));
-
-        let t_transpose = t1.transpose();
-        assert_eq!(t_transpose.get(&[0, 1]), Some(3.0));
-
-        assert!((t1.mean() - 2.5).abs() < 1e-6);
-        assert!((t1.variance() - 1.25).abs() < 1e-6);
-        assert!((t1.std_dev() - 1.118033988749895).abs() < 1e-6);
-    }
-}
+use put_lang::ast::{
+    BinaryOperationNode, BinaryOperator, BlockNode, Comments, DataType, Expr, ForNode,
+    ImportSource, LambdaBody, Pattern, ProgramNode, Stmt, UnaryOperationNode, UnaryOperator,
+    VariableNode,
+};
+use put_lang::builtins::NativeFunction;
+use put_lang::codegen_python::emit_python;
+use put_lang::codegen_rust::emit_rust;
+use put_lang::debugger::ConsoleDebugger;
+use put_lang::desugar::desugar;
+use put_lang::engine::{Engine, EngineError};
+use put_lang::formatter::{format_expr, format_program, format_statement};
+use put_lang::interpreter::{
+    EnumValue, Interpreter, Limits, RuntimeError, Value, DEFAULT_MAX_CALL_DEPTH,
+};
+use put_lang::lexer::{char_value, string_value, unescape, Lexer};
+use put_lang::optimizer::{fold_constants, fold_typed};
+use put_lang::parser::Parser;
+use put_lang::preprocessor::substitute_cfg;
+use put_lang::repl::Session;
+use put_lang::tensor::Tensor;
+use put_lang::token::{Span, TokenType};
+use put_lang::type_checker::{self, ErrorCode, TypeChecker, TypeError, Warning};
+use put_lang::visitor::{walk_expr, walk_expr_mut, walk_stmt, MutVisitor, Visitor};
+use put_lang::wasm;
+use put_lang::zom_parser::ProjectConfig;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The initializer of a `var name = value;` statement
+    fn initializer(statement: &Stmt) -> &Expr {
+        match statement {
+            Stmt::VarDeclaration(declaration) => declaration
+                .initializer
+                .as_ref()
+                .expect("Expected an initializer"),
+            other => panic!("Expected VarDeclaration, got {:?}", other),
+        }
+    }
+
+    // Collects what a program prints, for checking after it has run
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    // Runs `source`, returning the interpreter and everything it printed
+    fn run(source: &str) -> (Interpreter, String, Result<(), RuntimeError>) {
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        let result = interpreter.interpret(&program);
+        (interpreter, output.text(), result)
+    }
+
+    fn binary(expr: &Expr) -> &BinaryOperationNode {
+        match expr {
+            Expr::Binary(node) => node,
+            other => panic!("Expected BinaryOperationNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_basic_parsing() {
+        let source = "var x = 42 + 5;";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 1);
+
+        if let Stmt::VarDeclaration(declaration) = &program.statements[0] {
+            assert_eq!(declaration.name, "x");
+            assert!(matches!(declaration.initializer, Some(Expr::Binary(_))));
+        } else {
+            panic!("Expected VarDeclaration");
+        }
+    }
+
+    #[test]
+    fn test_complex_expression() {
+        let source = "var x = (42 + 5) * 2 - 3 / 1.5;";
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements.len(), 1);
+        assert_eq!(
+            program.to_sexpr(),
+            "(assign (var x) (- (* (+ 42 5) 2) (/ 3 1.5)))"
+        );
+    }
+
+    #[test]
+    fn test_keyword_tokens() {
+        let mut lexer = Lexer::new("fn return true false null value");
+        let types: Vec<TokenType> = lexer.tokenize().iter().map(|t| t.token_type).collect();
+
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Fun,
+                TokenType::Return,
+                TokenType::True,
+                TokenType::False,
+                TokenType::Nil,
+                TokenType::Identifier,
+                TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unexpected_characters_become_error_tokens() {
+        let mut lexer = Lexer::new("var x = 1 $ 2;\nvar y = é;");
+        let errors: Vec<_> = lexer
+            .tokenize()
+            .into_iter()
+            .filter(|t| t.token_type == TokenType::Error)
+            .collect();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].lexeme, "$");
+        assert_eq!((errors[0].line, errors[0].column), (1, 11));
+        assert_eq!(errors[1].lexeme, "é");
+        assert_eq!((errors[1].line, errors[1].column), (2, 9));
+    }
+
+    #[test]
+    fn test_parser_pulls_tokens_from_lexer() {
+        let program = Parser::new(Lexer::new("var x = 1; var y = x * 2;"))
+            .parse()
+            .unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        let program = Parser::new(Vec::new()).parse().unwrap();
+        assert!(program.statements.is_empty());
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let source = format!("var x = {}1{};", "(".repeat(10_000), ")".repeat(10_000));
+        let error = Parser::new(Lexer::new(&source)).parse().unwrap_err();
+        assert_eq!(error.message, "Nesting too deep.");
+
+        let error = Parser::new(Lexer::new(&"{".repeat(10_000)))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Nesting too deep.");
+
+        // Ordinary nesting is well within the limit
+        let source = format!("var x = {}1{};", "[".repeat(50), "]".repeat(50));
+        assert!(Parser::new(Lexer::new(&source)).parse().is_ok());
+    }
+
+    #[test]
+    fn test_string_literal_escapes() {
+        let tokens = Lexer::new(r#"var s = "say \"hi\"\n";"#).tokenize();
+        let string = tokens
+            .iter()
+            .find(|t| t.token_type == TokenType::String)
+            .unwrap();
+
+        assert_eq!(string.lexeme, r#""say \"hi\"\n""#);
+        assert_eq!(string_value(&string.lexeme).unwrap(), "say \"hi\"\n");
+        assert_eq!(unescape(r"a\tb\\c\u{1F600}").unwrap(), "a\tb\\c\u{1F600}");
+    }
+
+    #[test]
+    fn test_invalid_string_escapes() {
+        assert_eq!(
+            unescape(r"bad \q").unwrap_err(),
+            "Invalid escape sequence '\\q'."
+        );
+        assert!(unescape(r"\u{110000}").is_err());
+        assert!(unescape(r"\u{}").is_err());
+        assert!(unescape(r"\u0041").is_err());
+
+        let tokens = Lexer::new("\"unterminated").tokenize();
+        assert_eq!(tokens[0].token_type, TokenType::Error);
+
+        let error = Parser::new(Lexer::new("var x = 1;\nvar s = \"abc;\nvar y = 2;"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Unterminated string");
+        assert_eq!((error.span.line, error.span.column), (2, 9));
+    }
+
+    #[test]
+    fn test_multiline_string_literals() {
+        let source = "var s = \"\"\"line one\n  line two\"\"\";\nvar t = 1;";
+        let tokens = Lexer::new(source).tokenize();
+        assert_eq!(tokens[3].token_type, TokenType::String);
+        assert_eq!(
+            string_value(&tokens[3].lexeme).unwrap(),
+            "line one\n  line two"
+        );
+        // The `var` on the following line is attributed to line 3
+        assert_eq!(tokens[5].line, 3);
+
+        let block = "\"\"\"\n    SELECT *\n      FROM t\n\n    \"\"\"";
+        assert_eq!(string_value(block).unwrap(), "SELECT *\n  FROM t\n\n");
+
+        let unterminated = Lexer::new("\"\"\"never closed").tokenize();
+        assert_eq!(unterminated[0].token_type, TokenType::Error);
+    }
+
+    #[test]
+    fn test_char_literals() {
+        assert_eq!(char_value("'a'"), Ok('a'));
+        assert_eq!(char_value(r"'\n'"), Ok('\n'));
+        assert_eq!(char_value(r"'\''"), Ok('\''));
+        assert!(char_value("''").is_err());
+        assert!(char_value("'ab'").is_err());
+
+        let program = Parser::new(Lexer::new(r"var c = '\t';")).parse().unwrap();
+        match initializer(&program.statements[0]) {
+            Expr::Char(node) => assert_eq!(node.value, '\t'),
+            other => panic!("Expected CharNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bitwise_operator_precedence() {
+        let tokens = Lexer::new("& | ^ << >> < <= > >=").tokenize();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::ShiftLeft,
+                TokenType::ShiftRight,
+                TokenType::Less,
+                TokenType::LessEqual,
+                TokenType::Greater,
+                TokenType::GreaterEqual,
+                TokenType::EOF,
+            ]
+        );
+
+        // Parses as 1 | (2 ^ (3 & (4 << (1 + 1))))
+        let program = Parser::new(Lexer::new("var x = 1 | 2 ^ 3 & 4 << 1 + 1;"))
+            .parse()
+            .unwrap();
+        let or = binary(initializer(&program.statements[0]));
+        assert!(matches!(or.operator, BinaryOperator::BitOr));
+        let xor = binary(&or.right);
+        assert!(matches!(xor.operator, BinaryOperator::BitXor));
+        let and = binary(&xor.right);
+        assert!(matches!(and.operator, BinaryOperator::BitAnd));
+        let shift = binary(&and.right);
+        assert!(matches!(shift.operator, BinaryOperator::ShiftLeft));
+        assert!(matches!(binary(&shift.right).operator, BinaryOperator::Add));
+    }
+
+    #[test]
+    fn test_type_annotation_tokens() {
+        let tokens = Lexer::new("fn f(a: int) -> float x - y").tokenize();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Fun,
+                TokenType::Identifier,
+                TokenType::LeftParen,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Identifier,
+                TokenType::RightParen,
+                TokenType::Arrow,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Minus,
+                TokenType::Identifier,
+                TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_conditional_expression_is_right_associative() {
+        let program = Parser::new(Lexer::new("var x = a ? 1 : b ? 2 : 3;"))
+            .parse()
+            .unwrap();
+        let outer = match initializer(&program.statements[0]) {
+            Expr::Conditional(node) => node,
+            other => panic!("Expected ConditionalExpressionNode, got {:?}", other),
+        };
+        assert!(matches!(*outer.else_expression, Expr::Conditional(_)));
+        assert!(!matches!(*outer.then_expression, Expr::Conditional(_)));
+    }
+
+    #[test]
+    fn test_range_expressions() {
+        let types: Vec<TokenType> = Lexer::new("0..10 1..=n 1.5")
+            .map(|t| t.token_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Number,
+                TokenType::DotDot,
+                TokenType::Number,
+                TokenType::Number,
+                TokenType::DotDotEqual,
+                TokenType::Identifier,
+                TokenType::Number,
+                TokenType::EOF,
+            ]
+        );
+
+        let program = Parser::new(Lexer::new("var r = 0..=n + 1;"))
+            .parse()
+            .unwrap();
+        let range = match initializer(&program.statements[0]) {
+            Expr::Range(node) => node,
+            other => panic!("Expected RangeNode, got {:?}", other),
+        };
+        assert!(range.inclusive);
+        assert!(matches!(binary(&range.end).operator, BinaryOperator::Add));
+    }
+
+    #[test]
+    fn test_lexer_from_reader_matches_str_lexer() {
+        let source = "var s = \"\"\"a\nb\"\"\";\nvar x = (42 + 5) * 2;\n";
+        let streamed: Vec<(TokenType, String, usize, usize)> =
+            Lexer::from_reader(std::io::Cursor::new(source.as_bytes().to_vec()))
+                .map(|t| (t.token_type, t.lexeme, t.line, t.column))
+                .collect();
+        let buffered: Vec<(TokenType, String, usize, usize)> = Lexer::new(source)
+            .map(|t| (t.token_type, t.lexeme, t.line, t.column))
+            .collect();
+        assert_eq!(streamed, buffered);
+    }
+
+    #[test]
+    fn test_lexer_from_reader_reports_invalid_utf8() {
+        let bytes = b"var x = 1;\nvar \xff = 2;\n".to_vec();
+        let tokens: Vec<_> = Lexer::from_reader(std::io::Cursor::new(bytes)).collect();
+
+        assert_eq!(tokens[tokens.len() - 2].token_type, TokenType::Error);
+        assert_eq!(tokens[tokens.len() - 1].token_type, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let source = "fn scale(a: float, b) -> float { var c = a * b; var d = c; }";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        let function = match &program.statements[0] {
+            Stmt::FunctionDeclaration(node) => node,
+            other => panic!("Expected FunctionDeclarationNode, got {:?}", other),
+        };
+        assert_eq!(function.name, "scale");
+        assert_eq!(function.parameters.len(), 2);
+        assert_eq!(function.parameters[0].name, "a");
+        assert!(matches!(
+            function.parameters[0].data_type,
+            Some(DataType::Float)
+        ));
+        assert!(function.parameters[1].data_type.is_none());
+        assert!(matches!(function.return_type, Some(DataType::Float)));
+        assert_eq!(function.body.len(), 2);
+    }
+
+    #[test]
+    fn test_call_expressions() {
+        let program = Parser::new(Lexer::new("var y = 2 * scale(x, 1 + 2); reset();"))
+            .parse()
+            .unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        let product = binary(initializer(&program.statements[0]));
+        let call = match &*product.right {
+            Expr::Call(node) => node,
+            other => panic!("Expected CallNode, got {:?}", other),
+        };
+        assert!(matches!(&*call.callee, Expr::Variable(callee) if callee.name == "scale"));
+        assert_eq!(call.arguments.len(), 2);
+
+        match &program.statements[1] {
+            Stmt::Expression(Expr::Call(call)) => assert!(call.arguments.is_empty()),
+            other => panic!("Expected CallNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_statements() {
+        let source = "while (n) { var a = 1; { var b = 2; } step(); }";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        let while_node = match &program.statements[0] {
+            Stmt::While(node) => node,
+            other => panic!("Expected WhileNode, got {:?}", other),
+        };
+        let body = match &*while_node.body {
+            Stmt::Block(node) => node,
+            other => panic!("Expected BlockNode body, got {:?}", other),
+        };
+        assert_eq!(body.statements.len(), 3);
+        assert!(matches!(body.statements[1], Stmt::Block(_)));
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let source = "fn f(a) { if (a) return; return a * 2; }";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let function = match &program.statements[0] {
+            Stmt::FunctionDeclaration(node) => node,
+            other => panic!("Expected FunctionDeclarationNode, got {:?}", other),
+        };
+        assert_eq!(function.body.len(), 2);
+
+        match &function.body[0] {
+            Stmt::If(if_node) => {
+                assert!(matches!(&*if_node.then_branch, Stmt::Return(bare) if bare.value.is_none()))
+            }
+            other => panic!("Expected IfNode, got {:?}", other),
+        }
+        assert!(matches!(
+            &function.body[1],
+            Stmt::Return(with_value) if with_value.value.is_some()
+        ));
+    }
+
+    #[test]
+    fn test_boolean_literals() {
+        let program = Parser::new(Lexer::new("var done = false; while (true) {}"))
+            .parse()
+            .unwrap();
+        let literal = match initializer(&program.statements[0]) {
+            Expr::Boolean(node) => node,
+            other => panic!("Expected BooleanNode, got {:?}", other),
+        };
+        assert!(!literal.value);
+        assert!(matches!(literal.data_type, DataType::Boolean));
+
+        match &program.statements[1] {
+            Stmt::While(while_node) => {
+                assert!(matches!(&while_node.condition, Expr::Boolean(b) if b.value))
+            }
+            other => panic!("Expected WhileNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_literal_node() {
+        let program = Parser::new(Lexer::new(r#"greet("hello\tworld");"#))
+            .parse()
+            .unwrap();
+        let call = match &program.statements[0] {
+            Stmt::Expression(Expr::Call(call)) => call,
+            other => panic!("Expected CallNode, got {:?}", other),
+        };
+        let literal = match &call.arguments[0] {
+            Expr::String(node) => node,
+            other => panic!("Expected StringNode, got {:?}", other),
+        };
+        assert_eq!(literal.value, "hello\tworld");
+        assert!(matches!(literal.data_type, DataType::String));
+
+        let error = Parser::new(Lexer::new(r#"var s = "bad \q";"#))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Invalid escape sequence '\\q'.");
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let program = Parser::new(Lexer::new("var x = -5; var y = !done; var z = 2 * --a;"))
+            .parse()
+            .unwrap();
+        assert_eq!(program.statements.len(), 3);
+
+        assert!(matches!(
+            initializer(&program.statements[0]),
+            Expr::Unary(_)
+        ));
+        assert!(matches!(
+            initializer(&program.statements[1]),
+            Expr::Unary(_)
+        ));
+
+        let product = binary(initializer(&program.statements[2]));
+        let outer = match &*product.right {
+            Expr::Unary(node) => node,
+            other => panic!("Expected UnaryOperationNode, got {:?}", other),
+        };
+        assert!(matches!(outer.operator, UnaryOperator::Negate));
+        assert!(matches!(*outer.operand, Expr::Unary(_)));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let source = "for (var i = 0; i; step(i)) { work(i); } for x in 0..10 work(x);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        let for_in = match &program.statements[1] {
+            Stmt::ForIn(node) => node,
+            other => panic!("Expected ForInNode, got {:?}", other),
+        };
+        assert_eq!(for_in.variable, "x");
+        assert!(matches!(for_in.iterable, Expr::Range(_)));
+
+        let for_node = match &program.statements[0] {
+            Stmt::For(node) => node,
+            other => panic!("Expected ForNode, got {:?}", other),
+        };
+        assert!(for_node.initializer.is_some());
+        assert!(for_node.condition.is_some());
+        assert!(for_node.increment.is_some());
+    }
+
+    #[test]
+    fn test_for_loop_desugars_to_while() {
+        let for_node = ForNode::new(
+            None,
+            None,
+            Some(Expr::Variable(VariableNode::new("i".to_string()))),
+            Stmt::Block(BlockNode::new(Vec::new())),
+        );
+        let lowered = for_node.into_while();
+        assert_eq!(lowered.statements.len(), 1);
+
+        let while_node = match &lowered.statements[0] {
+            Stmt::While(node) => node,
+            other => panic!("Expected WhileNode, got {:?}", other),
+        };
+        assert!(matches!(&while_node.condition, Expr::Boolean(b) if b.value));
+        assert!(matches!(&*while_node.body, Stmt::Block(body) if body.statements.len() == 2));
+    }
+
+    #[test]
+    fn test_struct_declaration_and_member_access() {
+        let source = "struct Point { x: float, y: float, origin: Point } var d = p.origin.x * 2;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        let declaration = match &program.statements[0] {
+            Stmt::StructDeclaration(node) => node,
+            other => panic!("Expected StructDeclarationNode, got {:?}", other),
+        };
+        assert_eq!(declaration.name, "Point");
+        let names: Vec<&str> = declaration.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "y", "origin"]);
+        assert!(matches!(declaration.fields[0].data_type, DataType::Float));
+        assert!(
+            matches!(&declaration.fields[2].data_type, DataType::Struct(name) if name == "Point")
+        );
+
+        let product = binary(initializer(&program.statements[1]));
+        let access = match &*product.left {
+            Expr::MemberAccess(node) => node,
+            other => panic!("Expected MemberAccessNode, got {:?}", other),
+        };
+        assert_eq!(access.member, "x");
+        assert!(matches!(&*access.object, Expr::MemberAccess(inner) if inner.member == "origin"));
+    }
+
+    #[test]
+    fn test_enum_declaration_and_variants() {
+        let source = "enum Color { Red, Green, Blue, } fn paint(c: Color) {} var c = Color::Green;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 3);
+
+        let declaration = match &program.statements[0] {
+            Stmt::EnumDeclaration(node) => node,
+            other => panic!("Expected EnumDeclarationNode, got {:?}", other),
+        };
+        assert_eq!(declaration.name, "Color");
+        assert_eq!(declaration.variants, vec!["Red", "Green", "Blue"]);
+
+        let function = match &program.statements[1] {
+            Stmt::FunctionDeclaration(node) => node,
+            other => panic!("Expected FunctionDeclarationNode, got {:?}", other),
+        };
+        assert!(matches!(
+            &function.parameters[0].data_type,
+            Some(DataType::Enum(name)) if name == "Color"
+        ));
+
+        let variant = match initializer(&program.statements[2]) {
+            Expr::EnumVariant(node) => node,
+            other => panic!("Expected EnumVariantNode, got {:?}", other),
+        };
+        assert_eq!(variant.enum_name, "Color");
+        assert_eq!(variant.variant, "Green");
+    }
+
+    #[test]
+    fn test_match_statement() {
+        let source = r#"
+            match code {
+                0 => report("ok"),
+                -1 => { retry(); log(code); }
+                Status::Fatal => abort(),
+                _ => report("unknown"),
+            }
+        "#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 1);
+
+        let match_node = match &program.statements[0] {
+            Stmt::Match(node) => node,
+            other => panic!("Expected MatchNode, got {:?}", other),
+        };
+        assert_eq!(match_node.arms.len(), 4);
+        assert!(matches!(match_node.arms[0].pattern, Pattern::Literal(_)));
+        assert!(matches!(*match_node.arms[1].body, Stmt::Block(_)));
+        assert!(matches!(match_node.arms[3].pattern, Pattern::Wildcard));
+    }
+
+    #[test]
+    fn test_comparison_and_logical_precedence() {
+        // Parses as (((a + 1) < b) && c) || ((d == e) & f)
+        let program = Parser::new(Lexer::new("var t = a + 1 < b && c || d == e & f;"))
+            .parse()
+            .unwrap();
+
+        let or = binary(initializer(&program.statements[0]));
+        assert!(matches!(or.operator, BinaryOperator::Or));
+
+        let and = binary(&or.left);
+        assert!(matches!(and.operator, BinaryOperator::And));
+        let less = binary(&and.left);
+        assert!(matches!(less.operator, BinaryOperator::Less));
+        assert!(matches!(binary(&less.left).operator, BinaryOperator::Add));
+
+        let bit_and = binary(&or.right);
+        assert!(matches!(bit_and.operator, BinaryOperator::BitAnd));
+        assert!(matches!(
+            binary(&bit_and.left).operator,
+            BinaryOperator::Equal
+        ));
+    }
+
+    #[test]
+    fn test_operator_associativity_and_binding() {
+        // Parses as ((a - b) - c) + (-(f(x).y) * 2)
+        let program = Parser::new(Lexer::new("var v = a - b - c + -f(x).y * 2;"))
+            .parse()
+            .unwrap();
+
+        let sum = binary(initializer(&program.statements[0]));
+        assert_eq!(sum.operator, BinaryOperator::Add);
+        let difference = binary(&sum.left);
+        assert_eq!(difference.operator, BinaryOperator::Subtract);
+        assert_eq!(binary(&difference.left).operator, BinaryOperator::Subtract);
+
+        let product = binary(&sum.right);
+        match &*product.left {
+            Expr::Unary(negation) => assert!(matches!(*negation.operand, Expr::MemberAccess(_))),
+            other => panic!("Expected UnaryOperationNode, got {:?}", other),
+        }
+
+        let chained = Parser::new(Lexer::new("var r = 0..1..2;")).parse();
+        assert_eq!(chained.unwrap_err().message, "Ranges cannot be chained");
+    }
+
+    #[test]
+    fn test_visitors_reach_nested_nodes() {
+        struct VariableCounter(usize);
+        impl Visitor for VariableCounter {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let Expr::Variable(_) = expr {
+                    self.0 += 1;
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        struct Renamer;
+        impl MutVisitor for Renamer {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                if let Expr::Variable(variable) = expr {
+                    variable.name = variable.name.to_uppercase();
+                }
+                walk_expr_mut(self, expr);
+            }
+        }
+
+        let source = "fn f(a) { for x in a..b { match x { y => g(x, a.c) } } return a; }";
+        let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+
+        let mut counter = VariableCounter(0);
+        counter.visit_program(&program);
+        assert_eq!(counter.0, 8);
+
+        Renamer.visit_program_mut(&mut program);
+        let function = match &program.statements[0] {
+            Stmt::FunctionDeclaration(node) => node,
+            other => panic!("Expected FunctionDeclarationNode, got {:?}", other),
+        };
+        assert!(matches!(
+            &function.body[1],
+            Stmt::Return(ret) if matches!(&ret.value, Some(Expr::Variable(v)) if v.name == "A")
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors_carry_expected_found_and_span() {
+        let error = Parser::new(Lexer::new("var x = 1;\nvar y = (2 + 3;"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::RightParen));
+        assert_eq!(error.found.token_type, TokenType::Semicolon);
+        assert_eq!(
+            error.span,
+            Span {
+                line: 2,
+                column: 15,
+                length: 1
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Parse error: Expect ')' after expression. at line 2, column 15"
+        );
+
+        let error = Parser::new(Lexer::new("var z = * 2;")).parse().unwrap_err();
+        assert_eq!(error.expected, None);
+        assert_eq!(error.found.lexeme, "*");
+
+        let error = Parser::new(Lexer::new("var z = 1 $ 2;"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Unexpected character '$'");
+        assert_eq!(error.found.token_type, TokenType::Error);
+    }
+
+    #[test]
+    fn test_every_lexical_error_is_reported() {
+        let error = Parser::new(Lexer::new("var x = 1 $ 2;\nvar y = 3 # 4;"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Unexpected character '$'");
+        assert_eq!((error.span.line, error.span.column), (1, 11));
+        assert_eq!(error.others.len(), 1);
+        assert_eq!(error.others[0].message, "Unexpected character '#'");
+        assert_eq!(
+            (error.others[0].span.line, error.others[0].span.column),
+            (2, 11)
+        );
+        assert_eq!(
+            error.to_string(),
+            "Parse error: Unexpected character '$' at line 1, column 11\n\
+             Parse error: Unexpected character '#' at line 2, column 11"
+        );
+
+        // A syntax error ahead of the first lexical error is kept, and
+        // lexical errors after it are still found
+        let error = Parser::new(Lexer::new("var = 1;\nvar y = \"open"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.message, "Expect variable name.");
+        assert_eq!(error.others.len(), 1);
+        assert_eq!(error.others[0].message, "Unterminated string");
+    }
+
+    #[test]
+    fn test_tensor_literals() {
+        let program = Parser::new(Lexer::new("var m = [[1, 2, 3], [4, -5, 6.5],];"))
+            .parse()
+            .unwrap();
+        let literal = match initializer(&program.statements[0]) {
+            Expr::TensorLiteral(node) => node,
+            other => panic!("Expected TensorLiteralNode, got {:?}", other),
+        };
+        assert_eq!(literal.shape, vec![2, 3]);
+        assert_eq!(literal.elements.len(), 6);
+
+        let tensor = literal.to_tensor().expect("Expected constant tensor");
+        assert_eq!(tensor.get(&[1, 1]), Some(-5.0));
+        assert_eq!(tensor.get(&[1, 2]), Some(6.5));
+
+        let program = Parser::new(Lexer::new("var v = [x, 2]; var e = [];"))
+            .parse()
+            .unwrap();
+        match initializer(&program.statements[0]) {
+            Expr::TensorLiteral(node) => {
+                assert_eq!(node.shape, vec![2]);
+                assert!(node.to_tensor().is_none());
+            }
+            other => panic!("Expected TensorLiteralNode, got {:?}", other),
+        }
+        assert!(matches!(
+            initializer(&program.statements[1]),
+            Expr::TensorLiteral(node) if node.shape == vec![0]
+        ));
+
+        let error = Parser::new(Lexer::new("var r = [[1, 2], [3]];"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            error.message,
+            "Tensor rows must all have the same shape: expected [2], found [1]"
+        );
+        assert_eq!((error.span.line, error.span.column), (1, 18));
+    }
+
+    #[test]
+    fn test_variable_type_annotations() {
+        let source = "enum Mode { On, Off } var x: float = 1.5; var m: Mode; var n = 1;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let declared = |index: usize| match &program.statements[index] {
+            Stmt::VarDeclaration(node) => node.clone(),
+            other => panic!("Expected VarDeclaration, got {:?}", other),
+        };
+
+        let x = declared(1);
+        assert_eq!(x.data_type, Some(DataType::Float));
+        assert!(x.initializer.is_some());
+        let m = declared(2);
+        assert_eq!(m.data_type, Some(DataType::Enum("Mode".to_string())));
+        assert!(m.initializer.is_none());
+        assert_eq!(declared(3).data_type, None);
+
+        let error = Parser::new(Lexer::new("var y: = 2;")).parse().unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::Identifier));
+    }
+
+    #[test]
+    fn test_const_declarations() {
+        let program = Parser::new(Lexer::new("const PI: float = 3.14159; var r = PI * 2;"))
+            .parse()
+            .unwrap();
+        match &program.statements[0] {
+            Stmt::ConstDeclaration(node) => {
+                assert_eq!(node.name, "PI");
+                assert_eq!(node.data_type, Some(DataType::Float));
+                assert!(matches!(&node.value, Expr::Number(n) if n.value == "3.14159"));
+            }
+            other => panic!("Expected ConstDeclaration, got {:?}", other),
+        }
+        assert!(TypeChecker::check_program(&program).is_ok());
+
+        let error = Parser::new(Lexer::new("const E;")).parse().unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::Assign));
+
+        let program = Parser::new(Lexer::new("const N = 1; { var N = 2; }"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].message.clone()),
+            Err("Cannot redeclare constant 'N'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lambda_expressions() {
+        let source =
+            "var add = |a, b: int| a + b; var f = map(xs, fn(x) -> int { return x * 2; }); \
+                      var k = || 1 | 2; fn(x) { work(x); }(3);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 4);
+
+        let add = match initializer(&program.statements[0]) {
+            Expr::Lambda(node) => node,
+            other => panic!("Expected LambdaNode, got {:?}", other),
+        };
+        let names: Vec<&str> = add.parameters.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(add.parameters[1].data_type, Some(DataType::Integer));
+        assert!(
+            matches!(&add.body, LambdaBody::Expression(body) if matches!(**body, Expr::Binary(_)))
+        );
+
+        let call = match initializer(&program.statements[1]) {
+            Expr::Call(node) => node,
+            other => panic!("Expected CallNode, got {:?}", other),
+        };
+        match &call.arguments[1] {
+            Expr::Lambda(node) => {
+                assert_eq!(node.return_type, Some(DataType::Integer));
+                assert!(matches!(&node.body, LambdaBody::Block(body) if body.len() == 1));
+            }
+            other => panic!("Expected LambdaNode, got {:?}", other),
+        }
+
+        // The closure body extends as far as possible: `|| (1 | 2)`
+        match initializer(&program.statements[2]) {
+            Expr::Lambda(node) => {
+                assert!(node.parameters.is_empty());
+                assert!(matches!(&node.body, LambdaBody::Expression(body)
+                    if binary(body).operator == BinaryOperator::BitOr));
+            }
+            other => panic!("Expected LambdaNode, got {:?}", other),
+        }
+
+        assert!(matches!(
+            &program.statements[3],
+            Stmt::Expression(Expr::Call(call)) if matches!(*call.callee, Expr::Lambda(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_statements() {
+        let source = r#"use math; use math::linalg; import "lib/utils.put";"#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let sources: Vec<ImportSource> = program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Stmt::Import(node) => node.source.clone(),
+                other => panic!("Expected ImportNode, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ImportSource::Basket(vec!["math".to_string()]),
+                ImportSource::Basket(vec!["math".to_string(), "linalg".to_string()]),
+                ImportSource::File("lib/utils.put".to_string()),
+            ]
+        );
+
+        let error = Parser::new(Lexer::new("import utils;"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::String));
+
+        let config = ProjectConfig {
+            project_info: HashMap::new(),
+            dependencies: HashMap::from([("MathBasket".to_string(), "1.0.0".to_string())]),
+            build_settings: HashMap::new(),
+            runtime_settings: HashMap::new(),
+            custom_settings: HashMap::new(),
+        };
+        assert_eq!(config.basket_version("math"), Some(&"1.0.0".to_string()));
+        assert_eq!(
+            config.basket_version("MathBasket"),
+            Some(&"1.0.0".to_string())
+        );
+        assert_eq!(config.basket_version("stats"), None);
+    }
+
+    #[test]
+    fn test_print_statements() {
+        let program = Parser::new(Lexer::new(r#"print("x =", x + 1); print();"#))
+            .parse()
+            .unwrap();
+        match &program.statements[0] {
+            Stmt::Expression(Expr::Call(node)) => {
+                assert!(matches!(&*node.callee, Expr::Variable(v) if v.name == "print"));
+                assert_eq!(node.arguments.len(), 2);
+                assert!(matches!(node.arguments[0], Expr::String(_)));
+                assert!(matches!(node.arguments[1], Expr::Binary(_)));
+            }
+            other => panic!("Expected CallNode, got {:?}", other),
+        }
+        assert!(matches!(
+            &program.statements[1],
+            Stmt::Expression(Expr::Call(node)) if node.arguments.is_empty()
+        ));
+
+        // `print` is an ordinary builtin, so it can be passed around and
+        // called wherever an expression can appear
+        let (_, output, result) = run(r#"
+            print(type_of(print));
+            var say = print;
+            say("a", 1);
+            match 1 { 1 => print("one"), _ => print("other") }
+        "#);
+        result.unwrap();
+        assert_eq!(output, "function\na 1\none\n");
+
+        // Files saved while `print` was a statement load as calls
+        let legacy = ProgramNode::from_json(
+            r#"{"type":"Program","statements":[{"type":"Print","arguments":[]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy, Parser::new(Lexer::new("print();")).parse().unwrap());
+    }
+
+    #[test]
+    fn test_plain_assignment() {
+        let source = "x = x + 1; a = b = 2; p.x = 0; for (i = 0; i < n; i = i + 1) {}";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 4);
+
+        let assignment = |index: usize| match &program.statements[index] {
+            Stmt::Expression(Expr::Assignment(node)) => node.clone(),
+            other => panic!("Expected AssignmentNode, got {:?}", other),
+        };
+        let increment = assignment(0);
+        assert!(matches!(&*increment.target, Expr::Variable(v) if v.name == "x"));
+        assert_eq!(binary(&increment.value).operator, BinaryOperator::Add);
+
+        // Right-associative: a = (b = 2)
+        let chained = assignment(1);
+        assert!(matches!(*chained.value, Expr::Assignment(_)));
+        assert!(matches!(*assignment(2).target, Expr::MemberAccess(_)));
+        assert!(matches!(
+            &program.statements[3],
+            Stmt::For(node) if matches!(node.increment, Some(Expr::Assignment(_)))
+        ));
+
+        let error = Parser::new(Lexer::new("x + 1 = 2;")).parse().unwrap_err();
+        assert_eq!(error.message, "Invalid assignment target.");
+        assert_eq!(error.found.token_type, TokenType::Assign);
+
+        let program = Parser::new(Lexer::new("const LIMIT = 10; LIMIT = 11;"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].message.clone()),
+            Err("Cannot assign to constant 'LIMIT'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_conditional_precedence_with_assignment_and_logic() {
+        // x = ((a || b) ? (c && d) : e)
+        let program = Parser::new(Lexer::new("x = a || b ? c && d : e;"))
+            .parse()
+            .unwrap();
+        let assignment = match &program.statements[0] {
+            Stmt::Expression(Expr::Assignment(node)) => node,
+            other => panic!("Expected AssignmentNode, got {:?}", other),
+        };
+        let conditional = match &*assignment.value {
+            Expr::Conditional(node) => node,
+            other => panic!("Expected ConditionalExpressionNode, got {:?}", other),
+        };
+        assert_eq!(binary(&conditional.condition).operator, BinaryOperator::Or);
+        assert_eq!(
+            binary(&conditional.then_expression).operator,
+            BinaryOperator::And
+        );
+
+        // ok ? (x = 1) : (y = 2)
+        let program = Parser::new(Lexer::new("ok ? x = 1 : y = 2;"))
+            .parse()
+            .unwrap();
+        match &program.statements[0] {
+            Stmt::Expression(Expr::Conditional(node)) => {
+                assert!(matches!(*node.then_expression, Expr::Assignment(_)));
+                assert!(matches!(*node.else_expression, Expr::Assignment(_)));
+            }
+            other => panic!("Expected ConditionalExpressionNode, got {:?}", other),
+        }
+
+        // a ? b : (c ? d : (e = f))
+        let program = Parser::new(Lexer::new("a ? b : c ? d : e = f;"))
+            .parse()
+            .unwrap();
+        match &program.statements[0] {
+            Stmt::Expression(Expr::Conditional(outer)) => match &*outer.else_expression {
+                Expr::Conditional(inner) => {
+                    assert!(matches!(*inner.else_expression, Expr::Assignment(_)))
+                }
+                other => panic!("Expected nested conditional, got {:?}", other),
+            },
+            other => panic!("Expected ConditionalExpressionNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_do_while_loops() {
+        let program = Parser::new(Lexer::new(
+            "do { n = n - 1; } while (n > 0); do step(); while (busy());",
+        ))
+        .parse()
+        .unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        match &program.statements[0] {
+            Stmt::DoWhile(node) => {
+                assert!(matches!(&*node.body, Stmt::Block(block) if block.statements.len() == 1));
+                assert_eq!(binary(&node.condition).operator, BinaryOperator::Greater);
+            }
+            other => panic!("Expected DoWhileNode, got {:?}", other),
+        }
+        assert!(matches!(
+            &program.statements[1],
+            Stmt::DoWhile(node) if matches!(*node.body, Stmt::Expression(Expr::Call(_)))
+        ));
+
+        let error = Parser::new(Lexer::new("do {} while (x)"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::Semicolon));
+    }
+
+    #[test]
+    fn test_range_step() {
+        let source = "for i in 0..10 step 2 work(i); var r = n..=0 step -1; var step = 3;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 3);
+
+        match &program.statements[0] {
+            Stmt::ForIn(node) => match &node.iterable {
+                Expr::Range(range) => {
+                    assert!(!range.inclusive);
+                    assert!(
+                        matches!(range.step.as_deref(), Some(Expr::Number(n)) if n.value == "2")
+                    );
+                }
+                other => panic!("Expected RangeNode, got {:?}", other),
+            },
+            other => panic!("Expected ForInNode, got {:?}", other),
+        }
+        match initializer(&program.statements[1]) {
+            Expr::Range(range) => {
+                assert!(range.inclusive);
+                assert!(matches!(range.step.as_deref(), Some(Expr::Unary(_))));
+            }
+            other => panic!("Expected RangeNode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let source = "try { var q = a / b; print(q); } catch (e) { print(e); } try {} catch {}";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        match &program.statements[0] {
+            Stmt::Try(node) => {
+                assert_eq!(node.body.statements.len(), 2);
+                assert_eq!(node.error_name.as_deref(), Some("e"));
+                assert!(matches!(
+                    node.handler.statements[0],
+                    Stmt::Expression(Expr::Call(_))
+                ));
+            }
+            other => panic!("Expected TryNode, got {:?}", other),
+        }
+        assert!(matches!(&program.statements[1], Stmt::Try(node) if node.error_name.is_none()));
+
+        let error = Parser::new(Lexer::new("try { risky(); }"))
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.expected, Some(TokenType::Catch));
+    }
+
+    #[test]
+    fn test_null_and_optional_access() {
+        let source =
+            "var p: Point? = null; var x = p?.origin.x; var y = rows?.[0][1]; rows[i] = 0;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements.len(), 4);
+
+        match &program.statements[0] {
+            Stmt::VarDeclaration(node) => {
+                assert_eq!(
+                    node.data_type,
+                    Some(DataType::Optional(Box::new(DataType::Struct(
+                        "Point".to_string()
+                    ))))
+                );
+                assert!(matches!(node.initializer, Some(Expr::Null(_))));
+            }
+            other => panic!("Expected VarDeclaration, got {:?}", other),
+        }
+
+        // (p?.origin).x
+        match initializer(&program.statements[1]) {
+            Expr::MemberAccess(outer) => {
+                assert!(!outer.optional);
+                assert!(matches!(&*outer.object, Expr::MemberAccess(inner) if inner.optional));
+            }
+            other => panic!("Expected MemberAccessNode, got {:?}", other),
+        }
+        // (rows?.[0])[1]
+        match initializer(&program.statements[2]) {
+            Expr::Index(outer) => {
+                assert!(!outer.optional);
+                assert!(matches!(&*outer.object, Expr::Index(inner) if inner.optional));
+            }
+            other => panic!("Expected IndexNode, got {:?}", other),
+        }
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].to_string()),
+            Err("Type error [E001]: Undeclared variable 'rows' at line 1, column 52".to_string())
+        );
+
+        let error = Parser::new(Lexer::new("p?.x = 1;")).parse().unwrap_err();
+        assert_eq!(error.message, "Invalid assignment target.");
+
+        let program = Parser::new(Lexer::new("var n: int = null;"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].message.clone()),
+            Err("Cannot initialize 'n' of non-optional type Integer with null".to_string())
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let source = r#"use math::linalg; import "lib/utils.put";
+            struct Point { x: float, y: float? } enum Color { Red, Green }
+            const LIMIT: int = 10;
+            fn scale(p: Point, k) -> float { return p.x * k; }
+            var t = [[1, -2], [3, 4]]; var c = Color::Red; var s = "tab\there";
+            var f = |a, b: int| a + b; var g = fn(x) -> int { return x * 2; };
+            for (var i = 0; i < LIMIT; i = i + 1) { if (i == 3) print(i, 'q'); else {} }
+            for x in 0..=10 step 2 work(x); while (!done) tick(); do { n = n - 1; } while (n > 0);
+            match c { Color::Red => paint("r"), _ => {} }
+            try { risky(p?.x, rows?.[0]); } catch (e) { print(e); }
+            var n: int? = null; var m = ok ? 1 : (2 << 1);"#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+
+        let json = program.to_json();
+        assert!(json.starts_with(r#"{"type":"Program","statements":[{"type":"Import""#));
+        assert_eq!(ProgramNode::from_json(&json), Ok(program.clone()));
+
+        // Whitespace and explicit or omitted nulls are accepted on input
+        let handwritten = r#"{ "type": "Program", "statements": [
+            { "type": "VarDeclaration", "name": "x", "data_type": { "Optional": "Integer" } },
+            { "type": "Return", "value": null } ] }"#;
+        let loaded = ProgramNode::from_json(handwritten).unwrap();
+        assert_eq!(
+            loaded,
+            Parser::new(Lexer::new("var x: int?; return;"))
+                .parse()
+                .unwrap()
+        );
+
+        let error = ProgramNode::from_json(r#"{"type":"Program","statements":[{"type":"Loop"}]}"#)
+            .unwrap_err();
+        assert_eq!(error, "Unknown statement type 'Loop'");
+        let error = ProgramNode::from_json(
+            r#"{"type":"Program","statements":[{"type":"Expression","expression":{"type":"Variable","data_type":null}}]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(error, "Missing field 'name' in Variable");
+        assert!(ProgramNode::from_json(r#"{"type":"Program","statements":["#).is_err());
+    }
+
+    #[test]
+    fn test_putc_round_trip() {
+        let source = r#"use math; struct P { x: float } enum Color { Red }
+            fn scale(p: P, k) -> float { return p.x * k; }
+            var t = [[1, -2], [3, 4]]; var s = "naïve\ttext"; var c = Color::Red;
+            for x in 0..=10 step 2 work(x); match c { Color::Red => paint("r"), _ => {} }
+            var f = |a, b: int| a + b; var n: int? = null; var m = ok ? 1.5 : (2 << 1);"#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let bytes = program.to_putc();
+        assert_eq!(&bytes[..4], b"PUTC");
+        assert_eq!(ProgramNode::from_putc(&bytes), Ok(program));
+
+        // A loaded program runs like the one it was compiled from
+        let mut program = Parser::new(Lexer::new("fn sq(x) { return x * x; } print(sq(12));"))
+            .parse()
+            .unwrap();
+        desugar(&mut program);
+        let loaded = ProgramNode::from_putc(&program.to_putc()).unwrap();
+        let output = SharedOutput::default();
+        Interpreter::with_output(output.clone())
+            .interpret(&loaded)
+            .unwrap();
+        assert_eq!(output.text(), "144\n");
+
+        let mut corrupt = program.to_putc();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert_eq!(
+            ProgramNode::from_putc(&corrupt),
+            Err("Corrupt .putc file: checksum mismatch".to_string())
+        );
+        let mut future = program.to_putc();
+        future[4] = 9;
+        assert_eq!(
+            ProgramNode::from_putc(&future),
+            Err("Unsupported .putc format version 9 (expected 1)".to_string())
+        );
+        let truncated = program.to_putc();
+        assert!(ProgramNode::from_putc(&truncated[..truncated.len() - 3])
+            .unwrap_err()
+            .starts_with("Corrupt .putc file: expected"));
+        assert_eq!(
+            ProgramNode::from_putc(b"var x = 1;"),
+            Err("Not a .putc file".to_string())
+        );
+    }
+
+    #[test]
+    fn test_formatter_output() {
+        let source = "use math; struct P{x:float,y:int?} fn f(a,b:int)->int{if(a>b)return a;else{return b;}}\
+                      var t=[[1,-2],[3,4]]; for x in 0..=n step 2 { print(x,'\\n'); } \
+                      match c{Color::Red=>paint(\"r\"),_=>{}} try{risky();}catch(e){} var g=|x|x*2;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let formatted = format_program(&program);
+        assert_eq!(
+            formatted,
+            "use math;\n\
+             \n\
+             struct P {\n    x: float,\n    y: int?,\n}\n\
+             \n\
+             fn f(a, b: int) -> int {\n    if (a > b) return a;\n    else {\n        return b;\n    }\n}\n\
+             \n\
+             var t = [[1, -2], [3, 4]];\n\
+             for x in 0..=n step 2 {\n    print(x, '\\n');\n}\n\
+             match c {\n    Color::Red => paint(\"r\"),\n    _ => {}\n}\n\
+             try {\n    risky();\n} catch (e) {}\n\
+             var g = |x| x * 2;\n"
+        );
+
+        // Formatting is stable and preserves the tree
+        let reparsed = Parser::new(Lexer::new(&formatted)).parse().unwrap();
+        assert_eq!(reparsed, program);
+        assert_eq!(format_program(&reparsed), formatted);
+
+        // Long argument lists are wrapped one per line
+        let long_call = format!("report({});", ["some_long_argument_name"; 5].join(", "));
+        let program = Parser::new(Lexer::new(&long_call)).parse().unwrap();
+        assert_eq!(
+            format_program(&program),
+            format!(
+                "report(\n{}\n);\n",
+                ["    some_long_argument_name"; 5].join(",\n")
+            )
+        );
+    }
+
+    #[test]
+    fn test_formatter_parenthesizes_built_trees() {
+        // (a + b) * -(c - d), built without ParenthesisNodes
+        let variable = |name: &str| Expr::Variable(VariableNode::new(name.to_string()));
+        let sum = Expr::Binary(BinaryOperationNode::new(
+            variable("a"),
+            BinaryOperator::Add,
+            variable("b"),
+        ));
+        let difference = Expr::Binary(BinaryOperationNode::new(
+            variable("c"),
+            BinaryOperator::Subtract,
+            variable("d"),
+        ));
+        let negated = Expr::Unary(UnaryOperationNode::new(UnaryOperator::Negate, difference));
+        let product = Expr::Binary(BinaryOperationNode::new(
+            sum,
+            BinaryOperator::Multiply,
+            negated,
+        ));
+        assert_eq!(format_expr(&product), "(a + b) * -(c - d)");
+
+        // a - (b - c) keeps its grouping; (a - b) - c needs none
+        let right_nested = Expr::Binary(BinaryOperationNode::new(
+            variable("a"),
+            BinaryOperator::Subtract,
+            Expr::Binary(BinaryOperationNode::new(
+                variable("b"),
+                BinaryOperator::Subtract,
+                variable("c"),
+            )),
+        ));
+        assert_eq!(format_expr(&right_nested), "a - (b - c)");
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let fold = |source: &str| {
+            let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+            fold_constants(&mut program);
+            format_program(&program)
+        };
+
+        assert_eq!(fold("var x = (42 + 5) * 2;"), "var x = 94;\n");
+        assert_eq!(fold("var x = (42 + 5) * 2 - 3 / 1.5;"), "var x = 92.0;\n");
+        assert_eq!(fold("var x = 2 - 5 * 3;"), "var x = -13;\n");
+        assert_eq!(fold("var x = 1 << 4 | 1;"), "var x = 17;\n");
+        assert_eq!(fold("var b = 1 + 1 == 2 && !false;"), "var b = true;\n");
+        assert_eq!(fold("var s = \"ab\" + \"c\";"), "var s = \"abc\";\n");
+        // Only the constant part of a mixed expression folds
+        assert_eq!(fold("var y = x * (2 + 3);"), "var y = x * 5;\n");
+
+        // Operations that fail at runtime are kept so they still fail there
+        assert_eq!(fold("var z = 1 / 0;"), "var z = 1 / 0;\n");
+        assert_eq!(fold("var z = 1.5 / 0;"), "var z = 1.5 / 0;\n");
+        assert_eq!(
+            fold("var o = 9223372036854775807 + 1;"),
+            "var o = 9223372036854775807 + 1;\n"
+        );
+        assert_eq!(fold("var s = 1 << 64;"), "var s = 1 << 64;\n");
+
+        // Constants fold into later uses, except where a parameter hides them
+        assert_eq!(
+            fold("const N = 4 * 2; var a = N + 1; fn f(N) { return N; } N = 3;"),
+            "const N = 8;\nvar a = 9;\n\nfn f(N) {\n    return N;\n}\n\nN = 3;\n"
+        );
+    }
+
+    #[test]
+    fn test_constant_folding_respects_shadowing() {
+        let fold = |source: &str| {
+            let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+            fold_constants(&mut program);
+            format_program(&program)
+        };
+
+        // A local variable hides the constant from its declaration on, but
+        // its own initializer and code after its block still see it
+        assert_eq!(
+            fold("const K = 1; fn f() { var a = K; var K = K + 4; return K; } var b = K;"),
+            "const K = 1;\n\nfn f() {\n    var a = 1;\n    var K = 5;\n    return K;\n}\n\n\
+             var b = 1;\n"
+        );
+        assert_eq!(
+            fold("const K = 1; { var K = 2; print(K); } print(K);"),
+            "const K = 1;\n{\n    var K = 2;\n    print(K);\n}\nprint(1);\n"
+        );
+        assert_eq!(
+            fold("const K = 1; for K in xs { print(K); } print(K);"),
+            "const K = 1;\nfor K in xs {\n    print(K);\n}\nprint(1);\n"
+        );
+        assert_eq!(
+            fold("const K = 1; try { print(K); } catch (K) { print(K); }"),
+            "const K = 1;\ntry {\n    print(1);\n} catch (K) {\n    print(K);\n}\n"
+        );
+        assert_eq!(
+            fold("const K = 1; var f = |K| K + 1; fn g() { fn K() {} return K; }"),
+            "const K = 1;\nvar f = |K| K + 1;\n\nfn g() {\n    fn K() {}\n    return K;\n}\n"
+        );
+
+        // The checker rejects the hiding, but a program run without it
+        // still gets the local
+        let mut program = Parser::new(Lexer::new(
+            "const K = 1; fn f() { var K = 5; return K; } f();",
+        ))
+        .parse()
+        .unwrap();
+        fold_constants(&mut program);
+        assert_eq!(Engine::new().run(&program), Ok(Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let tokens = Lexer::new("+= -= *= /=").tokenize();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::PlusEqual,
+                TokenType::MinusEqual,
+                TokenType::StarEqual,
+                TokenType::SlashEqual,
+                TokenType::EOF
+            ]
+        );
+
+        let program = Parser::new(Lexer::new("a[i] *= b -= 2;")).parse().unwrap();
+        match &program.statements[0] {
+            Stmt::Expression(Expr::CompoundAssignment(outer)) => {
+                assert_eq!(outer.operator, BinaryOperator::Multiply);
+                assert!(matches!(&*outer.target, Expr::Index(_)));
+                assert!(matches!(
+                    &*outer.value,
+                    Expr::CompoundAssignment(inner) if inner.operator == BinaryOperator::Subtract
+                ));
+            }
+            other => panic!("Expected CompoundAssignmentNode, got {:?}", other),
+        }
+
+        let error = Parser::new(Lexer::new("f() += 1;")).parse().unwrap_err();
+        assert_eq!(error.message, "Invalid assignment target.");
+
+        let program = Parser::new(Lexer::new("const K = 1; K += 1;"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].message.clone()),
+            Err("Cannot assign to constant 'K'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_desugaring() {
+        let source = "for (var i = 0; i < n; i += 1) { total *= (i + 1); } var x = ((a));";
+        let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+        desugar(&mut program);
+
+        assert_eq!(
+            format_program(&program),
+            "{\n    var i = 0;\n    while (i < n) {\n        {\n            \
+             total = total * (i + 1);\n        }\n        i = i + 1;\n    }\n}\nvar x = a;\n"
+        );
+
+        // None of the lowered forms survive anywhere in the tree
+        struct SurfaceSyntax(usize);
+        impl Visitor for SurfaceSyntax {
+            fn visit_stmt(&mut self, stmt: &Stmt) {
+                if matches!(stmt, Stmt::For(_)) {
+                    self.0 += 1;
+                }
+                walk_stmt(self, stmt);
+            }
+            fn visit_expr(&mut self, expr: &Expr) {
+                if matches!(expr, Expr::Parenthesis(_) | Expr::CompoundAssignment(_)) {
+                    self.0 += 1;
+                }
+                walk_expr(self, expr);
+            }
+        }
+        let mut remaining = SurfaceSyntax(0);
+        remaining.visit_program(&program);
+        assert_eq!(remaining.0, 0);
+    }
+
+    #[test]
+    fn test_comments_are_kept_as_trivia() {
+        let tokens = Lexer::new("a // note\n/* multi\nline */ b / c").tokenize();
+        let summary: Vec<(TokenType, &str, usize)> = tokens
+            .iter()
+            .map(|t| (t.token_type, t.lexeme.as_str(), t.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (TokenType::Identifier, "a", 1),
+                (TokenType::Comment, "// note", 1),
+                (TokenType::Comment, "/* multi\nline */", 2),
+                (TokenType::Identifier, "b", 3),
+                (TokenType::Slash, "/", 3),
+                (TokenType::Identifier, "c", 3),
+                (TokenType::EOF, "", 3),
+            ]
+        );
+        let error = Lexer::new("/* never closed").tokenize();
+        assert_eq!(error[0].token_type, TokenType::Error);
+
+        let source = "// Leading comment\nuse math;\n/* block\n   comment */\n\
+                      fn f(a) { // after brace\n    var x = a; // trailing\n    return x;\n}\n\
+                      var y = f(1 /* inside */); // end\n";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let comments = |comments: Option<&Comments>, leading: bool| -> Vec<(String, bool)> {
+            let comments = comments.expect("expected comments");
+            let list = if leading {
+                &comments.leading
+            } else {
+                &comments.trailing
+            };
+            list.iter().map(|c| (c.text.clone(), c.same_line)).collect()
+        };
+        let owned = |list: &[(&str, bool)]| -> Vec<(String, bool)> {
+            list.iter()
+                .map(|(text, same_line)| (text.to_string(), *same_line))
+                .collect()
+        };
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(
+            comments(program.statements[0].comments(), true),
+            owned(&[("// Leading comment", false)])
+        );
+        assert_eq!(
+            comments(program.statements[1].comments(), true),
+            owned(&[("/* block\n   comment */", false)])
+        );
+        match &program.statements[1] {
+            Stmt::FunctionDeclaration(function) => {
+                assert_eq!(
+                    comments(function.body[0].comments(), true),
+                    owned(&[("// after brace", true)])
+                );
+                assert_eq!(
+                    comments(function.body[0].comments(), false),
+                    owned(&[("// trailing", true)])
+                );
+            }
+            other => panic!("Expected FunctionDeclarationNode, got {:?}", other),
+        }
+        assert_eq!(
+            comments(program.statements[2].comments(), false),
+            owned(&[("// end", true)])
+        );
+        match initializer(&program.statements[2]) {
+            Expr::Call(call) => assert_eq!(
+                comments(call.arguments[0].comments(), false),
+                owned(&[("/* inside */", true)])
+            ),
+            other => panic!("Expected CallNode, got {:?}", other),
+        }
+
+        // The formatter writes each comment back next to its node
+        assert_eq!(
+            format_program(&program),
+            "// Leading comment\nuse math;\n\n/* block\n   comment */\n\
+             fn f(a) { // after brace\n    var x = a; // trailing\n    return x;\n}\n\n\
+             var y = f(1 /* inside */); // end\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_round_trip_through_the_formatter() {
+        let cases = [
+            // Inside operations and call arguments
+            ("var a = 1 + /* c */ 2;", "var a = 1 + /* c */ 2;\n"),
+            (
+                "print(/* first */ 1, 2 /* second */);\nfn g() {}",
+                "print(/* first */ 1, 2 /* second */);\n\nfn g() {}\n",
+            ),
+            ("f(1, // one\n  2);", "f(\n    1, // one\n    2\n);\n"),
+            (
+                "var m = match x { 1 => \"a\", // one\n _ => \"b\" };",
+                "var m = match x {\n    1 => \"a\", // one\n    _ => \"b\",\n};\n",
+            ),
+            // Before and after statements, and alone in a block
+            (
+                "fn f() {\n    g();\n    // done\n}\n// next\nfn h() { // nothing\n}",
+                "fn f() {\n    g();\n    // done\n}\n\n// next\nfn h() { // nothing\n}\n",
+            ),
+            (
+                "fn f(x) {\n    x * 2 // doubled\n}",
+                "fn f(x) {\n    return x * 2; // doubled\n}\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            let program = Parser::new(Lexer::new(source)).parse().unwrap();
+            let formatted = format_program(&program);
+            assert_eq!(formatted, expected, "formatting {:?}", source);
+            let reparsed = Parser::new(Lexer::new(&formatted)).parse().unwrap();
+            assert_eq!(reparsed, program, "re-parsing {:?}", formatted);
+            assert_eq!(format_program(&reparsed), formatted);
+        }
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let source =
+            "var a = t.transpose(); var b = t.matmul(u).sum(); var n = s?.len(); var x = p.x;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+
+        match initializer(&program.statements[0]) {
+            Expr::MethodCall(call) => {
+                assert_eq!(call.method, "transpose");
+                assert!(call.arguments.is_empty());
+                assert!(!call.optional);
+                assert!(matches!(&*call.object, Expr::Variable(v) if v.name == "t"));
+            }
+            other => panic!("Expected MethodCallNode, got {:?}", other),
+        }
+        // Chained calls apply left to right: (t.matmul(u)).sum()
+        match initializer(&program.statements[1]) {
+            Expr::MethodCall(outer) => {
+                assert_eq!(outer.method, "sum");
+                assert!(matches!(
+                    &*outer.object,
+                    Expr::MethodCall(inner) if inner.method == "matmul" && inner.arguments.len() == 1
+                ));
+            }
+            other => panic!("Expected MethodCallNode, got {:?}", other),
+        }
+        assert!(matches!(
+            initializer(&program.statements[2]),
+            Expr::MethodCall(call) if call.optional && call.method == "len"
+        ));
+        // Without parentheses it is still a plain member access
+        assert!(matches!(
+            initializer(&program.statements[3]),
+            Expr::MemberAccess(_)
+        ));
+
+        let formatted = format_program(&program);
+        assert_eq!(
+            formatted,
+            "var a = t.transpose();\nvar b = t.matmul(u).sum();\nvar n = s?.len();\nvar x = p.x;\n"
+        );
+        assert_eq!(ProgramNode::from_json(&program.to_json()), Ok(program));
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let source = r#"var y = match x { 1 => "one", 2 => "two", _ => "other" };"#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+
+        match initializer(&program.statements[0]) {
+            Expr::Match(match_node) => {
+                assert!(matches!(&*match_node.subject, Expr::Variable(v) if v.name == "x"));
+                assert_eq!(match_node.arms.len(), 3);
+                assert!(matches!(match_node.arms[2].pattern, Pattern::Wildcard));
+                assert!(matches!(&match_node.arms[0].value, Expr::String(s) if s.value == "one"));
+            }
+            other => panic!("Expected MatchExpressionNode, got {:?}", other),
+        }
+
+        // It combines with other operators like any primary expression
+        let program = Parser::new(Lexer::new("var z = 1 + match b { true => 2, _ => 3 };"))
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            initializer(&program.statements[0]),
+            Expr::Binary(binary) if matches!(*binary.right, Expr::Match(_))
+        ));
+
+        let formatted = format_program(&program);
+        assert_eq!(
+            formatted,
+            "var z = 1 + match b {\n    true => 2,\n    _ => 3,\n};\n"
+        );
+        assert_eq!(
+            Parser::new(Lexer::new(&formatted)).parse(),
+            Ok(program.clone())
+        );
+        assert_eq!(ProgramNode::from_json(&program.to_json()), Ok(program));
+
+        assert!(Parser::new(Lexer::new("var y = match x {};"))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_sexpr_dump() {
+        let cases = [
+            ("var x = 42 + 5;", "(assign (var x) (+ 42 5))"),
+            ("var y: float?;", "(var y:float?)"),
+            (
+                "x += -a[i] * f(1, 2);",
+                "(+= x (* (- (index a i)) (call f 1 2)))",
+            ),
+            (
+                "fn add(a: int, b) -> int { return a + b; }",
+                "(fn add (a:int b) -> int (block (return (+ a b))))",
+            ),
+            (
+                r#"if (!ok) print("no"); else { t.sum(); }"#,
+                r#"(if (! ok) (call print "no") (block (method sum t)))"#,
+            ),
+            (
+                "for (var i = 0; i < n; ) {}",
+                "(for (assign (var i) 0) (< i n) () (block))",
+            ),
+            (
+                "var m = match c { 'a' => [1, 2], _ => |v| v?.w };",
+                "(assign (var m) (match c ('a' (tensor [2] 1 2)) (_ (lambda (v) (?. v w)))))",
+            ),
+            ("use math::linalg; // trivia", "(use math::linalg)"),
+        ];
+        for (source, expected) in cases {
+            let program = Parser::new(Lexer::new(source)).parse().unwrap();
+            assert_eq!(program.to_sexpr(), expected, "for {:?}", source);
+        }
+
+        let program = Parser::new(Lexer::new("a = b ? 0..10 step 2 : null;"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            program.statements[0].to_sexpr(),
+            "(= a (? b (.. 0 10 2) null))"
+        );
+    }
+
+    #[test]
+    fn test_newline_terminated_statements() {
+        let source =
+            "var x = 1\nvar y = x +\n    2\nprint(x,\n      y)\nf(fn() {\n    return\n})\n";
+        let program = Parser::new(Lexer::new(source))
+            .newline_terminated(true)
+            .parse()
+            .unwrap();
+        assert_eq!(program.statements.len(), 4);
+        // A trailing operator continues the expression onto the next line
+        assert!(matches!(
+            initializer(&program.statements[1]),
+            Expr::Binary(_)
+        ));
+        // `return` followed by a line break returns nothing
+        match &program.statements[3] {
+            Stmt::Expression(Expr::Call(call)) => match &call.arguments[0] {
+                Expr::Lambda(lambda) => assert!(matches!(
+                    &lambda.body,
+                    LambdaBody::Block(body) if matches!(&body[0], Stmt::Return(r) if r.value.is_none())
+                )),
+                other => panic!("Expected LambdaNode, got {:?}", other),
+            },
+            other => panic!("Expected a call statement, got {:?}", other),
+        }
+
+        // A line starting with an operator begins a new statement
+        let program = Parser::new(Lexer::new("var a = b\n-c\nvar d = (b\n- c); var e = 1"))
+            .newline_terminated(true)
+            .parse()
+            .unwrap();
+        assert_eq!(program.statements.len(), 4);
+        assert!(matches!(
+            initializer(&program.statements[0]),
+            Expr::Variable(_)
+        ));
+        assert!(matches!(
+            initializer(&program.statements[2]),
+            Expr::Parenthesis(_)
+        ));
+
+        // Two statements on one line still need a semicolon
+        assert!(Parser::new(Lexer::new("var a = 1 var b = 2"))
+            .newline_terminated(true)
+            .parse()
+            .is_err());
+        // Without the setting, semicolons stay mandatory
+        assert!(Parser::new(Lexer::new("var a = 1\nvar b = 2;"))
+            .parse()
+            .is_err());
+
+        let mut config = ProjectConfig {
+            project_info: HashMap::new(),
+            dependencies: HashMap::new(),
+            build_settings: HashMap::new(),
+            runtime_settings: HashMap::new(),
+            custom_settings: HashMap::new(),
+        };
+        assert!(!config.newline_terminated());
+        config
+            .build_settings
+            .insert("statement_terminator".to_string(), "newline".to_string());
+        assert!(config.newline_terminated());
+    }
+
+    #[test]
+    fn test_cfg_substitution() {
+        let source = r#"var d = @cfg("debug_mode"); var n = @cfg("workers") * 2; var r = @cfg("ratio"); var s = @cfg("name"); var o = @cfg("offset");"#;
+        let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert!(
+            matches!(initializer(&program.statements[0]), Expr::Cfg(cfg) if cfg.key == "debug_mode")
+        );
+        assert_eq!(
+            format_program(&program).lines().next(),
+            Some(r#"var d = @cfg("debug_mode");"#)
+        );
+        assert_eq!(
+            ProgramNode::from_json(&program.to_json()),
+            Ok(program.clone())
+        );
+
+        let settings = HashMap::from([
+            ("debug_mode".to_string(), "false".to_string()),
+            ("workers".to_string(), "4".to_string()),
+            ("ratio".to_string(), "0.5".to_string()),
+            ("name".to_string(), "demo build".to_string()),
+            ("offset".to_string(), "-3".to_string()),
+        ]);
+        substitute_cfg(&mut program, &settings).unwrap();
+        assert_eq!(
+            program.to_sexpr(),
+            r#"(assign (var d) false) (assign (var n) (* 4 2)) (assign (var r) 0.5) (assign (var s) "demo build") (assign (var o) (- 3))"#
+        );
+
+        let mut program = Parser::new(Lexer::new(r#"if (@cfg("verbose")) print(1);"#))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            substitute_cfg(&mut program, &settings),
+            Err("Undefined configuration setting 'verbose'".to_string())
+        );
+
+        assert!(Parser::new(Lexer::new(r#"var x = @env("HOME");"#))
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn test_interpreter_evaluates_programs() {
+        let source = r#"
+            var x = (42 + 5) * 2 - 3 / 1.5;
+            var total = 0;
+            var i = 1;
+            while (i <= 10) {
+                total += i;
+                i = i + 1;
+            }
+            for (var j = 0; j < 3; j += 1) print("j", j);
+            const greeting = "hi " + "there";
+            var big = total > 50 && !false ? "big" : "small";
+            if (total == 55.0) print(greeting, big); else print("no");
+            var word = match total { 10 => "ten", _ => "other" };
+        "#;
+        let (interpreter, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(interpreter.global("x"), Some(Value::Float(92.0)));
+        assert_eq!(interpreter.global("total"), Some(Value::Integer(55)));
+        assert_eq!(
+            interpreter.global("word"),
+            Some(Value::String("other".into()))
+        );
+        assert_eq!(output, "j 0\nj 1\nj 2\nhi there big\n");
+    }
+
+    #[test]
+    fn test_interpreter_runtime_errors() {
+        let error = |source: &str| run(source).2.unwrap_err().message;
+
+        assert_eq!(error("var x = 1 / 0;"), "Division by zero");
+        assert_eq!(
+            error("var x = 9223372036854775807 + 1;"),
+            "Integer overflow"
+        );
+        assert_eq!(error("y = 1;"), "Undefined variable 'y'");
+        assert_eq!(
+            error("const c = 1; c += 1;"),
+            "Cannot assign to constant 'c'"
+        );
+        assert_eq!(
+            error("if (1) print(1);"),
+            "Condition must be a bool, found int"
+        );
+        assert_eq!(
+            error(r#"var s = "a" - 1;"#),
+            "Cannot apply '-' to string and int"
+        );
+        // The right side of `||` is skipped once the left side is true
+        assert!(run("var ok = true || undefined;").2.is_ok());
+        assert_eq!(
+            error("var w = match 3 { 1 => 2 };"),
+            "No match arm matches the value 3"
+        );
+        assert_eq!(
+            RuntimeError::new("Division by zero").to_string(),
+            "Runtime error: Division by zero"
+        );
+    }
+
+    #[test]
+    fn test_lexical_scoping() {
+        let source = r#"
+            var x = "global";
+            var count = 0;
+            {
+                var x = "block";
+                count = count + 1;
+                print(x);
+            }
+            print(x);
+            for (var i = 0; i < 2; i += 1) {
+                var x = i;
+                count += 1;
+            }
+            fn outer() {
+                var x = "function";
+                fn inner() { return x; }
+                return inner();
+            }
+            print(outer(), count);
+        "#;
+        let (interpreter, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "block\nglobal\nfunction 3\n");
+        // Names declared inside blocks and loops don't leak out
+        assert_eq!(interpreter.global("i"), None);
+        assert_eq!(
+            interpreter.global("x"),
+            Some(Value::String("global".into()))
+        );
+
+        let source = r#"
+            fn fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            var f = fib(15);
+            fn nothing() {}
+            var n = nothing();
+        "#;
+        let (interpreter, _, result) = run(source);
+        result.unwrap();
+        assert_eq!(interpreter.global("f"), Some(Value::Integer(610)));
+        assert_eq!(interpreter.global("n"), Some(Value::Null));
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error("{ var hidden = 1; } hidden = 2;"),
+            "Undefined variable 'hidden'"
+        );
+        assert_eq!(
+            error("const c = 1; { var c = 2; } fn f() { c = 3; } f();"),
+            "Cannot assign to constant 'c'"
+        );
+        assert_eq!(
+            error("fn f(a) {} f(1, 2);"),
+            "'f' expects 1 argument but got 2"
+        );
+        assert_eq!(
+            error("var x = 1; x();"),
+            "Can only call functions, found int"
+        );
+        assert_eq!(error("return 1;"), "Cannot return from top-level code");
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let source = r#"
+            print(len("héllo"), abs(-3), abs(-2.5), type_of(1.0), type_of(len));
+            var n = "four".len();
+            var measure = len;
+            print(measure("ab"), n);
+        "#;
+        let (_, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "5 3 2.5 float function\n2 4\n");
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error("len(1);"),
+            "len() expects a string, array or tensor, found int"
+        );
+        assert_eq!(error(r#"abs(1, 2);"#), "'abs' expects 1 argument but got 2");
+        assert_eq!(
+            error("var x = 1; x.nope();"),
+            "Undefined method 'nope' on int"
+        );
+
+        // Embedders can add their own functions, or replace the standard ones
+        let program = Parser::new(Lexer::new("var d = double(21); print(d, double(0.5));"))
+            .parse()
+            .unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(output.clone());
+        interpreter.register_builtin(NativeFunction::new("double", Some(1), |_, arguments| {
+            match &arguments[0] {
+                Value::Integer(n) => Ok(Value::Integer(n * 2)),
+                Value::Float(n) => Ok(Value::Float(n * 2.0)),
+                other => Err(RuntimeError::new(format!("cannot double {}", other))),
+            }
+        }));
+        interpreter.register_builtin(NativeFunction::new(
+            "print",
+            None,
+            |interpreter, arguments| {
+                interpreter.write_output(&format!("> {}\n", arguments.len()))?;
+                Ok(Value::Null)
+            },
+        ));
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(interpreter.global("d"), Some(Value::Integer(42)));
+        assert_eq!(output.text(), "> 2\n");
+    }
+
+    #[test]
+    fn test_runtime_error_locations() {
+        let source = "fn ratio(a, b) {\n    return a / b;\n}\nfn outer() {\n    return ratio(1, 0);\n}\nvar r = outer();";
+        let error = run(source).2.unwrap_err();
+        assert_eq!(error.message, "Division by zero");
+        assert_eq!(
+            error.span,
+            Some(Span {
+                line: 2,
+                column: 14,
+                length: 1
+            })
+        );
+        let frames: Vec<(&str, usize)> = error
+            .trace
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.span.unwrap().line))
+            .collect();
+        assert_eq!(frames, vec![("ratio", 5), ("outer", 7)]);
+        assert_eq!(
+            error.render(source),
+            "Runtime error: Division by zero at line 2, column 14\n\
+             2 |     return a / b;\n\
+             \x20 |              ^\n    \
+             in ratio() called at line 5\n    \
+             in outer() called at line 7"
+        );
+
+        // The innermost failing expression is the one reported
+        let error = run("var a = 1;\nvar b = a + missing;").2.unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'missing'");
+        let span = error.span.unwrap();
+        assert_eq!((span.line, span.column, span.length), (2, 13, 7));
+        assert!(error.trace.is_empty());
+    }
+
+    #[test]
+    fn test_math_basket() {
+        let source = r#"
+            use math;
+            print(sqrt(16), pow(2, 10), pow(2, 0.5) == sqrt(2), floor(2.7), ceil(2));
+            print(sin(0), cos(0), floor(pi * 100), e > 2.7);
+        "#;
+        let (_, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "4.0 1024 true 2.0 2.0\n0.0 1.0 314.0 true\n");
+
+        // Nothing from the basket is in scope until it is used
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(error("var r = sqrt(4);"), "Undefined variable 'sqrt'");
+        assert_eq!(
+            error("use math; var r = sqrt(\"4\");"),
+            "sqrt() expects a number, found string"
+        );
+        assert_eq!(error("use stats;"), "Unknown basket 'stats'");
+    }
+
+    #[test]
+    fn test_file_builtins() {
+        let path =
+            std::env::temp_dir().join(format!("put_file_builtins_{}.txt", std::process::id()));
+        let source = format!(
+            r#"
+                var path = "{}";
+                write_file(path, "first\nsecond\n");
+                var lines = read_lines(path);
+                print(len(lines), lines[1], lines);
+                print(len(read_file(path)));
+            "#,
+            path.display()
+        );
+        let program = Parser::new(Lexer::new(&source)).parse().unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(output.clone()).allow_file_access(true);
+        let result = interpreter.interpret(&program);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+        assert_eq!(output.text(), "2 second [first, second]\n13\n");
+
+        // File access is off unless the embedder turns it on
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error(r#"read_file("data.txt");"#),
+            "read_file() needs file access, which this interpreter does not allow"
+        );
+        let program = Parser::new(Lexer::new(
+            r#"var lines = read_lines("/nonexistent/put/data.txt");"#,
+        ))
+        .parse()
+        .unwrap();
+        let error = Interpreter::with_output(SharedOutput::default())
+            .allow_file_access(true)
+            .interpret(&program)
+            .unwrap_err();
+        assert!(error
+            .message
+            .starts_with("Cannot read '/nonexistent/put/data.txt': "));
+    }
+
+    #[test]
+    fn test_random_builtins() {
+        let source = r#"
+            seed(2024);
+            var a = random();
+            var n = rand_int(1, 6);
+            seed(2024);
+            print(random() == a, rand_int(1, 6) == n);
+            print(a >= 0.0 && a < 1.0, n >= 1 && n <= 6, rand_int(3, 3));
+        "#;
+        let (_, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "true true\ntrue true 3\n");
+
+        // Two interpreters seeded alike agree
+        let first = run("seed(7); var x = rand_int(0, 1000000);").0.global("x");
+        let second = run("seed(7); var x = rand_int(0, 1000000);").0.global("x");
+        assert_eq!(first, second);
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error("rand_int(6, 1);"),
+            "rand_int() bounds are empty: 6 is greater than 1"
+        );
+        assert_eq!(
+            error("rand_int(1, 2.5);"),
+            "rand_int() expects int bounds, found float"
+        );
+    }
+
+    #[test]
+    fn test_string_builtins() {
+        let source = r#"
+            var fields = split("  a,b , c ", ",");
+            print(len(fields), join(fields, "|"));
+            print(trim(fields[1]), "put".to_upper(), to_upper(trim("  hi ")));
+            print(contains("tensor", "ten"), "tensor".contains('z'), fields.contains("a"));
+            var total = parse_number("40") + parse_number(" 2.5 ");
+            print(total, type_of(parse_number("7")));
+            print(join(split("1 2 3", " "), ", "));
+        "#;
+        let (_, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(
+            output,
+            "3   a|b | c \nb PUT HI\ntrue false false\n42.5 int\n1, 2, 3\n"
+        );
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error(r#"parse_number("12abc");"#),
+            "parse_number() cannot read '12abc' as a number"
+        );
+        assert_eq!(error("trim(3);"), "trim() expects a string, found int");
+        assert_eq!(
+            error(r#"split("abc", "");"#),
+            "split() separator must not be empty"
+        );
+        assert_eq!(
+            error(r#"join("abc", "");"#),
+            "join() expects an array, found string"
+        );
+    }
+
+    #[test]
+    fn test_tensor_values() {
+        let source = r#"
+            var a = [[1, 2], [3, 4]];
+            var b = [[5, 6], [7, 8]];
+            var sum = a + b;
+            var product = a @ b;
+            print(type_of(a), a * b - a);
+            var scaled = a @ b + a;
+            var shifted = a + [10, 20];
+            var outer = [[1], [2]] * [[1, 10, 100]];
+        "#;
+        let (interpreter, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(
+            output,
+            "tensor Tensor(shape=[2, 2], data=[4.0, 10.0, 18.0, 28.0])\n"
+        );
+        assert_eq!(
+            interpreter.global("sum"),
+            Some(Value::Tensor(Arc::new(Tensor::new(
+                vec![6.0, 8.0, 10.0, 12.0],
+                vec![2, 2]
+            ))))
+        );
+        assert_eq!(
+            interpreter.global("product"),
+            Some(Value::Tensor(Arc::new(Tensor::new(
+                vec![19.0, 22.0, 43.0, 50.0],
+                vec![2, 2]
+            ))))
+        );
+        // `@` binds as tightly as `*`
+        assert_eq!(
+            interpreter.global("scaled"),
+            Some(Value::Tensor(Arc::new(Tensor::new(
+                vec![20.0, 24.0, 46.0, 54.0],
+                vec![2, 2]
+            ))))
+        );
+
+        // Element-wise operators broadcast: sizes line up from the last
+        // dimension, and a size of 1 stretches to match the other
+        assert_eq!(
+            interpreter.global("shifted"),
+            Some(Value::Tensor(Arc::new(Tensor::new(
+                vec![11.0, 22.0, 13.0, 24.0],
+                vec![2, 2]
+            ))))
+        );
+        assert_eq!(
+            interpreter.global("outer"),
+            Some(Value::Tensor(Arc::new(Tensor::new(
+                vec![1.0, 10.0, 100.0, 2.0, 20.0, 200.0],
+                vec![2, 3]
+            ))))
+        );
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error("var t = [1, 2, 3] + [[1, 2]];"),
+            "Cannot apply '+' to tensors of shapes [3] and [1, 2]"
+        );
+        let errors = TypeChecker::check_program(
+            &Parser::new(Lexer::new(
+                "const A = [[1, 2, 3]]; const T = A * [[1], [2]]; var u = T - [1, 2];",
+            ))
+            .parse()
+            .unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            type_checker::report(&errors),
+            "Type error [E005]: Cannot apply '-' to tensors of shapes [2, 3] and [2] at line 1, column 60"
+        );
+        assert_eq!(
+            error("var t = [[1, 2]] @ [[1, 2]];"),
+            "Inner dimensions must match for matrix multiplication"
+        );
+        assert_eq!(
+            error("var t = [1, \"two\"];"),
+            "Tensor elements must be numbers, found string"
+        );
+        assert_eq!(error("var t = 2 @ 3;"), "Cannot apply '@' to int and int");
+    }
+
+    #[test]
+    fn test_int_converts_to_float() {
+        let output = SharedOutput::default();
+        let mut engine = Engine::with_output(output.clone());
+        engine
+            .eval(
+                "fn half(a: float) -> float { return a / 2; }
+                fn two() -> float { return 2; }
+                struct Point { x: float }
+                var x: float = 3;
+                const C: float = 4;
+                var y = 1.5;
+                y = 5;
+                var o: float? = 6;
+                print(half(3), two(), Point(1).x, x, C, y, o, true ? 1 : 2.5);",
+            )
+            .unwrap();
+        assert_eq!(output.text(), "1.5 2.0 1.0 3.0 4.0 5.0 6.0 1.0\n");
+        assert_eq!(engine.eval("var z = 7; z;"), Ok(Value::Integer(7)));
+    }
+
+    #[test]
+    fn test_emit_rust() {
+        let source = "fn scale(t: tensor, k: float) -> tensor { return t * [[k, k], [k, k]]; }
+            var w = [[1, 2], [3, 4]];
+            var n = 3;
+            var total = 0.0;
+            while (n > 0) { total = total + n / 2.0; n = n - 1; }
+            print(scale(w, total) @ w, total);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let rust = emit_rust(&program).unwrap();
+        assert!(rust.contains("use put_lang::tensor::Tensor;\n"));
+        assert_eq!(
+            &rust[rust.find("fn scale").unwrap()..],
+            "fn scale(t: Tensor, k: f64) -> Tensor {\n    \
+                 return &t * &Tensor::new(vec![k, k, k, k], vec![2, 2]);\n\
+             }\n\
+             \n\
+             fn main() {\n    \
+                 let mut w = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);\n    \
+                 let mut n = 3;\n    \
+                 let mut total = 0.0;\n    \
+                 while n > 0 {\n        \
+                     total = total + ((n as f64) / 2.0);\n        \
+                     n = n - 1;\n    \
+                 }\n    \
+                 println!(\"{} {:?}\", scale(w.clone(), total).matmul(&w)\
+                 .expect(\"matmul shapes do not match\"), total);\n\
+             }\n"
+        );
+
+        // An int goes where a float is declared or resolved as a float
+        let source = "fn half(a: float) -> float { return a / 2; }
+            fn two() -> float { return 2; }
+            var y: float = 1.5;
+            y = 4;
+            print(half(3), two(), true ? 1 : 2.5);";
+        let rust = emit_rust(&Parser::new(Lexer::new(source)).parse().unwrap()).unwrap();
+        assert_eq!(
+            &rust[rust.find("fn half").unwrap()..],
+            "fn half(a: f64) -> f64 {\n    \
+                 return a / (2 as f64);\n\
+             }\n\
+             \n\
+             fn two() -> f64 {\n    \
+                 return (2 as f64);\n\
+             }\n\
+             \n\
+             fn main() {\n    \
+                 let mut y: f64 = 1.5;\n    \
+                 y = (4 as f64);\n    \
+                 println!(\"{:?} {:?} {:?}\", half((3 as f64)), two(), \
+                 (if true { 1 as f64 } else { 2.5 }));\n\
+             }\n"
+        );
+
+        let error = |source: &str| emit_rust(&Parser::new(Lexer::new(source)).parse().unwrap());
+        assert_eq!(
+            error("fn f(x) { return x; }"),
+            Err("Cannot emit Rust for parameter 'x' of 'f' without a type annotation".to_string())
+        );
+        assert_eq!(
+            error("var f = |x| x + 1;"),
+            Err("Cannot emit Rust for closures".to_string())
+        );
+        // Programs are type-checked first
+        assert_eq!(
+            error("const K = 1; K = 2;"),
+            Err(
+                "Type error [E003]: Cannot assign to constant 'K' at line 1, column 14".to_string()
+            )
+        );
+        assert_eq!(
+            error("var s = \"a\" @ 2;"),
+            Err(
+                "Type error [E005]: Cannot apply '@' to String and Integer at line 1, column 13"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_emit_python() {
+        let source = "use math;
+            fn half(n: int) -> int { return n / 2; }
+            var w = [[1, 2], [3, 4]];
+            var i = 0;
+            do { i = i + 1; } while (i < 3 && !false);
+            var name = match i { 3 => \"three\", _ => \"other\" };
+            print(w @ w, half(i), sqrt(2.0), name);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let python = emit_python(&program).unwrap();
+        assert!(python.contains("import numpy as np\n"));
+        assert!(python.contains("def _div(a, b):\n"));
+        assert_eq!(
+            &python[python.find("def half").unwrap()..],
+            "def half(n):\n    \
+                 return _div(n, 2)\n\
+             \n\
+             w = np.array([1, 2, 3, 4], dtype=float).reshape([2, 2])\n\
+             i = 0\n\
+             while True:\n    \
+                 i = i + 1\n    \
+                 if not ((i < 3) and (not False)):\n        \
+                     break\n\
+             name = (lambda _s: \"three\" if _s == 3 else \"other\")(i)\n\
+             print(np.matmul(w, w), half(i), np.sqrt(2.0), name)\n"
+        );
+
+        // An int goes where a float is declared or resolved as a float
+        let source = "fn half(a: float) -> float { return a / 2; }
+            fn two() -> float { return 2; }
+            var y: float = 1.5;
+            y = 4;
+            print(half(3), two(), true ? 1 : 2.5);";
+        let python = emit_python(&Parser::new(Lexer::new(source)).parse().unwrap()).unwrap();
+        assert_eq!(
+            &python[python.find("def half").unwrap()..],
+            "def half(a):\n    \
+                 return a / 2\n\
+             \n\
+             def two():\n    \
+                 return float(2)\n\
+             \n\
+             y = 1.5\n\
+             y = float(4)\n\
+             print(half(float(3)), two(), float(1) if True else 2.5)\n"
+        );
+
+        let error = |source: &str| emit_python(&Parser::new(Lexer::new(source)).parse().unwrap());
+        assert_eq!(
+            error("var f = |x| { return x; };"),
+            Err("Cannot emit Python for closures with a block body".to_string())
+        );
+        assert_eq!(
+            error("use math; var x = sqrt(1.0, 2.0);"),
+            Err("'sqrt' expects 1 argument but got 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_playground_entry_points() {
+        assert_eq!(
+            wasm::parse("print(1);"),
+            Ok(
+                "{\"type\":\"Program\",\"statements\":[{\"type\":\"Expression\",\"expression\":\
+                {\"type\":\"Call\",\"callee\":{\"type\":\"Variable\",\"name\":\"print\",\
+                \"data_type\":null},\"arguments\":[{\"type\":\"Number\",\"value\":\"1\",\
+                \"data_type\":\"Integer\"}]}}]}"
+                    .to_string()
+            )
+        );
+        assert!(wasm::parse("print(1").is_err());
+        assert_eq!(wasm::check("var x: int = 1;"), Ok(()));
+        assert_eq!(
+            wasm::check("const K = 1; K = 2;"),
+            Err(
+                "Type error [E003]: Cannot assign to constant 'K' at line 1, column 14".to_string()
+            )
+        );
+        assert_eq!(
+            wasm::eval("var x = 2; print(x * 21);"),
+            Ok("42\n".to_string())
+        );
+        // Output printed before a runtime error is kept
+        assert_eq!(
+            wasm::eval("print(1); var d = 0; print(1 / d);"),
+            Err("1\nRuntime error: Division by zero at line 1, column 30\n\
+                 1 | print(1); var d = 0; print(1 / d);\n  |                              ^"
+                .to_string())
+        );
+        assert!(wasm::eval("read_file(\"x\");")
+            .unwrap_err()
+            .contains("read_file() needs file access"));
+    }
+
+    #[cfg(feature = "llvm")]
+    #[test]
+    fn test_emit_llvm() {
+        use put_lang::codegen_llvm::emit_llvm;
+
+        let source = "fn half(x: float) -> float { return x / 2; }
+            var n = 3;
+            print(half(n), n > 1 && true);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let ir = emit_llvm(&program).unwrap();
+        assert!(ir.contains("define private double @put.half(double %arg.x) {\n"));
+        assert!(ir.contains("declare i32 @printf(ptr, ...)\n"));
+        assert_eq!(
+            &ir[ir.find("define i32 @main").unwrap()..],
+            "define i32 @main() {\n\
+             entry:\n  \
+               %n.8 = alloca i64\n  \
+               store i64 3, ptr %n.8\n  \
+               %t9 = load i64, ptr %n.8\n  \
+               %t10 = sitofp i64 %t9 to double\n  \
+               %t11 = call double @put.half(double %t10)\n  \
+               call void @put_print_float(double %t11)\n  \
+               %t12 = call i32 @putchar(i32 32)\n  \
+               %t13 = load i64, ptr %n.8\n  \
+               %t14 = icmp sgt i64 %t13, 1\n  \
+               br i1 %t14, label %logic.right15, label %logic.end16\n\
+             logic.right15:\n  \
+               br label %logic.end16\n\
+             logic.end16:\n  \
+               %t17 = phi i1 [ false, %entry ], [ true, %logic.right15 ]\n  \
+               %t18 = select i1 %t17, ptr @.str.1, ptr @.str.2\n  \
+               %t19 = call i32 (ptr, ...) @printf(ptr @.fmt.str, ptr %t18)\n  \
+               %t20 = call i32 @putchar(i32 10)\n  \
+               ret i32 0\n\
+             }\n"
+        );
+
+        let error = |source: &str| emit_llvm(&Parser::new(Lexer::new(source)).parse().unwrap());
+        assert_eq!(
+            error("var t = [[1, 2]];"),
+            Err("Cannot emit LLVM IR for tensors".to_string())
+        );
+        assert_eq!(
+            error("fn f(x) { return; }"),
+            Err(
+                "Cannot emit LLVM IR for parameter 'x' of 'f' without a type annotation"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            error("return;"),
+            Err("Cannot emit LLVM IR for a return from top-level code".to_string())
+        );
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() {
+        let source = "var name = \"put\";
+            fn twice(n: int) -> int {
+                var doubled = n * 2;
+                breakpoint;
+                return doubled;
+            }
+            var total = 0;
+            for (var i = 0; i < 2; i = i + 1) { total = total + twice(i); }
+            print(total);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(program.statements[1].line(), None);
+        assert_eq!(program.statements[3].line(), Some(8));
+
+        let console = SharedOutput::default();
+        let debugger = ConsoleDebugger::new(&b"variables\n\nc\nvariables\n"[..], console.clone())
+            .break_at_line(9);
+        let output = SharedOutput::default();
+        Interpreter::with_output(output.clone())
+            .attach_debugger(debugger)
+            .interpret(&program)
+            .unwrap();
+        assert_eq!(output.text(), "2\n");
+        assert_eq!(
+            console.text(),
+            "Paused at breakpoint at line 4\n\
+             (put) doubled = 0\n\
+             n = 0\n\
+             name = \"put\"\n\
+             total = 0\n\
+             twice = <fn twice>\n\
+             (put) Paused at breakpoint at line 4\n\
+             (put) Paused at line breakpoint at line 9\n\
+             (put) name = \"put\"\n\
+             total = 2\n\
+             twice = <fn twice>\n\
+             (put) "
+        );
+
+        // Without a debugger, breakpoints do nothing
+        let (_, output, result) = run("breakpoint; print(1);");
+        assert!(result.is_ok());
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn test_debugger_stepping() {
+        let source = "fn twice(n: int) -> int {
+                var doubled = n * 2;
+                return doubled;
+            }
+            var x = twice(1);
+            var y = twice(x);
+            print(y);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let commands = "s\nbt\nn\np doubled\np x\n\nn\nprint\nfrobnicate\nc\n";
+        let console = SharedOutput::default();
+        let debugger = ConsoleDebugger::new(commands.as_bytes(), console.clone())
+            .break_at_line(5)
+            .show_source(source);
+        let output = SharedOutput::default();
+        Interpreter::with_output(output.clone())
+            .attach_debugger(debugger)
+            .interpret(&program)
+            .unwrap();
+        assert_eq!(output.text(), "4\n");
+        assert_eq!(
+            console.text(),
+            "Paused at line breakpoint at line 5\n\
+             5 |             var x = twice(1);\n\
+             (put) Stepped at line 2\n\
+             2 |                 var doubled = n * 2;\n\
+             (put) #0 twice() at line 2\n\
+             #1 <script> at line 5\n\
+             (put) Stepped at line 3\n\
+             3 |                 return doubled;\n\
+             (put) doubled = 2\n\
+             (put) No variable named 'x' here\n\
+             (put) Stepped at line 6\n\
+             6 |             var y = twice(x);\n\
+             (put) Stepped at line 7\n\
+             7 |             print(y);\n\
+             (put) Usage: print <name>\n\
+             (put) Unknown command 'frobnicate'; type help for the list\n\
+             (put) "
+        );
+    }
+
+    #[test]
+    fn test_profiler_counts() {
+        let source = "fn fib(n: int) -> int {
+                if (n < 2) { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                total = total + fib(i);
+            }
+            print(total);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::with_output(output.clone()).enable_profiling();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(output.text(), "7\n");
+
+        let profile = interpreter.profile().unwrap();
+        let functions = profile.functions();
+        assert_eq!(functions.len(), 1);
+        assert_eq!((functions[0].0, functions[0].1.count), ("fib", 19));
+        let mut runs: Vec<(usize, u64)> = profile
+            .lines()
+            .into_iter()
+            .map(|(line, counter)| (line, counter.count))
+            .collect();
+        runs.sort();
+        // Recursive calls count each visit to line 3 but time only the
+        // outermost
+        assert_eq!(runs, vec![(2, 19), (3, 7), (5, 1), (6, 1), (7, 5), (9, 1)]);
+        // A line includes the time of the calls made on it
+        let line = |wanted| profile.lines().into_iter().find(|&(l, _)| l == wanted);
+        assert!(line(7).unwrap().1.time >= line(3).unwrap().1.time);
+
+        let report = profile.report(source);
+        assert!(report.starts_with("function                  calls         time\nfib"));
+        assert!(report.contains("\n  line       runs         time  source\n"));
+        assert!(report.contains("     7          5 "));
+        assert!(report.contains(" ms  total = total + fib(i);\n"));
+
+        assert!(Interpreter::new().profile().is_none());
+    }
+
+    #[test]
+    fn test_trace_statements() {
+        let source = "fn twice(n: int) -> int {
+                return n * 2;
+            }
+            var word = \"hi\";
+            for (var i = 1; i < 3; i = i + 1) {
+                twice(i);
+            }
+            print(word);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let trace = SharedOutput::default();
+        let output = SharedOutput::default();
+        Interpreter::with_output(output.clone())
+            .trace_to(trace.clone())
+            .interpret(&program)
+            .unwrap();
+        assert_eq!(output.text(), "hi\n");
+        assert_eq!(
+            trace.text(),
+            "[line 4] var word = \"hi\"; => \"hi\"\n\
+             [line 5] var i = 1; => 1\n\
+             [line 2] return n * 2; => 2\n\
+             [line 6] twice(i); => 2\n\
+             [line 2] return n * 2; => 4\n\
+             [line 6] twice(i); => 4\n\
+             [line 8] print(word);\n"
+        );
+    }
+
+    #[test]
+    fn test_trace_quotes_source() {
+        let source = "var total = 0;
+            for (var i = 1; i < 3; i += 1) {
+                total += (i) * 2; // doubled
+            }
+            fn pick(a: int, b: int) -> int { return b - a; }
+            var picked = pick(3,
+                7);";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let trace = SharedOutput::default();
+        Interpreter::with_output(SharedOutput::default())
+            .trace_to(trace.clone())
+            .trace_source(source)
+            .interpret(&program)
+            .unwrap();
+        assert_eq!(
+            trace.text(),
+            "[line 1] var total = 0; => 0\n\
+             [line 2] var i = 1; => 1\n\
+             [line 3] total += (i) * 2; => 2\n\
+             [line 3] total += (i) * 2; => 6\n\
+             [line 5] return b - a; => 4\n\
+             [line 6] var picked = pick(3, 7); => 4\n"
+        );
+    }
+
+    #[test]
+    fn test_engine_eval() {
+        let output = SharedOutput::default();
+        let mut engine = Engine::with_output(output.clone());
+        assert_eq!(engine.eval("var x = 1 + 2;"), Ok(Value::Null));
+        assert_eq!(engine.eval("x * 2; // doubled"), Ok(Value::Integer(6)));
+        assert_eq!(
+            engine.eval("fn greet(name: string) -> string { return \"hi \" + name; }"),
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            engine.eval("print(greet(\"put\")); greet(\"you\");"),
+            Ok(Value::String("hi you".into()))
+        );
+        assert_eq!(output.text(), "hi put\n");
+
+        assert!(matches!(engine.eval("x +"), Err(EngineError::Parse(_))));
+        let source = "x / 0;";
+        let error = engine.eval(source).unwrap_err();
+        assert!(matches!(error, EngineError::Runtime(_)));
+        assert_eq!(
+            error.render(source),
+            "Runtime error: Division by zero at line 1, column 3\n1 | x / 0;\n  |   ^"
+        );
+        // A failed eval leaves earlier globals alone
+        assert_eq!(engine.eval("x;"), Ok(Value::Integer(3)));
+
+        // Scripts are type checked against the globals earlier ones defined
+        let error = engine.eval("greet(1); y;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Type error [E004]: 'greet' expects String for parameter 'name' but got Integer \
+             at line 1, column 7\n\
+             Type error [E001]: Undeclared variable 'y' at line 1, column 11"
+        );
+        assert_eq!(output.text(), "hi put\n");
+
+        let mut engine = Engine::new().set_cfg("mode", "fast");
+        assert_eq!(
+            engine.eval("@cfg(\"mode\");"),
+            Ok(Value::String("fast".into()))
+        );
+    }
+
+    #[test]
+    fn test_engine_register_fn() {
+        let mut engine = Engine::new();
+        engine.register_fn("greeting", || "hello");
+        engine.register_fn("scale", |x: f64, factor: i64| x * factor as f64);
+        engine.register_fn("longest", |words: Vec<String>| {
+            words.into_iter().max_by_key(String::len)
+        });
+        engine.register_fn("average", |tensor: Tensor| tensor.mean());
+        engine.register_fn("lookup", |key: String| match key.as_str() {
+            "answer" => Ok(Some(42)),
+            "nothing" => Ok(None),
+            _ => Err(format!("No entry for '{}'", key)),
+        });
+        engine.register_fn("describe", |value: Value, label: Option<String>| {
+            format!("{}: {}", label.unwrap_or_default(), value.type_name())
+        });
+
+        assert_eq!(
+            engine.eval("greeting();"),
+            Ok(Value::String("hello".into()))
+        );
+        assert_eq!(engine.eval("scale(2, 3);"), Ok(Value::Float(6.0)));
+        assert_eq!(
+            engine.eval("longest(split(\"a bcd ef\", \" \"));"),
+            Ok(Value::String("bcd".into()))
+        );
+        assert_eq!(engine.eval("average([1, 2, 3]);"), Ok(Value::Float(2.0)));
+        assert_eq!(engine.eval("lookup(\"answer\");"), Ok(Value::Integer(42)));
+        assert_eq!(engine.eval("lookup(\"nothing\");"), Ok(Value::Null));
+        assert_eq!(
+            engine.eval("describe(1.5, null);"),
+            Ok(Value::String(": float".into()))
+        );
+
+        let mut message = |source: &str| match engine.eval(source) {
+            Err(EngineError::Runtime(error)) => error.message,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+        assert_eq!(message("lookup(\"x\");"), "No entry for 'x'");
+        assert_eq!(
+            message("scale(\"2\", 3);"),
+            "scale() expects a number, found string"
+        );
+        assert_eq!(
+            message("scale(2.0, 3.0);"),
+            "scale() expects an int, found float"
+        );
+        assert_eq!(
+            message("longest([1, 2]);"),
+            "longest() expects an array, found tensor"
+        );
+        // The checker knows the host functions' arities
+        assert!(matches!(
+            engine.eval("average(1, 2);"),
+            Err(EngineError::Type(errors))
+                if errors[0].message == "'average' expects 1 argument but got 2"
+        ));
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        let message = |engine: &mut Engine, source: &str| match engine.eval(source) {
+            Err(EngineError::Runtime(error)) => error.message,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+
+        let mut engine = Engine::sandboxed(Limits {
+            max_instructions: Some(1000),
+            ..Limits::default()
+        });
+        assert_eq!(
+            message(&mut engine, "var n = 0; while (true) { n = n + 1; }"),
+            "Instruction limit of 1000 exceeded"
+        );
+        // Each eval starts with a fresh allowance
+        assert!(engine.eval("n;").is_ok());
+        assert_eq!(
+            message(&mut engine, "read_file(\"/etc/passwd\");"),
+            "read_file() needs file access, which this interpreter does not allow"
+        );
+
+        let mut engine = Engine::sandboxed(Limits {
+            timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        });
+        let started = Instant::now();
+        assert_eq!(
+            message(&mut engine, "while (true) {}"),
+            "Time limit of 20ms exceeded"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let mut engine = Engine::sandboxed(Limits {
+            max_value_size: Some(1024),
+            ..Limits::default()
+        });
+        assert_eq!(
+            message(&mut engine, "var s = \"x\"; while (true) { s = s + s; }"),
+            "Value size limit of 1024 bytes exceeded"
+        );
+        assert_eq!(engine.eval("len(s);"), Ok(Value::Integer(1024)));
+        // A tensor too big for the limit is refused before it is allocated
+        assert_eq!(
+            message(&mut engine, "zeros(1000000, 1000000, 1000000);"),
+            "Value size limit of 1024 bytes exceeded"
+        );
+        assert_eq!(
+            engine.eval("print(\"unseen\"); 1 + 1;"),
+            Ok(Value::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let source = "fn down(n: int) -> int {
+                if (n == 0) { return 0; }
+                return 1 + down(n - 1);
+            }
+            down(depth);";
+        let mut engine = Engine::new().max_call_depth(10);
+        assert_eq!(engine.eval("var depth = 9;"), Ok(Value::Null));
+        assert_eq!(engine.eval(source), Ok(Value::Integer(9)));
+        engine.eval("depth = 10;").unwrap();
+        let error = engine.eval(source).unwrap_err();
+        assert_eq!(
+            error.render(source),
+            "Runtime error: Stack overflow: calls nested more than 10 deep at line 3, column 32\n\
+             3 |                 return 1 + down(n - 1);\n  \
+               |                                ^\n    \
+                 in down() called at line 3\n    \
+                 ... repeated 8 more times\n    \
+                 in down() called at line 5"
+        );
+
+        // The default depth stays within a main thread's stack
+        let message = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let mut engine = Engine::new();
+                let source = "fn forever(n: int) -> int { return forever(n + 1); } forever(0);";
+                match engine.eval(source) {
+                    Err(EngineError::Runtime(error)) => error.message,
+                    other => format!("{:?}", other),
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            message,
+            format!(
+                "Stack overflow: calls nested more than {} deep",
+                DEFAULT_MAX_CALL_DEPTH
+            )
+        );
+    }
+
+    #[test]
+    fn test_closures_capture_environment() {
+        let (_, output, result) = run("var add_n = fn(x) { fn(y) { x + y } };
+            var add_two = add_n(2);
+            print(add_two(3), add_n(10)(5));
+            var count = 0;
+            var bump = || { count = count + 1; count };
+            bump();
+            print(count, bump());
+            fn make_counter() {
+                var n = 0;
+                fn next() -> int { n = n + 1; return n; }
+                return next;
+            }
+            var first = make_counter();
+            var second = make_counter();
+            first();
+            print(first(), second());
+            var twice = |f, x| f(f(x));
+            print(twice(|v| v * 3, 2));");
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(output, "5 15\n1 2\n2 1\n18\n");
+
+        // Only a function body's last expression without a ';' is its value
+        let body = |source: &str| match Parser::new(Lexer::new(source)).parse() {
+            Ok(program) => match &program.statements[0] {
+                Stmt::FunctionDeclaration(function) => function.body.clone(),
+                other => panic!("expected a function, got {:?}", other),
+            },
+            Err(error) => panic!("{}", error),
+        };
+        assert!(matches!(
+            body("fn f(x: int) { x * 2 }")[..],
+            [Stmt::Return(_)]
+        ));
+        assert!(matches!(
+            body("fn f(x: int) { x * 2; }")[..],
+            [Stmt::Expression(_)]
+        ));
+        assert!(matches!(
+            body("fn f(x: int) { if (x > 0) { x } }")[..],
+            [Stmt::If(_)]
+        ));
+        assert!(Parser::new(Lexer::new("fn f() { 1 2 }")).parse().is_err());
+    }
+
+    #[test]
+    fn test_spawn_and_channels() {
+        let (_, output, result) = run(r#"
+            var total = 10;
+            var results = channel();
+            var workers = 0;
+            while (workers < 3) {
+                var n = workers;
+                spawn { send(results, n * total); };
+                workers += 1;
+            }
+            var sum = 0;
+            while (workers > 0) {
+                sum += recv(results);
+                workers -= 1;
+            }
+            print(sum);
+            print(try_recv(results));
+            var square = spawn { var t = [1, 2] * [3, 4]; t };
+            print(join(square));
+            print(join(split("a b", " "), "-"));
+        "#);
+        result.unwrap();
+        assert_eq!(
+            output,
+            "30\nnull\nTensor(shape=[2], data=[3.0, 8.0])\na-b\n"
+        );
+
+        let (_, output, result) = run("var h = spawn { print(1); 1 / 0 }; join(h);");
+        assert_eq!(output, "1\n");
+        assert!(result.unwrap_err().message.contains("Division by zero"));
+
+        let (_, _, result) = run("var h = spawn { 1 }; join(h); join(h);");
+        assert_eq!(result.unwrap_err().message, "Thread already joined");
+        // One argument joins a thread, two join an array's elements
+        let (_, _, result) = run("join(\"a b\");");
+        assert_eq!(
+            result.unwrap_err().message,
+            "join() expects a thread, found string"
+        );
+        let (_, _, result) = run("join(split(\"a b\", \" \"), \"-\", 1);");
+        assert_eq!(
+            result.unwrap_err().message,
+            "'join' expects 1 or 2 arguments but got 3"
+        );
+
+        let mut engine = Engine::sandboxed(Limits {
+            timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        });
+        let error = engine.eval("recv(channel());").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Runtime error: Time limit of 50ms exceeded"
+        );
+
+        let program = Parser::new(Lexer::new("var h = spawn { 1 };"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            format_program(&program),
+            "var h = spawn {\n    return 1;\n};\n"
+        );
+    }
+
+    #[test]
+    fn test_copy_on_write_arrays() {
+        let (interpreter, output, result) = run(r#"
+            var a = split("x,y,z", ",");
+            var b = a;
+            b[0] = "changed";
+            a[2] += "!";
+            print(a, b);
+            fn set_first(array) { array[0] = 1; return array; }
+            var c = set_first(a);
+            print(a[0], c[0]);
+        "#);
+        result.unwrap();
+        assert_eq!(output, "[x, y, z!] [changed, y, z]\nx 1\n");
+        assert_eq!(
+            interpreter.global("b"),
+            Some(Value::Array(Arc::new(vec![
+                Value::String("changed".into()),
+                Value::String("y".into()),
+                Value::String("z".into()),
+            ])))
+        );
+
+        let (_, _, result) = run("var a = split(\"x\", \",\"); a[1] = \"y\";");
+        assert_eq!(
+            result.unwrap_err().message,
+            "Index 1 is out of bounds for an array of length 1"
+        );
+        let (_, _, result) = run("var n = 1; n[0] = 2;");
+        assert_eq!(result.unwrap_err().message, "Cannot index into int");
+    }
+
+    #[test]
+    fn test_import_modules() {
+        let directory = std::env::temp_dir().join(format!("put_modules_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("baskets")).unwrap();
+        let write =
+            |name: &str, source: &str| std::fs::write(directory.join(name), source).unwrap();
+        write(
+            "shapes.put",
+            "print(\"loading shapes\"); const sides = 4; fn area(w, h) { w * h }",
+        );
+        write(
+            "stats.put",
+            "import \"shapes.put\"; fn perimeter(s) { s * shapes.sides }",
+        );
+        write("baskets/geometry.put", "var unit = 1;");
+        write("a.put", "import \"b.put\";");
+        write("b.put", "import \"a.put\";");
+        let config = ProjectConfig {
+            project_info: HashMap::new(),
+            dependencies: HashMap::from([("GeometryBasket".to_string(), "1.0".to_string())]),
+            build_settings: HashMap::new(),
+            runtime_settings: HashMap::new(),
+            custom_settings: HashMap::new(),
+        };
+        let run_in = |source: &str| {
+            let program = Parser::new(Lexer::new(source)).parse().unwrap();
+            let output = SharedOutput::default();
+            let result = Interpreter::with_output(output.clone())
+                .allow_file_access(true)
+                .import_from(&directory)
+                .project(config.clone(), &directory)
+                .interpret(&program);
+            (output.text(), result)
+        };
+
+        let (output, result) = run_in(
+            r#"
+            import "shapes.put";
+            import "stats.put";
+            use geometry;
+            print(shapes.area(2, 3), stats.perimeter(5), shapes.sides, geometry.unit);
+            print(shapes);
+            "#,
+        );
+        result.unwrap();
+        assert_eq!(output, "loading shapes\n6 20 4 1\n<module shapes>\n");
+
+        let (_, result) = run_in("import \"a.put\";");
+        assert_eq!(
+            result.unwrap_err().message,
+            "In 'a.put': In 'b.put': Import cycle: a.put -> b.put -> a.put"
+        );
+        let (_, result) = run_in("import \"shapes.put\"; shapes.len(1);");
+        assert_eq!(
+            result.unwrap_err().message,
+            "Module 'shapes' has no member 'len'"
+        );
+        let (_, _, result) = run("import \"shapes.put\";");
+        assert_eq!(
+            result.unwrap_err().message,
+            "Cannot import 'shapes.put': this interpreter does not allow file access"
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_throw_and_catch() {
+        let (_, output, result) = run(r#"
+            fn check(n) {
+                if (n < 0) { throw "negative"; }
+                return n;
+            }
+            try { check(-1); print("unreachable"); } catch (e) { print("caught", e); }
+            try { print(1 / 0); } catch (e) { print(e); }
+            try { var t = [1, 2] + [1, 2, 3]; } catch (e) { print("shape", type_of(e)); }
+            try { throw 42; } catch (e) { print(e + 1); }
+            fn first() { try { return 1; } catch { return 2; } }
+            print(first());
+            try { try { throw "inner"; } catch (e) { throw e + "!"; } } catch (e) { print(e); }
+        "#);
+        result.unwrap();
+        assert_eq!(
+            output,
+            "caught negative\nDivision by zero\nshape string\n43\n1\ninner!\n"
+        );
+
+        let (_, _, result) = run("throw \"boom\";");
+        let error = result.unwrap_err();
+        assert_eq!(error.message, "Uncaught exception: \"boom\"");
+        assert_eq!(error.thrown, Some(Value::String("boom".into())));
+
+        let mut engine = Engine::sandboxed(Limits {
+            max_instructions: Some(100),
+            ..Limits::default()
+        });
+        let error = engine
+            .eval("try { while (true) {} } catch { print(1); }")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Runtime error: Instruction limit of 100 exceeded"
+        );
+
+        let program = Parser::new(Lexer::new("throw  x+1;")).parse().unwrap();
+        assert_eq!(format_program(&program), "throw x + 1;\n");
+    }
+
+    #[test]
+    fn test_engine_globals() {
+        let mut engine = Engine::with_output(SharedOutput::default());
+        engine.register_fn("double", |n: i64| n * 2);
+        engine
+            .eval("var weights = [0.5, 1.5]; const steps = double(3); fn loss(x) { x * x }")
+            .unwrap();
+        let mut program = Parser::new(Lexer::new("var done = steps > 5;"))
+            .parse()
+            .unwrap();
+        fold_constants(&mut program);
+        assert_eq!(engine.run(&program), Ok(Value::Null));
+
+        assert_eq!(engine.get("steps"), Some(Value::Integer(6)));
+        assert_eq!(engine.get("done"), Some(Value::Boolean(true)));
+        assert_eq!(engine.get("missing"), None);
+        let names: Vec<String> = engine.globals().map(|(name, _)| name).collect();
+        assert_eq!(names, ["done", "loss", "steps", "weights"]);
+        let weights = engine
+            .globals()
+            .find(|(name, _)| name == "weights")
+            .unwrap()
+            .1;
+        assert_eq!(weights.to_string(), "Tensor(shape=[2], data=[0.5, 1.5])");
+    }
+
+    #[test]
+    fn test_repl_session_save_and_load() {
+        let path = std::env::temp_dir().join(format!("put_session_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut session = Session::new(Engine::with_output(SharedOutput::default()));
+        let input = format!(
+            "var weights = [0.5, 1.5]\nconst scale = 9007199254740993\n\
+             fn apply(x) {{\n  return x * 2;\n}}\nvar words = split(\"a b\", \" \")\n\
+             apply(21)\nvar broken = \n\n:save {}\n:quit\nprint(1);\n",
+            path
+        );
+        let mut output = Vec::new();
+        session.run(input.as_bytes(), &mut output);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("put> ...> ...> put> put> 42\n"));
+        assert!(output.contains("Parse error"));
+        assert_eq!(session.history().len(), 5);
+        assert_eq!(session.history()[2], "fn apply(x) {\n  return x * 2;\n}");
+
+        let printed = SharedOutput::default();
+        let mut restored = Session::new(Engine::with_output(printed.clone()));
+        restored.load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(restored.history(), session.history());
+        let engine = restored.engine();
+        assert_eq!(engine.get("scale"), Some(Value::Integer(9007199254740993)));
+        assert!(engine.is_constant("scale"));
+        assert!(!engine.is_constant("weights"));
+        assert_eq!(
+            engine.get("words").unwrap().to_string(),
+            session.engine().get("words").unwrap().to_string()
+        );
+        assert_eq!(
+            engine.get("weights").unwrap().to_string(),
+            "Tensor(shape=[2], data=[0.5, 1.5])"
+        );
+        assert_eq!(restored.eval("apply(len(words))"), Ok(Value::Integer(4)));
+        restored.eval("print(len(words))").unwrap();
+        assert_eq!(printed.text(), "2\n");
+        assert!(restored.load("/nonexistent/session.json").is_err());
+    }
+
+    #[test]
+    fn test_cancel_running_script() {
+        let mut engine = Engine::with_output(SharedOutput::default());
+        let cancel = engine.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        let started = Instant::now();
+        let result = engine.eval(
+            "var c = channel();
+             var waiter = spawn { return recv(c); };
+             try { while (true) {} } catch (e) { print(e); }",
+        );
+        canceller.join().unwrap();
+        let error = match result {
+            Err(EngineError::Runtime(error)) => error,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+        assert_eq!(error.message, "Script cancelled");
+        assert!(!error.catchable);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The blocked thread noticed too, and later runs start afresh
+        assert_eq!(
+            engine.eval("join(waiter);").unwrap_err().to_string(),
+            "Runtime error: Script cancelled"
+        );
+        assert_eq!(engine.eval("1 + 1;"), Ok(Value::Integer(2)));
+    }
+
+    #[test]
+    fn test_type_checker_scopes() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert_eq!(
+            check("var x: int = 1; x = \"one\";"),
+            Err("Cannot assign String to 'x' of type Integer".to_string())
+        );
+        assert_eq!(
+            check("var label: string = 'c';"),
+            Err("Cannot initialize 'label' of type String with Char".to_string())
+        );
+        assert_eq!(
+            check("fn scale(n: int) { n = 2.5; }"),
+            Err("Cannot assign Float to 'n' of type Integer".to_string())
+        );
+        assert_eq!(
+            check("var best: float? = null; var count: int = 0; count = null;"),
+            Err("Cannot assign null to 'count' of non-optional type Integer".to_string())
+        );
+        // Ints widen to floats, and an inner declaration hides the outer
+        // one until its block ends
+        assert_eq!(check("var rate: float = 1; rate = (2);"), Ok(()));
+        assert_eq!(
+            check("var x: int = 1; { var x = \"inner\"; x = \"still\"; } x = 2;"),
+            Ok(())
+        );
+        assert_eq!(
+            check("var x: int = 1; { var x = \"inner\"; } x = \"outer\";"),
+            Err("Cannot assign String to 'x' of type Integer".to_string())
+        );
+        assert_eq!(check("fn f() { const K = 1; } var K = 2; K = 3;"), Ok(()));
+        assert_eq!(
+            check("for i in 0..3 { i = true; }"),
+            Err("Cannot assign Boolean to 'i' of type Integer".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_inference_from_initializers() {
+        let mut program = Parser::new(Lexer::new(
+            "var n = 3; var rate = 0.5; const NAME = \"put\"; var ok = (true);
+             var weights = [1, 2]; var copy = n; var nothing = null;
+             print(n, rate, NAME, ok, weights, copy, nothing);",
+        ))
+        .parse()
+        .unwrap();
+        TypeChecker::infer_types(&mut program).unwrap();
+        let Stmt::Expression(Expr::Call(print)) = &program.statements[7] else {
+            panic!("Expected CallNode, got {:?}", program.statements[7]);
+        };
+        let types: Vec<Option<DataType>> = print
+            .arguments
+            .iter()
+            .map(|argument| match argument {
+                Expr::Variable(variable) => variable.data_type.clone(),
+                other => panic!("Expected VariableNode, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            types,
+            [
+                Some(DataType::Integer),
+                Some(DataType::Float),
+                Some(DataType::String),
+                Some(DataType::Boolean),
+                Some(DataType::Tensor),
+                Some(DataType::Integer),
+                None,
+            ]
+        );
+        // The parser leaves the types to the checker
+        assert!(matches!(
+            Parser::new(Lexer::new("n;")).parse().unwrap().statements[0],
+            Stmt::Expression(Expr::Variable(VariableNode {
+                data_type: None,
+                ..
+            }))
+        ));
+
+        let program = Parser::new(Lexer::new("var count = 0; count = \"many\";"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            TypeChecker::check_program(&program).map_err(|errors| errors[0].message.clone()),
+            Err("Cannot assign String to 'count' of type Integer".to_string())
+        );
+        let program = Parser::new(Lexer::new("var total = 0.0; total = 1;"))
+            .parse()
+            .unwrap();
+        assert_eq!(TypeChecker::check_program(&program), Ok(()));
+    }
+
+    #[test]
+    fn test_binary_operation_types() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert_eq!(
+            check("var label = \"n = \" + 1;"),
+            Err("Cannot apply '+' to String and Integer".to_string())
+        );
+        assert_eq!(
+            check("var yes = true; var no = false; var q = yes / no;"),
+            Err("Cannot apply '/' to Boolean and Boolean".to_string())
+        );
+        assert_eq!(
+            check("var m = [[1, 2]] @ [[3], [4]]; var bad = m * 2;"),
+            Err("Cannot apply '*' to Tensor and Integer".to_string())
+        );
+        assert_eq!(
+            check("var flag = 1 && true;"),
+            Err("Cannot apply '&&' to Integer and Boolean".to_string())
+        );
+        assert_eq!(
+            check("var n = -\"text\";"),
+            Err("Cannot apply '-' to String".to_string())
+        );
+        // Result types feed into later checks
+        assert_eq!(
+            check("var mean: int = (1 + 2) / 2.0;"),
+            Err("Cannot initialize 'mean' of type Integer with Float".to_string())
+        );
+        assert_eq!(
+            check("var total = 0; total += 0.5;"),
+            Err("Cannot assign Float to 'total' of type Integer".to_string())
+        );
+        assert_eq!(
+            check("var ok: bool = 1 < 2.5 && \"a\" != \"b\" || 'a' >= 'b';"),
+            Ok(())
+        );
+        assert_eq!(
+            check("var name = \"put\"; name += \"-lang\"; var n = 1 << 3 | 1;"),
+            Ok(())
+        );
+        // Operands of unknown type are left to the interpreter
+        assert_eq!(check("fn f(a, b) { return a + b * 2; }"), Ok(()));
+    }
+
+    #[test]
+    fn test_undeclared_variables() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].to_string())
+        };
+        assert_eq!(
+            check("var total = 1;\nprint(totl);"),
+            Err("Type error [E001]: Undeclared variable 'totl' at line 2, column 7".to_string())
+        );
+        assert_eq!(
+            check("{ var inner = 1; } print(inner);"),
+            Err("Type error [E001]: Undeclared variable 'inner' at line 1, column 26".to_string())
+        );
+        assert!(check("print(late); var late = 1;").is_err());
+        // Functions run when called, so may use what is declared after them
+        assert!(check(
+            "fn even(n) { return n == 0 || odd(n - 1); } fn odd(n) { return even(n - 1); }"
+        )
+        .is_ok());
+        assert!(check("var f = |x| x * scale; var scale = 2; print(f(3));").is_ok());
+        assert!(check("use math; print(sqrt(pi), len([1]));").is_ok());
+        assert!(
+            check("for i in 0..3 { print(i); } try { throw 1; } catch (e) { print(e); }").is_ok()
+        );
+    }
+
+    #[test]
+    fn test_redeclaration_and_shadowing() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert_eq!(
+            check("var total = 0; var total = 1;"),
+            Err("'total' is already declared in this scope".to_string())
+        );
+        assert_eq!(
+            check("fn f(a, b, a) { return a; }"),
+            Err("'a' is already declared in this scope".to_string())
+        );
+        assert_eq!(
+            check("fn area() { return 1; } { const K = 2; var K = 3; }"),
+            Err("Cannot redeclare constant 'K'".to_string())
+        );
+        assert_eq!(
+            check("use math; use math; var x = 1; { var x = 2; }"),
+            Ok(())
+        );
+
+        let source = "var x = 1; { var x = 2; print(x); } fn f(x) { return x; } var len = 3;";
+        let mut program = Parser::new(Lexer::new(source)).parse().unwrap();
+        assert_eq!(TypeChecker::new().check(&mut program), Ok(vec![]));
+        assert_eq!(
+            TypeChecker::new()
+                .warn_on_shadowing(true)
+                .check(&mut program)
+                .map(|warnings| warnings.iter().map(Warning::to_string).collect::<Vec<_>>()),
+            Ok(vec![
+                "Warning: 'x' shadows a variable of an enclosing scope at line 1, column 18"
+                    .to_string(),
+                "Warning: 'x' shadows a variable of an enclosing scope at line 1, column 42"
+                    .to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_call_signatures() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert_eq!(
+            check("fn area(w: float, h: float) -> float { return w * h; } area(2.0);"),
+            Err("'area' expects 2 arguments but got 1".to_string())
+        );
+        assert_eq!(
+            check("fn greet(name: string) { print(name); } greet(42);"),
+            Err("'greet' expects String for parameter 'name' but got Integer".to_string())
+        );
+        assert_eq!(
+            check("fn twice(n: int) -> int { return n * 2; } twice(null);"),
+            Err("'twice' expects Integer for parameter 'n' but got null".to_string())
+        );
+        assert_eq!(
+            check("print(len(\"a\", \"b\"));"),
+            Err("'len' expects 1 argument but got 2".to_string())
+        );
+        // Results have the declared return type, and functions may call
+        // ones declared after them
+        assert_eq!(
+            check("fn half(n: int) -> float { return n / 2.0; } var s = half(3) + \"!\";"),
+            Err("Cannot apply '+' to Float and String".to_string())
+        );
+        assert_eq!(
+            check("fn first() { return second(1); } fn second(x: int) { return x; }"),
+            Ok(())
+        );
+        assert_eq!(
+            check("fn first() { return second(1, 2); } fn second(x: int) { return x; }"),
+            Err("'second' expects 1 argument but got 2".to_string())
+        );
+        assert_eq!(
+            check("fn scale(x: float, by) { return x; } scale(1, \"any\"); print(\"a\", 1);"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_return_types() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        assert_eq!(
+            check("fn name() -> string { return 42; }"),
+            Err("Cannot return Integer from 'name', which returns String".to_string())
+        );
+        assert_eq!(
+            check("fn count() -> int { return; }"),
+            Err("Missing return value in 'count', which returns Integer".to_string())
+        );
+        assert_eq!(
+            check("fn log(x) -> void { return x + 1; }"),
+            Err("Cannot return a value from 'log', which returns Void".to_string())
+        );
+        assert_eq!(
+            check("var f = |x: int| -> string x * 2;"),
+            Err("Cannot return Integer from a lambda, which returns String".to_string())
+        );
+        assert_eq!(
+            check("fn sign(n: int) -> int { if (n < 0) { return -1; } else if (n > 0) { return 1; } }"),
+            Err("Missing return at the end of 'sign', which returns Integer".to_string())
+        );
+        assert_eq!(
+            check(
+                "fn sign(n: int) -> int { if (n < 0) { return -1; } else if (n > 0) { return 1; } else { return 0; } }
+                 fn half(n: int) -> float? { if (n == 0) { throw \"zero\"; } return n / 2; }
+                 fn pick(n: int) -> string { match n { 1 => { return \"one\"; } _ => { return \"many\"; } } }
+                 fn nothing() -> int? { return null; }
+                 fn untyped(n) { if (n > 0) { return n; } }"
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_cast_expressions() {
+        // Casts bind tighter than `*` but looser than `-`
+        let program = Parser::new(Lexer::new("n = -x as int * 2;"))
+            .parse()
+            .unwrap();
+        assert_eq!(
+            program.statements[0].to_sexpr(),
+            "(= n (* (as (- x) int) 2))"
+        );
+
+        let (_, output, result) = run(
+            "print(7.9 as int, -7.9 as int, 3 as float, 'A' as int, 97 as char);
+                 print(\"42\" as int + 1, \" 2.5 \" as float, true as int, 1.5 as string + \"!\");
+                 var maybe: int? = null; print(maybe as float?);",
+        );
+        result.unwrap();
+        assert_eq!(output, "7 -7 3.0 65 a\n43 2.5 1 1.5!\nnull\n");
+        let (_, _, result) = run("var n = \"forty\" as int;");
+        assert_eq!(result.unwrap_err().message, "Cannot cast \"forty\" to int");
+
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        // Floats only become ints through a cast
+        assert_eq!(
+            check("var n: int = 2.5;"),
+            Err("Cannot initialize 'n' of type Integer with Float".to_string())
+        );
+        assert_eq!(check("var n: int = 2.5 as int; var x: float = n;"), Ok(()));
+        assert_eq!(
+            check("var f = true as float;"),
+            Err("Cannot cast Boolean to Float".to_string())
+        );
+        assert_eq!(
+            check("var s = (1 as string) * 2;"),
+            Err("Cannot apply '*' to String and Integer".to_string())
+        );
+        let program = Parser::new(Lexer::new(
+            "var n = 2.5 as int; print(n as string, 'a' as int);",
+        ))
+        .parse()
+        .unwrap();
+        let rust = emit_rust(&program).unwrap();
+        assert!(rust.contains("let mut n = 2.5 as i64;"), "{}", rust);
+        assert!(rust.contains("n.to_string(), ('a' as i64)"), "{}", rust);
+        let python = emit_python(&program).unwrap();
+        assert!(
+            python.contains("n = int(2.5)\nprint(str(n), ord(\"a\"))"),
+            "{}",
+            python
+        );
+    }
+
+    #[test]
+    fn test_type_errors_collected() {
+        let source = "var n: int = \"one\";\nprint(missing);\nconst K = 1; K = 2;\nvar n = 3;";
+        let errors = TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+            .unwrap_err();
+        let codes: Vec<&str> = errors.iter().map(|error| error.code.code()).collect();
+        assert_eq!(codes, ["E004", "E001", "E003", "E002"]);
+        assert_eq!(
+            errors[1],
+            TypeError {
+                code: ErrorCode::UndeclaredVariable,
+                message: "Undeclared variable 'missing'".to_string(),
+                span: Some(Span {
+                    line: 2,
+                    column: 7,
+                    length: 7
+                }),
+            }
+        );
+        assert_eq!(
+            type_checker::report(&errors[2..]),
+            "Type error [E003]: Cannot assign to constant 'K' at line 3, column 14\n\
+             Type error [E002]: 'n' is already declared in this scope at line 4, column 5"
+        );
+    }
+
+    #[test]
+    fn test_unused_warnings() {
+        let warnings = |source: &str| {
+            TypeChecker::new()
+                .check(&mut Parser::new(Lexer::new(source)).parse().unwrap())
+                .map(|warnings| warnings.iter().map(Warning::to_string).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            warnings(
+                "fn area(w, h, _unit) { var scale = 2; var total = 0; total = w * 2; return w; }"
+            ),
+            Ok(vec![
+                "Warning: Variable 'h' is never read at line 1, column 12".to_string(),
+                "Warning: Variable 'scale' is never read at line 1, column 28".to_string(),
+                "Warning: Variable 'total' is never read at line 1, column 43".to_string(),
+            ])
+        );
+        // Globals, imports and functions are never reported, nor are names
+        // read by a function declared before them
+        assert_eq!(
+            warnings(
+                "use math; var unused = 1; fn helper() {}
+                 { fn get() { return later; } var later = 1; print(get()); }"
+            ),
+            Ok(vec![])
+        );
+        assert_eq!(
+            warnings("var n = 1; n + 1; (n); print(n); n * 2;"),
+            Ok(vec![
+                "Warning: Result of 'n + 1' is never used at line 1, column 14".to_string(),
+                "Warning: Result of '(n)' is never used at line 1, column 19".to_string(),
+            ])
+        );
+        // Warnings quote the script as written, before constants fold
+        let mut program = put_lang::modules::parse_script("1 + 2; print(0);", None).unwrap();
+        assert_eq!(
+            TypeChecker::new().check(&mut program),
+            Ok(vec![Warning {
+                message: "Result of '1 + 2' is never used".to_string(),
+                span: Some(Span {
+                    line: 1,
+                    column: 3,
+                    length: 1
+                }),
+            }])
+        );
+        assert_eq!(warnings("var n = 0; n += 1; n = 2; print(n);"), Ok(vec![]));
+
+        let allowed = "fn f() {
+                 // #allow(unused_variables)
+                 var spare = 1;
+                 var other = 2; // #allow(unused)
+                 1 + 1; // #allow(unused_results)
+                 return 0;
+             }
+             // #allow(unused)
+             fn g(x) { var y = 2; x; return 0; }";
+        assert_eq!(warnings(allowed), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_generic_functions() {
+        let source = "fn first<T>(items: [T]) -> T { return items[0]; }
+                      var words: [string] = split(\"a,b\", \",\");
+                      print(first(words));";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        let Stmt::FunctionDeclaration(function) = &program.statements[0] else {
+            panic!(
+                "Expected FunctionDeclaration, got {:?}",
+                program.statements[0]
+            );
+        };
+        assert_eq!(function.type_parameters, ["T"]);
+        let element = DataType::Parameter("T".to_string());
+        assert_eq!(
+            function.parameters[0].data_type,
+            Some(DataType::Array(Box::new(element.clone())))
+        );
+        assert_eq!(function.return_type, Some(element));
+        assert!(format_program(&program)
+            .starts_with("fn first<T>(items: [T]) -> T {\n    return items[0];\n}\n"));
+        assert_eq!(TypeChecker::check_program(&program), Ok(()));
+        let (_, output, result) = run(source);
+        assert!(result.is_ok());
+        assert_eq!(output, "a\n");
+
+        // Tensor literals pass for arrays of their rows
+        let literals = "fn first<T>(items: [T]) -> T { return items[0]; }
+                        fn count<T>(items: [T]) -> int { return len(items); }
+                        var x: float = first([1.5, 2]);
+                        var row = first([[1, 2], [3, 4]]);
+                        var weights: [float] = [0.5, 0.25];
+                        print(x, count([1, 2, 3]), row, first(weights) + weights[1]);";
+        let program = Parser::new(Lexer::new(literals)).parse().unwrap();
+        assert_eq!(TypeChecker::check_program(&program), Ok(()));
+        let (_, output, result) = run(literals);
+        result.unwrap();
+        assert_eq!(output, "1.5 3 Tensor(shape=[2], data=[1.0, 2.0]) 0.75\n");
+
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap())
+                .map_err(|errors| errors[0].message.clone())
+        };
+        let first = "fn first<T>(items: [T]) -> T { return items[0]; }";
+        assert_eq!(
+            check(&format!(
+                "{} var words: [string] = split(\"a\", \",\"); var n: int = first(words);",
+                first
+            )),
+            Err("Cannot initialize 'n' of type Integer with String".to_string())
+        );
+        assert_eq!(
+            check(&format!("{} var x: float = first([[1, 2]]);", first)),
+            Err("Cannot initialize 'x' of type Float with Tensor".to_string())
+        );
+        assert_eq!(
+            check("fn same<T>(a: T, b: T) -> bool { return a == b; } same(1, \"one\");"),
+            Err("'same' expects Integer for parameter 'b' but got String".to_string())
+        );
+        assert_eq!(
+            check(&format!("{} first(1);", first)),
+            Err(
+                "'first' expects Array(Parameter(\"T\")) for parameter 'items' but got Integer"
+                    .to_string()
+            )
+        );
+        // In the body a type parameter stands for any type, so only its
+        // own values match it
+        assert_eq!(
+            check("fn zero<T>(x: T) -> T { return 0; }"),
+            Err("Cannot return Integer from 'zero', which returns Parameter(\"T\")".to_string())
+        );
+        assert_eq!(
+            check("fn id<T>(x: T) -> T { return x; } var s: string = id(\"a\"); var n: int = id(1); print(id(s) + \"!\", n);"),
+            Ok(())
+        );
+        assert_eq!(
+            emit_rust(
+                &Parser::new(Lexer::new("fn id<T>(x: T) -> T { return x; }"))
+                    .parse()
+                    .unwrap()
+            ),
+            Err("Cannot emit Rust for generic function 'id'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_constant_evaluation() {
+        let check = |source: &str| {
+            TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap()).map_err(
+                |errors| {
+                    errors
+                        .iter()
+                        .map(|error| (error.code, error.message.clone()))
+                        .collect::<Vec<_>>()
+                },
+            )
+        };
+        let failed =
+            |message: &str| Err(vec![(ErrorCode::ConstantEvaluation, message.to_string())]);
+        assert_eq!(
+            check("var n = 4; print(n / 0);"),
+            failed("Division by zero")
+        );
+        assert_eq!(
+            check("const SIZE = 4; const HALF = SIZE / 2; print(1.5 / (HALF - 2));"),
+            failed("Division by zero")
+        );
+        assert_eq!(
+            check("const MAX = 9223372036854775807; print(MAX + 1);"),
+            failed("Integer overflow")
+        );
+        assert_eq!(
+            check("const BITS = 8 * 8; print(1 << BITS);"),
+            failed("Shift amount 64 is out of range")
+        );
+        assert_eq!(
+            check("const CODE = 55296; print(CODE as char, (\"4\" + \"2\") as int, \"forty\" as float);"),
+            Err(vec![
+                (ErrorCode::InvalidCast, "Cannot cast 55296 to char".to_string()),
+                (ErrorCode::InvalidCast, "Cannot cast \"forty\" to float".to_string()),
+            ])
+        );
+        // Variables may change, so only constants are worked out
+        assert_eq!(
+            check("var zero = 0; const ONE = 1; print(ONE / zero, 8 >> 2, ONE / 2.0);"),
+            Ok(())
+        );
+        assert_eq!(
+            check("const TEN = \"10\" as int; print(1 / (TEN - 10));"),
+            failed("Division by zero")
+        );
+
+        // Tensor dimensions worked out from constants are checked, and so
+        // are the shapes of the tensors they make
+        assert_eq!(
+            check("const N = 2; var t = zeros(N - 3, 4);"),
+            failed("Tensor dimensions can't be negative, found -1")
+        );
+        assert_eq!(
+            check("var t = zeros(2, 1.5);"),
+            Err(vec![(
+                ErrorCode::TypeMismatch,
+                "'zeros' expects int dimensions but got Float".to_string()
+            )])
+        );
+        assert_eq!(
+            check("const N = 2; const W = zeros(N, 3); print(W @ zeros(N, 3));"),
+            Err(vec![(
+                ErrorCode::InvalidOperands,
+                "Cannot apply '@' to tensors of shapes [2, 3] and [2, 3]".to_string()
+            )])
+        );
+        assert_eq!(
+            check("const N = 2; const W = zeros(N, 3); print(W @ zeros(N + 1, 1) + [1]);"),
+            Ok(())
+        );
+        let (_, output, result) = run("var n = 2; print(zeros(n, 1), zeros(0)); zeros(n - 3);");
+        assert_eq!(
+            output,
+            "Tensor(shape=[2, 1], data=[0.0, 0.0]) Tensor(shape=[0], data=[])\n"
+        );
+        assert_eq!(
+            result.unwrap_err().message,
+            "Tensor dimensions can't be negative, found -1"
+        );
+    }
+
+    #[test]
+    fn test_typed_ast() {
+        let mut program = Parser::new(Lexer::new(
+            "const W = [[1, 2], [3, 4]]; var m = (W @ W); var n = (1 + 2) * 3.0; var s = \"a\" + \"b\";",
+        ))
+        .parse()
+        .unwrap();
+        // Nothing is resolved until the checker runs
+        let Stmt::VarDeclaration(declaration) = &program.statements[1] else {
+            panic!("Expected VarDeclaration, got {:?}", program.statements[1]);
+        };
+        assert_eq!(
+            declaration
+                .initializer
+                .as_ref()
+                .unwrap()
+                .resolved()
+                .data_type(),
+            None
+        );
+
+        TypeChecker::infer_types(&mut program).unwrap();
+        let resolved: Vec<(Option<DataType>, Option<Vec<usize>>)> = program
+            .statements
+            .iter()
+            .map(|statement| {
+                let value = match statement {
+                    Stmt::ConstDeclaration(declaration) => &declaration.value,
+                    Stmt::VarDeclaration(declaration) => declaration.initializer.as_ref().unwrap(),
+                    other => panic!("Expected a declaration, got {:?}", other),
+                };
+                (
+                    value.resolved().data_type().cloned(),
+                    value.resolved().shape().map(<[usize]>::to_vec),
+                )
+            })
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                (Some(DataType::Tensor), Some(vec![2, 2])),
+                (Some(DataType::Tensor), Some(vec![2, 2])),
+                (Some(DataType::Float), None),
+                (Some(DataType::String), None),
+            ]
+        );
+        let Stmt::VarDeclaration(declaration) = &program.statements[2] else {
+            panic!("Expected VarDeclaration, got {:?}", program.statements[2]);
+        };
+        let Expr::Binary(product) = declaration.initializer.as_ref().unwrap() else {
+            panic!("Expected BinaryOperationNode");
+        };
+        assert_eq!(
+            product.left.resolved().data_type(),
+            Some(&DataType::Integer)
+        );
+
+        // Code generators use the resolved types, so a float variable
+        // divides as a float
+        let python = emit_python(
+            &Parser::new(Lexer::new("var x = 1.5; print(x / 2);"))
+                .parse()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(python.contains("print(x / 2)"));
+        assert!(!python.contains("_div"));
+
+        // So does typed folding, which drops casts and int arithmetic that
+        // do nothing for the types involved
+        let mut program = Parser::new(Lexer::new(
+            "var n = 5; var f = 2.5; var s = \"7\";
+             print(n as int + 0, 1 * (n / 1), f as float, n as float, f + 0, s as int * 1);",
+        ))
+        .parse()
+        .unwrap();
+        TypeChecker::new().check(&mut program).unwrap();
+        fold_typed(&mut program);
+        assert_eq!(
+            format_statement(&program.statements[3]),
+            "print(n, (n), f, n as float, f + 0, s as int);"
+        );
+        // Without resolved types nothing is folded
+        let mut unchecked = Parser::new(Lexer::new("print(n + 0);")).parse().unwrap();
+        fold_typed(&mut unchecked);
+        assert_eq!(format_program(&unchecked), "print(n + 0);\n");
+    }
+
+    #[test]
+    fn test_conditions_and_definite_assignment() {
+        let errors = |source: &str| -> Vec<String> {
+            match TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap()) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors.iter().map(TypeError::to_string).collect(),
+            }
+        };
+        assert_eq!(
+            errors("if (1) {} while (\"x\") {} var n = 2 > 1 ? 1 : 0;"),
+            [
+                "Type error [E004]: Condition of 'if' must be a bool, found Integer at line 1, column 5",
+                "Type error [E004]: Condition of 'while' must be a bool, found String at line 1, column 18",
+            ]
+        );
+        assert_eq!(
+            errors("var c = true; var x; if (c) { x = 1; }\nprint(x);"),
+            ["Type error [E010]: Variable 'x' may be read before it is assigned at line 2, column 7"]
+        );
+        // Every path that carries on assigns it
+        assert_eq!(
+            errors(
+                "var c = true; var y; if (c) { y = 1; } else { throw \"no\"; } print(y);
+                 var z; try { z = 1; } catch (e) { z = 2; } print(z);
+                 var t; match 2 { 1 => t = 1, _ => { t = 2; } } print(t);"
+            ),
+            Vec::<String>::new()
+        );
+        // Loop bodies and right operands may not run
+        assert_eq!(
+            errors(
+                "var c = false; var w; while (c) { w = 1; } print(w);
+                 var a; c && (a = true); print(a);
+                 var b; for (var i = 0; i < 3; i += 1) { b = i; } print(b);"
+            )
+            .len(),
+            3
+        );
+        // Function bodies run later, and optionals start out null
+        assert_eq!(
+            errors("var g; fn show() { print(g); } g = 1; show(); var o: int?; print(o);"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("var d; do { d = 5; } while (false); print(d);"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_number_literals_out_of_range() {
+        let errors = |source: &str| -> Vec<String> {
+            match TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap()) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors.iter().map(TypeError::to_string).collect(),
+            }
+        };
+        assert_eq!(
+            errors("print(9223372036854775807, -9223372036854775807 - 1);"),
+            Vec::<String>::new()
+        );
+        let float = format!("{}.5", "9".repeat(400));
+        assert_eq!(
+            errors(&format!("var big = 9223372036854775808;\nprint({});", float)),
+            [
+                "Type error [E013]: Integer literal '9223372036854775808' is out of range at line 1, column 11".to_string(),
+                format!(
+                    "Type error [E013]: Float literal '{}' is out of range at line 2, column 7",
+                    float
+                ),
+            ]
+        );
+        assert!(matches!(
+            Engine::new().eval("print(99999999999999999999);"),
+            Err(EngineError::Type(errors)) if errors[0].code == ErrorCode::NumberOutOfRange
+        ));
+    }
+
+    #[test]
+    fn test_ranges_only_in_for_headers() {
+        let errors = |source: &str| -> Vec<String> {
+            match TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap()) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors.iter().map(TypeError::to_string).collect(),
+            }
+        };
+        assert_eq!(
+            errors("for i in 0..=10 step 2 { print(i); }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("var r = 0..10;\nfor i in 0.5..2 step 0 { print(i); }"),
+            [
+                "Type error [E011]: A range can only be used as what a 'for' loop counts over at line 1, column 10",
+                "Type error [E004]: Range bounds and steps must be int, found Float at line 2, column 10",
+                "Type error [E009]: Range step must be positive, found 0 at line 2, column 22",
+            ]
+        );
+        let (_, _, result) = run("var r = 0..10;");
+        assert_eq!(
+            result.unwrap_err().message,
+            "A range can only be used as what a 'for' loop counts over"
+        );
+    }
+
+    #[test]
+    fn test_enum_values() {
+        let source = r#"
+            enum Color { Red, Green, Blue }
+            var c = Color::Green;
+            match c {
+                Color::Red => { print("red"); }
+                Color::Green => { print("green"); }
+                _ => { print("other"); }
+            }
+            var name = match c { Color::Blue => "blue", _ => "not blue" };
+            print(c, c == Color::Green, c == Color::Red, name, type_of(c));
+        "#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        TypeChecker::check_program(&program).unwrap();
+        let (interpreter, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "green\nColor::Green true false not blue enum\n");
+        assert_eq!(
+            interpreter.global("c"),
+            Some(Value::Enum(Arc::new(EnumValue {
+                enum_name: "Color".to_string(),
+                variant: "Green".to_string(),
+            })))
+        );
+
+        let errors = TypeChecker::check_program(
+            &Parser::new(Lexer::new(
+                "enum Color { Red } var a = Color::Purple; var b = Shade::Dark;",
+            ))
+            .parse()
+            .unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            type_checker::report(&errors),
+            "Type error [E001]: Enum 'Color' has no variant 'Purple' at line 1, column 28\n\
+             Type error [E001]: Undeclared enum 'Shade' at line 1, column 51"
+        );
+    }
+
+    #[test]
+    fn test_struct_values() {
+        let source = r#"
+            struct Point { x: float, y: float }
+            fn norm2(p: Point) -> float { return p.x * p.x + p.y * p.y; }
+            var p = Point(3.0, 4.0);
+            var q = p;
+            p.x = 6.0;
+            p.y += 1.0;
+            print(p, q.x, norm2(q), type_of(p), p == Point(6.0, 5.0));
+        "#;
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+        TypeChecker::check_program(&program).unwrap();
+        let (_, output, result) = run(source);
+        result.unwrap();
+        // Structs are values: assigning to `p.x` leaves `q` as it was
+        assert_eq!(output, "Point { x: 6.0, y: 5.0 } 3.0 25.0 struct true\n");
+
+        let (_, _, result) = run("struct P { x: int } var p = P(1); print(p.z);");
+        assert_eq!(result.unwrap_err().message, "Struct 'P' has no field 'z'");
+
+        let errors = TypeChecker::check_program(
+            &Parser::new(Lexer::new(
+                "struct P { x: int } var p = P(\"a\"); p.x = true; print(p.z, P());",
+            ))
+            .parse()
+            .unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            type_checker::report(&errors),
+            "Type error [E004]: 'P' expects Integer for parameter 'x' but got String at line 1, column 31\n\
+             Type error [E004]: Cannot assign Boolean to field 'x' of type Integer at line 1, column 43\n\
+             Type error [E012]: Struct 'P' has no field 'z' at line 1, column 56\n\
+             Type error [E006]: 'P' expects 1 argument but got 0 at line 1, column 61"
+        );
+
+        let program = Parser::new(Lexer::new(
+            "struct Point { x: float, y: float } var p = Point(3.0, 4.0); p.x = 6.0; print(p);",
+        ))
+        .parse()
+        .unwrap();
+        let rust = emit_rust(&program).unwrap();
+        assert!(rust.contains("let mut p = Point { x: 3.0, y: 4.0 };\n"));
+        assert!(rust.contains("p.x = 6.0;\n"));
+        let python = emit_python(&program).unwrap();
+        assert!(python.contains("p = Point(3.0, 4.0)\n"));
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let source = r#"
+            var total = 0;
+            for i in 0..4 { total += i; }
+            for i in 0..=10 step 5 { print(i); }
+            for word in split("a,b", ",") { print(word); }
+            for i in 3..3 { print("never"); }
+            fn first_square_over(limit) {
+                for n in 1..limit { if (n * n > limit) { return n; } }
+                return -1;
+            }
+            print(total, first_square_over(10));
+        "#;
+        TypeChecker::check_program(&Parser::new(Lexer::new(source)).parse().unwrap()).unwrap();
+        let (interpreter, output, result) = run(source);
+        result.unwrap();
+        assert_eq!(output, "0\n5\n10\na\nb\n6 4\n");
+        // The loop variable is scoped to the loop
+        assert_eq!(interpreter.global("i"), None);
+
+        let error = |source: &str| run(source).2.unwrap_err().message;
+        assert_eq!(
+            error("var n = 0; for i in 0..10 step n { }"),
+            "Range step must be positive, found 0"
+        );
+        assert_eq!(error("for x in 5 { }"), "Cannot loop over int");
+    }
+
+    #[test]
+    fn test_last_statement_hooks() {
+        let source = "var x = 1;\nx + 1;\nvar y = 2;\ny * 3;";
+        let program = Parser::new(Lexer::new(source)).parse().unwrap();
+
+        // The final expression is traced like any other statement and its
+        // value is still the program's
+        let trace = SharedOutput::default();
+        let mut interpreter = Interpreter::new().trace_to(trace.clone());
+        assert_eq!(
+            interpreter.evaluate_program(&program),
+            Ok(Value::Integer(6))
+        );
+        assert_eq!(
+            trace.text(),
+            "[line 1] var x = 1; => 1\n\
+             [line 2] x + 1; => 2\n\
+             [line 3] var y = 2; => 2\n\
+             [line 4] y * 3; => 6\n"
+        );
+
+        let console = SharedOutput::default();
+        let debugger = ConsoleDebugger::new(&b"variables\n"[..], console.clone()).break_at_line(4);
+        let mut interpreter = Interpreter::new().attach_debugger(debugger);
+        assert_eq!(
+            interpreter.evaluate_program(&program),
+            Ok(Value::Integer(6))
+        );
+        assert_eq!(
+            console.text(),
+            "Paused at line breakpoint at line 4\n\
+             (put) x = 1\n\
+             y = 2\n\
+             (put) "
+        );
+    }
+
+    #[test]
+    fn test_tensor_operations() {
+        let t1 = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let t2 = Tensor::new(vec![5.0, 6.0, 7.0, 8.0], vec![2, 2]);
+
+        let t_add = &t1 + &t2;
+        assert_eq!(t_add.get(&[0, 0]), Some(6.0));
+
+        let t_sub = &t1 - &t2;
+        assert_eq!(t_sub.get(&[0, 0]), Some(-4.0));
+
+        let t_mul = &t1 * &t2;
+        assert_eq!(t_mul.get(&[0, 0]), Some(5.0));
+
+        let t_matmul = t1.matmul(&t2).unwrap();
+        assert_eq!(t_matmul.get(&[0, 0]), Some(19.0));
+
+        let t_transpose = t1.transpose();
+        assert_eq!(t_transpose.get(&[0, 1]), Some(3.0));
+
+        assert!((t1.mean() - 2.5).abs() < 1e-6);
+        assert!((t1.variance() - 1.25).abs() < 1e-6);
+        assert!((t1.std_dev() - 1.118033988749895).abs() < 1e-6);
+    }
+}